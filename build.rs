@@ -0,0 +1,13 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/brickbeam.proto");
+        // The vendored protoc keeps the build free of a system protobuf installation.
+        let mut config = tonic_build::Config::new();
+        config.protoc_executable(protoc_bin_vendored::protoc_bin_path().unwrap());
+        tonic_build::configure()
+            .compile_protos_with_config(config, &["proto/brickbeam.proto"], &["proto"])
+            .unwrap();
+    }
+}
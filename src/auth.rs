@@ -0,0 +1,113 @@
+//! # Authentication
+//!
+//! A small shared access check for brickbeam's network control surfaces (such as the `osc`
+//! server), so a misconfigured network doesn't let anyone on the Wi-Fi drive the trains.
+//!
+//! Every server takes an `Auth` and passes the credential presented by a client to
+//! `Auth::authorize`. How the credential travels depends on the transport, e.g. as the first
+//! argument of every OSC message or in the `authorization` metadata of every gRPC call.
+
+use crate::{Error, Result};
+
+/// The environment variable the `brickbeam` command line tool reads its token from.
+pub const TOKEN_ENV: &str = "BRICKBEAM_TOKEN";
+
+/// The access policy of a network server.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub enum Auth {
+    /// Accepts every client. Only suitable for isolated networks.
+    #[default]
+    Open,
+    /// Requires clients to present the given static token.
+    Token(String),
+}
+
+impl Auth {
+    /// Creates a policy requiring the given static token.
+    pub fn token(token: impl Into<String>) -> Self {
+        Self::Token(token.into())
+    }
+
+    /// Requires the token stored in the environment variable `name`, or accepts everyone if the
+    /// variable is unset or empty.
+    pub fn from_env(name: &str) -> Self {
+        match std::env::var(name) {
+            Ok(token) if !token.is_empty() => Self::Token(token),
+            _ => Self::Open,
+        }
+    }
+
+    /// Returns `true` if clients have to present a credential.
+    pub fn is_required(&self) -> bool {
+        !matches!(self, Self::Open)
+    }
+
+    /// Checks the credential presented by a client.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unauthorized` if a token is required and the credential is missing or wrong.
+    pub fn authorize(&self, credential: Option<&str>) -> Result<()> {
+        match (self, credential) {
+            (Self::Open, _) => Ok(()),
+            (Self::Token(token), Some(credential)) if constant_time_eq(token, credential) => Ok(()),
+            (Self::Token(_), Some(_)) => Err(Error::Unauthorized("Invalid token".to_string())),
+            (Self::Token(_), None) => Err(Error::Unauthorized("Missing token".to_string())),
+        }
+    }
+}
+
+// Never print the token itself, e.g. in logged server configurations.
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open => write!(f, "Open"),
+            Self::Token(_) => write!(f, "Token(<redacted>)"),
+        }
+    }
+}
+
+/// Compares two strings in time independent of where they differ.
+fn constant_time_eq(expected: &str, actual: &str) -> bool {
+    let expected = expected.as_bytes();
+    let actual = actual.as_bytes();
+    expected.len() == actual.len()
+        && expected
+            .iter()
+            .zip(actual)
+            .fold(0u8, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_accepts_everyone() {
+        assert!(Auth::Open.authorize(None).is_ok());
+        assert!(Auth::Open.authorize(Some("anything")).is_ok());
+        assert!(!Auth::default().is_required());
+    }
+
+    #[test]
+    fn test_token_requires_matching_credential() {
+        let auth = Auth::token("s3cret");
+        assert!(auth.is_required());
+        assert!(auth.authorize(Some("s3cret")).is_ok());
+        assert!(matches!(
+            auth.authorize(Some("s3cres")),
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(
+            auth.authorize(Some("s3cret!")),
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(auth.authorize(None), Err(Error::Unauthorized(_))));
+    }
+
+    #[test]
+    fn test_debug_redacts_token() {
+        assert_eq!(format!("{:?}", Auth::token("s3cret")), "Token(<redacted>)");
+    }
+}
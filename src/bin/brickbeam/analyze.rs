@@ -0,0 +1,247 @@
+//! `brickbeam analyze`: decodes captured pulse dumps into PF messages.
+//!
+//! Accepted dump formats, detected automatically:
+//!
+//! * `ir-ctl -r` / `mode2` output, e.g. `+158 -263 +158 -553 ...` or `pulse 158` / `space 263` lines.
+//! * Pronto hex, e.g. `0000 006D 0012 0000 0006 000A ...`.
+//! * Raw JSON, either an array of microsecond durations or an object with a `pulses` array.
+//!
+//! Every PF frame found in the dump is printed with its 16 bits, LRC status, message name and an
+//! ASCII waveform (one character per 79 µs, `^` for a mark and `_` for a space).
+
+use brickbeam::{
+    decode::{find_frames, MAX_ZERO_BIT},
+    scancode::{frame_to_scancode, scancode_table, scancode_to_frame},
+    Error, Result,
+};
+use serde_json::Value;
+use std::io::Write;
+
+/// The microseconds represented by one character of the ASCII waveform.
+const WAVEFORM_RESOLUTION: u32 = 79;
+
+/// Analyzes the dump in `text` and writes a report for every frame to `output`.
+pub fn run(text: &str, mut output: impl Write) -> Result<()> {
+    let pulses = parse_dump(text).map_err(Error::ProtocolError)?;
+    let frames = find_frames(&pulses);
+    if frames.is_empty() {
+        writeln!(output, "No PF frame found in {} pulses", pulses.len())?;
+        return Ok(());
+    }
+
+    let table = scancode_table();
+    for (index, frame) in frames.iter().enumerate() {
+        writeln!(output, "Frame {} at {} µs", index + 1, frame.offset)?;
+        match frame.bits {
+            Ok(bits) => {
+                let expected_lrc = scancode_to_frame(bits >> 4, false) & 0xF;
+                let (scancode, toggle) = frame_to_scancode((bits & !0xF) | expected_lrc)
+                    .expect("A frame with a recomputed LRC is always valid");
+                let name = table
+                    .iter()
+                    .find(|entry| entry.scancode == scancode)
+                    .map_or("Unknown message", |entry| entry.name.as_str());
+                writeln!(output, "  Bits:    {:016b} (0x{:04X})", bits, bits)?;
+                if bits & 0xF == expected_lrc {
+                    writeln!(output, "  LRC:     ok")?;
+                } else {
+                    writeln!(
+                        output,
+                        "  LRC:     mismatch, expected 0x{:X} but got 0x{:X}",
+                        expected_lrc,
+                        bits & 0xF
+                    )?;
+                }
+                writeln!(output, "  Message: {}, toggle {}", name, u8::from(toggle))?;
+            }
+            Err(ref reason) => writeln!(output, "  Error:   {}", reason)?,
+        }
+        let (waveform, labels) = waveform(frame.pulses);
+        writeln!(output, "  {}", waveform)?;
+        writeln!(output, "  {}", labels)?;
+    }
+    Ok(())
+}
+
+/// Parses a dump in any of the supported formats into alternating mark and space durations,
+/// starting with a mark.
+fn parse_dump(text: &str) -> std::result::Result<Vec<u32>, String> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        parse_json(trimmed)
+    } else if trimmed.starts_with("0000 ") {
+        parse_pronto(trimmed)
+    } else {
+        parse_ir_ctl(trimmed)
+    }
+}
+
+fn parse_json(text: &str) -> std::result::Result<Vec<u32>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let array = match &value {
+        Value::Array(array) => array,
+        Value::Object(object) => match object.get("pulses") {
+            Some(Value::Array(array)) => array,
+            _ => return Err("Expected a 'pulses' array".to_string()),
+        },
+        _ => return Err("Expected an array of pulses".to_string()),
+    };
+    array
+        .iter()
+        .map(|pulse| {
+            pulse
+                .as_u64()
+                .and_then(|pulse| u32::try_from(pulse).ok())
+                .ok_or_else(|| format!("Invalid pulse {}", pulse))
+        })
+        .collect()
+}
+
+fn parse_pronto(text: &str) -> std::result::Result<Vec<u32>, String> {
+    let words = text
+        .split_whitespace()
+        .map(|word| {
+            u16::from_str_radix(word, 16).map_err(|_| format!("Invalid Pronto word '{}'", word))
+        })
+        .collect::<std::result::Result<Vec<u16>, String>>()?;
+    let [_, frequency, once, repeat, durations @ ..] = words.as_slice() else {
+        return Err("Pronto code is too short".to_string());
+    };
+    if *frequency == 0 {
+        return Err("Pronto code has no carrier frequency".to_string());
+    }
+    let pairs = usize::from(*once) + usize::from(*repeat);
+    if durations.len() < 2 * pairs {
+        return Err(format!(
+            "Pronto code announces {} burst pairs but contains {}",
+            pairs,
+            durations.len() / 2
+        ));
+    }
+    // One carrier period lasts `frequency` * 0.241246 µs.
+    let period = f64::from(*frequency) * 0.241246;
+    Ok(durations[..2 * pairs]
+        .iter()
+        .map(|&cycles| (f64::from(cycles) * period).round() as u32)
+        .collect())
+}
+
+fn parse_ir_ctl(text: &str) -> std::result::Result<Vec<u32>, String> {
+    let mut pulses: Vec<u32> = Vec::new();
+    let mut words = text.split_whitespace();
+    while let Some(word) = words.next() {
+        let (mark, duration) = match word {
+            "pulse" => (true, words.next()),
+            "space" => (false, words.next()),
+            "timeout" | "carrier" => {
+                words.next();
+                continue;
+            }
+            _ if word.starts_with('+') => (true, Some(&word[1..])),
+            _ if word.starts_with('-') => (false, Some(&word[1..])),
+            _ => return Err(format!("Unexpected word '{}'", word)),
+        };
+        let duration: u32 = duration
+            .and_then(|duration| duration.parse().ok())
+            .ok_or_else(|| format!("Missing or invalid duration after '{}'", word))?;
+        // Marks sit at even indexes; consecutive marks or spaces are merged.
+        if pulses.is_empty() && !mark {
+            continue;
+        }
+        if (pulses.len() % 2 == 0) == mark {
+            pulses.push(duration);
+        } else if let Some(last) = pulses.last_mut() {
+            *last += duration;
+        }
+    }
+    Ok(pulses)
+}
+
+/// Renders the frame as an ASCII waveform and a label line marking each symbol: `S` for the
+/// start, the bit value, and `E` for the stop mark.
+fn waveform(pulses: &[u32]) -> (String, String) {
+    let mut waveform = String::new();
+    let mut labels = String::new();
+    for (index, chunk) in pulses.chunks(2).enumerate() {
+        let label = match index {
+            0 => 'S',
+            _ if chunk.len() == 1 => 'E',
+            _ if chunk.iter().sum::<u32>() <= MAX_ZERO_BIT => '0',
+            _ => '1',
+        };
+        let width = waveform.chars().count();
+        labels.push_str(&" ".repeat(width - labels.chars().count()));
+        labels.push(label);
+        for (position, &duration) in chunk.iter().enumerate() {
+            let level = if position == 0 { "^" } else { "_" };
+            let length = ((duration + WAVEFORM_RESOLUTION / 2) / WAVEFORM_RESOLUTION).max(1);
+            waveform.push_str(&level.repeat(length as usize));
+        }
+    }
+    (waveform, labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brickbeam::{Channel, MockTransmitterRecorder, Output, SingleOutputCommand};
+    use std::sync::Arc;
+
+    fn encode_pwm_5() -> Vec<u32> {
+        let transmitter = Arc::new(MockTransmitterRecorder::default());
+        let brick_beam = brickbeam::BrickBeam::from_transmitter(Arc::clone(&transmitter));
+        brick_beam
+            .create_speed_remote_controller(Channel::One, Output::RED)
+            .unwrap()
+            .send(SingleOutputCommand::PWM(5))
+            .unwrap();
+        transmitter.sent()[0].clone()
+    }
+
+    #[test]
+    fn test_parse_dump_formats() {
+        assert_eq!(parse_dump("[158, 263, 158]"), Ok(vec![158, 263, 158]));
+        assert_eq!(parse_dump(r#"{"pulses": [158, 553]}"#), Ok(vec![158, 553]));
+        assert_eq!(
+            parse_dump("-5000 +158 -263 +100 +58 -553 timeout 12000"),
+            Ok(vec![158, 263, 158, 553])
+        );
+        assert_eq!(
+            parse_dump("pulse 158\nspace 1026\npulse 158"),
+            Ok(vec![158, 1026, 158])
+        );
+        assert_eq!(
+            parse_dump("0000 006D 0001 0000 0006 000A"),
+            Ok(vec![158, 263])
+        );
+        assert!(parse_dump("0000 006D 0002 0000 0006 000A").is_err());
+        assert!(parse_dump("+158 bogus").is_err());
+    }
+
+    #[test]
+    fn test_analyze_encoded_frame() {
+        let pulses = encode_pwm_5();
+        let dump = serde_json::to_string(&pulses).unwrap();
+        let mut output = Vec::new();
+        run(&dump, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("LRC:     ok"), "{}", output);
+        assert!(output.contains("Message: One RED PWM(5)"), "{}", output);
+        assert!(output.contains("^_"), "{}", output);
+    }
+
+    #[test]
+    fn test_analyze_reports_lrc_mismatch() {
+        let mut pulses = encode_pwm_5();
+        // Turn the last LRC bit into its opposite.
+        let last_bit = 2 + 2 * 15 + 1;
+        pulses[last_bit] = if pulses[last_bit] < 400 { 553 } else { 263 };
+        let mut output = Vec::new();
+        run(&serde_json::to_string(&pulses).unwrap(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("LRC:     mismatch"), "{}", output);
+        assert!(output.contains("One RED PWM(5)"), "{}", output);
+    }
+}
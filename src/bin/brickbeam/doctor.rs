@@ -0,0 +1,432 @@
+//! `brickbeam doctor`: checks the IR hardware setup and explains how to fix what's missing.
+//!
+//! The checks run in order, each printed as `[PASS]`, `[FAIL]` (with a `fix:` line) or `[SKIP]`:
+//!
+//! * rc-core devices are registered in sysfs, i.e. an IR driver or overlay is loaded.
+//! * One of them is a transmitter, not only a receiver.
+//! * Its `/dev/lircN` node exists.
+//! * The current user may open it, directly or through the owning group.
+//! * The driver reports that it can send (feature `cir`).
+//! * With `--loopback <RX-DEVICE>`, a frame sent on the transmitter arrives at a receiver placed
+//!   in front of the IR LED (feature `cir`).
+
+use brickbeam::{enumerate_rc_devices_in, select_transmitter, Error, RcDevice, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// The fix for a missing transmitter on a Raspberry Pi, the most common setup.
+const OVERLAY_HINT: &str = "Enable an IR transmitter overlay, e.g. add \
+    `dtoverlay=gpio-ir-tx,gpio_pin=18` to /boot/firmware/config.txt (/boot/config.txt on older \
+    systems) and reboot, or load the driver with `sudo modprobe gpio-ir-tx`";
+
+/// Where the checks look for the system state; the real locations unless testing.
+pub struct Environment {
+    pub sysfs_root: PathBuf,
+    pub group_file: PathBuf,
+    pub proc_status: PathBuf,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            sysfs_root: PathBuf::from(brickbeam::SYSFS_RC_ROOT),
+            group_file: PathBuf::from("/etc/group"),
+            proc_status: PathBuf::from("/proc/self/status"),
+        }
+    }
+}
+
+/// What the doctor was asked to examine.
+#[derive(Default)]
+pub struct Options {
+    /// The transmission device; selected like `BrickBeam::new_auto` does if `None`.
+    pub device: Option<PathBuf>,
+    /// A receiver device for the loopback test; the test is skipped if `None`.
+    pub loopback: Option<PathBuf>,
+}
+
+enum Outcome {
+    Pass(String),
+    Fail { problem: String, fix: String },
+    Skip(String),
+}
+
+fn fail(problem: impl Into<String>, fix: impl Into<String>) -> Outcome {
+    Outcome::Fail {
+        problem: problem.into(),
+        fix: fix.into(),
+    }
+}
+
+/// Runs all checks, writing the report to `output`.
+///
+/// # Errors
+///
+/// Returns `Error::ProtocolError` naming the number of failed checks if any check failed.
+pub fn run(env: &Environment, options: &Options, mut output: impl Write) -> Result<()> {
+    let mut failed = 0;
+    for (name, outcome) in checks(env, options) {
+        match outcome {
+            Outcome::Pass(detail) => writeln!(output, "[PASS] {}: {}", name, detail)?,
+            Outcome::Skip(reason) => writeln!(output, "[SKIP] {}: {}", name, reason)?,
+            Outcome::Fail { problem, fix } => {
+                failed += 1;
+                writeln!(output, "[FAIL] {}: {}", name, problem)?;
+                writeln!(output, "       fix: {}", fix)?;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(Error::ProtocolError(format!("{} check(s) failed", failed)));
+    }
+    Ok(())
+}
+
+fn checks(env: &Environment, options: &Options) -> Vec<(&'static str, Outcome)> {
+    let mut report = Vec::new();
+    let devices = enumerate_rc_devices_in(&env.sysfs_root).unwrap_or_default();
+    report.push(("rc-core devices", check_rc_devices(&devices)));
+    report.push(("transmitter", check_transmitter(&devices)));
+
+    let Some(device) = options
+        .device
+        .clone()
+        .or_else(|| select_transmitter(&devices))
+    else {
+        for name in ["device node", "permissions", "send capability", "loopback"] {
+            report.push((name, Outcome::Skip("no transmission device".into())));
+        }
+        return report;
+    };
+    let node = check_device_node(&device);
+    let node_exists = matches!(node, Outcome::Pass(_));
+    report.push(("device node", node));
+    if !node_exists {
+        for name in ["permissions", "send capability", "loopback"] {
+            report.push((
+                name,
+                Outcome::Skip(format!("{} is missing", device.display())),
+            ));
+        }
+        return report;
+    }
+    report.push(("permissions", check_permissions(env, &device)));
+    report.push(("send capability", check_send_capability(&device)));
+    report.push((
+        "loopback",
+        match &options.loopback {
+            Some(receiver) => check_loopback(&device, receiver),
+            None => Outcome::Skip("pass --loopback <RX-DEVICE> to test a receiver".into()),
+        },
+    ));
+    report
+}
+
+fn check_rc_devices(devices: &[RcDevice]) -> Outcome {
+    if devices.is_empty() {
+        return fail("no IR devices registered with rc-core", OVERLAY_HINT);
+    }
+    let names: Vec<String> = devices
+        .iter()
+        .map(|device| format!("{} ({})", device.name, device.driver))
+        .collect();
+    Outcome::Pass(names.join(", "))
+}
+
+fn check_transmitter(devices: &[RcDevice]) -> Outcome {
+    if devices.is_empty() {
+        return Outcome::Skip("no IR devices".into());
+    }
+    let lirc = |device: &RcDevice| {
+        device
+            .lirc_device
+            .as_ref()
+            .map_or("no LIRC device".into(), |path| path.display().to_string())
+    };
+    if let Some(device) = devices.iter().find(|device| device.is_known_transmitter()) {
+        return Outcome::Pass(format!(
+            "{} uses {} ({})",
+            device.name,
+            device.driver,
+            lirc(device)
+        ));
+    }
+    if let Some(device) = devices.iter().find(|device| device.is_transmit_only()) {
+        return Outcome::Pass(format!(
+            "{} ({}) looks transmit-only ({})",
+            device.name,
+            device.driver,
+            lirc(device)
+        ));
+    }
+    fail(
+        "only IR receivers found; their LIRC devices usually can't send",
+        format!("{}, next to the receiver overlay", OVERLAY_HINT),
+    )
+}
+
+fn check_device_node(device: &Path) -> Outcome {
+    if device.exists() {
+        Outcome::Pass(format!("{} exists", device.display()))
+    } else {
+        fail(
+            format!("{} does not exist", device.display()),
+            "Check `dmesg | grep -i lirc` for driver errors, and that the kernel has \
+             CONFIG_LIRC enabled",
+        )
+    }
+}
+
+fn check_permissions(env: &Environment, device: &Path) -> Outcome {
+    // The LIRC device is opened for reading and writing, see `CirPulseTransmitter`.
+    let error = match OpenOptions::new().read(true).write(true).open(device) {
+        Ok(_) => return Outcome::Pass(format!("{} is readable and writable", device.display())),
+        Err(e) => e,
+    };
+    if error.kind() != io::ErrorKind::PermissionDenied {
+        return fail(
+            format!("can't open {}: {}", device.display(), error),
+            "Check that no other program holds the device exclusively",
+        );
+    }
+    let Ok(gid) = fs::metadata(device).map(|metadata| metadata.gid()) else {
+        return fail(
+            format!("can't open {}: {}", device.display(), error),
+            "Run brickbeam as a user that may access the device",
+        );
+    };
+    let group = group_name(&env.group_file, gid).unwrap_or_else(|| gid.to_string());
+    let problem = format!("{} belongs to group {}: {}", device.display(), group, error);
+    if process_groups(&env.proc_status).contains(&gid) {
+        fail(
+            problem,
+            "The group can't write to the device; add a udev rule such as \
+             `KERNEL==\"lirc*\", GROUP=\"video\", MODE=\"0660\"`",
+        )
+    } else {
+        fail(
+            problem,
+            format!(
+                "Add yourself to the group with `sudo usermod -aG {} $USER`, then log out and \
+                 back in",
+                group
+            ),
+        )
+    }
+}
+
+/// Looks up the name of a group in an `/etc/group` style file.
+fn group_name(group_file: &Path, gid: u32) -> Option<String> {
+    fs::read_to_string(group_file)
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse::<u32>().ok()?;
+            (id == gid).then(|| name.to_owned())
+        })
+}
+
+/// Returns the supplementary groups of the process from a `/proc/self/status` style file.
+fn process_groups(proc_status: &Path) -> Vec<u32> {
+    fs::read_to_string(proc_status)
+        .unwrap_or_default()
+        .lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .map(|groups| {
+            groups
+                .split_whitespace()
+                .filter_map(|gid| gid.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "cir")]
+fn check_send_capability(device: &Path) -> Outcome {
+    match cir::lirc::open(device) {
+        Ok(lirc) if lirc.can_send() => Outcome::Pass(format!("{} can send", device.display())),
+        Ok(_) => fail(
+            format!("{} is a receive-only device", device.display()),
+            format!("Pass the transmitter with --device. {}", OVERLAY_HINT),
+        ),
+        Err(e) => fail(
+            format!("can't query {}: {}", device.display(), e),
+            "Check that the path is a LIRC device",
+        ),
+    }
+}
+
+#[cfg(not(feature = "cir"))]
+fn check_send_capability(_device: &Path) -> Outcome {
+    Outcome::Skip("built without the cir feature".into())
+}
+
+/// Sends a Combo PWM stop message and waits for a receiver to see its 18 marks.
+#[cfg(feature = "cir")]
+fn check_loopback(device: &Path, receiver: &Path) -> Outcome {
+    use brickbeam::{BrickBeam, Channel, ProtocolKind, RawMessageFields};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    const PF_MARKS: usize = 18;
+
+    let mut rx = match cir::lirc::open(receiver) {
+        Ok(rx) if rx.can_receive_raw() => rx,
+        Ok(_) => {
+            return fail(
+                format!("{} can't receive raw pulses", receiver.display()),
+                "Pass a receiver device, e.g. the one of the gpio-ir overlay",
+            )
+        }
+        Err(e) => {
+            return fail(
+                format!("can't open {}: {}", receiver.display(), e),
+                "Check the receiver path and its permissions",
+            )
+        }
+    };
+    let (marks, received) = mpsc::channel();
+    // The read blocks until IR arrives; the thread is left behind if nothing ever does.
+    thread::spawn(move || {
+        let mut raw = Vec::with_capacity(256);
+        let mut count = 0;
+        while count < PF_MARKS {
+            if rx.receive_raw(&mut raw).is_err() {
+                break;
+            }
+            count += raw.iter().filter(|pulse| pulse.is_pulse()).count();
+        }
+        let _ = marks.send(count);
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    let stop = RawMessageFields {
+        toggle: false,
+        channel: Channel::One,
+        address: false,
+        mode: 0,
+        data: 0,
+    };
+    let sent = BrickBeam::new(device).and_then(|brick_beam| {
+        brick_beam.send_message(ProtocolKind::ComboPwm, stop)?;
+        Ok(())
+    });
+    if let Err(e) = sent {
+        return fail(
+            format!("sending on {} failed: {}", device.display(), e),
+            "Fix the checks above first",
+        );
+    }
+    match received.recv_timeout(Duration::from_secs(1)) {
+        Ok(count) if count >= PF_MARKS => {
+            Outcome::Pass(format!("{} received the test frame", receiver.display()))
+        }
+        _ => fail(
+            format!("{} didn't receive the test frame", receiver.display()),
+            "Point the IR LED at the receiver from a few centimeters away, and check the LED's \
+             wiring and GPIO pin",
+        ),
+    }
+}
+
+#[cfg(not(feature = "cir"))]
+fn check_loopback(_device: &Path, _receiver: &Path) -> Outcome {
+    Outcome::Skip("built without the cir feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_root(test: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("brickbeam-doctor-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn fake_rc(root: &Path, name: &str, driver: &str, lirc: &str, receiver: bool) {
+        let dir = root.join("rc").join(name);
+        fs::create_dir_all(dir.join(lirc)).unwrap();
+        fs::write(dir.join("uevent"), format!("DRV_NAME={}\n", driver)).unwrap();
+        if receiver {
+            fs::create_dir_all(dir.join("input0")).unwrap();
+            fs::write(dir.join("protocols"), "[nec] lirc\n").unwrap();
+        }
+    }
+
+    fn environment(root: &Path) -> Environment {
+        fs::create_dir_all(root.join("rc")).unwrap();
+        fs::write(root.join("group"), "root:x:0:\nvideo:x:44:pi\n").unwrap();
+        fs::write(root.join("status"), "Name:\tbrickbeam\nGroups:\t4 27 44\n").unwrap();
+        Environment {
+            sysfs_root: root.join("rc"),
+            group_file: root.join("group"),
+            proc_status: root.join("status"),
+        }
+    }
+
+    fn report(env: &Environment, options: &Options) -> (String, bool) {
+        let mut output = Vec::new();
+        let passed = run(env, options, &mut output).is_ok();
+        (String::from_utf8(output).unwrap(), passed)
+    }
+
+    #[test]
+    fn test_doctor_without_ir_devices() {
+        let root = fake_root("empty");
+        let (report, passed) = report(&environment(&root), &Options::default());
+        assert!(!passed);
+        assert!(report.contains("[FAIL] rc-core devices"));
+        assert!(report.contains("dtoverlay=gpio-ir-tx"));
+        assert!(report.contains("[SKIP] permissions: no transmission device"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_doctor_with_receiver_only() {
+        let root = fake_root("receiver");
+        let env = environment(&root);
+        fake_rc(&root, "rc0", "gpio-ir", "lirc0", true);
+        let (report, passed) = report(&env, &Options::default());
+        assert!(!passed);
+        assert!(report.contains("[PASS] rc-core devices: rc0 (gpio-ir)"));
+        assert!(report.contains("[FAIL] transmitter: only IR receivers found"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_doctor_with_accessible_transmitter() {
+        let root = fake_root("transmitter");
+        let env = environment(&root);
+        fake_rc(&root, "rc0", "gpio-ir-tx", "lirc0", false);
+        let device = root.join("lirc0");
+        fs::write(&device, "").unwrap();
+        let options = Options {
+            device: Some(device),
+            loopback: None,
+        };
+        let (report, _) = report(&env, &options);
+        assert!(report.contains("[PASS] transmitter: rc0 uses gpio-ir-tx (/dev/lirc0)"));
+        assert!(report.contains("[PASS] device node"));
+        assert!(report.contains("[PASS] permissions"));
+        assert!(report.contains("[SKIP] loopback"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_group_lookup() {
+        let root = fake_root("groups");
+        let env = environment(&root);
+        assert_eq!(group_name(&env.group_file, 44), Some("video".to_owned()));
+        assert_eq!(group_name(&env.group_file, 7), None);
+        assert_eq!(process_groups(&env.proc_status), vec![4, 27, 44]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+//! `brickbeam` command line tool.
+//!
+//! ```text
+//! brickbeam [--device <PATH>] [--trace] pipe
+//! brickbeam [--device <PATH>] [--loopback <RX-DEVICE>] doctor
+//! brickbeam analyze <FILE>
+//! brickbeam sandbox <FILE>
+//! brickbeam [--device <PATH>] [--trace] [--advertise <NAME>] osc <ADDRESS>    (feature `osc`)
+//! brickbeam [--device <PATH>] [--trace] [--advertise <NAME>] grpc <ADDRESS>    (feature `grpc`)
+//! brickbeam [--device <PATH>] [--trace] agent <ADDRESS>    (feature `network`)
+//! ```
+//!
+//! * `--device` - The transmission device (e.g. /dev/lirc0); selected automatically if omitted.
+//! * `--trace` - Prints every pulse train sent to the device (see `BrickBeamBuilder::mirror_to_emulator`).
+//! * `--loopback` - Lets `doctor` check that the given receiver sees what the device sends.
+//! * `--advertise` - Announces the OSC or gRPC server via mDNS under the given name (feature `mdns`).
+//!
+//! If the `BRICKBEAM_TOKEN` environment variable is set, network servers only accept clients
+//! presenting that token (see `brickbeam::auth`).
+
+mod analyze;
+mod doctor;
+mod pipe;
+
+use brickbeam::{BrickBeam, BrickBeamBuilder, Result};
+use std::path::PathBuf;
+use std::process::ExitCode;
+#[cfg(any(feature = "osc", feature = "grpc"))]
+use {
+    signal_hook::consts::{SIGINT, SIGTERM},
+    std::sync::atomic::AtomicBool,
+    std::sync::Arc,
+};
+
+const USAGE: &str = "Usage: brickbeam [--device <PATH>] [--trace] <COMMAND>
+
+Commands:
+  pipe              Reads newline-delimited commands from stdin and transmits them
+  doctor            Checks the IR hardware setup and suggests fixes
+  analyze <FILE>    Decodes a pulse dump (ir-ctl, Pronto or JSON); '-' reads stdin
+  sandbox <FILE>    Runs a sequence file on virtual receivers and prints the output timeline
+  osc <ADDRESS>     Serves OSC messages on a UDP address, e.g. 0.0.0.0:9000 (feature osc)
+  grpc <ADDRESS>    Serves the gRPC service on a TCP address, e.g. 0.0.0.0:50051 (feature grpc)
+  agent <ADDRESS>   Transmits the pulses of network clients, e.g. 0.0.0.0:3838 (feature network)
+
+Options:
+  --loopback <RX-DEVICE>    Makes doctor send a test frame to the given receiver";
+
+#[cfg(all(any(feature = "osc", feature = "grpc"), feature = "mdns"))]
+const ADVERTISE_USAGE: &str = "
+  --advertise <NAME>        Announces the OSC or gRPC server via mDNS";
+
+/// Returns the usage text, listing only the options this build supports.
+fn usage() -> String {
+    #[allow(unused_mut)]
+    let mut usage = USAGE.to_string();
+    #[cfg(all(any(feature = "osc", feature = "grpc"), feature = "mdns"))]
+    usage.push_str(ADVERTISE_USAGE);
+    usage
+}
+
+fn main() -> ExitCode {
+    let mut builder = BrickBeam::builder();
+    let mut args = std::env::args().skip(1);
+    let mut command = None;
+    let mut operands = Vec::new();
+    let mut doctor_options = doctor::Options::default();
+    #[cfg(all(any(feature = "osc", feature = "grpc"), feature = "mdns"))]
+    let mut advertise = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--device" => match args.next() {
+                Some(path) => {
+                    doctor_options.device = Some(PathBuf::from(&path));
+                    builder = builder.device(path);
+                }
+                None => return usage_error("--device requires a path"),
+            },
+            "--loopback" => match args.next() {
+                Some(path) => doctor_options.loopback = Some(PathBuf::from(path)),
+                None => return usage_error("--loopback requires a receiver device"),
+            },
+            "--trace" => builder = builder.mirror_to_emulator(true),
+            #[cfg(all(any(feature = "osc", feature = "grpc"), feature = "mdns"))]
+            "--advertise" => match args.next() {
+                Some(name) => advertise = Some(name),
+                None => return usage_error("--advertise requires a name"),
+            },
+            "-h" | "--help" => {
+                println!("{}", usage());
+                return ExitCode::SUCCESS;
+            }
+            _ if command.is_none() && !arg.starts_with('-') => command = Some(arg),
+            _ if command.is_some() && (arg == "-" || !arg.starts_with('-')) => operands.push(arg),
+            _ => return usage_error(&format!("Unexpected argument '{}'", arg)),
+        }
+    }
+
+    let result = match (command.as_deref(), operands.as_slice()) {
+        (Some("pipe"), []) => run_pipe(builder),
+        (Some("doctor"), []) => doctor::run(
+            &doctor::Environment::default(),
+            &doctor_options,
+            std::io::stdout(),
+        ),
+        (Some("doctor"), _) => return usage_error("doctor does not take arguments"),
+        (Some("analyze"), [file]) => run_analyze(file),
+        (Some("analyze"), _) => return usage_error("analyze requires exactly one file"),
+        (Some("sandbox"), [file]) => run_sandbox(file),
+        (Some("sandbox"), _) => return usage_error("sandbox requires exactly one file"),
+        (Some("pipe"), _) => return usage_error("pipe does not take arguments"),
+        #[cfg(feature = "osc")]
+        (Some("osc"), [address]) => run_osc(
+            builder,
+            address,
+            #[cfg(feature = "mdns")]
+            advertise,
+        ),
+        #[cfg(feature = "osc")]
+        (Some("osc"), _) => return usage_error("osc requires exactly one address"),
+        #[cfg(feature = "grpc")]
+        (Some("grpc"), [address]) => run_grpc(
+            builder,
+            address,
+            #[cfg(feature = "mdns")]
+            advertise,
+        ),
+        #[cfg(feature = "grpc")]
+        (Some("grpc"), _) => return usage_error("grpc requires exactly one address"),
+        #[cfg(feature = "network")]
+        (Some("agent"), [address]) => run_agent(builder, address),
+        #[cfg(feature = "network")]
+        (Some("agent"), _) => return usage_error("agent requires exactly one address"),
+        (Some(other), _) => return usage_error(&format!("Unknown command '{}'", other)),
+        (None, _) => return usage_error("Missing command"),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_pipe(builder: BrickBeamBuilder) -> Result<()> {
+    let brick_beam = builder.build()?;
+    let stdin = std::io::stdin();
+    pipe::run(&brick_beam, stdin.lock(), std::io::stdout())
+}
+
+#[cfg(feature = "osc")]
+fn run_osc(
+    builder: BrickBeamBuilder,
+    address: &str,
+    #[cfg(feature = "mdns")] advertise: Option<String>,
+) -> Result<()> {
+    let brick_beam = builder.build()?;
+    let mut player = brick_beam.create_sequence_player(brickbeam::Sequence::new())?;
+    let server = brickbeam::osc::OscServer::bind(address)?
+        .with_auth(brickbeam::auth::Auth::from_env(brickbeam::auth::TOKEN_ENV));
+    eprintln!("Listening for OSC on {}", server.local_addr()?);
+    #[cfg(feature = "mdns")]
+    let registration = match advertise {
+        Some(name) => {
+            use brickbeam::mdns::{ServiceAdvertisement, OSC_SERVICE_TYPE};
+            let port = server.local_addr()?.port();
+            Some(ServiceAdvertisement::new(name, OSC_SERVICE_TYPE, port).publish()?)
+        }
+        None => None,
+    };
+    let stop = stop_on_signals()?;
+    let result = server.serve_until(&mut player, &stop);
+    #[cfg(feature = "mdns")]
+    drop(registration);
+    result
+}
+
+#[cfg(feature = "grpc")]
+fn run_grpc(
+    builder: BrickBeamBuilder,
+    address: &str,
+    #[cfg(feature = "mdns")] advertise: Option<String>,
+) -> Result<()> {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    let brick_beam = Arc::new(builder.build()?);
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(address).await?;
+        let local_addr = listener.local_addr()?;
+        let server = brickbeam::grpc::GrpcServer::new(brick_beam)
+            .with_auth(brickbeam::auth::Auth::from_env(brickbeam::auth::TOKEN_ENV));
+        eprintln!("Listening for gRPC on {}", local_addr);
+        #[cfg(feature = "mdns")]
+        let registration = match advertise {
+            Some(name) => {
+                use brickbeam::mdns::{ServiceAdvertisement, GRPC_SERVICE_TYPE};
+                Some(
+                    ServiceAdvertisement::new(name, GRPC_SERVICE_TYPE, local_addr.port())
+                        .publish()?,
+                )
+            }
+            None => None,
+        };
+        let stop = stop_on_signals()?;
+        let shutdown = async move {
+            while !stop.load(Ordering::Relaxed) {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+        let result = server.serve_with_shutdown(listener, shutdown).await;
+        #[cfg(feature = "mdns")]
+        drop(registration);
+        result
+    })
+}
+
+/// Returns a flag raised by SIGINT or SIGTERM, so servers stop serving and withdraw their
+/// advertisement on the way out.
+#[cfg(any(feature = "osc", feature = "grpc"))]
+fn stop_on_signals() -> Result<Arc<AtomicBool>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&stop))?;
+    }
+    Ok(stop)
+}
+
+#[cfg(feature = "network")]
+fn run_agent(builder: BrickBeamBuilder, address: &str) -> Result<()> {
+    let brick_beam = builder.build()?;
+    let agent = brickbeam::PulseAgent::bind(address)?
+        .with_auth(brickbeam::auth::Auth::from_env(brickbeam::auth::TOKEN_ENV));
+    eprintln!("Forwarding pulses received on {}", agent.local_addr()?);
+    agent.serve(brick_beam.transmitter())
+}
+
+fn run_analyze(file: &str) -> Result<()> {
+    analyze::run(&read_input(file)?, std::io::stdout())
+}
+
+fn run_sandbox(file: &str) -> Result<()> {
+    let sequence = match brickbeam::Sequence::parse(&read_input(file)?) {
+        Ok(sequence) => sequence,
+        Err(issues) => {
+            for issue in &issues {
+                eprintln!("{}", issue);
+            }
+            return Err(brickbeam::Error::ProtocolError(format!(
+                "{} issue(s) in {}",
+                issues.len(),
+                file
+            )));
+        }
+    };
+    let mut sandbox = brickbeam::sandbox::Sandbox::new()?;
+    for entry in sandbox.run(&sequence)? {
+        println!("{}", entry);
+    }
+    Ok(())
+}
+
+/// Reads a whole file, or stdin if `file` is `-`.
+fn read_input(file: &str) -> Result<String> {
+    if file == "-" {
+        Ok(std::io::read_to_string(std::io::stdin())?)
+    } else {
+        Ok(std::fs::read_to_string(file)?)
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("error: {}\n\n{}", message, usage());
+    ExitCode::from(2)
+}
@@ -0,0 +1,130 @@
+//! `brickbeam pipe`: executes newline-delimited commands read from stdin.
+//!
+//! Every line is either a step of the sequence file grammar (see `brickbeam::Sequence::parse`),
+//! e.g. `1 pwm red 5` or `train cargo 2` followed by `cargo combo 3 -3 wait 2s`, or a JSON object
+//! with the same words as fields:
+//!
+//! ```text
+//! {"channel": 1, "action": "pwm", "output": "red", "speed": 5}
+//! {"train": "cargo", "action": "combo", "red": 3, "blue": -3, "wait": "2s"}
+//! {"channel": 4, "action": "direct", "red": "forward", "blue": "brake"}
+//! {"channel": 1, "action": "extended", "command": "toggle-address"}
+//! ```
+//!
+//! Each executed command answers with `ok <airtime in µs>`, each rejected one with `error: <reason>`.
+//! Errors never end the pipe, so a driving script can simply keep writing.
+
+use brickbeam::{BrickBeam, PulseTransmitter, Result, Sequence, StepParser};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+
+/// Executes every line of `input` and writes one response line per command to `output`.
+pub fn run<T: PulseTransmitter>(
+    brick_beam: &BrickBeam<T>,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> Result<()> {
+    let mut parser = StepParser::new();
+    let mut player = brick_beam.create_sequence_player(Sequence::new())?;
+    for line in input.lines() {
+        let line = line?;
+        let step = if line.trim_start().starts_with('{') {
+            json_to_line(&line).and_then(|line| parser.parse_line(&line))
+        } else {
+            parser.parse_line(&line)
+        };
+        let response = match step {
+            Ok(None) => continue,
+            Err(message) => format!("error: {}", message),
+            Ok(Some(step)) => match Sequence::from(vec![step]).validate().first() {
+                Some(issue) => format!("error: {}", issue.message),
+                None => match player.play_step(step) {
+                    Ok(airtime) => format!("ok {}", airtime.as_micros()),
+                    Err(e) => format!("error: {}", e),
+                },
+            },
+        };
+        writeln!(output, "{}", response)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Translates a JSON command into the equivalent line of the sequence grammar.
+fn json_to_line(json: &str) -> std::result::Result<String, String> {
+    let value: Value = serde_json::from_str(json).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| "Expected a JSON object".to_string())?;
+    let field = |name: &str| -> std::result::Result<String, String> {
+        match object.get(name) {
+            Some(Value::String(word)) => Ok(word.clone()),
+            Some(Value::Number(number)) => Ok(number.to_string()),
+            Some(other) => Err(format!("Invalid value {} for '{}'", other, name)),
+            None => Err(format!("Missing field '{}'", name)),
+        }
+    };
+
+    let target = field("train").or_else(|_| field("channel"))?;
+    let action = field("action")?;
+    let arguments: &[&str] = match action.as_str() {
+        "pwm" => &["output", "speed"],
+        "combo" | "direct" => &["red", "blue"],
+        "extended" => &["command"],
+        _ => return Err(format!("Unknown action '{}'", action)),
+    };
+    let mut words = vec![target, action];
+    for name in arguments {
+        words.push(field(name)?);
+    }
+    if object.contains_key("wait") {
+        words.extend(["wait".to_string(), field("wait")?]);
+    } else if object.contains_key("wait_ms") {
+        words.extend(["wait".to_string(), format!("{}ms", field("wait_ms")?)]);
+    }
+    Ok(words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brickbeam::MockTransmitterRecorder;
+
+    #[test]
+    fn test_json_to_line() {
+        assert_eq!(
+            json_to_line(r#"{"channel": 1, "action": "pwm", "output": "red", "speed": -5}"#),
+            Ok("1 pwm red -5".to_string())
+        );
+        assert_eq!(
+            json_to_line(
+                r#"{"train": "cargo", "action": "combo", "red": 3, "blue": 0, "wait_ms": 20}"#
+            ),
+            Ok("cargo combo 3 0 wait 20ms".to_string())
+        );
+        assert!(json_to_line(r#"{"channel": 1, "action": "pwm", "output": "red"}"#).is_err());
+        assert!(json_to_line("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_pipe_answers_every_line_and_keeps_going() {
+        let transmitter = MockTransmitterRecorder::default();
+        let brick_beam = BrickBeam::from_transmitter(transmitter);
+        let input = "train cargo 2\n\
+                     # comment\n\
+                     cargo pwm red 5\n\
+                     1 pwm red 12\n\
+                     nonsense\n\
+                     {\"channel\": 3, \"action\": \"direct\", \"red\": \"forward\", \"blue\": \"brake\"}\n";
+        let mut output = Vec::new();
+        run(&brick_beam, input.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let responses: Vec<&str> = output.lines().collect();
+        assert_eq!(responses.len(), 4);
+        assert!(responses[0].starts_with("ok "));
+        assert!(responses[1].starts_with("error: Invalid speed 12"));
+        assert!(responses[2].starts_with("error: "));
+        assert!(responses[3].starts_with("ok "));
+    }
+}
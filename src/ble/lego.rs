@@ -0,0 +1,93 @@
+//! # lego-powered-up Adapter
+//!
+//! Drives the motors of hubs connected with the `lego-powered-up` crate through `MotorControl`,
+//! for applications that already use it for the Bluetooth side and only want brickbeam's fleet
+//! and orchestration layers on top.
+//!
+//! `lego-powered-up` is async and keeps its hub connections on a tokio runtime, while
+//! `MotorControl` blocks. The adapter therefore blocks on a handle of that runtime, which has to
+//! be called from outside of its async tasks: from a thread of its own, or within
+//! `tokio::task::spawn_blocking`.
+
+use crate::{motor::MotorControl, Error, Result};
+use lego_powered_up::iodevice::motor::EncoderMotor;
+use lego_powered_up::notifications::Power;
+use tokio::runtime::Handle;
+
+/// A motor of the `lego-powered-up` crate, such as the `IoDevice` of a hub port.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::ble::lego::LegoPoweredUpMotor;
+/// use brickbeam::TrainControl;
+/// use lego_powered_up::{consts::named_port, setup};
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let runtime = tokio::runtime::Runtime::new()?;
+///     let motor = runtime.block_on(async {
+///         let hub = setup::single_hub().await?;
+///         let hub = hub.mutex.lock().await;
+///         hub.io_from_port(named_port::A)
+///     })?;
+///
+///     let mut train = LegoPoweredUpMotor::new(motor, runtime.handle().clone());
+///     train.set_speed(50)?;
+///     Ok(())
+/// }
+/// ```
+pub struct LegoPoweredUpMotor<M: EncoderMotor> {
+    motor: M,
+    runtime: Handle,
+}
+
+impl<M: EncoderMotor> LegoPoweredUpMotor<M> {
+    /// Wraps `motor`, whose hub is connected on the tokio runtime of `runtime`.
+    pub fn new(motor: M, runtime: Handle) -> Self {
+        Self { motor, runtime }
+    }
+
+    /// Returns the wrapped motor, e.g. for the commands `MotorControl` doesn't cover.
+    pub fn motor(&self) -> &M {
+        &self.motor
+    }
+
+    fn start_power(&self, power: Power) -> Result<()> {
+        self.runtime
+            .block_on(self.motor.start_power(power))
+            .map_err(|e| Error::Transmitting(format!("Powered Up error: {}", e)))
+    }
+}
+
+/// Converts a power in percent into the `StartPower` value of `lego-powered-up`.
+fn power_of(percent: i8) -> Power {
+    let percent = percent.clamp(-100, 100);
+    match percent {
+        0 => Power::Float,
+        1.. => Power::Cw(percent.unsigned_abs()),
+        _ => Power::Ccw(percent.unsigned_abs()),
+    }
+}
+
+impl<M: EncoderMotor> MotorControl for LegoPoweredUpMotor<M> {
+    fn set_power(&mut self, percent: i8) -> Result<()> {
+        self.start_power(power_of(percent))
+    }
+
+    fn brake(&mut self) -> Result<()> {
+        self.start_power(Power::Brake)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_to_power() {
+        assert_eq!(power_of(0), Power::Float);
+        assert_eq!(power_of(40), Power::Cw(40));
+        assert_eq!(power_of(-40), Power::Ccw(40));
+        assert_eq!(power_of(i8::MAX), Power::Cw(100));
+        assert_eq!(power_of(i8::MIN), Power::Ccw(100));
+    }
+}
@@ -0,0 +1,35 @@
+//! # Bluetooth LE Backends
+//!
+//! Optional backends that drive LEGO®-compatible Bluetooth LE receivers through the same
+//! `MotorControl` trait as the IR Power Functions controllers:
+//! - `powered_up` (feature `powered-up`) for LEGO® Powered Up hubs,
+//! - `sbrick` (feature `sbrick`) for SBrick receivers,
+//! - `lego` (feature `lego-powered-up`) for motors of hubs connected with the `lego-powered-up`
+//!   crate.
+//!
+//! The `powered_up` and `sbrick` motors only encode the messages. Writing them to the GATT
+//! characteristic is delegated to a `GattWriter`. Either feature brings `BlePeripheral`, which
+//! connects to the device and writes through `btleplug`; applications with a Bluetooth stack of
+//! their own implement the trait on top of it instead. On Linux, btleplug talks to BlueZ through
+//! libdbus, which the `vendored-dbus` feature builds from source for targets without its
+//! development files.
+
+#[cfg(feature = "lego-powered-up")]
+pub mod lego;
+#[cfg(any(feature = "powered-up", feature = "sbrick"))]
+mod peripheral;
+#[cfg(feature = "powered-up")]
+pub mod powered_up;
+#[cfg(feature = "sbrick")]
+pub mod sbrick;
+
+#[cfg(any(feature = "powered-up", feature = "sbrick"))]
+pub use peripheral::BlePeripheral;
+
+use crate::Result;
+
+/// Writes raw bytes to the control characteristic of a connected Bluetooth LE device.
+pub trait GattWriter {
+    /// Writes one complete message to the device's control characteristic.
+    fn write(&self, data: &[u8]) -> Result<()>;
+}
@@ -0,0 +1,224 @@
+//! # btleplug Peripheral
+//!
+//! A blocking `GattWriter` on top of `btleplug`, so Powered Up hubs and SBricks can be driven
+//! without implementing the Bluetooth side in the application. The peripheral runs its own
+//! single-worker tokio runtime and blocks the caller on every write, like the IR transmitters.
+
+use super::GattWriter;
+use crate::{Error, Result};
+use btleplug::api::{
+    Central, CharPropFlags, Characteristic, Manager as _, Peripheral as _, PeripheralProperties,
+    ScanFilter, WriteType,
+};
+use btleplug::platform::{Manager, Peripheral};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+/// How often the scan checks the discovered devices for a match.
+const SCAN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A connected Bluetooth LE device whose control characteristic the motors write to.
+///
+/// `connect` picks the first device advertising the given service, which is how Powered Up hubs
+/// announce themselves. SBricks don't advertise their remote control service, so they are found
+/// by name with `connect_named`. The device is disconnected when the peripheral is dropped.
+///
+/// # Example
+#[cfg_attr(feature = "powered-up", doc = "```no_run")]
+#[cfg_attr(not(feature = "powered-up"), doc = "```ignore")]
+/// use brickbeam::ble::powered_up::{
+///     HubPort, PoweredUpMotor, LWP3_CHARACTERISTIC_UUID, LWP3_SERVICE_UUID,
+/// };
+/// use brickbeam::ble::BlePeripheral;
+/// use brickbeam::{MotorControl, Result};
+/// use std::time::Duration;
+///
+/// fn main() -> Result<()> {
+///     let hub = BlePeripheral::connect(
+///         LWP3_SERVICE_UUID,
+///         LWP3_CHARACTERISTIC_UUID,
+///         Duration::from_secs(10),
+///     )?;
+///     let mut train = PoweredUpMotor::new(&hub, HubPort::A);
+///     train.set_power(50)?;
+///     Ok(())
+/// }
+/// ```
+pub struct BlePeripheral {
+    runtime: Runtime,
+    peripheral: Peripheral,
+    characteristic: Characteristic,
+}
+
+impl BlePeripheral {
+    /// Connects to the first device advertising `service` and writes to its `characteristic`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if a UUID is malformed, and `Error::Transmitting` if there
+    /// is no Bluetooth adapter, no matching device shows up within `timeout`, or the device
+    /// lacks the characteristic.
+    pub fn connect(service: &str, characteristic: &str, timeout: Duration) -> Result<Self> {
+        let service = parse_uuid(service)?;
+        Self::connect_where(characteristic, timeout, |properties| {
+            properties.services.contains(&service)
+        })
+    }
+
+    /// Connects to the first device called `name` and writes to its `characteristic`.
+    ///
+    /// # Errors
+    ///
+    /// The same as `connect`.
+    pub fn connect_named(name: &str, characteristic: &str, timeout: Duration) -> Result<Self> {
+        Self::connect_where(characteristic, timeout, |properties| {
+            properties.local_name.as_deref() == Some(name)
+        })
+    }
+
+    /// Scans for the first device whose advertisement `matches`, connects and looks up the
+    /// characteristic, all within `timeout`.
+    fn connect_where(
+        characteristic: &str,
+        timeout: Duration,
+        matches: impl Fn(&PeripheralProperties) -> bool,
+    ) -> Result<Self> {
+        let characteristic = parse_uuid(characteristic)?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let (peripheral, characteristic) = runtime
+            .block_on(tokio::time::timeout(
+                timeout,
+                open(characteristic, &matches),
+            ))
+            .map_err(|_| {
+                Error::Transmitting(format!(
+                    "No matching Bluetooth LE device connected within {:?}",
+                    timeout
+                ))
+            })??;
+        Ok(Self {
+            runtime,
+            peripheral,
+            characteristic,
+        })
+    }
+
+    /// Returns the connected btleplug peripheral, e.g. to subscribe to notifications.
+    pub fn peripheral(&self) -> &Peripheral {
+        &self.peripheral
+    }
+}
+
+impl GattWriter for BlePeripheral {
+    fn write(&self, data: &[u8]) -> Result<()> {
+        let write_type = if self
+            .characteristic
+            .properties
+            .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
+        {
+            WriteType::WithoutResponse
+        } else {
+            WriteType::WithResponse
+        };
+        self.runtime
+            .block_on(
+                self.peripheral
+                    .write(&self.characteristic, data, write_type),
+            )
+            .map_err(bluetooth_error)
+    }
+}
+
+impl Drop for BlePeripheral {
+    fn drop(&mut self) {
+        // The device drops the connection on its own after a while; nothing to do on failure.
+        let _ = self.runtime.block_on(self.peripheral.disconnect());
+    }
+}
+
+/// Scans on the first adapter until a device `matches`, then connects to it and returns it
+/// together with its `characteristic`.
+async fn open(
+    characteristic: Uuid,
+    matches: &impl Fn(&PeripheralProperties) -> bool,
+) -> Result<(Peripheral, Characteristic)> {
+    let manager = Manager::new().await.map_err(bluetooth_error)?;
+    let adapter = manager
+        .adapters()
+        .await
+        .map_err(bluetooth_error)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Transmitting("No Bluetooth adapter found".to_string()))?;
+    adapter
+        .start_scan(ScanFilter::default())
+        .await
+        .map_err(bluetooth_error)?;
+    let peripheral = loop {
+        let mut found = None;
+        for peripheral in adapter.peripherals().await.map_err(bluetooth_error)? {
+            let properties = peripheral.properties().await.map_err(bluetooth_error)?;
+            if properties.as_ref().is_some_and(matches) {
+                found = Some(peripheral);
+                break;
+            }
+        }
+        match found {
+            Some(peripheral) => break peripheral,
+            None => tokio::time::sleep(SCAN_POLL_INTERVAL).await,
+        }
+    };
+    // Scanning slows down the connection on some adapters, and a failure to stop is harmless.
+    let _ = adapter.stop_scan().await;
+    peripheral.connect().await.map_err(bluetooth_error)?;
+    peripheral
+        .discover_services()
+        .await
+        .map_err(bluetooth_error)?;
+    let characteristic = peripheral
+        .characteristics()
+        .into_iter()
+        .find(|found| found.uuid == characteristic)
+        .ok_or_else(|| {
+            Error::Transmitting(format!(
+                "The device has no characteristic {}",
+                characteristic
+            ))
+        })?;
+    Ok((peripheral, characteristic))
+}
+
+fn parse_uuid(uuid: &str) -> Result<Uuid> {
+    Uuid::parse_str(uuid)
+        .map_err(|e| Error::ProtocolError(format!("Invalid UUID {:?}: {}", uuid, e)))
+}
+
+fn bluetooth_error(error: btleplug::Error) -> Error {
+    Error::Transmitting(format!("Bluetooth LE error: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_uuids_rejected_before_scanning() {
+        let timeout = Duration::from_millis(1);
+        assert!(matches!(
+            BlePeripheral::connect(
+                "not-a-uuid",
+                "00001624-1212-efde-1623-785feabcd123",
+                timeout
+            ),
+            Err(Error::ProtocolError(_))
+        ));
+        assert!(matches!(
+            BlePeripheral::connect_named("SBrick", "02b8cbcc", timeout),
+            Err(Error::ProtocolError(_))
+        ));
+    }
+}
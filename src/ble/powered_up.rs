@@ -0,0 +1,155 @@
+//! # Powered Up Backend
+//!
+//! Drives motors attached to LEGO® Powered Up hubs (City train hub, Technic hub, ...) using the
+//! LEGO Wireless Protocol 3.0 (LWP3). Every message is written to the hub's single LWP3
+//! characteristic (`LWP3_CHARACTERISTIC_UUID`) of the `LWP3_SERVICE_UUID` service.
+//!
+//! Only the subset needed for `MotorControl` is implemented: the Port Output Command
+//! `StartPower`, where a power of 0 floats the motor and 127 brakes it.
+
+use super::GattWriter;
+use crate::{motor::MotorControl, Result};
+
+/// The LWP3 hub service UUID.
+pub const LWP3_SERVICE_UUID: &str = "00001623-1212-efde-1623-785feabcd123";
+
+/// The LWP3 hub characteristic UUID, used for both commands and notifications.
+pub const LWP3_CHARACTERISTIC_UUID: &str = "00001624-1212-efde-1623-785feabcd123";
+
+const MESSAGE_TYPE_PORT_OUTPUT_COMMAND: u8 = 0x81;
+/// Execute immediately and request command feedback.
+const STARTUP_AND_COMPLETION: u8 = 0x11;
+const SUB_COMMAND_WRITE_DIRECT_MODE_DATA: u8 = 0x51;
+const MODE_POWER: u8 = 0x00;
+const POWER_FLOAT: i8 = 0;
+const POWER_BRAKE: i8 = 127;
+
+/// Hub ports of a Powered Up hub. The City train hub only has ports A and B.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HubPort {
+    A = 0x00,
+    B = 0x01,
+    C = 0x02,
+    D = 0x03,
+}
+
+/// Encodes the LWP3 `StartPower` port output command for the given port and raw power value.
+fn encode_start_power(port: HubPort, power: i8) -> [u8; 8] {
+    [
+        8,
+        0x00, // hub id, always 0
+        MESSAGE_TYPE_PORT_OUTPUT_COMMAND,
+        port as u8,
+        STARTUP_AND_COMPLETION,
+        SUB_COMMAND_WRITE_DIRECT_MODE_DATA,
+        MODE_POWER,
+        power as u8,
+    ]
+}
+
+/// A motor attached to one port of a Powered Up hub.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::ble::powered_up::{HubPort, PoweredUpMotor};
+/// use brickbeam::ble::GattWriter;
+/// use brickbeam::{MotorControl, Result};
+///
+/// struct Hub; // wraps e.g. a connected btleplug peripheral
+/// impl GattWriter for Hub {
+///     fn write(&self, data: &[u8]) -> Result<()> {
+///         println!("LWP3 write: {:02x?}", data);
+///         Ok(())
+///     }
+/// }
+///
+/// fn main() -> Result<()> {
+///     let hub = Hub;
+///     let mut train = PoweredUpMotor::new(&hub, HubPort::A);
+///     train.set_power(50)?;
+///     train.brake()?;
+///     Ok(())
+/// }
+/// ```
+pub struct PoweredUpMotor<'a, W: GattWriter> {
+    writer: &'a W,
+    port: HubPort,
+}
+
+impl<'a, W: GattWriter> PoweredUpMotor<'a, W> {
+    pub fn new(writer: &'a W, port: HubPort) -> Self {
+        Self { writer, port }
+    }
+
+    fn start_power(&self, power: i8) -> Result<()> {
+        self.writer.write(&encode_start_power(self.port, power))
+    }
+}
+
+impl<W: GattWriter> MotorControl for PoweredUpMotor<'_, W> {
+    fn set_power(&mut self, percent: i8) -> Result<()> {
+        self.start_power(percent.clamp(-100, 100))
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.start_power(POWER_FLOAT)
+    }
+
+    fn brake(&mut self) -> Result<()> {
+        self.start_power(POWER_BRAKE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockGattRecorder {
+        written: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl GattWriter for MockGattRecorder {
+        fn write(&self, data: &[u8]) -> Result<()> {
+            self.written.lock().unwrap().push(data.to_vec());
+            Ok(())
+        }
+    }
+
+    struct MockGattFail;
+
+    impl GattWriter for MockGattFail {
+        fn write(&self, _data: &[u8]) -> Result<()> {
+            Err(Error::Transmitting("Mock failure".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_start_power_encoding() {
+        let hub = MockGattRecorder::default();
+        let mut motor = PoweredUpMotor::new(&hub, HubPort::B);
+        motor.set_power(-50).unwrap();
+        motor.set_power(120).unwrap();
+        motor.stop().unwrap();
+        motor.brake().unwrap();
+
+        let written = hub.written.lock().unwrap();
+        assert_eq!(
+            written[0],
+            vec![0x08, 0x00, 0x81, 0x01, 0x11, 0x51, 0x00, 0xCE]
+        );
+        assert_eq!(written[1][7], 100);
+        assert_eq!(written[2][7], 0);
+        assert_eq!(written[3][7], 127);
+    }
+
+    #[test]
+    fn test_write_failure_is_reported() {
+        let hub = MockGattFail;
+        let mut motor = PoweredUpMotor::new(&hub, HubPort::A);
+        assert!(motor.set_power(10).is_err());
+    }
+}
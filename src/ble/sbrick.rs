@@ -0,0 +1,156 @@
+//! # SBrick Backend
+//!
+//! Drives motors attached to Vengit SBrick (and SBrick Plus) receivers, a popular Bluetooth LE
+//! replacement for the Power Functions IR receiver. Commands are written to the "Remote control
+//! commands" characteristic (`SBRICK_COMMAND_CHARACTERISTIC_UUID`) of the
+//! `SBRICK_REMOTE_CONTROL_SERVICE_UUID` service.
+//!
+//! SBricks don't advertise the service, so `BlePeripheral::connect_named` finds them by their
+//! name, `SBRICK_DEFAULT_NAME` unless it was changed in the SBrick app.
+//!
+//! **Note**: the SBrick stops all outputs when its watchdog (0.5 s by default) expires without a
+//! command, so applications have to repeat the last command periodically.
+
+use super::GattWriter;
+use crate::{motor::MotorControl, Result};
+
+/// The SBrick remote control service UUID.
+pub const SBRICK_REMOTE_CONTROL_SERVICE_UUID: &str = "4dc591b0-857c-41de-b5f1-15abda665b0c";
+
+/// The SBrick remote control commands characteristic UUID.
+pub const SBRICK_COMMAND_CHARACTERISTIC_UUID: &str = "02b8cbcc-0e25-4bda-8790-a15f53e6010f";
+
+/// The name an SBrick advertises until it is renamed.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::ble::sbrick::{
+///     SBrickMotor, SBrickPort, SBRICK_COMMAND_CHARACTERISTIC_UUID, SBRICK_DEFAULT_NAME,
+/// };
+/// use brickbeam::ble::BlePeripheral;
+/// use brickbeam::{MotorControl, Result};
+/// use std::time::Duration;
+///
+/// fn main() -> Result<()> {
+///     let sbrick = BlePeripheral::connect_named(
+///         SBRICK_DEFAULT_NAME,
+///         SBRICK_COMMAND_CHARACTERISTIC_UUID,
+///         Duration::from_secs(10),
+///     )?;
+///     let mut motor = SBrickMotor::new(&sbrick, SBrickPort::A);
+///     motor.set_power(40)?;
+///     Ok(())
+/// }
+/// ```
+pub const SBRICK_DEFAULT_NAME: &str = "SBrick";
+
+const COMMAND_BRAKE: u8 = 0x00;
+const COMMAND_DRIVE: u8 = 0x01;
+const DIRECTION_CLOCKWISE: u8 = 0x00;
+const DIRECTION_COUNTER_CLOCKWISE: u8 = 0x01;
+
+/// The four outputs of an SBrick.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SBrickPort {
+    A = 0x00,
+    B = 0x01,
+    C = 0x02,
+    D = 0x03,
+}
+
+/// Encodes the SBrick `drive` command; `percent` is mapped onto the 0..=255 power range.
+fn encode_drive(port: SBrickPort, percent: i8) -> [u8; 4] {
+    let percent = percent.clamp(-100, 100);
+    let direction = if percent < 0 {
+        DIRECTION_COUNTER_CLOCKWISE
+    } else {
+        DIRECTION_CLOCKWISE
+    };
+    let power = (u32::from(percent.unsigned_abs()) * 255 + 50) / 100;
+    [COMMAND_DRIVE, port as u8, direction, power as u8]
+}
+
+/// Encodes the SBrick `brake` command.
+fn encode_brake(port: SBrickPort) -> [u8; 2] {
+    [COMMAND_BRAKE, port as u8]
+}
+
+/// A motor attached to one output of an SBrick.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::ble::sbrick::{SBrickMotor, SBrickPort};
+/// use brickbeam::ble::GattWriter;
+/// use brickbeam::{MotorControl, Result};
+///
+/// struct SBrick; // wraps e.g. a connected btleplug peripheral
+/// impl GattWriter for SBrick {
+///     fn write(&self, data: &[u8]) -> Result<()> {
+///         println!("SBrick write: {:02x?}", data);
+///         Ok(())
+///     }
+/// }
+///
+/// fn main() -> Result<()> {
+///     let sbrick = SBrick;
+///     let mut motor = SBrickMotor::new(&sbrick, SBrickPort::C);
+///     motor.set_power(-30)?;
+///     motor.stop()?;
+///     Ok(())
+/// }
+/// ```
+pub struct SBrickMotor<'a, W: GattWriter> {
+    writer: &'a W,
+    port: SBrickPort,
+}
+
+impl<'a, W: GattWriter> SBrickMotor<'a, W> {
+    pub fn new(writer: &'a W, port: SBrickPort) -> Self {
+        Self { writer, port }
+    }
+}
+
+impl<W: GattWriter> MotorControl for SBrickMotor<'_, W> {
+    fn set_power(&mut self, percent: i8) -> Result<()> {
+        self.writer.write(&encode_drive(self.port, percent))
+    }
+
+    fn brake(&mut self) -> Result<()> {
+        self.writer.write(&encode_brake(self.port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockGattRecorder {
+        written: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl GattWriter for MockGattRecorder {
+        fn write(&self, data: &[u8]) -> Result<()> {
+            self.written.lock().unwrap().push(data.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_drive_and_brake_encoding() {
+        let sbrick = MockGattRecorder::default();
+        let mut motor = SBrickMotor::new(&sbrick, SBrickPort::C);
+        motor.set_power(100).unwrap();
+        motor.set_power(-50).unwrap();
+        motor.stop().unwrap();
+        motor.brake().unwrap();
+
+        let written = sbrick.written.lock().unwrap();
+        assert_eq!(written[0], vec![0x01, 0x02, 0x00, 0xFF]);
+        assert_eq!(written[1], vec![0x01, 0x02, 0x01, 0x80]);
+        assert_eq!(written[2], vec![0x01, 0x02, 0x00, 0x00]);
+        assert_eq!(written[3], vec![0x00, 0x02]);
+    }
+}
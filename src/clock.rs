@@ -0,0 +1,156 @@
+//! # Clock
+//!
+//! Every pause brickbeam takes between transmissions (message slots, repeat gaps, settle times)
+//! asks a `Clock` for the time and sleeps on it. In production that is `SystemClock`; tests swap
+//! in `MockClock` (feature `test-util`), whose sleeps advance virtual time instantly, so timing
+//! behavior can be asserted exactly and without slowing CI down.
+//!
+//! A transmitter provides the clock that paces it (`PulseTransmitter::clock`), so one mock clock
+//! returned by a test transmitter reaches the controllers and wrappers built on top of it. The
+//! `Scheduler` takes the current time as an argument and needs no clock of its own.
+//!
+//! Background threads (keepalives, the hotplug watcher, sequence waits) also have to wake up
+//! when they are told to stop, so they wait on a condition variable rather than `sleep`. Their
+//! deadlines are still taken on the clock, and `Clock::wait_slice` lets a `MockClock` wake them
+//! often enough to notice when a test moves its time on.
+
+use std::sync::{Condvar, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// A source of time that can also wait.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Blocks for the given duration.
+    fn sleep(&self, duration: Duration);
+
+    /// Returns how long a thread waiting on a condition variable for `remaining` time of this
+    /// clock blocks before it looks at the clock again.
+    ///
+    /// Real clocks block for all of `remaining`, the default. Virtual clocks return a short real
+    /// slice instead, as their time moves on without notifying the condition variable.
+    fn wait_slice(&self, remaining: Duration) -> Duration {
+        remaining
+    }
+}
+
+/// Blocks on `condvar` until it is notified or `deadline` has passed on `clock`.
+///
+/// Like `Condvar::wait_timeout`, it may return before either happened, so callers check their
+/// condition and the time again in a loop.
+pub(crate) fn wait_until<'a, T>(
+    clock: &dyn Clock,
+    condvar: &Condvar,
+    guard: MutexGuard<'a, T>,
+    deadline: Instant,
+) -> MutexGuard<'a, T> {
+    let remaining = deadline.saturating_duration_since(clock.now());
+    if remaining.is_zero() {
+        return guard;
+    }
+    condvar
+        .wait_timeout(guard, clock.wait_slice(remaining))
+        .unwrap_or_else(|e| e.into_inner())
+        .0
+}
+
+/// The real time of the operating system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// How often threads waiting on a `MockClock` look at its time.
+#[cfg(any(test, feature = "test-util"))]
+const MOCK_WAIT_SLICE: Duration = Duration::from_millis(1);
+
+/// A virtual clock for tests: time stands still until `advance` or `sleep` moves it on.
+///
+/// # Example
+#[cfg_attr(feature = "test-util", doc = "```rust")]
+#[cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+/// use brickbeam::{Clock, MockClock};
+/// use std::time::Duration;
+///
+/// let clock = MockClock::new();
+/// let started = clock.now();
+/// clock.sleep(Duration::from_secs(60));
+/// assert_eq!(clock.now() - started, Duration::from_secs(60));
+/// assert_eq!(clock.slept(), Duration::from_secs(60));
+/// ```
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug)]
+pub struct MockClock {
+    state: std::sync::Mutex<(Instant, Duration)>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockClock {
+    /// Creates a clock starting at the current real time.
+    pub fn new() -> Self {
+        Self {
+            state: std::sync::Mutex::new((Instant::now(), Duration::ZERO)),
+        }
+    }
+
+    /// Moves the time on without counting it as slept.
+    pub fn advance(&self, duration: Duration) {
+        self.lock().0 += duration;
+    }
+
+    /// Returns the total time passed to `sleep` so far.
+    pub fn slept(&self) -> Duration {
+        self.lock().1
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (Instant, Duration)> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.lock().0
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let mut state = self.lock();
+        state.0 += duration;
+        state.1 += duration;
+    }
+
+    fn wait_slice(&self, remaining: Duration) -> Duration {
+        remaining.min(MOCK_WAIT_SLICE)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for std::sync::Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+
+    fn wait_slice(&self, remaining: Duration) -> Duration {
+        (**self).wait_slice(remaining)
+    }
+}
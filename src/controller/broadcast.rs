@@ -0,0 +1,254 @@
+use crate::{
+    device::PulseTransmitter,
+    protocols::{
+        duration_of, wait_out_slot, ComboDirectCommand, ComboDirectProtocol, ComboPwmCommand,
+        ComboPwmProtocol, ExtendedCommand, ExtendedProtocol, OutputSelector, SingleOutputCommand,
+        SingleOutputProtocol, MAX_MESSAGE_DURATION,
+    },
+    Channel, Result,
+};
+use std::time::Duration;
+
+/// `Broadcast` sends the same command on all four channels, e.g. for "lights on everywhere"
+/// or stop-all semantics.
+///
+/// Sending the same message to every channel back-to-back floods the IR medium, so each message is
+/// given its own time slot before the next channel is addressed. The slot defaults to the maximum
+/// PF message length and can be widened with `with_message_slot`.
+///
+/// # Fields
+///
+/// * `pulse_transmitter` - A reference to an object that implements the `PulseTransmitter` trait, used to send pulses.
+/// * `message_slot` - The minimum start-to-start time between two consecutive channels.
+/// * `single_output` / `extended` - One stateful protocol per channel, so every receiver sees its own toggle sequence.
+/// * `combo_direct` / `combo_pwm` - Stateless protocols shared by all channels.
+///
+/// # Thread Safety
+///
+/// Like the remote controllers, the `send_*` methods take `&mut self` because the per-channel protocols
+/// keep toggle state. Wrap the instance in a `Mutex` if it must be shared across threads.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, Result};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     let mut everyone = brick_beam.broadcast()?;
+///     everyone.stop_all()?;
+///     Ok(())
+/// }
+/// ```
+pub struct Broadcast<'a, T: PulseTransmitter> {
+    pulse_transmitter: &'a T,
+    message_slot: Duration,
+    single_output: [SingleOutputProtocol; 4],
+    extended: [ExtendedProtocol; 4],
+    combo_direct: ComboDirectProtocol,
+    combo_pwm: ComboPwmProtocol,
+}
+
+impl<'a, T: PulseTransmitter> Broadcast<'a, T> {
+    pub fn new(pulse_transmitter: &'a T) -> Result<Self> {
+        Ok(Self {
+            pulse_transmitter,
+            message_slot: MAX_MESSAGE_DURATION,
+            single_output: [
+                SingleOutputProtocol::new()?,
+                SingleOutputProtocol::new()?,
+                SingleOutputProtocol::new()?,
+                SingleOutputProtocol::new()?,
+            ],
+            extended: [
+                ExtendedProtocol::new()?,
+                ExtendedProtocol::new()?,
+                ExtendedProtocol::new()?,
+                ExtendedProtocol::new()?,
+            ],
+            combo_direct: ComboDirectProtocol::new()?,
+            combo_pwm: ComboPwmProtocol::new()?,
+        })
+    }
+
+    /// Sets the minimum start-to-start time between the messages of two consecutive channels.
+    ///
+    /// A slot shorter than a message's airtime is extended to that airtime.
+    pub fn with_message_slot(mut self, message_slot: Duration) -> Self {
+        self.message_slot = message_slot;
+        self
+    }
+
+    /// Sends a Single Output command to the selected output(s) on every channel.
+    ///
+    /// Selecting both outputs sends the command to every channel's red output first, then to
+    /// every channel's blue output.
+    pub fn send_single_output(
+        &mut self,
+        output: impl Into<OutputSelector>,
+        cmd: SingleOutputCommand,
+    ) -> Result<Duration> {
+        let mut airtime = Duration::ZERO;
+        for (index, output) in output.into().outputs().enumerate() {
+            if index > 0 {
+                self.pulse_transmitter.flush()?;
+                self.pulse_transmitter.clock().sleep(self.message_slot);
+            }
+            let protocols = &mut self.single_output;
+            airtime += Self::each_channel(self.pulse_transmitter, self.message_slot, |channel| {
+                protocols[channel as usize].encode_cmd(channel, output, cmd)
+            })?;
+        }
+        Ok(airtime)
+    }
+
+    /// Sends a Combo Direct command on every channel.
+    pub fn send_combo_direct(&mut self, cmd: ComboDirectCommand) -> Result<Duration> {
+        let protocol = &self.combo_direct;
+        Self::each_channel(self.pulse_transmitter, self.message_slot, |channel| {
+            protocol.encode_cmd(channel, cmd)
+        })
+    }
+
+    /// Sends a Combo PWM command on every channel.
+    pub fn send_combo_pwm(&mut self, cmd: ComboPwmCommand) -> Result<Duration> {
+        let protocol = &self.combo_pwm;
+        Self::each_channel(self.pulse_transmitter, self.message_slot, |channel| {
+            protocol.encode_cmd(channel, cmd)
+        })
+    }
+
+    /// Sends an Extended command on every channel.
+    pub fn send_extended(&mut self, cmd: ExtendedCommand) -> Result<Duration> {
+        let protocols = &mut self.extended;
+        Self::each_channel(self.pulse_transmitter, self.message_slot, |channel| {
+            protocols[channel as usize].encode_cmd(channel, cmd)
+        })
+    }
+
+    /// Floats both outputs on every channel.
+    pub fn stop_all(&mut self) -> Result<Duration> {
+        self.send_combo_pwm(ComboPwmCommand::stopped())
+    }
+
+    /// Encodes and sends one message per channel, keeping each message in its own time slot.
+    ///
+    /// Returns the summed airtime of all transmitted messages.
+    fn each_channel(
+        pulse_transmitter: &T,
+        message_slot: Duration,
+        mut encode: impl FnMut(Channel) -> Result<Vec<u32>>,
+    ) -> Result<Duration> {
+        let mut airtime = Duration::ZERO;
+        for (index, channel) in Channel::iter().enumerate() {
+            if index > 0 {
+                pulse_transmitter.flush()?;
+            }
+            let pulses = encode(channel)?;
+            let clock = pulse_transmitter.clock();
+            let started = clock.now();
+            pulse_transmitter.send_pulses(&pulses)?;
+            let message_airtime = duration_of(&pulses);
+            airtime += message_airtime;
+            if index + 1 < Channel::ALL.len() {
+                wait_out_slot(clock, started, message_airtime, message_slot);
+            }
+        }
+        Ok(airtime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use crate::{DirectState, Error, Output};
+
+    struct MockTransmitterFail;
+
+    impl PulseTransmitter for MockTransmitterFail {
+        fn send_pulses(&self, _pulses: &[u32]) -> Result<()> {
+            Err(Error::Transmitting("Mock failure".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_broadcast_sends_one_message_per_channel_with_gaps() {
+        let transmitter = MockTransmitterRecorder::new();
+        let mut broadcast = Broadcast::new(&transmitter).expect("Should create Broadcast");
+        broadcast
+            .send_combo_direct(ComboDirectCommand {
+                red: DirectState::Forward,
+                blue: DirectState::Forward,
+            })
+            .unwrap();
+
+        let sent = transmitter.timeline();
+        assert_eq!(sent.len(), 4);
+        for pair in sent.windows(2) {
+            assert_ne!(pair[0].1, pair[1].1, "Each channel must get its own frame");
+            assert_eq!(pair[1].0 - pair[0].0, MAX_MESSAGE_DURATION);
+        }
+    }
+
+    #[test]
+    fn test_broadcast_honours_custom_message_slot() {
+        let transmitter = MockTransmitterRecorder::new();
+        let slot = Duration::from_millis(30);
+        let mut broadcast = Broadcast::new(&transmitter)
+            .expect("Should create Broadcast")
+            .with_message_slot(slot);
+        broadcast.stop_all().unwrap();
+
+        let sent = transmitter.timeline();
+        for pair in sent.windows(2) {
+            assert_eq!(pair[1].0 - pair[0].0, slot);
+        }
+    }
+
+    #[test]
+    fn test_broadcast_toggle_is_tracked_per_channel() {
+        let transmitter = MockTransmitterRecorder::new();
+        let mut broadcast = Broadcast::new(&transmitter).expect("Should create Broadcast");
+        broadcast
+            .send_single_output(Output::RED, SingleOutputCommand::PWM(3))
+            .unwrap();
+        broadcast
+            .send_single_output(Output::RED, SingleOutputCommand::PWM(3))
+            .unwrap();
+
+        let sent = transmitter.timeline();
+        for channel in 0..4 {
+            assert_ne!(
+                sent[channel].1,
+                sent[channel + 4].1,
+                "The toggle bit must flip between repeated commands on the same channel"
+            );
+        }
+    }
+
+    #[test]
+    fn test_broadcast_single_output_to_both_outputs() {
+        let transmitter = MockTransmitterRecorder::new();
+        let mut broadcast = Broadcast::new(&transmitter).expect("Should create Broadcast");
+        broadcast
+            .send_single_output(OutputSelector::Both, SingleOutputCommand::PWM(3))
+            .unwrap();
+
+        let sent = transmitter.timeline();
+        assert_eq!(sent.len(), 8);
+        for pair in sent.windows(2) {
+            assert!(pair[1].0 - pair[0].0 >= MAX_MESSAGE_DURATION);
+        }
+    }
+
+    #[test]
+    fn test_broadcast_stops_on_failure() {
+        let transmitter = MockTransmitterFail;
+        let mut broadcast = Broadcast::new(&transmitter).expect("Should create Broadcast");
+        let result = broadcast.stop_all();
+        match result {
+            Err(Error::Transmitting(msg)) => assert!(msg.contains("Mock failure")),
+            _ => panic!("Expected Transmitting error"),
+        }
+    }
+}
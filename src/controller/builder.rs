@@ -0,0 +1,296 @@
+use crate::{
+    controller::{BrickBeam, HardwarePreset, RepeatPolicy, TransmissionProfile},
+    device::{
+        DynPulseTransmitter, MirrorTransmitter, PulseTransmitter, PulseTransmitterEmulator,
+        SettleTransmitter,
+    },
+    Error, Result,
+};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Called with the reason when `build` falls back to the emulator.
+#[derive(Clone)]
+struct FallbackHandler(Arc<dyn Fn(&Error) + Send + Sync>);
+
+impl fmt::Debug for FallbackHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FallbackHandler")
+    }
+}
+
+/// A builder for `BrickBeam` instances with optional transmission features.
+///
+/// Unlike `BrickBeam::new`, the builder produces a `BrickBeam<DynPulseTransmitter>` so that
+/// features which wrap the device (such as mirroring) can be combined freely at runtime.
+///
+/// # Example
+#[cfg_attr(feature = "single-output", doc = "```rust")]
+#[cfg_attr(not(feature = "single-output"), doc = "```ignore")]
+/// use brickbeam::{BrickBeam, Channel, Output, SingleOutputCommand, Result};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::builder()
+///         .device("/dev/lirc0")
+///         .mirror_to_emulator(true)
+///         .build()?;
+///     let mut motor = brick_beam.create_speed_remote_controller(Channel::One, Output::RED)?;
+///     motor.send(SingleOutputCommand::PWM(5))?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BrickBeamBuilder {
+    tx_device_path: Option<PathBuf>,
+    mirror_to_emulator: bool,
+    fall_back_to_emulator: bool,
+    fallback_handler: Option<FallbackHandler>,
+    settle_time: Option<Duration>,
+    profile: Option<TransmissionProfile>,
+    hardware: Option<HardwarePreset>,
+    repeat_policy: Option<RepeatPolicy>,
+}
+
+impl BrickBeamBuilder {
+    /// Creates a builder with default settings: automatic device selection, no mirroring.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses the given transmission device (e.g. /dev/lirc0) instead of selecting one automatically.
+    pub fn device(mut self, tx_device_path: impl Into<PathBuf>) -> Self {
+        self.tx_device_path = Some(tx_device_path.into());
+        self
+    }
+
+    /// Additionally sends every pulse train to `PulseTransmitterEmulator`, which prints a live
+    /// trace of exactly what goes to the IR LED.
+    pub fn mirror_to_emulator(mut self, enabled: bool) -> Self {
+        self.mirror_to_emulator = enabled;
+        self
+    }
+
+    /// Falls back to `PulseTransmitterEmulator` if the device can't be opened, instead of failing
+    /// `build`. Set a `fallback_handler` to learn why the device couldn't be opened.
+    ///
+    /// This lets demos and the CI of downstream applications run the same code path with and
+    /// without IR hardware. Leave it off in production, where a missing device should be an error.
+    pub fn fall_back_to_emulator(mut self, enabled: bool) -> Self {
+        self.fall_back_to_emulator = enabled;
+        self
+    }
+
+    /// Sets a handler called with the error of the device when `build` falls back to the
+    /// emulator, e.g. to log a warning.
+    pub fn fallback_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&Error) + Send + Sync + 'static,
+    {
+        self.fallback_handler = Some(FallbackHandler(Arc::new(handler)));
+        self
+    }
+
+    /// Keeps the IR medium quiet for `settle_time` after opening the device and after receiver
+    /// state changes such as `ExtendedCommand::ToggleAddress` (see `SettleTransmitter`).
+    pub fn settle_time(mut self, settle_time: Duration) -> Self {
+        self.settle_time = Some(settle_time);
+        self
+    }
+
+    /// Applies the transmission settings of a profile (see `TransmissionProfile`): the carrier
+    /// configuration of the device and, as the repeat policy of the controllers, how often every
+    /// message is repeated.
+    pub fn profile(mut self, profile: TransmissionProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Applies the transmission settings suited to the transmitter hardware (see
+    /// `HardwarePreset`). A `profile` takes precedence over the preset.
+    pub fn hardware(mut self, preset: HardwarePreset) -> Self {
+        self.hardware = Some(preset);
+        self
+    }
+
+    /// Sets the repeat policy of the controllers (see `BrickBeam::with_repeat_policy`).
+    ///
+    /// Without it, messages are sent as the copies of the `profile` or `hardware` preset, or as
+    /// the five copies of the PF spec if neither is set.
+    pub fn repeat_policy(mut self, policy: RepeatPolicy) -> Self {
+        self.repeat_policy = Some(policy);
+        self
+    }
+
+    /// Opens the device and builds the `BrickBeam` instance.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the device can't be opened, unless `fall_back_to_emulator` is enabled.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BrickBeam<DynPulseTransmitter>>` - A result containing the new `BrickBeam` instance or an error.
+    pub fn build(self) -> Result<BrickBeam<DynPulseTransmitter>> {
+        let profile = self
+            .profile
+            .or_else(|| self.hardware.map(HardwarePreset::profile));
+        let tx_device_path = self.tx_device_path;
+        let handler = self.fallback_handler.as_ref();
+        let primary = open_or_fall_back(self.fall_back_to_emulator, handler, || {
+            Ok(match tx_device_path {
+                Some(tx_device_path) => Box::new(crate::device::open_default(tx_device_path)?),
+                None => Box::new(crate::device::open_auto()?),
+            })
+        })?;
+        if let Some(profile) = profile {
+            primary.set_carrier(profile.carrier, profile.duty_cycle)?;
+        }
+        let primary: DynPulseTransmitter = match self.settle_time {
+            Some(settle_time) => Box::new(SettleTransmitter::new(primary, settle_time)),
+            None => primary,
+        };
+        let transmitter: DynPulseTransmitter = if self.mirror_to_emulator {
+            Box::new(MirrorTransmitter::new(primary, PulseTransmitterEmulator))
+        } else {
+            primary
+        };
+        let repeat_policy = self
+            .repeat_policy
+            .or(profile.map(TransmissionProfile::repeat_policy))
+            .unwrap_or(RepeatPolicy::spec());
+        Ok(BrickBeam::from_transmitter(transmitter).with_repeat_policy(repeat_policy))
+    }
+}
+
+/// Opens the primary transmitter, replacing it with the emulator on failure if `fall_back` is
+/// set and reporting the error to its handler, if any.
+fn open_or_fall_back(
+    fall_back: bool,
+    handler: Option<&FallbackHandler>,
+    open: impl FnOnce() -> Result<DynPulseTransmitter>,
+) -> Result<DynPulseTransmitter> {
+    match open() {
+        Err(e) if fall_back => {
+            if let Some(FallbackHandler(handler)) = handler {
+                handler(&e);
+            }
+            Ok(Box::new(PulseTransmitterEmulator))
+        }
+        result => result,
+    }
+}
+
+#[cfg(all(test, feature = "single-output"))]
+mod tests {
+    use super::*;
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+    use crate::SingleOutputCommand;
+    use crate::{Channel, Output};
+
+    #[test]
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+    fn test_builder_defaults() {
+        let beam = BrickBeamBuilder::new()
+            .device("/dev/lirc0")
+            .build()
+            .unwrap();
+        let mut motor = beam
+            .create_speed_remote_controller(Channel::One, Output::RED)
+            .unwrap();
+        assert!(motor.send(SingleOutputCommand::PWM(5)).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+    fn test_builder_with_settle_time() {
+        let settle_time = Duration::from_millis(20);
+        let started = std::time::Instant::now();
+        let beam = BrickBeam::builder()
+            .device("/dev/lirc0")
+            .settle_time(settle_time)
+            .build()
+            .unwrap();
+        let mut motor = beam
+            .create_speed_remote_controller(Channel::One, Output::RED)
+            .unwrap();
+        assert!(motor.send(SingleOutputCommand::PWM(5)).is_ok());
+        assert!(started.elapsed() >= settle_time);
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+    fn test_builder_with_profile() {
+        let profile = TransmissionProfile::large_hall().with_repeat_gap(Duration::from_millis(20));
+        let started = std::time::Instant::now();
+        let beam = BrickBeam::builder()
+            .device("/dev/lirc0")
+            .profile(profile)
+            .build()
+            .unwrap();
+        let mut motor = beam
+            .create_speed_remote_controller(Channel::One, Output::RED)
+            .unwrap();
+        assert!(motor.send(SingleOutputCommand::PWM(5)).is_ok());
+        assert!(started.elapsed() >= 4 * profile.repeat_gap);
+    }
+
+    #[test]
+    fn test_fall_back_to_emulator() {
+        let missing = || -> Result<DynPulseTransmitter> {
+            Err(crate::Error::Transmitting("Mock failure".to_string()))
+        };
+        assert!(open_or_fall_back(false, None, missing).is_err());
+        let transmitter = open_or_fall_back(true, None, missing).unwrap();
+        assert!(transmitter.send_pulses(&[157, 1026]).is_ok());
+
+        let reasons = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let handler = FallbackHandler(Arc::new({
+            let reasons = Arc::clone(&reasons);
+            move |e: &Error| reasons.lock().unwrap().push(e.to_string())
+        }));
+        let transmitter = open_or_fall_back(true, Some(&handler), missing).unwrap();
+        assert!(transmitter.send_pulses(&[157, 1026]).is_ok());
+        assert_eq!(reasons.lock().unwrap().len(), 1);
+        assert!(reasons.lock().unwrap()[0].contains("Mock failure"));
+
+        let beam = BrickBeam::builder()
+            .device("/dev/lirc0")
+            .fall_back_to_emulator(true)
+            .fallback_handler(|e| eprintln!("Falling back to the emulator: {}", e))
+            .build()
+            .unwrap();
+        assert!(beam
+            .create_speed_remote_controller(Channel::One, Output::RED)
+            .is_ok());
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+    fn test_builder_with_hardware_preset() {
+        let beam = BrickBeam::builder()
+            .device("/dev/lirc0")
+            .hardware(HardwarePreset::PwmIrTx)
+            .build()
+            .unwrap();
+        let mut motor = beam
+            .create_speed_remote_controller(Channel::One, Output::RED)
+            .unwrap();
+        assert!(motor.send(SingleOutputCommand::PWM(5)).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+    fn test_builder_with_mirror() {
+        let beam = BrickBeam::builder()
+            .device("/dev/lirc0")
+            .mirror_to_emulator(true)
+            .build()
+            .unwrap();
+        let mut motor = beam
+            .create_speed_remote_controller(Channel::One, Output::RED)
+            .unwrap();
+        assert!(motor.send(SingleOutputCommand::PWM(5)).is_ok());
+    }
+}
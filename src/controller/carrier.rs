@@ -0,0 +1,24 @@
+use crate::device::PulseTransmitter;
+use crate::protocols::timing::{CARRIER_HZ, DUTY_CYCLE};
+use crate::Result;
+
+/// The carrier frequency in Hz and duty cycle in percent the PF protocols are sent with.
+pub(crate) const PF_CARRIER: (u32, u32) = (CARRIER_HZ, DUTY_CYCLE);
+
+/// Sends one message modulated with `carrier`, then switches the transmitter back to `restore`
+/// once the message is on air, so the PF controllers sharing it keep working.
+///
+/// The carrier is restored even if the transmission fails; the first error is returned.
+pub(crate) fn transmit_with_carrier<T: PulseTransmitter + ?Sized>(
+    transmitter: &T,
+    pulses: &[u32],
+    carrier: (u32, u32),
+    restore: (u32, u32),
+) -> Result<()> {
+    transmitter.set_carrier(carrier.0, carrier.1)?;
+    let sent = transmitter
+        .send_pulses(pulses)
+        .and_then(|()| transmitter.flush());
+    let restored = transmitter.set_carrier(restore.0, restore.1);
+    sent.and(restored)
+}
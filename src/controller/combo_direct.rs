@@ -1,8 +1,10 @@
 use crate::{
+    controller::repetition::{transmit, RepeatPolicy},
     device::PulseTransmitter,
-    protocols::{ComboDirectCommand, ComboDirectProtocol},
-    Channel, Result,
+    protocols::{timing::PulseTiming, ComboDirectCommand, ComboDirectProtocol, Lrc, ToggleMode},
+    Address, Channel, DirectState, LogicalChannel, Output, OutputSelector, Result,
 };
+use std::time::Duration;
 
 /// `DirectRemoteController` is a struct that represents a remote controller for the LEGO® Power Functions IR Remote Control 8885.
 ///
@@ -14,8 +16,8 @@ use crate::{
 ///
 /// # Thread Safety
 ///
-/// Although the internal protocol used by `DirectRemoteController` is stateless (it does not maintain mutable state),
-/// the public API requires a mutable reference (i.e. the `send` method takes `&mut self`), which prevents concurrent use.
+/// Although the internal protocol used by `DirectRemoteController` is stateless (it does not maintain mutable state)
+/// unless `ToggleMode::Alternate` is selected, the public API requires a mutable reference (i.e. the `send` method takes `&mut self`), which prevents concurrent use.
 /// If you must share an instance across threads, wrap the controller (or the underlying transmitter) in a synchronization primitive (e.g. a `Mutex`).
 ///
 /// # Errors
@@ -24,7 +26,10 @@ use crate::{
 pub struct DirectRemoteController<'a, T: PulseTransmitter> {
     channel: Channel,
     pulse_transmitter: &'a T,
+    repeat_policy: RepeatPolicy,
     protocol: ComboDirectProtocol,
+    // The states the receiver got last, kept for the output `set_state` leaves alone.
+    states: ComboDirectCommand,
 }
 
 impl<'a, T: PulseTransmitter> DirectRemoteController<'a, T> {
@@ -33,19 +38,145 @@ impl<'a, T: PulseTransmitter> DirectRemoteController<'a, T> {
         Ok(Self {
             protocol,
             pulse_transmitter,
+            repeat_policy: RepeatPolicy::default(),
             channel,
+            states: (DirectState::Float, DirectState::Float).into(),
         })
     }
 
-    pub fn send(&mut self, cmd: ComboDirectCommand) -> Result<()> {
+    /// Sends subsequent messages with adjusted symbol lengths, e.g. to probe a marginal receiver.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the IRP for `timing` can't be built.
+    pub fn with_timing(mut self, timing: PulseTiming) -> Result<Self> {
+        self.protocol.set_timing(timing)?;
+        Ok(self)
+    }
+
+    /// Sends subsequent messages with the given LRC, e.g. a wrong one to check that receivers
+    /// ignore corrupted messages.
+    pub fn with_lrc(mut self, lrc: Lrc) -> Self {
+        self.protocol.set_lrc(lrc);
+        self
+    }
+
+    /// Sends subsequent messages to receivers switched to the extra address space, or back to
+    /// the default one.
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.protocol.set_address(address);
+        self
+    }
+
+    /// Chooses the toggle bit of subsequent messages as `mode` says, e.g. `ToggleMode::Alternate`
+    /// for receivers that ignore a repeated command.
+    pub fn with_toggle_mode(mut self, mode: ToggleMode) -> Self {
+        self.protocol.set_toggle_mode(mode);
+        self
+    }
+
+    /// Sends subsequent messages as the copies `policy` asks for, e.g. `RepeatPolicy::spec()`
+    /// for distant receivers. `send` blocks until the last copy is on air.
+    pub fn with_repeat_policy(mut self, policy: RepeatPolicy) -> Self {
+        self.repeat_policy = policy;
+        self
+    }
+
+    /// Returns the channel the controller sends on.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Returns the address space the controller sends to.
+    pub fn address(&self) -> Address {
+        self.protocol.address()
+    }
+
+    /// Returns the logical channel the controller sends on, combining channel and address.
+    pub fn logical_channel(&self) -> LogicalChannel {
+        LogicalChannel::new(self.channel, self.address())
+    }
+
+    /// Returns how the toggle bit of subsequent messages is chosen.
+    pub fn toggle_mode(&self) -> ToggleMode {
+        self.protocol.toggle_mode()
+    }
+
+    /// Changes how the toggle bit of subsequent messages is chosen, e.g. to
+    /// `ToggleMode::Fixed` with a bit tracked by the caller.
+    pub fn set_toggle_mode(&mut self, mode: ToggleMode) {
+        self.protocol.set_toggle_mode(mode);
+    }
+
+    /// Retargets subsequent messages to another channel, keeping the timing, LRC and toggle
+    /// mode.
+    ///
+    /// An alternating toggle bit carries over, since a receiver takes the first message it
+    /// sees as new either way. The outputs of the new receiver are assumed to float.
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+        self.states = (DirectState::Float, DirectState::Float).into();
+    }
+
+    /// Retargets subsequent messages to the receiver in another address space, keeping the
+    /// channel, timing and LRC, e.g. to drive two receivers sharing a channel.
+    pub fn set_address(&mut self, address: Address) {
+        self.protocol.set_address(address);
+        self.states = (DirectState::Float, DirectState::Float).into();
+    }
+
+    /// Sets the state of the selected output(s) and returns the airtime of the transmitted
+    /// message.
+    ///
+    /// A Combo Direct message always carries both states, so an output that isn't selected
+    /// keeps the state it was last sent, or floats if it hasn't been sent one yet.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{BrickBeam, Channel, DirectState, Output, OutputSelector, Result};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+    ///     let mut crane = brick_beam.create_direct_remote_controller(Channel::One)?;
+    ///     crane.set_state(OutputSelector::Both, DirectState::Forward)?;
+    ///     crane.set_state(Output::RED, DirectState::Brake)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_state(
+        &mut self,
+        outputs: impl Into<OutputSelector>,
+        state: DirectState,
+    ) -> Result<Duration> {
+        let mut cmd = self.states;
+        for output in outputs.into().outputs() {
+            match output {
+                Output::RED => cmd.red = state,
+                Output::BLUE => cmd.blue = state,
+            }
+        }
+        self.send(cmd)
+    }
+
+    /// Sends a command to both outputs and returns the airtime of the transmitted message,
+    /// including all copies of the repeat policy and the pauses between them.
+    pub fn send(&mut self, cmd: ComboDirectCommand) -> Result<Duration> {
         let pulses = self.protocol.encode_cmd(self.channel, cmd)?;
-        self.pulse_transmitter.send_pulses(&pulses)
+        let airtime = transmit(
+            self.pulse_transmitter,
+            self.channel,
+            &pulses,
+            self.repeat_policy,
+        )?;
+        self.states = cmd;
+        Ok(airtime)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::device::MockTransmitterRecorder;
     use crate::device::PulseTransmitter;
     use crate::protocols::Channel;
     use crate::{DirectState, Error, Result};
@@ -119,4 +250,66 @@ mod tests {
             _ => panic!("Unexpected error variant"),
         }
     }
+
+    #[test]
+    fn test_alternating_toggle_flips_per_send_not_per_copy() {
+        let transmitter = MockTransmitterRecorder::default();
+        let cmd = ComboDirectCommand::from((DirectState::Brake, DirectState::Brake));
+        let mut controller = DirectRemoteController::new(&transmitter, Channel::Two)
+            .unwrap()
+            .with_toggle_mode(ToggleMode::Alternate)
+            .with_repeat_policy(RepeatPolicy::fixed(2, Duration::ZERO));
+        controller.send(cmd).unwrap();
+        controller.send(cmd).unwrap();
+        controller.set_toggle_mode(ToggleMode::Fixed(true));
+        controller.send(cmd).unwrap();
+
+        let toggles: Vec<bool> = transmitter
+            .sent()
+            .iter()
+            .map(|pulses| pulses[3] > 400)
+            .collect();
+        assert_eq!(toggles, [false, false, true, true, true, true]);
+    }
+
+    #[test]
+    fn test_with_address_sets_address_bit() {
+        let transmitter = MockTransmitterRecorder::default();
+        let cmd = ComboDirectCommand::from((DirectState::Forward, DirectState::Float));
+        let mut controller = DirectRemoteController::new(&transmitter, Channel::One).unwrap();
+        assert_eq!(controller.address(), Address::Default);
+        controller.send(cmd).unwrap();
+        let mut controller = controller.with_address(Address::Extra);
+        assert_eq!(controller.address(), Address::Extra);
+        controller.send(cmd).unwrap();
+
+        let sent = transmitter.sent();
+        // The address bit is the fifth bit of the frame, so its space is the twelfth pulse.
+        assert!(sent[0][11] < 400);
+        assert!(sent[1][11] > 400);
+        // The LRC covers the address bit.
+        assert_ne!(sent[0][27..35], sent[1][27..35]);
+    }
+
+    #[test]
+    fn test_set_state_keeps_other_output() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = DirectRemoteController::new(&transmitter, Channel::Two).unwrap();
+        controller
+            .set_state(OutputSelector::Both, DirectState::Forward)
+            .unwrap();
+        controller
+            .set_state(Output::RED, DirectState::Brake)
+            .unwrap();
+        controller
+            .send((DirectState::Forward, DirectState::Forward).into())
+            .unwrap();
+        controller
+            .send((DirectState::Brake, DirectState::Forward).into())
+            .unwrap();
+
+        let sent = transmitter.sent();
+        assert_eq!(sent[0], sent[2]);
+        assert_eq!(sent[1], sent[3]);
+    }
 }
@@ -1,8 +1,14 @@
 use crate::{
+    controller::{
+        keepalive::Keepalive,
+        repetition::{transmit, RepeatPolicy},
+    },
     device::PulseTransmitter,
-    protocols::{ComboPwmCommand, ComboPwmProtocol},
-    Channel, Result,
+    protocols::{timing::PulseTiming, ComboPwmCommand, ComboPwmProtocol, Lrc},
+    Address, Channel, LogicalChannel, MotorProfile, Output, OutputSelector, Result, StepRounding,
 };
+use std::thread::Scope;
+use std::time::Duration;
 
 /// `ComboSpeedRemoteController` is a struct that represents a remote controller for the LEGO® Power Functions Speed IR Remote Control 8879.
 ///
@@ -24,7 +30,15 @@ use crate::{
 pub struct ComboSpeedRemoteController<'a, T: PulseTransmitter> {
     channel: Channel,
     pulse_transmitter: &'a T,
+    repeat_policy: RepeatPolicy,
     protocol: ComboPwmProtocol,
+    step_rounding: StepRounding,
+    keepalive: Option<Keepalive>,
+    dedupe: bool,
+    // The last command the receiver got, while dedupe is enabled.
+    last_sent: Option<ComboPwmCommand>,
+    // The speeds the receiver got last, kept for the output `set_speed` leaves alone.
+    speeds: ComboPwmCommand,
 }
 
 impl<'a, T: PulseTransmitter> ComboSpeedRemoteController<'a, T> {
@@ -33,19 +47,216 @@ impl<'a, T: PulseTransmitter> ComboSpeedRemoteController<'a, T> {
         Ok(Self {
             protocol,
             pulse_transmitter,
+            repeat_policy: RepeatPolicy::default(),
             channel,
+            step_rounding: StepRounding::default(),
+            keepalive: None,
+            dedupe: false,
+            last_sent: None,
+            speeds: ComboPwmCommand::stopped(),
         })
     }
 
-    pub fn send(&mut self, cmd: ComboPwmCommand) -> Result<()> {
+    /// Sends subsequent messages with adjusted symbol lengths, e.g. to probe a marginal receiver.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the IRP for `timing` can't be built.
+    pub fn with_timing(mut self, timing: PulseTiming) -> Result<Self> {
+        self.protocol.set_timing(timing)?;
+        Ok(self)
+    }
+
+    /// Sends subsequent messages with the given LRC, e.g. a wrong one to check that receivers
+    /// ignore corrupted messages.
+    pub fn with_lrc(mut self, lrc: Lrc) -> Self {
+        self.protocol.set_lrc(lrc);
+        self
+    }
+
+    /// Rejects PWM speeds outside -7 to 8 with `Error::InvalidSpeed` instead of clamping them,
+    /// so an application can report invalid input rather than move the train unexpectedly.
+    pub fn with_strict_speeds(mut self, strict: bool) -> Self {
+        self.protocol.set_strict(strict);
+        self
+    }
+
+    /// Sends subsequent messages to receivers switched to the extra address space, or back to
+    /// the default one.
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.protocol.set_address(address);
+        self
+    }
+
+    /// Rounds the percentages of `set_speed_percent` to PWM steps as `rounding` says.
+    pub fn with_step_rounding(mut self, rounding: StepRounding) -> Self {
+        self.step_rounding = rounding;
+        self
+    }
+
+    /// Skips a command equal to the last one sent successfully, so a loop polling two throttles
+    /// can pass every reading to `send` without flooding the room with identical messages.
+    ///
+    /// A skipped command returns a zero airtime; a running keepalive keeps refreshing the last
+    /// command. A new channel or address or a failed send make the next command go out in any
+    /// case.
+    pub fn with_dedupe(mut self, dedupe: bool) -> Self {
+        self.dedupe = dedupe;
+        self.last_sent = None;
+        self
+    }
+
+    /// Sends subsequent messages as the copies `policy` asks for, e.g. `RepeatPolicy::spec()`
+    /// for distant receivers. `send` blocks until the last copy is on air.
+    pub fn with_repeat_policy(mut self, policy: RepeatPolicy) -> Self {
+        self.repeat_policy = policy;
+        self
+    }
+
+    /// Returns the channel the controller sends on.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Returns the address space the controller sends to.
+    pub fn address(&self) -> Address {
+        self.protocol.address()
+    }
+
+    /// Returns the logical channel the controller sends on, combining channel and address.
+    pub fn logical_channel(&self) -> LogicalChannel {
+        LogicalChannel::new(self.channel, self.address())
+    }
+
+    /// Retargets subsequent messages to another channel, keeping the timing and LRC.
+    ///
+    /// The next command is sent even if dedupe is enabled and it equals the last one, and the
+    /// outputs of the new receiver are assumed to float.
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+        self.last_sent = None;
+        self.speeds = ComboPwmCommand::stopped();
+    }
+
+    /// Retargets subsequent messages to the receiver in another address space, keeping the
+    /// channel, timing and LRC, e.g. to drive two receivers sharing a channel.
+    pub fn set_address(&mut self, address: Address) {
+        self.protocol.set_address(address);
+        self.last_sent = None;
+        self.speeds = ComboPwmCommand::stopped();
+    }
+
+    /// Sets the PWM speed (-7 to 8) of the selected output(s) and returns the airtime of the
+    /// transmitted message.
+    ///
+    /// A Combo PWM message always carries both speeds, so an output that isn't selected keeps
+    /// the speed it was last sent, or floats if it hasn't been sent one yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidSpeed` for speeds outside -7 to 8, without sending anything.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{BrickBeam, Channel, Output, OutputSelector, Result};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+    ///     let mut train = brick_beam.create_combo_speed_remote_controller(Channel::One)?;
+    ///     train.set_speed(OutputSelector::Both, 5)?;
+    ///     // Only the lights on the blue output go off; the motor keeps running.
+    ///     train.set_speed(Output::BLUE, 0)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_speed(&mut self, outputs: impl Into<OutputSelector>, speed: i8) -> Result<Duration> {
+        let mut cmd = self.speeds;
+        for output in outputs.into().outputs() {
+            cmd = match output {
+                Output::RED => cmd.with_red(speed)?,
+                Output::BLUE => cmd.with_blue(speed)?,
+            };
+        }
+        self.send(cmd)
+    }
+
+    /// Sets the speeds of both outputs in percent, from -100.0 (full reverse) to 100.0 (full
+    /// forward), and returns the airtime of the transmitted message.
+    ///
+    /// The percentages are spread linearly over the PWM steps and rounded as set by
+    /// `with_step_rounding`, so UIs can pass the positions of two sliders as is.
+    pub fn set_speed_percent(&mut self, red: f32, blue: f32) -> Result<Duration> {
+        let step = |percent| MotorProfile::linear().step_percent(percent, self.step_rounding);
+        self.send(ComboPwmCommand::new(step(red), step(blue))?)
+    }
+
+    /// Sends a command to both outputs and returns the airtime of the transmitted message,
+    /// including all copies of the repeat policy and the pauses between them.
+    pub fn send(&mut self, cmd: ComboPwmCommand) -> Result<Duration> {
+        if self.dedupe && self.last_sent == Some(cmd) {
+            return Ok(Duration::ZERO);
+        }
         let pulses = self.protocol.encode_cmd(self.channel, cmd)?;
-        self.pulse_transmitter.send_pulses(&pulses)
+        self.last_sent = None;
+        let airtime = transmit(
+            self.pulse_transmitter,
+            self.channel,
+            &pulses,
+            self.repeat_policy,
+        )?;
+        if self.dedupe {
+            self.last_sent = Some(cmd);
+        }
+        self.speeds = cmd;
+        if let Some(keepalive) = &self.keepalive {
+            keepalive.update(&pulses, self.pulse_transmitter.clock().now());
+        }
+        Ok(airtime)
+    }
+
+    /// Retransmits the last command every `interval` in which no other command was sent, as
+    /// Combo PWM receivers stop both outputs after about a second without a signal.
+    ///
+    /// The keepalive refreshes the commands sent from now on, on a thread of `scope`. The scope
+    /// waits for that thread when it ends, so call `stop_keepalive` or drop the controller
+    /// within the scope. Starting it again replaces the running keepalive.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{BrickBeam, Channel, ComboPwmCommand, Result};
+    /// use std::{thread, time::Duration};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+    ///     thread::scope(|scope| {
+    ///         let mut train = brick_beam.create_combo_speed_remote_controller(Channel::One)?;
+    ///         train.start_keepalive(scope, Duration::from_millis(500));
+    ///         train.send(ComboPwmCommand::new(5, 0)?)?;
+    ///         // The train keeps going, although nothing is sent for a while.
+    ///         thread::sleep(Duration::from_millis(1200));
+    ///         train.send(ComboPwmCommand::stopped())?;
+    ///         Ok(())
+    ///     })
+    /// }
+    /// ```
+    pub fn start_keepalive<'scope>(&mut self, scope: &'scope Scope<'scope, '_>, interval: Duration)
+    where
+        'a: 'scope,
+        T: Sync,
+    {
+        self.keepalive = Some(Keepalive::start(scope, self.pulse_transmitter, interval));
+    }
+
+    /// Stops retransmitting the last command.
+    pub fn stop_keepalive(&mut self) {
+        self.keepalive = None;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::device::MockTransmitterRecorder;
     use crate::device::PulseTransmitter;
     use crate::protocols::Channel;
     use crate::{Error, Result};
@@ -92,6 +303,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_set_speed_percent_rounds_both_outputs() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = ComboSpeedRemoteController::new(&transmitter, Channel::One).unwrap();
+        controller.set_speed_percent(50.0, -100.0).unwrap();
+        controller
+            .send(ComboPwmCommand::new(4, -7).unwrap())
+            .unwrap();
+        let mut controller = controller.with_step_rounding(StepRounding::TowardZero);
+        controller.set_speed_percent(50.0, -100.0).unwrap();
+        controller
+            .send(ComboPwmCommand::new(3, -7).unwrap())
+            .unwrap();
+
+        let sent = transmitter.sent();
+        assert_eq!(sent[0], sent[1]);
+        assert_eq!(sent[2], sent[3]);
+    }
+
     #[test]
     fn test_combo_speed_send_fails() {
         let transmitter = MockTransmitterFail;
@@ -109,4 +339,102 @@ mod tests {
             _ => panic!("Unexpected error variant"),
         }
     }
+
+    #[test]
+    fn test_spec_repeat_policy_sends_five_identical_copies() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = ComboSpeedRemoteController::new(&transmitter, Channel::Four)
+            .unwrap()
+            .with_repeat_policy(RepeatPolicy::spec());
+        let airtime = controller
+            .send(ComboPwmCommand::new(5, -3).unwrap())
+            .unwrap();
+
+        let sent = transmitter.sent();
+        assert_eq!(sent.len(), 5);
+        // The pauses of channel 4 and the last copy.
+        assert_eq!(
+            airtime,
+            Duration::from_millis(384) + crate::duration_of(&sent[4])
+        );
+        assert!(sent.iter().all(|copy| *copy == sent[0]));
+        assert_eq!(transmitter.clock.slept(), Duration::from_millis(384));
+    }
+
+    #[test]
+    fn test_keepalive_refreshes_last_command() {
+        use std::thread;
+
+        let transmitter = MockTransmitterRecorder::default();
+        let interval = Duration::from_millis(10);
+        thread::scope(|scope| {
+            let mut controller =
+                ComboSpeedRemoteController::new(&transmitter, Channel::One).unwrap();
+            controller.start_keepalive(scope, interval);
+            controller
+                .send(ComboPwmCommand::new(5, 0).unwrap())
+                .unwrap();
+            for refreshes in 1..=3 {
+                transmitter.clock.advance(interval);
+                transmitter.wait_until_sent(1 + refreshes);
+            }
+            controller.stop_keepalive();
+        });
+
+        let sent = transmitter.sent();
+        assert_eq!(sent.len(), 4);
+        assert!(sent.iter().all(|pulses| *pulses == sent[0]));
+    }
+
+    #[test]
+    fn test_dedupe_skips_repeats_but_keeps_keepalive() {
+        use std::thread;
+
+        let transmitter = MockTransmitterRecorder::default();
+        let interval = Duration::from_millis(10);
+        let cmd = ComboPwmCommand::new(5, 0).unwrap();
+        thread::scope(|scope| {
+            let mut controller = ComboSpeedRemoteController::new(&transmitter, Channel::One)
+                .unwrap()
+                .with_dedupe(true);
+            controller.start_keepalive(scope, interval);
+            controller.send(cmd).unwrap();
+            for refreshes in 1..=3 {
+                assert_eq!(controller.send(cmd).unwrap(), Duration::ZERO);
+                transmitter.clock.advance(interval);
+                transmitter.wait_until_sent(1 + refreshes);
+            }
+            controller.stop_keepalive();
+            controller.set_channel(Channel::Two);
+            controller.send(cmd).unwrap();
+        });
+
+        let sent = transmitter.sent();
+        assert_eq!(sent.len(), 5);
+        assert!(sent[..4].iter().all(|pulses| *pulses == sent[0]));
+        assert_ne!(sent[4], sent[0]);
+    }
+
+    #[test]
+    fn test_set_speed_keeps_other_output() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = ComboSpeedRemoteController::new(&transmitter, Channel::One).unwrap();
+        controller.set_speed(OutputSelector::Both, 5).unwrap();
+        controller.set_speed(Output::BLUE, -2).unwrap();
+        assert!(matches!(
+            controller.set_speed(Output::RED, 9),
+            Err(Error::InvalidSpeed(9))
+        ));
+        controller
+            .send(ComboPwmCommand::new(5, 5).unwrap())
+            .unwrap();
+        controller
+            .send(ComboPwmCommand::new(5, -2).unwrap())
+            .unwrap();
+
+        let sent = transmitter.sent();
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[0], sent[2]);
+        assert_eq!(sent[1], sent[3]);
+    }
 }
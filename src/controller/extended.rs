@@ -1,7 +1,12 @@
+use crate::controller::{
+    registry::ControllerRegistry,
+    repetition::{transmit, RepeatPolicy},
+};
 use crate::device::PulseTransmitter;
-use crate::protocols::ExtendedCommand;
-use crate::protocols::ExtendedProtocol;
-use crate::{Channel, Result};
+use crate::protocols::{timing::PulseTiming, ExtendedCommand, Lrc, ToggleState};
+use crate::protocols::{wait_out_slot, ExtendedProtocol, MAX_MESSAGE_DURATION};
+use crate::{Address, Channel, Error, LogicalChannel, Result};
+use std::time::Duration;
 
 /// # ExtendedRemoteController
 ///
@@ -13,6 +18,15 @@ use crate::{Channel, Result};
 /// * `channel` - The channel on which the remote controller operates.
 /// * `pulse_transmitter` - A reference to an object that implements the `PulseTransmitter` trait, used to send pulses.
 /// * `protocol` - An instance of `ExtendedProtocol` used to encode commands.
+/// * `speed` - The assumed speed step of the red output (-7 to 7).
+///
+/// # Speed Tracking
+///
+/// The Extended protocol only offers relative speed changes for the red output. The controller
+/// therefore assumes the receiver starts at step 0 and follows every `IncrementSpeedOnRedOutput`,
+/// `DecrementSpeedOnRedOutput` and `BrakeThenFloatOnRedOutput` it sends. `set_speed` uses that
+/// estimate to reach an absolute step. Commands from other remotes on the same channel are not
+/// seen, so send `BrakeThenFloatOnRedOutput` to get back in sync with the receiver.
 ///
 /// # Thread Safety
 ///
@@ -26,7 +40,10 @@ use crate::{Channel, Result};
 pub struct ExtendedRemoteController<'a, T: PulseTransmitter> {
     channel: Channel,
     pulse_transmitter: &'a T,
+    repeat_policy: RepeatPolicy,
     protocol: ExtendedProtocol,
+    speed: i8,
+    registry: Option<&'a ControllerRegistry>,
 }
 
 impl<'a, T: PulseTransmitter> ExtendedRemoteController<'a, T> {
@@ -35,13 +52,172 @@ impl<'a, T: PulseTransmitter> ExtendedRemoteController<'a, T> {
         Ok(Self {
             protocol,
             pulse_transmitter,
+            repeat_policy: RepeatPolicy::default(),
             channel,
+            speed: 0,
+            registry: None,
         })
     }
 
-    pub fn send(&mut self, cmd: ExtendedCommand) -> Result<()> {
+    /// Sends subsequent messages with adjusted symbol lengths, e.g. to probe a marginal receiver.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the IRP for `timing` can't be built.
+    pub fn with_timing(mut self, timing: PulseTiming) -> Result<Self> {
+        self.protocol.set_timing(timing)?;
+        Ok(self)
+    }
+
+    /// Sends subsequent messages with the given LRC, e.g. a wrong one to check that receivers
+    /// ignore corrupted messages.
+    pub fn with_lrc(mut self, lrc: Lrc) -> Self {
+        self.protocol.set_lrc(lrc);
+        self
+    }
+
+    /// Sends subsequent messages to a receiver switched to the extra address space, or back to
+    /// the default one, until a `ToggleAddress` command switches the receiver again.
+    ///
+    /// A controller obtained from `BrickBeam::extended` continues with the toggle state shared
+    /// for the receiver in that address space.
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.protocol.set_address(address);
+        if self.registry.is_some() {
+            self.protocol.share_toggle(self.toggle_state());
+        }
+        self
+    }
+
+    /// Shares the toggle state with the other controllers of a `BrickBeam` registry.
+    pub(crate) fn with_registry(mut self, registry: &'a ControllerRegistry) -> Self {
+        self.registry = Some(registry);
+        self.protocol.share_toggle(self.toggle_state());
+        self
+    }
+
+    fn toggle_state(&self) -> ToggleState {
+        match self.registry {
+            Some(registry) => registry.toggle(self.logical_channel(), None),
+            None => ToggleState::new(self.address()),
+        }
+    }
+
+    /// Sends subsequent messages as the copies `policy` asks for, e.g. `RepeatPolicy::spec()`
+    /// for distant receivers. `send` blocks until the last copy is on air.
+    pub fn with_repeat_policy(mut self, policy: RepeatPolicy) -> Self {
+        self.repeat_policy = policy;
+        self
+    }
+
+    /// Returns the channel the controller sends on.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Returns the address space the controller sends to, which `ToggleAddress` flips.
+    pub fn address(&self) -> Address {
+        self.protocol.address()
+    }
+
+    /// Returns the logical channel the controller sends on, combining channel and address.
+    pub fn logical_channel(&self) -> LogicalChannel {
+        LogicalChannel::new(self.channel, self.address())
+    }
+
+    /// Retargets subsequent messages to another channel, keeping the timing, LRC and address.
+    ///
+    /// A controller obtained from `BrickBeam::extended` continues with the toggle bit shared for
+    /// the new channel; any other controller starts the new channel with a fresh toggle bit. The speed
+    /// estimate restarts at step 0, as for a new controller.
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+        self.protocol.share_toggle(self.toggle_state());
+        self.speed = 0;
+    }
+
+    /// Sends an extended command and returns the airtime of the transmitted message, including
+    /// all copies of the repeat policy and the pauses between them.
+    pub fn send(&mut self, cmd: ExtendedCommand) -> Result<Duration> {
         let pulses = self.protocol.encode_cmd(self.channel, cmd)?;
-        self.pulse_transmitter.send_pulses(&pulses)
+        let airtime = self.transmit(&pulses)?;
+        match cmd {
+            ExtendedCommand::IncrementSpeedOnRedOutput => self.speed = (self.speed + 1).min(7),
+            ExtendedCommand::DecrementSpeedOnRedOutput => self.speed = (self.speed - 1).max(-7),
+            ExtendedCommand::BrakeThenFloatOnRedOutput => self.speed = 0,
+            ExtendedCommand::ToggleAddress => self.pulse_transmitter.settle(),
+            _ => (),
+        }
+        Ok(airtime)
+    }
+
+    /// Sends a message with any 4-bit function and returns the airtime of the transmitted
+    /// message, e.g. to experiment with the reserved functions 0b0011, 0b0101 or 0b1000.
+    ///
+    /// Functions of an `ExtendedCommand` are sent as that command, so the speed estimate and
+    /// address follow them; reserved functions leave the speed estimate as it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if `function` doesn't fit in 4 bits.
+    pub fn send_raw_function(&mut self, function: u8) -> Result<Duration> {
+        if let Ok(cmd) = ExtendedCommand::try_from(function) {
+            return self.send(cmd);
+        }
+        let pulses = self.protocol.encode_function(self.channel, function)?;
+        self.transmit(&pulses)
+    }
+
+    fn transmit(&self, pulses: &[u32]) -> Result<Duration> {
+        transmit(
+            self.pulse_transmitter,
+            self.channel,
+            pulses,
+            self.repeat_policy,
+        )
+    }
+
+    /// Returns the assumed speed step of the red output.
+    pub fn estimated_speed(&self) -> i8 {
+        self.speed
+    }
+
+    /// Brings the red output to the speed step `target` (-7 to 7) by sending as many
+    /// increments or decrements as needed, each in its own message slot.
+    ///
+    /// Returns the summed airtime of all transmitted messages; nothing is sent if the estimated
+    /// speed already matches.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::ProtocolError` if `target` is out of range. If a transmission fails, the
+    /// estimated speed reflects the steps sent so far.
+    pub fn set_speed(&mut self, target: i8) -> Result<Duration> {
+        if !(-7..=7).contains(&target) {
+            return Err(Error::ProtocolError(format!(
+                "Speed step {} is out of range -7 to 7",
+                target
+            )));
+        }
+        let mut airtime = Duration::ZERO;
+        while self.speed != target {
+            let cmd = if target > self.speed {
+                ExtendedCommand::IncrementSpeedOnRedOutput
+            } else {
+                ExtendedCommand::DecrementSpeedOnRedOutput
+            };
+            if airtime > Duration::ZERO {
+                self.pulse_transmitter.flush()?;
+            }
+            let clock = self.pulse_transmitter.clock();
+            let started = clock.now();
+            let message_airtime = self.send(cmd)?;
+            airtime += message_airtime;
+            if self.speed != target {
+                wait_out_slot(clock, started, message_airtime, MAX_MESSAGE_DURATION);
+            }
+        }
+        Ok(airtime)
     }
 }
 
@@ -110,6 +286,49 @@ mod tests {
         // For coverage, the main thing is verifying these calls don't fail or panic.
     }
 
+    #[test]
+    fn test_extended_set_speed_tracks_estimate() {
+        let transmitter = MockTransmitterSuccess;
+        let mut controller = ExtendedRemoteController::new(&transmitter, Channel::One)
+            .expect("Should create ExtendedRemoteController");
+        assert_eq!(controller.estimated_speed(), 0);
+
+        let airtime = controller.set_speed(3).unwrap();
+        assert_eq!(controller.estimated_speed(), 3);
+        assert!(airtime > Duration::ZERO);
+
+        controller.set_speed(-2).unwrap();
+        assert_eq!(controller.estimated_speed(), -2);
+        assert_eq!(controller.set_speed(-2).unwrap(), Duration::ZERO);
+
+        controller
+            .send(ExtendedCommand::BrakeThenFloatOnRedOutput)
+            .unwrap();
+        assert_eq!(controller.estimated_speed(), 0);
+        assert!(matches!(
+            controller.set_speed(8),
+            Err(Error::ProtocolError(_))
+        ));
+    }
+
+    #[test]
+    fn test_extended_send_raw_function() {
+        let transmitter = MockTransmitterSuccess;
+        let mut controller = ExtendedRemoteController::new(&transmitter, Channel::One)
+            .expect("Should create ExtendedRemoteController");
+
+        assert!(controller.send_raw_function(0b0011).unwrap() > Duration::ZERO);
+        assert_eq!(controller.estimated_speed(), 0);
+        controller.send_raw_function(0b0001).unwrap();
+        assert_eq!(controller.estimated_speed(), 1);
+        controller.send_raw_function(0b0110).unwrap();
+        assert_eq!(controller.address(), Address::Extra);
+        assert!(matches!(
+            controller.send_raw_function(0x10),
+            Err(Error::ProtocolError(_))
+        ));
+    }
+
     #[test]
     fn test_extended_send_fails() {
         let transmitter = MockTransmitterFail;
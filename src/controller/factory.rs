@@ -1,18 +1,51 @@
+#[cfg(any(feature = "single-output", feature = "extended"))]
+use crate::controller::registry::ControllerRegistry;
+#[cfg(feature = "combo-pwm")]
+use crate::controller::ComboSpeedRemoteController;
+#[cfg(feature = "combo-direct")]
+use crate::controller::DirectRemoteController;
+#[cfg(feature = "extended")]
+use crate::controller::ExtendedRemoteController;
+#[cfg(feature = "generic")]
+use crate::controller::GenericRemoteController;
+#[cfg(feature = "rcx")]
+use crate::controller::RcxRemoteController;
+#[cfg(any(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+use crate::LogicalChannel;
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+use crate::{
+    controller::Broadcast,
+    sequence::{Sequence, SequencePlayer},
+};
+#[cfg(feature = "single-output")]
+use crate::{controller::SpeedRemoteController, Output};
 use crate::{
     controller::{
-        ComboSpeedRemoteController, DirectRemoteController, ExtendedRemoteController,
-        SpeedRemoteController,
+        repetition::{transmit, RepeatPolicy},
+        BrickBeamBuilder,
     },
     device::{DefaultPulseTransmitter, PulseTransmitter},
+    protocols::{timing::PulseTiming, ProtocolKind, RawMessageFields},
     Result,
 };
-use crate::{Channel, Output};
 use std::path::Path;
+use std::time::Duration;
 
 /// The primary API for creating various remote controllers for LEGO IR transmission.
 ///
 /// This struct abstracts the details of the underlying `PulseTransmitter`.
 /// On Linux, with the default Cargo feature `cir`, it uses [cir crate](https://docs.rs/cir) that uses the Linux kernel's LIRC (rc-core) drivers for IR transmitter.
+/// With the `lirc-raw` feature instead, it drives the same LIRC device with plain ioctl and write calls.
 /// on other platforms, it uses an emulator that is intended only for quick and easy compilation, not for production use.
 ///
 /// Once initialized, you can create remote controllers that wrap the underlying LEGO® IR transmission protocols.
@@ -21,10 +54,17 @@ use std::path::Path;
 /// * for the Single Output protocol via create_speed_remote_controller(),
 /// * for the Combo PWM protocol via create_combo_speed_remote_controller(),
 /// * for the Combo Direct protocol via create_direct_remote_controller(),
-/// * and for the Extended protocol via create_extended_remote_controller().
+/// * for the Extended protocol via create_extended_remote_controller(),
+/// * for Mindstorms RCX bricks via create_rcx_remote_controller() (`rcx` feature),
+/// * and for NEC and RC-5 devices via create_generic_remote_controller() (`generic` feature).
+///
+/// The `create_*` methods return a new controller on every call, each with its own toggle bit.
+/// `speed()` and `extended()` instead keep one toggle state per receiver in a registry owned by
+/// this instance, so all controllers they return stay in sync with the receiver.
 ///
 /// # Examples
-/// ```rust
+#[cfg_attr(feature = "single-output", doc = "```rust")]
+#[cfg_attr(not(feature = "single-output"), doc = "```ignore")]
 /// use brickbeam::{BrickBeam, Channel, Output, SingleOutputCommand, Result};
 ///
 /// fn main() -> Result<()> {
@@ -36,10 +76,13 @@ use std::path::Path;
 /// ```
 pub struct BrickBeam<T: PulseTransmitter = DefaultPulseTransmitter> {
     pulse_transmitter: T,
+    repeat_policy: RepeatPolicy,
+    #[cfg(any(feature = "single-output", feature = "extended"))]
+    registry: ControllerRegistry,
 }
 
 impl BrickBeam<DefaultPulseTransmitter> {
-    #[cfg(feature = "cir")]
+    #[cfg(any(feature = "cir", feature = "lirc-raw"))]
     /// Creates a new `BrickBeam` instance using the Linux Kernel's LIRC (rc-core) IR transmitter.
     ///
     /// This function initializes a `BrickBeam` instance by setting up the Linux-specific IR transmitter.
@@ -52,11 +95,11 @@ impl BrickBeam<DefaultPulseTransmitter> {
     ///
     /// * `Result<Self>` - A result containing the new `BrickBeam` instance or an error.
     pub fn new(tx_device_path: impl AsRef<Path>) -> Result<Self> {
-        let pulse_transmitter = crate::device::CirPulseTransmitter::new(tx_device_path)?;
-        Ok(Self { pulse_transmitter })
+        let pulse_transmitter = crate::device::open_default(tx_device_path)?;
+        Ok(Self::from_transmitter(pulse_transmitter))
     }
 
-    #[cfg(not(feature = "cir"))]
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
     /// Creates a new `BrickBeam` instance for non‑Linux platforms using a simulated IR transmitter.
     ///
     /// # Arguments
@@ -68,87 +111,372 @@ impl BrickBeam<DefaultPulseTransmitter> {
     /// * `Result<Self>` - A result containing the new `BrickBeam` instance or an error.
     pub fn new(_tx_device_path: impl AsRef<Path>) -> Result<Self> {
         let pulse_transmitter = crate::device::PulseTransmitterEmulator;
-        Ok(Self { pulse_transmitter })
+        Ok(Self::from_transmitter(pulse_transmitter))
+    }
+
+    /// Creates a new `BrickBeam` instance on the most suitable IR transmitter of this system.
+    ///
+    /// The rc-core metadata in `/sys/class/rc` is used to prefer dedicated transmitter drivers
+    /// (such as `gpio-ir-tx` or `pwm-ir-tx`) over receivers, e.g. a TV-card, that also expose a
+    /// `/dev/lircX` device. Without the `cir` or `lirc-raw` feature, the emulator is used.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self>` - A result containing the new `BrickBeam` instance, or an error if no
+    ///   LIRC device was found.
+    pub fn auto() -> Result<Self> {
+        let pulse_transmitter = crate::device::open_auto()?;
+        Ok(Self::from_transmitter(pulse_transmitter))
+    }
+
+    /// Creates a new `BrickBeam` instance on a `/dev/lirc*` device that can transmit.
+    ///
+    /// Unlike `auto`, which goes by the rc-core metadata alone, every LIRC device is opened and
+    /// its driver asked whether it can send, so the receiver of a Raspberry Pi with both the RX
+    /// and TX overlays is never picked. Among several transmitters, the one `auto` would choose
+    /// wins. `discover_lirc_devices` lists the devices and their features. Without the `cir` or
+    /// `lirc-raw` feature, the emulator is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Io` of kind `NotFound` if no device can transmit, naming why each one was
+    /// skipped.
+    pub fn discover() -> Result<Self> {
+        let pulse_transmitter = crate::device::open_discovered()?;
+        Ok(Self::from_transmitter(pulse_transmitter))
+    }
+
+    /// Returns a `BrickBeamBuilder` to configure optional transmission features such as mirroring.
+    pub fn builder() -> BrickBeamBuilder {
+        BrickBeamBuilder::new()
     }
 }
 
 impl<T: PulseTransmitter> BrickBeam<T> {
+    /// Creates a new `BrickBeam` instance on top of a custom `PulseTransmitter`.
+    ///
+    /// Use this to plug in your own transmitter or a wrapper such as `HotplugTransmitter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pulse_transmitter` - The transmitter used by all controllers of this instance.
+    pub fn from_transmitter(pulse_transmitter: T) -> Self {
+        Self {
+            pulse_transmitter,
+            repeat_policy: RepeatPolicy::spec(),
+            #[cfg(any(feature = "single-output", feature = "extended"))]
+            registry: ControllerRegistry::default(),
+        }
+    }
+
+    /// Sends the messages of the controllers created afterwards, and of `send_message`, as the
+    /// copies `policy` asks for. Single controllers can still override it.
+    ///
+    /// By default, every message is sent five times with the pauses of the PF spec
+    /// (`RepeatPolicy::spec()`), as the receivers expect. `RepeatPolicy::single()` trades that
+    /// reliability for latency, e.g. for rapid joystick updates.
+    pub fn with_repeat_policy(mut self, policy: RepeatPolicy) -> Self {
+        self.repeat_policy = policy;
+        self
+    }
+
+    /// Returns the repeat policy given to new controllers.
+    pub fn repeat_policy(&self) -> RepeatPolicy {
+        self.repeat_policy
+    }
+
     /// Creates a Speed Remote Controller using the Single Output protocol.
     ///
     /// # Arguments
     ///
-    /// * `channel` - The channel (1 to 4) to be used for the controller.
+    /// * `channel` - The channel (1 to 4), or logical channel (1 to 8), to be used for the controller.
     /// * `output` - The output (Red, Blue) to be used for the controller.
     ///
     /// # Returns
     ///
     /// * `Result<SpeedRemoteController<T>>` - A result containing the new `SpeedRemoteController` instance or an error.
+    #[cfg(feature = "single-output")]
     pub fn create_speed_remote_controller(
         &self,
-        channel: Channel,
+        channel: impl Into<LogicalChannel>,
+        output: Output,
+    ) -> Result<SpeedRemoteController<'_, T>> {
+        let channel = channel.into();
+        Ok(
+            SpeedRemoteController::new(&self.pulse_transmitter, channel.channel(), output)?
+                .with_address(channel.address())
+                .with_repeat_policy(self.repeat_policy),
+        )
+    }
+
+    /// Returns the Speed Remote Controller of this instance for a channel and output.
+    ///
+    /// Unlike `create_speed_remote_controller`, every controller returned for the same channel
+    /// and output shares one toggle bit, owned by this instance and created on the first call.
+    /// Obtaining the controller again wherever it is needed therefore can't desynchronize the
+    /// receiver, which ignores a message repeating the toggle bit of the previous one.
+    /// Numerical PWM tracking remains per controller.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel` - The channel (1 to 4), or logical channel (1 to 8), to be used for the controller.
+    /// * `output` - The output (Red, Blue) to be used for the controller.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SpeedRemoteController<T>>` - A result containing the shared `SpeedRemoteController` or an error.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{BrickBeam, Channel, Output, SingleOutputCommand, Result};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+    ///     brick_beam.speed(Channel::One, Output::RED)?.send(SingleOutputCommand::PWM(3))?;
+    ///     // Flips the toggle bit again, so the receiver accepts the second command.
+    ///     brick_beam.speed(Channel::One, Output::RED)?.send(SingleOutputCommand::PWM(0))?;
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "single-output")]
+    pub fn speed(
+        &self,
+        channel: impl Into<LogicalChannel>,
         output: Output,
-    ) -> Result<SpeedRemoteController<T>> {
-        SpeedRemoteController::new(&self.pulse_transmitter, channel, output)
+    ) -> Result<SpeedRemoteController<'_, T>> {
+        Ok(self
+            .create_speed_remote_controller(channel, output)?
+            .with_registry(&self.registry))
     }
 
     /// Creates a Combo Speed Remote Controller using the Combo PWM protocol.
     ///
     /// # Arguments
     ///
-    /// * `channel` - The channel (1 to 4) to be used for the controller.
+    /// * `channel` - The channel (1 to 4), or logical channel (1 to 8), to be used for the controller.
     ///
     /// # Returns
     ///
     /// * `Result<ComboSpeedRemoteController<T>>` - A result containing the new `ComboSpeedRemoteController` instance or an error.
+    #[cfg(feature = "combo-pwm")]
     pub fn create_combo_speed_remote_controller(
         &self,
-        channel: Channel,
-    ) -> Result<ComboSpeedRemoteController<T>> {
-        ComboSpeedRemoteController::new(&self.pulse_transmitter, channel)
+        channel: impl Into<LogicalChannel>,
+    ) -> Result<ComboSpeedRemoteController<'_, T>> {
+        let channel = channel.into();
+        Ok(
+            ComboSpeedRemoteController::new(&self.pulse_transmitter, channel.channel())?
+                .with_address(channel.address())
+                .with_repeat_policy(self.repeat_policy),
+        )
     }
 
     /// Creates a Direct Remote Controller using the Combo Direct protocol.
     ///
     /// # Arguments
     ///
-    /// * `channel` - The channel (1 to 4) to be used for the controller.
+    /// * `channel` - The channel (1 to 4), or logical channel (1 to 8), to be used for the controller.
     ///
     /// # Returns
     ///
     /// * `Result<DirectRemoteController<T>>` - A result containing the new `DirectRemoteController` instance or an error.
+    #[cfg(feature = "combo-direct")]
     pub fn create_direct_remote_controller(
         &self,
-        channel: Channel,
-    ) -> Result<DirectRemoteController<T>> {
-        DirectRemoteController::new(&self.pulse_transmitter, channel)
+        channel: impl Into<LogicalChannel>,
+    ) -> Result<DirectRemoteController<'_, T>> {
+        let channel = channel.into();
+        Ok(
+            DirectRemoteController::new(&self.pulse_transmitter, channel.channel())?
+                .with_address(channel.address())
+                .with_repeat_policy(self.repeat_policy),
+        )
     }
 
     /// Creates an Extended Remote Controller.
     ///
     /// # Arguments
     ///
-    /// * `channel` - The channel (1 to 4) to be used for the controller.
+    /// * `channel` - The channel (1 to 4), or logical channel (1 to 8), to be used for the controller.
     ///
     /// # Returns
     ///
     /// * `Result<ExtendedRemoteController<T>>` - A result containing the new `ExtendedRemoteController` instance or an error.
+    #[cfg(feature = "extended")]
     pub fn create_extended_remote_controller(
         &self,
-        channel: Channel,
-    ) -> Result<ExtendedRemoteController<T>> {
-        ExtendedRemoteController::new(&self.pulse_transmitter, channel)
+        channel: impl Into<LogicalChannel>,
+    ) -> Result<ExtendedRemoteController<'_, T>> {
+        let channel = channel.into();
+        Ok(
+            ExtendedRemoteController::new(&self.pulse_transmitter, channel.channel())?
+                .with_address(channel.address())
+                .with_repeat_policy(self.repeat_policy),
+        )
+    }
+
+    /// Returns the Extended Remote Controller of this instance for a channel.
+    ///
+    /// Every controller returned for the same channel shares one toggle bit and address, owned
+    /// by this instance and created on the first call (see `speed`). Speed tracking remains per
+    /// controller.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel` - The channel (1 to 4), or logical channel (1 to 8), to be used for the controller.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ExtendedRemoteController<T>>` - A result containing the shared `ExtendedRemoteController` or an error.
+    #[cfg(feature = "extended")]
+    pub fn extended(
+        &self,
+        channel: impl Into<LogicalChannel>,
+    ) -> Result<ExtendedRemoteController<'_, T>> {
+        Ok(self
+            .create_extended_remote_controller(channel)?
+            .with_registry(&self.registry))
+    }
+
+    /// Creates a new RCX Remote Controller for LEGO® Mindstorms RCX bricks in range.
+    ///
+    /// # Returns
+    ///
+    /// * `RcxRemoteController<T>` - The new `RcxRemoteController` instance.
+    #[cfg(feature = "rcx")]
+    pub fn create_rcx_remote_controller(&self) -> RcxRemoteController<'_, T> {
+        RcxRemoteController::new(&self.pulse_transmitter)
+    }
+
+    /// Creates a new Generic Remote Controller for NEC and RC-5 devices, such as IR-controlled
+    /// lights or cameras.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<GenericRemoteController<T>>` - A result containing the new `GenericRemoteController` instance or an error.
+    #[cfg(feature = "generic")]
+    pub fn create_generic_remote_controller(&self) -> Result<GenericRemoteController<'_, T>> {
+        GenericRemoteController::new(&self.pulse_transmitter)
+    }
+
+    /// Creates a `Broadcast` helper that sends the same command on all four channels,
+    /// leaving a full message slot between channels.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Broadcast<T>>` - A result containing the new `Broadcast` instance or an error.
+    #[cfg(all(
+        feature = "single-output",
+        feature = "combo-direct",
+        feature = "combo-pwm",
+        feature = "extended"
+    ))]
+    pub fn broadcast(&self) -> Result<Broadcast<'_, T>> {
+        Broadcast::new(&self.pulse_transmitter)
+    }
+
+    /// Creates a `SequencePlayer` that plays the given sequence on this instance's transmitter.
+    ///
+    /// The player sends with the repeat policy of this instance and shares the toggle bits of
+    /// `speed` and `extended`, so sequences and controllers can take turns on one receiver.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SequencePlayer<T>>` - A result containing the new `SequencePlayer` instance or an error.
+    #[cfg(all(
+        feature = "single-output",
+        feature = "combo-direct",
+        feature = "combo-pwm",
+        feature = "extended"
+    ))]
+    pub fn create_sequence_player(
+        &self,
+        sequence: impl Into<Sequence>,
+    ) -> Result<SequencePlayer<'_, T>> {
+        Ok(SequencePlayer::new(&self.pulse_transmitter, sequence)?
+            .with_repeat_policy(self.repeat_policy)
+            .with_registry(&self.registry))
+    }
+
+    /// Sends a message assembled from its bit-level fields, bypassing the controllers.
+    ///
+    /// No toggle bit or address is tracked: the message is sent exactly as given, with the
+    /// standard timing, a computed LRC and the repeat policy of this instance. Returns the
+    /// airtime of the transmitted message.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if a field doesn't fit the protocol, or the error of the
+    /// transmitter.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{BrickBeam, Channel, ProtocolKind, RawMessageFields, Result};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+    ///     // Combo PWM on channel 1: red forward 5, blue backward 3.
+    ///     let fields = RawMessageFields {
+    ///         toggle: false,
+    ///         channel: Channel::One,
+    ///         address: false,
+    ///         mode: 0xD,
+    ///         data: 5,
+    ///     };
+    ///     brick_beam.send_message(ProtocolKind::ComboPwm, fields)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn send_message(&self, kind: ProtocolKind, fields: RawMessageFields) -> Result<Duration> {
+        let pulses = PulseTiming::STANDARD.encode_frame(fields.frame(kind)?);
+        transmit(
+            &self.pulse_transmitter,
+            fields.channel,
+            &pulses,
+            self.repeat_policy,
+        )
+    }
+
+    /// Returns the transmitter used by all controllers of this instance, e.g. to subscribe to
+    /// the events of an `EventTransmitter`.
+    pub fn transmitter(&self) -> &T {
+        &self.pulse_transmitter
+    }
+
+    /// Blocks until all pulses sent so far by any controller of this instance are on air.
+    ///
+    /// Useful as a barrier in sequencing code, e.g. before switching to another channel.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<()>` - A result indicating success or failure.
+    pub fn flush(&self) -> Result<()> {
+        self.pulse_transmitter.flush()
     }
 }
 
-#[cfg(test)]
+#[cfg(all(
+    test,
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
 mod tests {
-    use crate::{Channel, Error, Output, PulseTransmitter, SingleOutputCommand};
+    use crate::{
+        Channel, ComboPwmCommand, DirectState, Error, ExtendedCommand, LogicalChannel, Output,
+        ProtocolKind, PulseTransmitter, RawMessageFields, Sequence, SequenceAction, SequenceStep,
+        SingleOutputCommand,
+    };
+    use std::time::Duration;
 
-    use super::BrickBeam;
+    use super::{BrickBeam, RepeatPolicy};
+    use crate::device::MockTransmitterRecorder;
 
     #[test]
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
     fn test_brick_beam_factory() {
-        // On a non-Linux system or with no cir feature, this just uses the emulator.
+        // Without a LIRC backend, this just uses the emulator.
         let beam = BrickBeam::new("/dev/lirc0").unwrap();
         beam.create_speed_remote_controller(Channel::One, Output::RED)
             .unwrap();
@@ -170,9 +498,7 @@ mod tests {
 
     #[test]
     fn test_send_fails() {
-        let beam = BrickBeam {
-            pulse_transmitter: FailingTransmitter,
-        };
+        let beam = BrickBeam::from_transmitter(FailingTransmitter);
         let mut motor = beam
             .create_speed_remote_controller(Channel::One, Output::RED)
             .unwrap();
@@ -183,4 +509,209 @@ mod tests {
             _ => panic!("Expected Transmitting error"),
         }
     }
+
+    #[test]
+    #[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+    fn test_auto_uses_emulator_without_a_lirc_backend() {
+        let beam = BrickBeam::auto().unwrap();
+        beam.create_speed_remote_controller(Channel::One, Output::RED)
+            .unwrap();
+    }
+
+    /// Returns a beam sending every message once, so each message is one entry of `sent`.
+    fn recording_beam() -> BrickBeam<MockTransmitterRecorder> {
+        BrickBeam::from_transmitter(MockTransmitterRecorder::default())
+            .with_repeat_policy(RepeatPolicy::single())
+    }
+
+    /// Returns the toggle bit of every message sent so far, read from the space of its first bit.
+    fn toggle_bits(beam: &BrickBeam<MockTransmitterRecorder>) -> Vec<bool> {
+        let sent = beam.pulse_transmitter.sent();
+        sent.iter().map(|pulses| pulses[3] > 400).collect()
+    }
+
+    #[test]
+    fn test_registry_shares_toggle_per_receiver() {
+        let beam = recording_beam();
+        let pwm = SingleOutputCommand::PWM(3);
+        beam.speed(Channel::One, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        beam.speed(Channel::One, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        beam.speed(Channel::One, Output::BLUE)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        beam.create_speed_remote_controller(Channel::One, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        beam.speed(Channel::One, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        assert_eq!(toggle_bits(&beam), [false, true, false, false, false]);
+    }
+
+    #[test]
+    fn test_registry_shares_extended_address() {
+        let beam = recording_beam();
+        beam.extended(Channel::Two)
+            .unwrap()
+            .send(ExtendedCommand::ToggleAddress)
+            .unwrap();
+        beam.extended(Channel::Two)
+            .unwrap()
+            .send(ExtendedCommand::IncrementSpeedOnRedOutput)
+            .unwrap();
+        assert_eq!(toggle_bits(&beam), [false, true]);
+        let sent = beam.pulse_transmitter.sent();
+        // The address follows the toggle, escape and two channel bits.
+        assert!(sent[0][11] < 400);
+        assert!(sent[1][11] > 400);
+    }
+
+    #[test]
+    fn test_logical_channels_address_the_extra_receivers() {
+        let beam = recording_beam();
+        let pwm = SingleOutputCommand::PWM(3);
+        beam.speed(Channel::One, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        let mut motor = beam.speed(LogicalChannel::Five, Output::RED).unwrap();
+        assert_eq!(motor.channel(), Channel::One);
+        assert_eq!(motor.logical_channel(), LogicalChannel::Five);
+        motor.send(pwm).unwrap();
+        let mut lights = beam.extended(LogicalChannel::Eight).unwrap();
+        assert_eq!(lights.logical_channel(), LogicalChannel::Eight);
+        lights
+            .send(ExtendedCommand::IncrementSpeedOnRedOutput)
+            .unwrap();
+        beam.create_direct_remote_controller(LogicalChannel::Six)
+            .unwrap()
+            .send((DirectState::Forward, DirectState::Float).into())
+            .unwrap();
+        beam.create_combo_speed_remote_controller(LogicalChannel::Seven)
+            .unwrap()
+            .send(ComboPwmCommand::stopped())
+            .unwrap();
+
+        // Channel One and logical channel Five are separate receivers, each with its own toggle.
+        assert_eq!(toggle_bits(&beam)[..2], [false, false]);
+        let sent = beam.pulse_transmitter.sent();
+        assert!(sent[0][11] < 400);
+        for pulses in &sent[1..4] {
+            assert!(pulses[11] > 400);
+        }
+        // Combo PWM messages carry the address in their first bit.
+        assert!(sent[4][3] > 400);
+    }
+
+    #[test]
+    fn test_spec_repeats_by_default() {
+        let beam = BrickBeam::from_transmitter(MockTransmitterRecorder::default());
+        let airtime = beam
+            .speed(Channel::One, Output::RED)
+            .unwrap()
+            .send(SingleOutputCommand::PWM(3))
+            .unwrap();
+        let sent = beam.pulse_transmitter.sent();
+        assert_eq!(sent.len(), 5);
+        assert!(sent.iter().all(|pulses| pulses == &sent[0]));
+        // Channel One waits 4 slots of 16 ms before the first copy, then 4, 5, 5 and 6.
+        assert_eq!(
+            airtime,
+            Duration::from_millis(24 * 16) + crate::duration_of(&sent[4])
+        );
+    }
+
+    #[test]
+    fn test_repeat_policy_applies_to_new_controllers() {
+        let beam = BrickBeam::from_transmitter(MockTransmitterRecorder::default())
+            .with_repeat_policy(RepeatPolicy::fixed(3, Duration::ZERO));
+        let pwm = SingleOutputCommand::PWM(3);
+        beam.speed(Channel::One, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        beam.speed(Channel::One, Output::RED)
+            .unwrap()
+            .with_repeat_policy(RepeatPolicy::single())
+            .send(pwm)
+            .unwrap();
+        assert_eq!(toggle_bits(&beam), [false, false, false, true]);
+    }
+
+    #[test]
+    fn test_sequence_player_shares_registry_toggle() {
+        let beam = recording_beam();
+        let pwm = SingleOutputCommand::PWM(3);
+        beam.speed(Channel::One, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        let step = SequenceStep {
+            channel: Channel::One,
+            action: SequenceAction::SingleOutput(Output::RED, pwm),
+            wait: Duration::ZERO,
+        };
+        beam.create_sequence_player(Sequence::new())
+            .unwrap()
+            .play_step(step)
+            .unwrap();
+        beam.speed(Channel::One, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        assert_eq!(toggle_bits(&beam), [false, true, false]);
+    }
+
+    #[test]
+    fn test_set_channel_migrates_shared_toggle() {
+        let beam = recording_beam();
+        let pwm = SingleOutputCommand::PWM(3);
+        beam.speed(Channel::Two, Output::RED)
+            .unwrap()
+            .send(pwm)
+            .unwrap();
+        let mut motor = beam.speed(Channel::One, Output::RED).unwrap();
+        motor.set_channel(Channel::Two);
+        motor.send(pwm).unwrap();
+        motor.set_channel(Channel::One);
+        motor.send(pwm).unwrap();
+        assert_eq!(toggle_bits(&beam), [false, true, false]);
+    }
+
+    #[test]
+    fn test_send_message_matches_controller() {
+        let beam = recording_beam();
+        let fields = RawMessageFields {
+            toggle: false,
+            channel: Channel::Three,
+            address: false,
+            mode: 0b100,
+            data: 3,
+        };
+        let airtime = beam
+            .send_message(ProtocolKind::SingleOutput, fields)
+            .unwrap();
+        beam.create_speed_remote_controller(Channel::Three, Output::RED)
+            .unwrap()
+            .send(SingleOutputCommand::PWM(3))
+            .unwrap();
+        let sent = beam.pulse_transmitter.sent();
+        assert_eq!(sent[0], sent[1]);
+        assert_eq!(airtime, crate::duration_of(&sent[0]));
+    }
+
+    #[test]
+    fn test_flush_defaults_to_ok() {
+        let beam = BrickBeam::from_transmitter(crate::PulseTransmitterEmulator);
+        assert!(beam.flush().is_ok());
+    }
 }
@@ -0,0 +1,123 @@
+use crate::controller::carrier::{transmit_with_carrier, PF_CARRIER};
+use crate::device::PulseTransmitter;
+use crate::protocols::{duration_of, CustomIrpProtocol, GenericCode, GenericProtocol};
+use crate::Result;
+use std::time::Duration;
+
+/// # GenericRemoteController
+///
+/// The Generic Remote Controller sends NEC and RC-5 codes, so a layout can also trigger
+/// IR-controlled lights, cameras or other devices from the process that drives its trains.
+///
+/// # Carrier
+///
+/// `send` switches the transmitter to the carrier of the code (38 kHz for NEC, 36 kHz for RC-5,
+/// both at 33%) for each message and back to the PF carrier afterwards (38 kHz at 33%, or as set
+/// with `with_pf_carrier`), so PF controllers on the same transmitter keep working.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, GenericCode, Result};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     let mut lights = brick_beam.create_generic_remote_controller()?;
+///     lights.send(GenericCode::Nec { address: 0x00, command: 0x45 })?;
+///     lights.send(GenericCode::Rc5 { address: 5, command: 12 })?;
+///     Ok(())
+/// }
+/// ```
+///
+/// # Thread Safety
+///
+/// The controller keeps the RC-5 toggle bit, so `send` requires a mutable reference. Wrap the
+/// instance in a `Mutex` to share it across threads.
+///
+/// # Errors
+///
+/// This controller's methods will return an error if a code is out of range, or if the pulse
+/// transmitter fails to change the carrier or to send pulses.
+pub struct GenericRemoteController<'a, T: PulseTransmitter> {
+    pulse_transmitter: &'a T,
+    protocol: GenericProtocol,
+    pf_carrier: (u32, u32),
+}
+
+impl<'a, T: PulseTransmitter> GenericRemoteController<'a, T> {
+    pub fn new(pulse_transmitter: &'a T) -> Result<Self> {
+        Ok(Self {
+            pulse_transmitter,
+            protocol: GenericProtocol::new()?,
+            pf_carrier: PF_CARRIER,
+        })
+    }
+
+    /// Sets the carrier restored after every message, e.g. the one of a `TransmissionProfile`.
+    pub fn with_pf_carrier(mut self, carrier: u32, duty_cycle: u32) -> Self {
+        self.pf_carrier = (carrier, duty_cycle);
+        self
+    }
+
+    /// Sends a code and returns the airtime of the transmitted message.
+    pub fn send(&mut self, code: GenericCode) -> Result<Duration> {
+        let pulses = self.protocol.encode(code)?;
+        self.transmit(&pulses, (code.carrier(), 33))
+    }
+
+    /// Sends a message of a user-supplied IRP, modulated as the IRP specifies (33% duty cycle
+    /// unless given), and returns the airtime of the transmitted message.
+    pub fn send_custom(&mut self, protocol: &CustomIrpProtocol) -> Result<Duration> {
+        let pulses = protocol.encode()?;
+        let carrier = (
+            protocol.carrier(),
+            protocol.duty_cycle().map_or(33, u32::from),
+        );
+        self.transmit(&pulses, carrier)
+    }
+
+    fn transmit(&self, pulses: &[u32], carrier: (u32, u32)) -> Result<Duration> {
+        transmit_with_carrier(self.pulse_transmitter, pulses, carrier, self.pf_carrier)?;
+        Ok(duration_of(pulses))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+
+    #[test]
+    fn test_generic_send_uses_carrier_of_code() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = GenericRemoteController::new(&transmitter).unwrap();
+        let airtime = controller
+            .send(GenericCode::Rc5 {
+                address: 0,
+                command: 1,
+            })
+            .unwrap();
+        assert_eq!(airtime, Duration::from_millis(114));
+        controller
+            .send(GenericCode::Nec {
+                address: 0,
+                command: 1,
+            })
+            .unwrap();
+        let custom =
+            CustomIrpProtocol::new("{40k,600}<1,-1|2,-1>(4,-1,F:7,D:5,^45m)[D:0..31,F:0..127]")
+                .unwrap()
+                .with_var("D", 1)
+                .with_var("F", 21);
+        assert_eq!(
+            controller.send_custom(&custom).unwrap(),
+            Duration::from_millis(45)
+        );
+        let carriers: Vec<u32> = transmitter
+            .carriers()
+            .iter()
+            .map(|&(carrier, _)| carrier)
+            .collect();
+        assert_eq!(carriers, [36_000, 38_000, 38_000, 38_000, 40_000, 38_000]);
+        assert!(transmitter.sent().iter().all(|pulses| !pulses.is_empty()));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::clock;
+use crate::device::PulseTransmitter;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::Scope;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct State {
+    // The message to refresh, if any, and when it is due on the transmitter's clock.
+    pulses: Option<(Vec<u32>, Instant)>,
+    stopped: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    interval: Duration,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Retransmits the last message of a controller on a scoped thread whenever it has been
+/// quiet for an interval.
+///
+/// The interval is measured on the clock of the transmitter, so a `MockClock` drives the
+/// refreshes in tests. The thread exits once the `Keepalive` is dropped.
+pub(crate) struct Keepalive {
+    shared: Arc<Shared>,
+}
+
+impl Keepalive {
+    /// Starts the refresher thread in `scope`, sending on `transmitter`.
+    pub(crate) fn start<'scope, T>(
+        scope: &'scope Scope<'scope, '_>,
+        transmitter: &'scope T,
+        interval: Duration,
+    ) -> Self
+    where
+        T: PulseTransmitter + Sync,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::default(),
+            changed: Condvar::new(),
+            interval,
+        });
+        let refresher = Arc::clone(&shared);
+        scope.spawn(move || run(&refresher, transmitter));
+        Self { shared }
+    }
+
+    /// Makes `pulses`, sent at `now`, the message to refresh one interval later.
+    pub(crate) fn update(&self, pulses: &[u32], now: Instant) {
+        self.replace(Some((pulses.to_vec(), now + self.shared.interval)));
+    }
+
+    /// Stops refreshing until the next `update`.
+    #[cfg_attr(not(feature = "single-output"), allow(dead_code))]
+    pub(crate) fn pause(&self) {
+        self.replace(None);
+    }
+
+    fn replace(&self, pulses: Option<(Vec<u32>, Instant)>) {
+        self.shared.lock().pulses = pulses;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl Drop for Keepalive {
+    fn drop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+fn run(shared: &Shared, transmitter: &impl PulseTransmitter) {
+    let clock = transmitter.clock();
+    let mut state = shared.lock();
+    loop {
+        if state.stopped {
+            return;
+        }
+        let Some((pulses, due)) = &mut state.pulses else {
+            state = shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+            continue;
+        };
+        let now = clock.now();
+        if now < *due {
+            let due = *due;
+            state = clock::wait_until(clock, &shared.changed, state, due);
+            continue;
+        }
+        *due = now + shared.interval;
+        let pulses = pulses.clone();
+        drop(state);
+        // A failed refresh is retried after the next interval, like a lost message.
+        let _ = transmitter.send_pulses(&pulses);
+        state = shared.lock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::device::MockTransmitterRecorder;
+    use std::thread;
+
+    #[test]
+    fn test_refreshes_latest_message_until_paused() {
+        let transmitter = MockTransmitterRecorder::default();
+        let clock = &transmitter.clock;
+        let interval = Duration::from_millis(500);
+        thread::scope(|scope| {
+            let keepalive = Keepalive::start(scope, &transmitter, interval);
+            clock.advance(5 * interval);
+
+            keepalive.update(&[1, 2], clock.now());
+            keepalive.update(&[3, 4], clock.now());
+            clock.advance(interval);
+            transmitter.wait_until_sent(1);
+            clock.advance(interval);
+            transmitter.wait_until_sent(2);
+
+            // Nothing is refreshed while paused, so the next message is the updated one.
+            keepalive.pause();
+            clock.advance(5 * interval);
+            keepalive.update(&[5, 6], clock.now());
+            clock.advance(interval);
+            transmitter.wait_until_sent(3);
+            drop(keepalive);
+        });
+        assert_eq!(transmitter.sent(), [vec![3, 4], vec![3, 4], vec![5, 6]]);
+    }
+}
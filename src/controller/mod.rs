@@ -9,21 +9,85 @@
 //! - `combo_speed` for Combo PWM protocol (two outputs, PWM),
 //! - `extended` for the Extended protocol (toggle bits, brake, etc.),
 //! - `speed` for the Single Output protocol (commonly called “Speed Remote”),
-//! - `factory` for the core `BrickBeam` struct that instantiates controllers.
+//! - `rcx` for Mindstorms RCX bricks (behind the `rcx` feature),
+//! - `generic` for NEC and RC-5 devices such as lights or cameras (behind the `generic` feature),
+//! - `train` for driving a train with smooth acceleration and deceleration ramps,
+//! - `tank` for driving a two-motor tracked or skid-steer vehicle from throttle and steering,
+//! - `broadcast` for sending the same command on all four channels,
+//! - `factory` for the core `BrickBeam` struct that instantiates controllers,
+//! - `carrier` for sending non-PF messages on their own carrier (behind `rcx` or `generic`),
+//! - `builder` for configuring a `BrickBeam` with optional transmission features,
+//! - `keepalive` for refreshing the last command of a speed controller in the background,
+//! - `profile` for environment- and hardware-tuned transmission settings used by the builder,
+//! - `registry` for the toggle states `BrickBeam` shares between the controllers it hands out,
+//! - `repetition` for the `RepeatPolicy` deciding how often each message is sent.
+//!
+//! Each controller is only compiled with the cargo feature of its protocol; `broadcast` needs
+//! all four.
 //!
 //! **Thread Safety**:
 //!   All the controllers produce IR signals in a “send” method that requires `&mut self`.
 //!   This design ensures no concurrent “send” from multiple threads. If multi-threaded
 //!   access is needed, wrap your controller instance in a Mutex.
 //!
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+mod broadcast;
+mod builder;
+#[cfg(any(feature = "rcx", feature = "generic"))]
+mod carrier;
+#[cfg(feature = "combo-direct")]
 mod combo_direct;
+#[cfg(feature = "combo-pwm")]
 mod combo_speed;
+#[cfg(feature = "extended")]
 mod extended;
 mod factory;
+#[cfg(feature = "generic")]
+mod generic;
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+mod keepalive;
+mod profile;
+#[cfg(feature = "rcx")]
+mod rcx;
+#[cfg(any(feature = "single-output", feature = "extended"))]
+pub(crate) mod registry;
+pub(crate) mod repetition;
+#[cfg(feature = "single-output")]
 mod speed;
+#[cfg(feature = "combo-pwm")]
+mod tank;
+#[cfg(feature = "single-output")]
+mod train;
 
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+pub use broadcast::Broadcast;
+pub use builder::BrickBeamBuilder;
+#[cfg(feature = "combo-direct")]
 pub use combo_direct::DirectRemoteController;
+#[cfg(feature = "combo-pwm")]
 pub use combo_speed::ComboSpeedRemoteController;
+#[cfg(feature = "extended")]
 pub use extended::ExtendedRemoteController;
 pub use factory::BrickBeam;
+#[cfg(feature = "generic")]
+pub use generic::GenericRemoteController;
+pub use profile::{HardwarePreset, TransmissionProfile};
+#[cfg(feature = "rcx")]
+pub use rcx::RcxRemoteController;
+pub use repetition::{RepeatPolicy, RepeatStrategy};
+#[cfg(feature = "single-output")]
 pub use speed::SpeedRemoteController;
+#[cfg(feature = "combo-pwm")]
+pub use tank::TankDrive;
+#[cfg(feature = "single-output")]
+pub use train::{RampProfile, TrainController};
@@ -0,0 +1,261 @@
+use crate::controller::RepeatPolicy;
+use crate::protocols::timing::{CARRIER_HZ, DUTY_CYCLE, MAX_MESSAGE_DURATION};
+use crate::{Error, Result};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Transmission settings tuned for an environment, selectable with `BrickBeamBuilder::profile`.
+///
+/// A profile bundles how often every message is repeated, how far apart the copies are and how
+/// the IR bursts are modulated. The named presets cover common situations; any field can be
+/// adjusted for a custom profile.
+///
+/// | Name            | Repeats | Gap   | Carrier | Duty cycle |
+/// |-----------------|---------|-------|---------|------------|
+/// | `close-range`   | 1       | 16 ms | 38 kHz  | 33%        |
+/// | `large-hall`    | 5       | 32 ms | 38 kHz  | 33%        |
+/// | `sunlit-room`   | 5       | 16 ms | 38 kHz  | 50%        |
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, Result, TransmissionProfile};
+/// use std::time::Duration;
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::builder()
+///         .device("/dev/lirc0")
+///         .profile(TransmissionProfile::large_hall().with_repeats(3))
+///         .build()?;
+///     let named: TransmissionProfile = "sunlit-room".parse()?;
+///     assert_eq!(named, TransmissionProfile::sunlit_room());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransmissionProfile {
+    /// How many copies of every message are sent.
+    pub repeats: u32,
+    /// The minimum start-to-start time between two copies.
+    pub repeat_gap: Duration,
+    /// The carrier frequency in Hz.
+    pub carrier: u32,
+    /// The duty cycle of the carrier in percent.
+    pub duty_cycle: u32,
+}
+
+impl TransmissionProfile {
+    /// The names accepted by `FromStr`.
+    pub const NAMES: [&'static str; 3] = ["close-range", "large-hall", "sunlit-room"];
+
+    /// A single copy of every message: lowest latency, for receivers within a few meters.
+    pub const fn close_range() -> Self {
+        Self {
+            repeats: 1,
+            repeat_gap: MAX_MESSAGE_DURATION,
+            carrier: CARRIER_HZ,
+            duty_cycle: DUTY_CYCLE,
+        }
+    }
+
+    /// Five widely spaced copies, so reflections of one copy have died down before the next.
+    pub const fn large_hall() -> Self {
+        Self {
+            repeats: 5,
+            repeat_gap: Duration::from_millis(32),
+            ..Self::close_range()
+        }
+    }
+
+    /// Five copies with longer bursts, which stand out better against the infrared in sunlight.
+    pub const fn sunlit_room() -> Self {
+        Self {
+            repeats: 5,
+            duty_cycle: 50,
+            ..Self::close_range()
+        }
+    }
+
+    /// Returns the profile with a different number of copies per message.
+    pub const fn with_repeats(mut self, repeats: u32) -> Self {
+        self.repeats = repeats;
+        self
+    }
+
+    /// Returns the profile with a different start-to-start time between copies.
+    pub const fn with_repeat_gap(mut self, repeat_gap: Duration) -> Self {
+        self.repeat_gap = repeat_gap;
+        self
+    }
+
+    /// Returns the profile with a different carrier frequency (Hz) and duty cycle (percent).
+    pub const fn with_carrier(mut self, carrier: u32, duty_cycle: u32) -> Self {
+        self.carrier = carrier;
+        self.duty_cycle = duty_cycle;
+        self
+    }
+
+    /// Returns the copies of the profile as the `RepeatPolicy` the builder sets on the
+    /// controllers.
+    pub const fn repeat_policy(self) -> RepeatPolicy {
+        RepeatPolicy::fixed(self.repeats, self.repeat_gap)
+    }
+}
+
+impl Default for TransmissionProfile {
+    fn default() -> Self {
+        Self::close_range()
+    }
+}
+
+impl FromStr for TransmissionProfile {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "close-range" => Ok(Self::close_range()),
+            "large-hall" => Ok(Self::large_hall()),
+            "sunlit-room" => Ok(Self::sunlit_room()),
+            other => Err(Error::ProtocolError(format!(
+                "Unknown transmission profile '{}', expected one of {}",
+                other,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// Transmission settings suited to a kind of transmitter hardware, selectable with
+/// `BrickBeamBuilder::hardware`.
+///
+/// | Preset      | Drivers                       | Repeats | Gap   | Carrier | Duty cycle      |
+/// |-------------|-------------------------------|---------|-------|---------|-----------------|
+/// | `GpioIrTx`  | `gpio-ir-tx`                  | 2       | 16 ms | 38 kHz  | 33%             |
+/// | `PwmIrTx`   | `pwm-ir-tx`                   | 1       | 16 ms | 38 kHz  | 33%             |
+/// | `UsbBlaster`| `mceusb`, `iguanair`, `irtoy` | 2       | 32 ms | 38 kHz  | fixed by device |
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, HardwarePreset, Result};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::builder()
+///         .device("/dev/lirc0")
+///         .hardware(HardwarePreset::PwmIrTx)
+///         .build()?;
+///     assert_eq!(HardwarePreset::for_driver("gpio-ir-tx"), Some(HardwarePreset::GpioIrTx));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HardwarePreset {
+    /// An IR LED on a GPIO pin, driven by the `gpio-ir-tx` overlay (e.g. on a Raspberry Pi).
+    ///
+    /// The kernel bit-bangs the carrier with interrupts disabled, so it honours carrier and duty
+    /// cycle, but a busy system can stretch single pulses. A second copy of every message costs
+    /// little and covers the occasional corrupted one. Every copy occupies a CPU core for its
+    /// airtime, which is why there are no more.
+    GpioIrTx,
+    /// An IR LED on a hardware PWM pin, driven by the `pwm-ir-tx` overlay.
+    ///
+    /// The PWM peripheral generates an exact carrier without CPU load, so one copy suffices.
+    /// Only some pins of a board can output PWM, and the audio output may share the PWM block.
+    PwmIrTx,
+    /// A USB IR transceiver, such as an MCE-compatible dongle, an IguanaWorks or an IR Toy.
+    ///
+    /// These set the carrier, but the duty cycle is fixed by the device and the request is
+    /// ignored. USB scheduling delays consecutive copies unpredictably, so they are spaced
+    /// further apart to be sure they never overlap.
+    UsbBlaster,
+}
+
+impl HardwarePreset {
+    /// The names accepted by `FromStr`.
+    pub const NAMES: [&'static str; 3] = ["gpio-ir-tx", "pwm-ir-tx", "usb"];
+
+    /// Returns the preset for a kernel driver name as found in `RcDevice::driver`, if known.
+    pub fn for_driver(driver: &str) -> Option<Self> {
+        match driver {
+            "gpio-ir-tx" => Some(Self::GpioIrTx),
+            "pwm-ir-tx" => Some(Self::PwmIrTx),
+            "mceusb" | "iguanair" | "irtoy" | "redrat3" | "ttusbir" => Some(Self::UsbBlaster),
+            _ => None,
+        }
+    }
+
+    /// Returns the transmission settings of the preset.
+    pub const fn profile(self) -> TransmissionProfile {
+        match self {
+            Self::GpioIrTx => TransmissionProfile::close_range().with_repeats(2),
+            Self::PwmIrTx => TransmissionProfile::close_range(),
+            Self::UsbBlaster => TransmissionProfile::close_range()
+                .with_repeats(2)
+                .with_repeat_gap(Duration::from_millis(32)),
+        }
+    }
+}
+
+impl FromStr for HardwarePreset {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "gpio-ir-tx" => Ok(Self::GpioIrTx),
+            "pwm-ir-tx" => Ok(Self::PwmIrTx),
+            "usb" => Ok(Self::UsbBlaster),
+            other => Err(Error::ProtocolError(format!(
+                "Unknown hardware preset '{}', expected one of {}",
+                other,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_profiles() {
+        for name in TransmissionProfile::NAMES {
+            assert!(name.parse::<TransmissionProfile>().is_ok(), "{}", name);
+        }
+        assert_eq!(
+            " Large-Hall ".parse::<TransmissionProfile>().unwrap(),
+            TransmissionProfile::large_hall()
+        );
+        assert!("stadium".parse::<TransmissionProfile>().is_err());
+    }
+
+    #[test]
+    fn test_hardware_presets() {
+        for name in HardwarePreset::NAMES {
+            assert!(name.parse::<HardwarePreset>().is_ok(), "{}", name);
+        }
+        assert_eq!(
+            HardwarePreset::for_driver("mceusb"),
+            Some(HardwarePreset::UsbBlaster)
+        );
+        assert_eq!(HardwarePreset::for_driver("cx88xx"), None);
+        assert_eq!(HardwarePreset::GpioIrTx.profile().repeats, 2);
+        assert_eq!(
+            HardwarePreset::UsbBlaster.profile().repeat_gap,
+            Duration::from_millis(32)
+        );
+    }
+
+    #[test]
+    fn test_custom_profile() {
+        let profile = TransmissionProfile::close_range()
+            .with_repeats(3)
+            .with_repeat_gap(Duration::from_millis(20))
+            .with_carrier(36_000, 25);
+        assert_eq!(profile.repeats, 3);
+        assert_eq!(profile.repeat_gap, Duration::from_millis(20));
+        assert_eq!((profile.carrier, profile.duty_cycle), (36_000, 25));
+        assert_eq!(
+            profile.repeat_policy(),
+            RepeatPolicy::fixed(3, Duration::from_millis(20))
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::controller::carrier::{transmit_with_carrier, PF_CARRIER};
+use crate::device::PulseTransmitter;
+use crate::protocols::{duration_of, RcxCommand, RcxProtocol, RCX_CARRIER};
+use crate::Result;
+use std::time::Duration;
+
+/// # RcxRemoteController
+///
+/// The RCX Remote Controller sends direct commands to a LEGO® Mindstorms RCX brick, the way its
+/// IR tower does, so the same transmitter can drive PF receivers and older RCX bricks.
+///
+/// # Carrier
+///
+/// RCX messages are modulated at 76 kHz and 50% duty cycle, unless set otherwise with
+/// `with_carrier`. `send` switches the transmitter to that carrier for each message and back to
+/// the PF carrier afterwards (38 kHz at 33%, or as set with `with_pf_carrier`), so PF controllers
+/// on the same transmitter keep working. Transmitters that can't change the carrier send at
+/// their fixed one, which the RCX also accepts at 38 kHz.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, RcxCommand, RcxMotorState, RcxMotors, Result};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     let mut rcx = brick_beam.create_rcx_remote_controller();
+///     rcx.send(RcxCommand::SetMotorPower(RcxMotors::A | RcxMotors::C, 5))?;
+///     rcx.send(RcxCommand::SetMotorState(RcxMotors::A | RcxMotors::C, RcxMotorState::On))?;
+///     Ok(())
+/// }
+/// ```
+///
+/// # Thread Safety
+///
+/// The controller keeps the toggle bit of the opcodes, so `send` requires a mutable reference.
+/// Wrap the instance in a `Mutex` to share it across threads.
+///
+/// # Errors
+///
+/// This controller's methods will return an error if a command parameter is out of range, or if
+/// the pulse transmitter fails to change the carrier or to send pulses.
+pub struct RcxRemoteController<'a, T: PulseTransmitter> {
+    pulse_transmitter: &'a T,
+    protocol: RcxProtocol,
+    carrier: (u32, u32),
+    pf_carrier: (u32, u32),
+}
+
+impl<'a, T: PulseTransmitter> RcxRemoteController<'a, T> {
+    pub fn new(pulse_transmitter: &'a T) -> Self {
+        Self {
+            pulse_transmitter,
+            protocol: RcxProtocol::new(),
+            carrier: (RCX_CARRIER, 50),
+            pf_carrier: PF_CARRIER,
+        }
+    }
+
+    /// Sends subsequent messages at another bit rate, e.g. 4800 baud for an RCX 2.0 set up for it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` for a bit rate of 0.
+    pub fn with_baud(mut self, baud: u32) -> Result<Self> {
+        self.protocol.set_baud(baud)?;
+        Ok(self)
+    }
+
+    /// Returns the bit rate of subsequent messages.
+    pub fn baud(&self) -> u32 {
+        self.protocol.baud()
+    }
+
+    /// Modulates subsequent messages with the given carrier frequency in Hz and duty cycle in
+    /// percent, e.g. 38 kHz for the original RCX tower.
+    pub fn with_carrier(mut self, carrier: u32, duty_cycle: u32) -> Self {
+        self.carrier = (carrier, duty_cycle);
+        self
+    }
+
+    /// Sets the carrier restored after every message, e.g. the one of a `TransmissionProfile`.
+    pub fn with_pf_carrier(mut self, carrier: u32, duty_cycle: u32) -> Self {
+        self.pf_carrier = (carrier, duty_cycle);
+        self
+    }
+
+    /// Sends an RCX command and returns the airtime of the transmitted message.
+    pub fn send(&mut self, cmd: RcxCommand) -> Result<Duration> {
+        let pulses = self.protocol.encode_cmd(cmd)?;
+        self.transmit(&pulses)
+    }
+
+    /// Sends a message with any opcode and parameters, e.g. for commands `RcxCommand` lacks, and
+    /// returns the airtime of the transmitted message. Bit 3 of the opcode is the toggle bit.
+    pub fn send_opcode(&mut self, opcode: u8, params: &[u8]) -> Result<Duration> {
+        let pulses = self.protocol.encode_opcode(opcode, params)?;
+        self.transmit(&pulses)
+    }
+
+    fn transmit(&self, pulses: &[u32]) -> Result<Duration> {
+        transmit_with_carrier(
+            self.pulse_transmitter,
+            pulses,
+            self.carrier,
+            self.pf_carrier,
+        )?;
+        Ok(duration_of(pulses))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use crate::{Error, RcxMotors};
+    #[test]
+    fn test_rcx_send_switches_carrier_and_back() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = RcxRemoteController::new(&transmitter);
+        let airtime = controller.send(RcxCommand::Alive).unwrap();
+        // 7 frames of 11 bits at 2400 baud.
+        assert_eq!(airtime, Duration::from_micros(32_083));
+
+        assert_eq!(transmitter.carriers(), [(76_000, 50), (38_000, 33)]);
+        assert_eq!(transmitter.sent().len(), 1);
+    }
+
+    #[test]
+    fn test_rcx_send_restores_carrier_on_failure() {
+        let transmitter = MockTransmitterRecorder::new();
+        transmitter.set_failing(true);
+        let mut controller = RcxRemoteController::new(&transmitter).with_pf_carrier(38_000, 50);
+        assert!(matches!(
+            controller.send(RcxCommand::StopAllTasks),
+            Err(Error::Transmitting(_))
+        ));
+        assert!(matches!(
+            controller.send(RcxCommand::SetMotorPower(RcxMotors::A, 9)),
+            Err(Error::ProtocolError(_))
+        ));
+        assert_eq!(transmitter.carriers(), [(76_000, 50), (38_000, 50)]);
+    }
+}
@@ -0,0 +1,27 @@
+use crate::protocols::ToggleState;
+use crate::{LogicalChannel, Output};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The toggle states of the controllers handed out by `BrickBeam::speed` and
+/// `BrickBeam::extended`, created on first use.
+///
+/// Single Output controllers are keyed by logical channel and output, Extended controllers by
+/// logical channel alone.
+#[derive(Debug, Default)]
+pub(crate) struct ControllerRegistry {
+    toggles: Mutex<HashMap<(LogicalChannel, Option<Output>), ToggleState>>,
+}
+
+impl ControllerRegistry {
+    /// Returns the toggle state of a receiver, creating it on first use with the address of
+    /// `channel`.
+    pub(crate) fn toggle(&self, channel: LogicalChannel, output: Option<Output>) -> ToggleState {
+        self.toggles
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry((channel, output))
+            .or_insert_with(|| ToggleState::new(channel.address()))
+            .clone()
+    }
+}
@@ -0,0 +1,213 @@
+// Only the controllers transmit, and each needs its protocol feature.
+#![cfg_attr(
+    not(any(
+        feature = "single-output",
+        feature = "combo-direct",
+        feature = "combo-pwm",
+        feature = "extended"
+    )),
+    allow(dead_code)
+)]
+
+use crate::{
+    device::PulseTransmitter,
+    protocols::{duration_of, timing::spec_repeat_slots, wait_out_slot, MAX_MESSAGE_DURATION},
+    Channel, Result,
+};
+use std::time::Duration;
+
+/// How the copies of a message are spaced, see `RepeatPolicy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatStrategy {
+    /// The first copy goes out at once, the others start `gap` apart.
+    Fixed,
+    /// The pauses the PF spec prescribes for the channel (see `timing::spec_repeat_delays`),
+    /// in units of `gap` instead of 16 ms. Copies beyond the fifth repeat the last pause.
+    Spec,
+}
+
+/// How often and how far apart the controllers send every message, trading latency for
+/// reliability.
+///
+/// A single copy suits rapid updates to a nearby receiver, e.g. from a joystick, where the next
+/// update follows soon anyway. Receivers at a distance or behind obstacles need several copies to
+/// catch one intact. All copies carry the same toggle bit, so a receiver acts on one of them only.
+///
+/// The policy is set on a `BrickBeam` for the controllers it creates, or on a single controller.
+/// A `BrickBeam` sends the five copies of the PF spec unless told otherwise; a controller created
+/// on its own, and the `Default` policy, send a single copy. `BrickBeamBuilder::profile` sets the
+/// policy from the repeats of a `TransmissionProfile` (see `TransmissionProfile::repeat_policy`).
+///
+/// # Example
+#[cfg_attr(feature = "combo-pwm", doc = "```rust")]
+#[cfg_attr(not(feature = "combo-pwm"), doc = "```ignore")]
+/// use brickbeam::{BrickBeam, Channel, ComboPwmCommand, RepeatPolicy, Result};
+/// use std::time::Duration;
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?.with_repeat_policy(RepeatPolicy::spec());
+///     let mut far = brick_beam.create_combo_speed_remote_controller(Channel::One)?;
+///     let mut joystick = brick_beam
+///         .create_combo_speed_remote_controller(Channel::Two)?
+///         .with_repeat_policy(RepeatPolicy::single());
+///     far.send(ComboPwmCommand::new(5, 5)?)?;
+///     joystick.send(ComboPwmCommand::new(3, -3)?)?;
+///     let burst = RepeatPolicy::fixed(3, Duration::from_millis(20));
+///     assert_eq!(burst.count, 3);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatPolicy {
+    /// How many copies of every message are sent; 0 counts as 1.
+    pub count: u32,
+    /// The start-to-start time between copies for `Fixed`, or the pause unit for `Spec`.
+    pub gap: Duration,
+    pub strategy: RepeatStrategy,
+}
+
+impl RepeatPolicy {
+    /// A single copy of every message, the lowest latency.
+    pub const fn single() -> Self {
+        Self::fixed(1, MAX_MESSAGE_DURATION)
+    }
+
+    /// `count` copies starting `gap` apart.
+    pub const fn fixed(count: u32, gap: Duration) -> Self {
+        Self {
+            count,
+            gap,
+            strategy: RepeatStrategy::Fixed,
+        }
+    }
+
+    /// The five copies with the channel-dependent pauses of the PF spec. Each message then
+    /// takes about 0.4 s.
+    pub const fn spec() -> Self {
+        Self {
+            count: 5,
+            gap: MAX_MESSAGE_DURATION,
+            strategy: RepeatStrategy::Spec,
+        }
+    }
+
+    /// Returns the policy with a different number of copies.
+    pub const fn with_count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Returns the delay before the first copy and the start-to-start times of the following
+    /// copies on `channel`.
+    fn schedule(self, channel: Channel) -> (Duration, impl Iterator<Item = Duration>) {
+        let slots = spec_repeat_slots(channel);
+        let lead = match self.strategy {
+            RepeatStrategy::Fixed => Duration::ZERO,
+            RepeatStrategy::Spec => self.gap * slots[0],
+        };
+        let gaps = (1..self.count.max(1) as usize).map(move |copy| match self.strategy {
+            RepeatStrategy::Fixed => self.gap,
+            RepeatStrategy::Spec => self.gap * slots[copy.min(slots.len() - 1)],
+        });
+        (lead, gaps)
+    }
+}
+
+impl Default for RepeatPolicy {
+    fn default() -> Self {
+        Self::single()
+    }
+}
+
+/// Sends the pulses of a message on `channel` as the copies `policy` asks for.
+///
+/// Returns the time the message takes from the call until its last copy has left the medium:
+/// the pause before the first copy, the slots of all copies but the last, and the airtime of the
+/// last one. With a single copy, that's the airtime of the message.
+pub(crate) fn transmit<T: PulseTransmitter + ?Sized>(
+    transmitter: &T,
+    channel: Channel,
+    pulses: &[u32],
+    policy: RepeatPolicy,
+) -> Result<Duration> {
+    let clock = transmitter.clock();
+    let airtime = duration_of(pulses);
+    let (lead, gaps) = policy.schedule(channel);
+    let mut total = lead;
+    clock.sleep(lead);
+    for gap in gaps {
+        let started = clock.now();
+        transmitter.send_pulses(pulses)?;
+        wait_out_slot(clock, started, airtime, gap);
+        total += airtime.max(gap);
+    }
+    transmitter.send_pulses(pulses)?;
+    Ok(total + airtime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::device::MockTransmitterRecorder;
+
+    /// Returns the delay before the first copy and the start-to-start times of the others.
+    fn timeline(policy: RepeatPolicy, channel: Channel) -> (Duration, Vec<Duration>) {
+        let transmitter = MockTransmitterRecorder::default();
+        let before = transmitter.clock.now();
+        transmit(&transmitter, channel, &[157, 1026], policy).unwrap();
+        let sent = transmitter.sent_at();
+        let gaps = sent.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        (sent[0] - before, gaps)
+    }
+
+    #[test]
+    fn test_spec_repeats_follow_channel_schedule() {
+        let ms = Duration::from_millis;
+        assert_eq!(
+            timeline(RepeatPolicy::spec(), Channel::Two),
+            (ms(48), vec![ms(48), ms(80), ms(80), ms(128)])
+        );
+        // Further copies repeat the last pause, in units of the gap.
+        let longer = RepeatPolicy {
+            gap: ms(10),
+            ..RepeatPolicy::spec().with_count(7)
+        };
+        assert_eq!(
+            timeline(longer, Channel::One),
+            (ms(40), vec![ms(40), ms(50), ms(50), ms(60), ms(60), ms(60)])
+        );
+    }
+
+    #[test]
+    fn test_returns_total_time_of_all_copies() {
+        let ms = Duration::from_millis;
+        let transmitter = MockTransmitterRecorder::default();
+        let pulses = [5000, 5000];
+        // Channel 1 pauses 4, 4, 5, 5 and 6 slots of 16 ms around the copies.
+        let total = transmit(&transmitter, Channel::One, &pulses, RepeatPolicy::spec()).unwrap();
+        assert_eq!(total, ms(64 + 64 + 80 + 80 + 96 + 10));
+        let fixed = RepeatPolicy::fixed(5, ms(20));
+        let total = transmit(&transmitter, Channel::One, &pulses, fixed).unwrap();
+        assert_eq!(total, ms(4 * 20 + 10));
+        let single = transmit(&transmitter, Channel::One, &pulses, RepeatPolicy::single());
+        assert_eq!(single.unwrap(), ms(10));
+    }
+
+    #[test]
+    fn test_fixed_and_single_policies() {
+        let ms = Duration::from_millis;
+        assert_eq!(
+            timeline(RepeatPolicy::fixed(3, ms(20)), Channel::Four),
+            (Duration::ZERO, vec![ms(20), ms(20)])
+        );
+        assert_eq!(
+            timeline(RepeatPolicy::single(), Channel::Four),
+            (Duration::ZERO, vec![])
+        );
+        assert_eq!(
+            timeline(RepeatPolicy::fixed(0, ms(20)), Channel::Four),
+            (Duration::ZERO, vec![])
+        );
+    }
+}
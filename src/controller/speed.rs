@@ -1,8 +1,19 @@
 use crate::{
+    controller::{
+        keepalive::Keepalive,
+        registry::ControllerRegistry,
+        repetition::{transmit, RepeatPolicy},
+    },
     device::PulseTransmitter,
-    protocols::{SingleOutputCommand, SingleOutputProtocol},
-    Channel, Output, Result,
+    protocols::{
+        timing::PulseTiming, wait_out_slot, Lrc, SingleOutputCommand, SingleOutputDiscrete,
+        SingleOutputProtocol, ToggleState, MAX_MESSAGE_DURATION,
+    },
+    Address, Channel, Error, LogicalChannel, MotorProfile, Output, OutputSelector, Result,
+    StepRounding,
 };
+use std::thread::Scope;
+use std::time::Duration;
 
 /// `SpeedRemoteController` is a struct that represents a remote controller for the LEGO® Power Functions Speed IR Remote Control 8879.
 ///
@@ -12,6 +23,15 @@ use crate::{
 /// * `output` - The output (e.g., RED or BLUE) that the remote controller controls.
 /// * `pulse_transmitter` - A reference to an object that implements the `PulseTransmitter` trait, used to send pulses.
 /// * `protocol` - An instance of `SingleOutputProtocol` used to encode commands.
+/// * `numeric_pwm` - The tracked numerical PWM step of the receiver, if tracking is enabled and the step is known.
+///
+/// # Numerical PWM Tracking
+///
+/// Receivers configured for numerical PWM only understand relative `IncrementNumericalPwm` and
+/// `DecrementNumericalPwm` steps. After `track_numeric_pwm` declares the receiver's current step,
+/// `seek_numeric_pwm` sends as many steps as needed to reach an absolute step. Commands sent with
+/// `send` keep the tracked step up to date where their effect is known (PWM, numerical steps, full
+/// forward/backward); any other discrete command makes the step unknown until it is declared again.
 ///
 /// # Thread Safety
 ///
@@ -38,7 +58,21 @@ pub struct SpeedRemoteController<'a, T: PulseTransmitter> {
     channel: Channel,
     output: Output,
     pulse_transmitter: &'a T,
+    repeat_policy: RepeatPolicy,
     protocol: SingleOutputProtocol,
+    numeric_pwm: Option<i8>,
+    motor_profile: MotorProfile,
+    step_rounding: StepRounding,
+    registry: Option<&'a ControllerRegistry>,
+    // The toggle state of the output not currently addressed, unless shared by a registry.
+    other_output_toggle: ToggleState,
+    keepalive: Option<Keepalive>,
+    dedupe: bool,
+    // The last PWM command the receiver got, while dedupe is enabled.
+    last_pwm: Option<SingleOutputCommand>,
+    soft_step_delay: Option<Duration>,
+    // The PWM step the receiver runs at, if known, for soft start and stop.
+    pwm_step: Option<i8>,
 }
 
 impl<'a, T: PulseTransmitter> SpeedRemoteController<'a, T> {
@@ -47,23 +81,460 @@ impl<'a, T: PulseTransmitter> SpeedRemoteController<'a, T> {
         Ok(Self {
             protocol,
             pulse_transmitter,
+            repeat_policy: RepeatPolicy::default(),
             channel,
             output,
+            numeric_pwm: None,
+            motor_profile: MotorProfile::default(),
+            step_rounding: StepRounding::default(),
+            registry: None,
+            other_output_toggle: ToggleState::default(),
+            keepalive: None,
+            dedupe: false,
+            last_pwm: None,
+            soft_step_delay: None,
+            pwm_step: Some(0),
         })
     }
 
+    /// Sends subsequent messages with adjusted symbol lengths, e.g. to probe a marginal receiver.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the IRP for `timing` can't be built.
+    pub fn with_timing(mut self, timing: PulseTiming) -> Result<Self> {
+        self.protocol.set_timing(timing)?;
+        Ok(self)
+    }
+
+    /// Sends subsequent messages with the given LRC, e.g. a wrong one to check that receivers
+    /// ignore corrupted messages.
+    pub fn with_lrc(mut self, lrc: Lrc) -> Self {
+        self.protocol.set_lrc(lrc);
+        self
+    }
+
+    /// Rejects PWM speeds outside -7 to 8 with `Error::InvalidSpeed` instead of clamping them,
+    /// so an application can report invalid input rather than move the train unexpectedly.
+    pub fn with_strict_speeds(mut self, strict: bool) -> Self {
+        self.protocol.set_strict(strict);
+        self
+    }
+
+    /// Sends subsequent messages to receivers switched to the extra address space, or back to
+    /// the default one (see `set_address`).
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.set_address(address);
+        self
+    }
+
+    /// Maps the percentages of `MotorControl` through `profile`, tuned for the motor on the output.
+    pub fn with_motor_profile(mut self, profile: MotorProfile) -> Self {
+        self.motor_profile = profile;
+        self
+    }
+
+    /// Returns the motor profile used by `MotorControl`.
+    pub fn motor_profile(&self) -> MotorProfile {
+        self.motor_profile
+    }
+
+    /// Rounds the percentages of `set_speed_percent` to PWM steps as `rounding` says.
+    pub fn with_step_rounding(mut self, rounding: StepRounding) -> Self {
+        self.step_rounding = rounding;
+        self
+    }
+
+    /// Skips a PWM command equal to the last one sent successfully, so a loop polling a throttle
+    /// can pass every reading to `send` without flooding the room with identical messages.
+    ///
+    /// Only PWM commands are skipped, as discrete ones are relative or toggle the output and so
+    /// take effect each time. A skipped command returns a zero airtime; a running keepalive keeps
+    /// refreshing the last command. A new channel, address or output, a discrete command or a
+    /// failed send make the next PWM command go out in any case.
+    pub fn with_dedupe(mut self, dedupe: bool) -> Self {
+        self.dedupe = dedupe;
+        self.last_pwm = None;
+        self
+    }
+
+    /// Starts and stops the motor softly: a PWM command more than one step away from the
+    /// current step is preceded by every step in between, one per `step_delay` (start to start,
+    /// at least the airtime of a message). `send` blocks until the requested step is sent.
+    ///
+    /// The motor is assumed to stand still initially. Braking with `PWM(8)` takes effect at
+    /// once. The current step becomes unknown on a new channel, address or output and after
+    /// discrete commands other than full forward and backward; the next PWM command is then sent
+    /// directly.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{BrickBeam, Channel, Output, Result, SingleOutputCommand};
+    /// use std::time::Duration;
+    ///
+    /// fn main() -> Result<()> {
+    ///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+    ///     let mut motor = brick_beam
+    ///         .speed(Channel::One, Output::RED)?
+    ///         .with_soft_steps(Duration::from_millis(20));
+    ///     // Sends the steps 1 to 6 first.
+    ///     motor.send(SingleOutputCommand::PWM(7))?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn with_soft_steps(mut self, step_delay: Duration) -> Self {
+        self.soft_step_delay = Some(step_delay);
+        self
+    }
+
+    /// Shares the toggle state with the other controllers of a `BrickBeam` registry.
+    pub(crate) fn with_registry(mut self, registry: &'a ControllerRegistry) -> Self {
+        self.registry = Some(registry);
+        self.protocol.share_toggle(self.toggle_state());
+        self
+    }
+
+    fn toggle_state(&self) -> ToggleState {
+        match self.registry {
+            Some(registry) => registry.toggle(self.logical_channel(), Some(self.output)),
+            None => ToggleState::default(),
+        }
+    }
+
+    /// Sends subsequent messages as the copies `policy` asks for, e.g. `RepeatPolicy::spec()`
+    /// for distant receivers. `send` blocks until the last copy is on air.
+    pub fn with_repeat_policy(mut self, policy: RepeatPolicy) -> Self {
+        self.repeat_policy = policy;
+        self
+    }
+
+    /// Returns the transmitter the controller sends with, e.g. for its clock.
+    pub(crate) fn pulse_transmitter(&self) -> &'a T {
+        self.pulse_transmitter
+    }
+
+    /// Returns the channel the controller sends on.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Returns the address space the controller sends to.
+    pub fn address(&self) -> Address {
+        self.protocol.address()
+    }
+
+    /// Returns the logical channel the controller sends on, combining channel and address.
+    pub fn logical_channel(&self) -> LogicalChannel {
+        LogicalChannel::new(self.channel, self.address())
+    }
+
+    /// Retargets subsequent messages to another channel, keeping the timing and LRC.
+    ///
+    /// A controller obtained from `BrickBeam::speed` continues with the toggle state shared for
+    /// the new channel; any other controller starts the new channel with a fresh toggle bit. The
+    /// tracked numerical PWM step becomes unknown.
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+        self.protocol.share_toggle(self.toggle_state());
+        self.other_output_toggle = ToggleState::default();
+        self.numeric_pwm = None;
+        self.pwm_step = None;
+        self.last_pwm = None;
+        self.pause_keepalive();
+    }
+
+    /// Retargets subsequent messages to the receiver in another address space, keeping the
+    /// channel, timing and LRC, e.g. to drive two receivers sharing a channel.
+    ///
+    /// Like `set_channel`, this addresses another receiver: a controller obtained from
+    /// `BrickBeam::speed` continues with the toggle state shared for it, any other controller
+    /// starts with a fresh toggle bit. The tracked numerical PWM step becomes unknown.
+    pub fn set_address(&mut self, address: Address) {
+        if address == self.address() {
+            return;
+        }
+        self.protocol.set_address(address);
+        self.protocol.share_toggle(self.toggle_state());
+        self.other_output_toggle = ToggleState::default();
+        self.numeric_pwm = None;
+        self.pwm_step = None;
+        self.last_pwm = None;
+        self.pause_keepalive();
+    }
+
+    /// Returns the output the controller drives.
+    pub fn output(&self) -> Output {
+        self.output
+    }
+
+    /// Retargets subsequent messages to the other output of the receiver, keeping the timing
+    /// and LRC, so one controller can drive both motors of a receiver.
+    ///
+    /// Each output keeps its own toggle bit: a controller obtained from `BrickBeam::speed` uses
+    /// the one shared for the new output, any other controller the one it last used for that
+    /// output. The tracked numerical PWM step becomes unknown.
+    pub fn set_output(&mut self, output: Output) {
+        if output == self.output {
+            return;
+        }
+        self.output = output;
+        let toggle = match self.registry {
+            Some(registry) => registry.toggle(self.logical_channel(), Some(output)),
+            None => std::mem::replace(&mut self.other_output_toggle, self.protocol.toggle_state()),
+        };
+        self.protocol.share_toggle(toggle);
+        self.numeric_pwm = None;
+        self.pwm_step = None;
+        self.last_pwm = None;
+        self.pause_keepalive();
+    }
+
+    /// Sends a command to the selected output(s) of the receiver, red first, and returns the
+    /// summed airtime of the messages.
+    ///
+    /// Each output is addressed as after `set_output`, with its own toggle bit, and the
+    /// controller returns to its own output afterwards. Selecting another output than its own
+    /// therefore makes the tracked numerical PWM step unknown, as `set_output` does.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{BrickBeam, Channel, Output, OutputSelector, Result, SingleOutputCommand};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+    ///     let mut motors = brick_beam.speed(Channel::One, Output::RED)?;
+    ///     // Both motors of a dual-motor train.
+    ///     motors.send_to(OutputSelector::Both, SingleOutputCommand::PWM(4))?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn send_to(
+        &mut self,
+        outputs: impl Into<OutputSelector>,
+        cmd: SingleOutputCommand,
+    ) -> Result<Duration> {
+        let own_output = self.output;
+        let mut airtime = Duration::ZERO;
+        let mut result = Ok(());
+        for (index, output) in outputs.into().outputs().enumerate() {
+            self.set_output(output);
+            let sent = match index {
+                0 => self.send(cmd),
+                _ => self.pulse_transmitter.flush().and_then(|()| self.send(cmd)),
+            };
+            match sent {
+                Ok(message_airtime) => airtime += message_airtime,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.set_output(own_output);
+        result.map(|()| airtime)
+    }
+
+    /// Sets the motor speed in percent, from -100.0 (full reverse) to 100.0 (full forward), and
+    /// returns the airtime of the transmitted message.
+    ///
+    /// The percentage is mapped onto a PWM step through the motor profile, rounded as set by
+    /// `with_step_rounding`, so UIs can pass the position of a slider as is.
+    pub fn set_speed_percent(&mut self, percent: f32) -> Result<Duration> {
+        let step = self.motor_profile.step_percent(percent, self.step_rounding);
+        self.send(SingleOutputCommand::PWM(step))
+    }
+
     /// Sends a command to the motor.
     ///
     /// Accepts either a PWM value or a discrete command.
-    pub fn send(&mut self, cmd: SingleOutputCommand) -> Result<()> {
+    /// Returns the airtime of the transmitted message, including all copies of the repeat policy
+    /// and the pauses between them, so follow-up actions can be scheduled precisely.
+    ///
+    /// With `with_soft_steps`, the airtime includes the intermediate steps.
+    pub fn send(&mut self, cmd: SingleOutputCommand) -> Result<Duration> {
+        if self.dedupe && self.last_pwm == Some(cmd) {
+            return Ok(Duration::ZERO);
+        }
+        let (Some(step_delay), Some(mut current), SingleOutputCommand::PWM(target @ -7..=7)) =
+            (self.soft_step_delay, self.pwm_step, cmd)
+        else {
+            return self.send_once(cmd);
+        };
+        let mut airtime = Duration::ZERO;
+        while (target - current).abs() > 1 {
+            current += (target - current).signum();
+            if airtime > Duration::ZERO {
+                self.pulse_transmitter.flush()?;
+            }
+            let clock = self.pulse_transmitter.clock();
+            let started = clock.now();
+            let message_airtime = self.send_once(SingleOutputCommand::PWM(current))?;
+            airtime += message_airtime;
+            wait_out_slot(clock, started, message_airtime, step_delay);
+        }
+        if airtime > Duration::ZERO {
+            self.pulse_transmitter.flush()?;
+        }
+        Ok(airtime + self.send_once(cmd)?)
+    }
+
+    /// Encodes and transmits a single command, keeping the tracked state up to date.
+    fn send_once(&mut self, cmd: SingleOutputCommand) -> Result<Duration> {
         let pulses = self.protocol.encode_cmd(self.channel, self.output, cmd)?;
-        self.pulse_transmitter.send_pulses(&pulses)
+        self.last_pwm = None;
+        let airtime = transmit(
+            self.pulse_transmitter,
+            self.channel,
+            &pulses,
+            self.repeat_policy,
+        )?;
+        if self.dedupe && matches!(cmd, SingleOutputCommand::PWM(_)) {
+            self.last_pwm = Some(cmd);
+        }
+        self.numeric_pwm = self.numeric_pwm.and_then(|step| match cmd {
+            SingleOutputCommand::PWM(8) => Some(0),
+            SingleOutputCommand::PWM(speed) => Some(speed.clamp(-7, 7)),
+            SingleOutputCommand::Discrete(SingleOutputDiscrete::IncrementNumericalPwm) => {
+                Some((step + 1).min(7))
+            }
+            SingleOutputCommand::Discrete(SingleOutputDiscrete::DecrementNumericalPwm) => {
+                Some((step - 1).max(-7))
+            }
+            SingleOutputCommand::Discrete(SingleOutputDiscrete::FullForward) => Some(7),
+            SingleOutputCommand::Discrete(SingleOutputDiscrete::FullBackward) => Some(-7),
+            SingleOutputCommand::Discrete(_) => None,
+        });
+        self.pwm_step = match cmd {
+            SingleOutputCommand::PWM(8) => Some(0),
+            SingleOutputCommand::PWM(speed) => Some(speed.clamp(-7, 7)),
+            SingleOutputCommand::Discrete(SingleOutputDiscrete::FullForward) => Some(7),
+            SingleOutputCommand::Discrete(SingleOutputDiscrete::FullBackward) => Some(-7),
+            SingleOutputCommand::Discrete(_) => None,
+        };
+        if let Some(keepalive) = &self.keepalive {
+            // Refreshing a relative command would repeat its effect once the toggle bit moved on.
+            match cmd {
+                SingleOutputCommand::PWM(_) => {
+                    keepalive.update(&pulses, self.pulse_transmitter.clock().now())
+                }
+                SingleOutputCommand::Discrete(_) => keepalive.pause(),
+            }
+        }
+        Ok(airtime)
+    }
+
+    /// Retransmits the last PWM command every `interval` in which no other command was sent,
+    /// for receivers that stop the motor when the signal is lost.
+    ///
+    /// The keepalive refreshes the PWM commands sent from now on, on a thread of `scope`; a
+    /// discrete command or a new channel or output pauses it until the next PWM command. The
+    /// scope waits for that thread when it ends, so call `stop_keepalive` or drop the controller
+    /// within the scope. Starting it again replaces the running keepalive.
+    ///
+    /// Refreshes repeat the toggle bit of the command, so run the keepalive on one controller
+    /// per output only.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{BrickBeam, Channel, Output, Result, SingleOutputCommand};
+    /// use std::{thread, time::Duration};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+    ///     thread::scope(|scope| {
+    ///         let mut motor = brick_beam.speed(Channel::One, Output::RED)?;
+    ///         motor.start_keepalive(scope, Duration::from_millis(500));
+    ///         motor.send(SingleOutputCommand::PWM(4))?;
+    ///         thread::sleep(Duration::from_millis(1200));
+    ///         motor.stop_keepalive();
+    ///         Ok(())
+    ///     })
+    /// }
+    /// ```
+    pub fn start_keepalive<'scope>(&mut self, scope: &'scope Scope<'scope, '_>, interval: Duration)
+    where
+        'a: 'scope,
+        T: Sync,
+    {
+        self.keepalive = Some(Keepalive::start(scope, self.pulse_transmitter, interval));
+    }
+
+    /// Stops retransmitting the last PWM command.
+    pub fn stop_keepalive(&mut self) {
+        self.keepalive = None;
+    }
+
+    fn pause_keepalive(&self) {
+        if let Some(keepalive) = &self.keepalive {
+            keepalive.pause();
+        }
+    }
+
+    /// Enables numerical PWM tracking, declaring the receiver's current step (-7 to 7).
+    ///
+    /// A freshly powered receiver starts at step 0.
+    pub fn track_numeric_pwm(&mut self, current: i8) -> Result<()> {
+        if !(-7..=7).contains(&current) {
+            return Err(Error::ProtocolError(format!(
+                "Numerical PWM step {} is out of range -7 to 7",
+                current
+            )));
+        }
+        self.numeric_pwm = Some(current);
+        Ok(())
+    }
+
+    /// Returns the tracked numerical PWM step, or `None` if it is unknown.
+    pub fn numeric_pwm(&self) -> Option<i8> {
+        self.numeric_pwm
+    }
+
+    /// Steps the receiver to the absolute numerical PWM `step` (-7 to 7).
+    ///
+    /// Each step is sent in its own message slot so the receiver registers every one of them.
+    /// Returns the summed airtime of all transmitted messages; nothing is sent if the receiver is
+    /// already at `step`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::ProtocolError` if `step` is out of range or the current step is unknown
+    /// (see `track_numeric_pwm`). If a transmission fails, the tracked step reflects the steps
+    /// sent so far.
+    pub fn seek_numeric_pwm(&mut self, step: i8) -> Result<Duration> {
+        if !(-7..=7).contains(&step) {
+            return Err(Error::ProtocolError(format!(
+                "Numerical PWM step {} is out of range -7 to 7",
+                step
+            )));
+        }
+        let mut airtime = Duration::ZERO;
+        loop {
+            let current = self.numeric_pwm.ok_or_else(|| {
+                Error::ProtocolError("The numerical PWM step of the receiver is unknown".into())
+            })?;
+            let discrete = match step.cmp(&current) {
+                std::cmp::Ordering::Equal => return Ok(airtime),
+                std::cmp::Ordering::Greater => SingleOutputDiscrete::IncrementNumericalPwm,
+                std::cmp::Ordering::Less => SingleOutputDiscrete::DecrementNumericalPwm,
+            };
+            if airtime > Duration::ZERO {
+                self.pulse_transmitter.flush()?;
+            }
+            let clock = self.pulse_transmitter.clock();
+            let started = clock.now();
+            let message_airtime = self.send(SingleOutputCommand::Discrete(discrete))?;
+            airtime += message_airtime;
+            if self.numeric_pwm != Some(step) {
+                wait_out_slot(clock, started, message_airtime, MAX_MESSAGE_DURATION);
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::device::MockTransmitterRecorder;
     use crate::device::PulseTransmitter;
     use crate::Error;
     use crate::{Channel, Output};
@@ -94,6 +565,27 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_speed_remote_controller_returns_airtime() {
+        let transmitter = MockTransmitterSuccess;
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        let airtime = controller.send(SingleOutputCommand::PWM(5)).unwrap();
+        assert_eq!(airtime, Duration::from_micros(10_820));
+    }
+
+    #[test]
+    fn test_airtime_includes_all_copies() {
+        let transmitter = MockTransmitterRecorder::default();
+        let gap = Duration::from_millis(40);
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .unwrap()
+            .with_repeat_policy(RepeatPolicy::fixed(5, gap));
+        let airtime = controller.send(SingleOutputCommand::PWM(5)).unwrap();
+        assert_eq!(airtime, 4 * gap + Duration::from_micros(10_820));
+        assert_eq!(transmitter.clock.slept(), 4 * gap);
+    }
+
     #[test]
     fn test_speed_remote_controller_discrete_success() {
         let transmitter = MockTransmitterSuccess;
@@ -105,6 +597,216 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_keepalive_refreshes_pwm_only() {
+        let transmitter = MockTransmitterRecorder::default();
+        let interval = Duration::from_millis(10);
+        std::thread::scope(|scope| {
+            let mut controller =
+                SpeedRemoteController::new(&transmitter, Channel::One, Output::RED).unwrap();
+            controller.start_keepalive(scope, interval);
+            controller.send(SingleOutputCommand::PWM(4)).unwrap();
+            for refreshes in 1..=3 {
+                transmitter.clock.advance(interval);
+                transmitter.wait_until_sent(1 + refreshes);
+            }
+            controller
+                .send(SingleOutputCommand::Discrete(
+                    SingleOutputDiscrete::ToggleDirection,
+                ))
+                .unwrap();
+            transmitter.clock.advance(5 * interval);
+            // Only the next PWM command is refreshed again.
+            controller.send(SingleOutputCommand::PWM(4)).unwrap();
+            transmitter.clock.advance(interval);
+            transmitter.wait_until_sent(7);
+            controller.stop_keepalive();
+        });
+
+        let sent = transmitter.sent();
+        assert_eq!(sent.len(), 7);
+        assert_ne!(sent[4], sent[0]);
+        assert!(sent[5] != sent[4] && sent[6] == sent[5]);
+    }
+
+    #[test]
+    fn test_seek_numeric_pwm_sends_one_step_per_difference() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        controller.track_numeric_pwm(0).unwrap();
+
+        let airtime = controller.seek_numeric_pwm(3).unwrap();
+        assert_eq!(controller.numeric_pwm(), Some(3));
+        controller.seek_numeric_pwm(1).unwrap();
+        assert_eq!(controller.numeric_pwm(), Some(1));
+        assert_eq!(controller.seek_numeric_pwm(1).unwrap(), Duration::ZERO);
+
+        let sent = transmitter.sent();
+        assert_eq!(sent.len(), 5);
+        assert_eq!(
+            airtime,
+            sent[..3]
+                .iter()
+                .map(|pulses| crate::duration_of(pulses))
+                .sum()
+        );
+        for pair in sent[..3].windows(2) {
+            assert_ne!(pair[0], pair[1], "The toggle bit must flip between steps");
+        }
+    }
+
+    #[test]
+    fn test_set_channel_starts_afresh() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        controller.track_numeric_pwm(0).unwrap();
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+        controller.set_channel(Channel::Three);
+        assert_eq!(controller.channel(), Channel::Three);
+        assert_eq!(controller.numeric_pwm(), None);
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+
+        let sent = transmitter.sent();
+        // Toggle bit, then the two channel bits after the escape bit.
+        let bits = |pulses: &[u32]| [3, 7, 9].map(|i| pulses[i] > 400);
+        assert_eq!(bits(&sent[0]), [false, false, false]);
+        assert_eq!(bits(&sent[1]), [false, true, false]);
+    }
+
+    #[test]
+    fn test_dedupe_skips_repeated_pwm_only() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .unwrap()
+            .with_dedupe(true);
+        let toggle = SingleOutputCommand::Discrete(SingleOutputDiscrete::ToggleDirection);
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+        assert_eq!(
+            controller.send(SingleOutputCommand::PWM(3)).unwrap(),
+            Duration::ZERO
+        );
+        controller.send(toggle).unwrap();
+        controller.send(toggle).unwrap();
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+        controller.set_output(Output::BLUE);
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+
+        assert_eq!(transmitter.sent().len(), 5);
+    }
+
+    #[test]
+    fn test_soft_steps_send_intermediate_pwm() {
+        let transmitter = MockTransmitterRecorder::default();
+        let step_delay = Duration::from_millis(20);
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .unwrap()
+            .with_soft_steps(step_delay);
+        let airtime = controller.send(SingleOutputCommand::PWM(3)).unwrap();
+        controller.send(SingleOutputCommand::PWM(8)).unwrap();
+        controller.send(SingleOutputCommand::PWM(-2)).unwrap();
+        controller.set_output(Output::BLUE);
+        controller.send(SingleOutputCommand::PWM(5)).unwrap();
+
+        let sent = transmitter.timeline();
+        // The spaces of bits 8 to 11 carry the PWM nibble.
+        let steps: Vec<u8> = sent
+            .iter()
+            .map(|(_, pulses)| {
+                [19, 21, 23, 25]
+                    .iter()
+                    .fold(0, |nibble, &i| nibble << 1 | u8::from(pulses[i] > 400))
+            })
+            .collect();
+        assert_eq!(steps, [1, 2, 3, 8, 15, 14, 5]);
+        assert_eq!(sent[1].0 - sent[0].0, step_delay);
+        assert_eq!(sent[2].0 - sent[1].0, step_delay);
+        assert!(airtime > Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_set_speed_percent_uses_profile_and_rounding() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .unwrap()
+            .with_motor_profile(MotorProfile::train())
+            .with_step_rounding(StepRounding::AwayFromZero);
+        controller.track_numeric_pwm(0).unwrap();
+        controller.set_speed_percent(0.5).unwrap();
+        assert_eq!(controller.numeric_pwm(), Some(3));
+        controller.set_speed_percent(-100.0).unwrap();
+        assert_eq!(controller.numeric_pwm(), Some(-7));
+        controller.set_speed_percent(0.0).unwrap();
+        assert_eq!(controller.numeric_pwm(), Some(0));
+    }
+
+    #[test]
+    fn test_set_address_addresses_another_receiver() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::Two, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        controller.track_numeric_pwm(0).unwrap();
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+        controller.set_address(Address::Extra);
+        assert_eq!(controller.logical_channel(), LogicalChannel::Six);
+        assert_eq!(controller.numeric_pwm(), None);
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+        controller.set_address(Address::Default);
+        controller.send(SingleOutputCommand::PWM(3)).unwrap();
+
+        let sent = transmitter.sent();
+        // Toggle bit and address bit.
+        let bits = |pulses: &[u32]| [3, 11].map(|i| pulses[i] > 400);
+        assert_eq!(bits(&sent[0]), [false, false]);
+        assert_eq!(bits(&sent[1]), [false, true]);
+        assert_eq!(bits(&sent[2]), [false, false]);
+    }
+
+    #[test]
+    fn test_set_output_keeps_toggle_per_output() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        let pwm = SingleOutputCommand::PWM(3);
+        controller.send(pwm).unwrap();
+        controller.set_output(Output::BLUE);
+        assert_eq!(controller.output(), Output::BLUE);
+        controller.send(pwm).unwrap();
+        controller.set_output(Output::RED);
+        controller.send(pwm).unwrap();
+
+        let sent = transmitter.sent();
+        // Toggle bit and output bit.
+        let bits = |pulses: &[u32]| [3, 17].map(|i| pulses[i] > 400);
+        assert_eq!(bits(&sent[0]), [false, false]);
+        assert_eq!(bits(&sent[1]), [false, true]);
+        assert_eq!(bits(&sent[2]), [true, false]);
+    }
+
+    #[test]
+    fn test_seek_numeric_pwm_requires_known_step() {
+        let transmitter = MockTransmitterSuccess;
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        assert!(matches!(
+            controller.seek_numeric_pwm(2),
+            Err(Error::ProtocolError(_))
+        ));
+
+        controller.track_numeric_pwm(0).unwrap();
+        controller.send(SingleOutputCommand::PWM(-4)).unwrap();
+        assert_eq!(controller.numeric_pwm(), Some(-4));
+        controller
+            .send(SingleOutputCommand::Discrete(
+                SingleOutputDiscrete::ToggleDirection,
+            ))
+            .unwrap();
+        assert_eq!(controller.numeric_pwm(), None);
+        assert!(controller.track_numeric_pwm(8).is_err());
+    }
+
     #[test]
     fn test_speed_remote_controller_failure() {
         let transmitter = MockTransmitterFail;
@@ -118,4 +820,22 @@ mod tests {
             panic!("Unexpected error variant");
         }
     }
+
+    #[test]
+    fn test_send_to_both_outputs() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut controller = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        let pwm = SingleOutputCommand::PWM(3);
+        controller.send_to(OutputSelector::Both, pwm).unwrap();
+        assert_eq!(controller.output(), Output::RED);
+        controller.send(pwm).unwrap();
+
+        let sent = transmitter.sent();
+        // Toggle bit and output bit.
+        let bits = |pulses: &[u32]| [3, 17].map(|i| pulses[i] > 400);
+        assert_eq!(bits(&sent[0]), [false, false]);
+        assert_eq!(bits(&sent[1]), [false, true]);
+        assert_eq!(bits(&sent[2]), [true, false]);
+    }
 }
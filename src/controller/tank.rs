@@ -0,0 +1,196 @@
+use crate::{
+    controller::ComboSpeedRemoteController, device::PulseTransmitter, ComboPwmCommand, Output,
+    Result,
+};
+use std::time::Duration;
+
+/// The largest PWM step of a Combo PWM output.
+const MAX_STEP: f32 = 7.0;
+
+/// Drives a tracked or skid-steer vehicle with one motor per side from a throttle and a steering
+/// input, as given by a joystick.
+///
+/// Both motors hang off one receiver, so every update is a single Combo PWM message that changes
+/// both sides at once. The inputs range from -1.0 to 1.0: throttle forward is positive, steering
+/// right is positive. The left side gets `throttle + mixing · steering` and the right side
+/// `throttle - mixing · steering`. If either exceeds the range, both are scaled down together so
+/// the vehicle keeps its curve. The result is then rounded to the PWM steps -7 to 7.
+///
+/// Inputs within the deadband count as 0, so a joystick resting slightly off-center doesn't
+/// creep. Larger inputs are rescaled, so the full range is still reachable.
+///
+/// # Example
+#[cfg_attr(feature = "combo-pwm", doc = "```rust")]
+#[cfg_attr(not(feature = "combo-pwm"), doc = "```ignore")]
+/// use brickbeam::{BrickBeam, Channel, Output, Result, TankDrive};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     let controller = brick_beam.create_combo_speed_remote_controller(Channel::One)?;
+///     let mut tank = TankDrive::new(controller)
+///         .with_left_output(Output::BLUE)
+///         .with_reversed(Output::RED);
+///     // Full throttle with full right steering stops the right track.
+///     assert_eq!(tank.mix(1.0, 1.0), (7, 0));
+///     tank.drive(0.6, -0.2)?;
+///     tank.stop()?;
+///     Ok(())
+/// }
+/// ```
+pub struct TankDrive<'a, T: PulseTransmitter> {
+    controller: ComboSpeedRemoteController<'a, T>,
+    left: Output,
+    reversed: [bool; 2],
+    mixing: f32,
+    deadband: f32,
+}
+
+impl<'a, T: PulseTransmitter> TankDrive<'a, T> {
+    /// Drives the vehicle through `controller`, with the left motor on the red output, full
+    /// steering mixing and a deadband of 0.05.
+    pub fn new(controller: ComboSpeedRemoteController<'a, T>) -> Self {
+        Self {
+            controller,
+            left: Output::RED,
+            reversed: [false; 2],
+            mixing: 1.0,
+            deadband: 0.05,
+        }
+    }
+
+    /// Puts the left motor on `output` and the right motor on the other one.
+    pub fn with_left_output(mut self, output: Output) -> Self {
+        self.left = output;
+        self
+    }
+
+    /// Reverses the motor on `output`, for motors mounted mirrored or wired backward.
+    pub fn with_reversed(mut self, output: Output) -> Self {
+        self.reversed[output as usize] = true;
+        self
+    }
+
+    /// Sets how strongly steering acts on the sides, from 0.0 (no steering) to 1.0 (full
+    /// steering, turning on the spot at zero throttle). Values outside are clamped.
+    pub fn with_mixing(mut self, mixing: f32) -> Self {
+        self.mixing = mixing.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the deadband of both inputs, from 0.0 to just below 1.0. Values outside are clamped.
+    pub fn with_deadband(mut self, deadband: f32) -> Self {
+        self.deadband = deadband.clamp(0.0, 0.99);
+        self
+    }
+
+    /// Returns the controller that sends the messages.
+    pub fn controller(&mut self) -> &mut ComboSpeedRemoteController<'a, T> {
+        &mut self.controller
+    }
+
+    /// Computes the PWM steps of the left and right motor for the given inputs, before the
+    /// output assignment and reversal are applied.
+    pub fn mix(&self, throttle: f32, steering: f32) -> (i8, i8) {
+        let throttle = self.apply_deadband(throttle);
+        let steering = self.apply_deadband(steering);
+        let left = throttle + self.mixing * steering;
+        let right = throttle - self.mixing * steering;
+        let scale = left.abs().max(right.abs()).max(1.0);
+        let step = |side: f32| (side / scale * MAX_STEP).round() as i8;
+        (step(left), step(right))
+    }
+
+    /// Sends the motor speeds for the given inputs and returns the airtime of the message.
+    pub fn drive(&mut self, throttle: f32, steering: f32) -> Result<Duration> {
+        let (left, right) = self.mix(throttle, steering);
+        let mut speeds = [0; 2];
+        speeds[self.left as usize] = left;
+        speeds[1 - self.left as usize] = right;
+        for (speed, reversed) in speeds.iter_mut().zip(self.reversed) {
+            if reversed {
+                *speed = -*speed;
+            }
+        }
+        self.controller
+            .send(ComboPwmCommand::new(speeds[0], speeds[1])?)
+    }
+
+    /// Lets both motors float.
+    pub fn stop(&mut self) -> Result<Duration> {
+        self.controller.send(ComboPwmCommand::stopped())
+    }
+
+    fn apply_deadband(&self, input: f32) -> f32 {
+        let input = if input.is_nan() {
+            0.0
+        } else {
+            input.clamp(-1.0, 1.0)
+        };
+        if input.abs() <= self.deadband {
+            0.0
+        } else {
+            input.signum() * (input.abs() - self.deadband) / (1.0 - self.deadband)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use crate::Channel;
+
+    fn tank(transmitter: &MockTransmitterRecorder) -> TankDrive<'_, MockTransmitterRecorder> {
+        TankDrive::new(ComboSpeedRemoteController::new(transmitter, Channel::One).unwrap())
+    }
+
+    #[test]
+    fn test_mix() {
+        let transmitter = MockTransmitterRecorder::default();
+        let tank = tank(&transmitter);
+        assert_eq!(tank.mix(0.0, 0.0), (0, 0));
+        assert_eq!(tank.mix(1.0, 0.0), (7, 7));
+        assert_eq!(tank.mix(-1.0, 0.0), (-7, -7));
+        assert_eq!(tank.mix(0.0, 1.0), (7, -7));
+        assert_eq!(tank.mix(1.0, 1.0), (7, 0));
+        assert_eq!(tank.mix(0.03, -0.04), (0, 0));
+        assert_eq!(tank.mix(f32::NAN, 5.0), (7, -7));
+
+        let gentle = tank.with_mixing(0.5).with_deadband(0.0);
+        assert_eq!(gentle.mix(0.0, 1.0), (4, -4));
+        assert_eq!(gentle.mix(1.0, 1.0), (7, 2));
+    }
+
+    #[test]
+    fn test_drive_assigns_and_reverses_outputs() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut tank = tank(&transmitter)
+            .with_left_output(Output::BLUE)
+            .with_reversed(Output::RED);
+        tank.drive(1.0, 1.0).unwrap();
+        tank.stop().unwrap();
+
+        let mut combo = ComboSpeedRemoteController::new(&transmitter, Channel::One).unwrap();
+        // Left (blue) full forward, right (red) stopped, so its reversal changes nothing.
+        combo.send(ComboPwmCommand::new(0, 7).unwrap()).unwrap();
+        combo.send(ComboPwmCommand::stopped()).unwrap();
+
+        let sent = transmitter.sent();
+        assert_eq!(sent[0], sent[2]);
+        assert_eq!(sent[1], sent[3]);
+    }
+
+    #[test]
+    fn test_reversed_right_output() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut tank = tank(&transmitter).with_reversed(Output::BLUE);
+        tank.drive(0.0, 1.0).unwrap();
+        ComboSpeedRemoteController::new(&transmitter, Channel::One)
+            .unwrap()
+            .send(ComboPwmCommand::new(7, 7).unwrap())
+            .unwrap();
+
+        let sent = transmitter.sent();
+        assert_eq!(sent[0], sent[1]);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{
+    controller::SpeedRemoteController, device::PulseTransmitter, BrakeBehavior, MotorControl,
+    Result, SingleOutputCommand, StepRounding,
+};
+use std::time::Duration;
+
+/// How the speed changes over the course of a ramp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RampProfile {
+    /// Changes the speed at a constant rate.
+    #[default]
+    Linear,
+    /// Starts slowly and changes faster towards the end.
+    EaseIn,
+    /// Changes fast at first and slowly towards the end.
+    EaseOut,
+    /// Starts and ends slowly, like a train pulling away with a load and coming to a halt.
+    EaseInOut,
+}
+
+impl RampProfile {
+    /// Maps the elapsed fraction of a ramp (0.0 to 1.0) onto the fraction of the speed change.
+    fn apply(self, progress: f32) -> f32 {
+        match self {
+            Self::Linear => progress,
+            Self::EaseIn => progress * progress,
+            Self::EaseOut => 1.0 - (1.0 - progress) * (1.0 - progress),
+            Self::EaseInOut => progress * progress * (3.0 - 2.0 * progress),
+        }
+    }
+}
+
+/// Drives a train on one output with smooth speed changes, built on a `SpeedRemoteController`.
+///
+/// `ramp_to` moves the speed to a target over a given time, following the acceleration profile
+/// when the train gets faster and the deceleration profile when it gets slower. A ramp that
+/// reverses the train first slows it down to a stop and then speeds it up the other way. Speeds
+/// are percentages from -100 (full reverse) to 100 (full forward), mapped onto PWM steps through
+/// the motor profile of the controller; a message is only sent when the step changes.
+///
+/// The train is assumed to stand still with its motor floating initially, so the first message
+/// is sent once the speed leaves step 0. As a `MotorControl`, it changes the speed at once.
+///
+/// # Example
+#[cfg_attr(feature = "single-output", doc = "```no_run")]
+#[cfg_attr(not(feature = "single-output"), doc = "```ignore")]
+/// use brickbeam::{BrickBeam, Channel, MotorProfile, Output, RampProfile, Result, TrainController};
+/// use std::time::Duration;
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     let motor = brick_beam
+///         .create_speed_remote_controller(Channel::One, Output::RED)?
+///         .with_motor_profile(MotorProfile::train());
+///     let mut train = TrainController::new(motor)
+///         .with_acceleration(RampProfile::EaseIn)
+///         .with_deceleration(RampProfile::EaseOut);
+///     train.ramp_to(60, Duration::from_secs(3))?;
+///     train.ramp_to(0, Duration::from_secs(2))?;
+///     Ok(())
+/// }
+/// ```
+pub struct TrainController<'a, T: PulseTransmitter> {
+    controller: SpeedRemoteController<'a, T>,
+    acceleration: RampProfile,
+    deceleration: RampProfile,
+    interval: Duration,
+    speed: i8,
+    // The step the receiver got last, 8 after braking.
+    step: i8,
+}
+
+impl<'a, T: PulseTransmitter> TrainController<'a, T> {
+    /// Drives the train through `controller`, with linear ramps updated every 100 ms.
+    pub fn new(controller: SpeedRemoteController<'a, T>) -> Self {
+        Self {
+            controller,
+            acceleration: RampProfile::default(),
+            deceleration: RampProfile::default(),
+            interval: Duration::from_millis(100),
+            speed: 0,
+            step: 0,
+        }
+    }
+
+    /// Sets how the speed rises while the train gets faster.
+    pub fn with_acceleration(mut self, profile: RampProfile) -> Self {
+        self.acceleration = profile;
+        self
+    }
+
+    /// Sets how the speed falls while the train gets slower.
+    pub fn with_deceleration(mut self, profile: RampProfile) -> Self {
+        self.deceleration = profile;
+        self
+    }
+
+    /// Sets how often a ramp recomputes the speed, at least 1 ms. Shorter intervals follow the
+    /// profiles more closely, but with only 7 steps per direction rarely change what is sent.
+    pub fn with_update_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Returns the controller that sends the messages.
+    pub fn controller(&mut self) -> &mut SpeedRemoteController<'a, T> {
+        &mut self.controller
+    }
+
+    /// Returns the current speed in percent.
+    pub fn speed(&self) -> i8 {
+        self.speed
+    }
+
+    /// Changes the speed to `speed` percent (-100 to 100, clamped) over `duration`, blocking
+    /// until the target is reached.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first message that fails; the ramp stops there and `speed`
+    /// reports the last speed sent successfully.
+    pub fn ramp_to(&mut self, speed: i8, duration: Duration) -> Result<()> {
+        let target = speed.clamp(-100, 100);
+        if self.speed != 0 && target != 0 && (self.speed < 0) != (target < 0) {
+            // Stop first, sharing the time in proportion to the speed changes.
+            let slowing = u32::from(self.speed.unsigned_abs());
+            let stopping = duration * slowing / (slowing + u32::from(target.unsigned_abs()));
+            self.ramp_segment(0, stopping)?;
+            return self.ramp_segment(target, duration.saturating_sub(stopping));
+        }
+        self.ramp_segment(target, duration)
+    }
+
+    /// Ramps to `target` without crossing 0.
+    fn ramp_segment(&mut self, target: i8, duration: Duration) -> Result<()> {
+        let from = self.speed;
+        let profile = if target.unsigned_abs() >= from.unsigned_abs() {
+            self.acceleration
+        } else {
+            self.deceleration
+        };
+        let clock = self.controller.pulse_transmitter().clock();
+        let started = clock.now();
+        let ticks = duration
+            .as_nanos()
+            .div_ceil(self.interval.as_nanos())
+            .max(1) as u32;
+        for tick in 1..=ticks {
+            let progress = tick as f32 / ticks as f32;
+            let due = started + duration * tick / ticks;
+            clock.sleep(due.saturating_duration_since(clock.now()));
+            let speed = if tick == ticks {
+                target
+            } else {
+                let change = f32::from(target) - f32::from(from);
+                (f32::from(from) + change * profile.apply(progress)).round() as i8
+            };
+            self.send_speed(speed)?;
+        }
+        Ok(())
+    }
+
+    /// Sends `speed` if it maps to another step than the receiver got last.
+    fn send_speed(&mut self, speed: i8) -> Result<()> {
+        let step = self
+            .controller
+            .motor_profile()
+            .step_percent(f32::from(speed), StepRounding::Nearest);
+        if step != self.step {
+            self.controller.send(SingleOutputCommand::PWM(step))?;
+            self.step = step;
+        }
+        self.speed = speed;
+        Ok(())
+    }
+}
+
+/// Sets the speed at once, without a ramp.
+impl<T: PulseTransmitter> MotorControl for TrainController<'_, T> {
+    fn set_power(&mut self, percent: i8) -> Result<()> {
+        self.send_speed(percent.clamp(-100, 100))
+    }
+
+    fn brake(&mut self) -> Result<()> {
+        self.controller.brake()?;
+        self.step = match self.controller.motor_profile().brake {
+            BrakeBehavior::Brake => 8,
+            BrakeBehavior::Float => 0,
+        };
+        self.speed = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::device::MockTransmitterRecorder;
+    use crate::{Channel, Output, TrainControl};
+
+    /// Returns the PWM nibbles sent, each with the time slept before it.
+    fn nibbles(transmitter: &MockTransmitterRecorder) -> Vec<(Duration, u8)> {
+        // The tests never advance the clock, so it has only moved while sleeping.
+        let created = transmitter.clock.now() - transmitter.clock.slept();
+        transmitter
+            .timeline()
+            .into_iter()
+            .map(|(at, pulses)| {
+                // The spaces of bits 8 to 11 carry the PWM nibble.
+                let nibble = [19, 21, 23, 25]
+                    .iter()
+                    .fold(0, |nibble, &i| nibble << 1 | u8::from(pulses[i] > 400));
+                (at - created, nibble)
+            })
+            .collect()
+    }
+
+    fn train(
+        transmitter: &MockTransmitterRecorder,
+    ) -> TrainController<'_, MockTransmitterRecorder> {
+        TrainController::new(
+            SpeedRemoteController::new(transmitter, Channel::One, Output::RED).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_linear_ramp_sends_each_step_once() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut train = train(&transmitter);
+        train.ramp_to(100, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(train.speed(), 100);
+        let sent = nibbles(&transmitter);
+        let steps: Vec<u8> = sent.iter().map(|&(_, nibble)| nibble).collect();
+        assert_eq!(steps, [1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(sent[0].0, Duration::from_millis(100));
+        assert_eq!(sent[6].0, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_profiles_shape_the_ramp() {
+        let first_change = |profile| {
+            let transmitter = MockTransmitterRecorder::default();
+            let mut train = train(&transmitter).with_acceleration(profile);
+            train.ramp_to(100, Duration::from_secs(1)).unwrap();
+            let sent = nibbles(&transmitter);
+            assert_eq!(sent.last().unwrap().1, 7);
+            sent[0].0
+        };
+        assert_eq!(
+            first_change(RampProfile::EaseIn),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            first_change(RampProfile::EaseOut),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn test_reversing_stops_first() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut train = train(&transmitter);
+        train.set_speed(50).unwrap();
+        train.ramp_to(-100, Duration::from_millis(1500)).unwrap();
+
+        assert_eq!(train.speed(), -100);
+        let sent = nibbles(&transmitter);
+        let stopped = sent.iter().position(|&(_, nibble)| nibble == 0).unwrap();
+        assert!(sent[1..stopped].iter().all(|&(_, nibble)| nibble < 8));
+        assert!(sent[stopped + 1..].iter().all(|&(_, nibble)| nibble > 8));
+        // A third of the ramp is needed to stop from half speed.
+        assert_eq!(sent[stopped].0, Duration::from_millis(500));
+        assert_eq!(sent.last().unwrap(), &(Duration::from_millis(1500), 9));
+    }
+}
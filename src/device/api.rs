@@ -1,3 +1,5 @@
+use crate::clock::{Clock, SystemClock};
+
 /// A trait representing the ability to transmit IR pulses.
 ///
 /// An implementor of this trait is responsible for taking a slice of pulse widths (in microseconds)
@@ -49,4 +51,85 @@ pub trait PulseTransmitter {
     /// The first value is the length of time to transmit (LED on), the second is a gap (LED off),
     /// and so on, until the entire IR message is complete.
     fn send_pulses(&self, pulses: &[u32]) -> crate::Result<()>;
+
+    /// Blocks until every pulse handed to `send_pulses` so far has actually been emitted.
+    ///
+    /// Use this as a barrier when sequencing messages, e.g. before switching channels, to be sure
+    /// the previous message is fully on air. The default implementation is a no-op, which is correct
+    /// for transmitters whose `send_pulses` only returns once the transmission is complete.
+    fn flush(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Announces that the receivers just changed state (e.g. after `ToggleAddress`) and may
+    /// ignore a message that follows immediately.
+    ///
+    /// Transmitters with a quiet period, such as `SettleTransmitter`, delay the next transmission.
+    /// The default implementation does nothing.
+    fn settle(&self) {}
+
+    /// Sets the modulation of the IR bursts: the carrier frequency in Hz and the duty cycle in
+    /// percent (PF uses 38 kHz at 33%).
+    ///
+    /// Transmitters whose hardware cannot change the modulation ignore the request. The default
+    /// implementation does nothing.
+    fn set_carrier(&self, _carrier: u32, _duty_cycle: u32) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the clock that paces transmissions through this transmitter: the gaps between
+    /// messages, repeats and settle times are measured and waited out on it.
+    ///
+    /// Wrappers return the clock of the transmitter they wrap. The default implementation returns
+    /// the `SystemClock`; test transmitters return a `MockClock` to run without real delays.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+}
+
+/// A type-erased, thread-safe `PulseTransmitter`, as produced by `BrickBeamBuilder`.
+pub type DynPulseTransmitter = Box<dyn PulseTransmitter + Send + Sync>;
+
+impl<T: PulseTransmitter + ?Sized> PulseTransmitter for Box<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> crate::Result<()> {
+        (**self).send_pulses(pulses)
+    }
+
+    fn flush(&self) -> crate::Result<()> {
+        (**self).flush()
+    }
+
+    fn settle(&self) {
+        (**self).settle()
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> crate::Result<()> {
+        (**self).set_carrier(carrier, duty_cycle)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        (**self).clock()
+    }
+}
+
+impl<T: PulseTransmitter + ?Sized> PulseTransmitter for std::sync::Arc<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> crate::Result<()> {
+        (**self).send_pulses(pulses)
+    }
+
+    fn flush(&self) -> crate::Result<()> {
+        (**self).flush()
+    }
+
+    fn settle(&self) {
+        (**self).settle()
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> crate::Result<()> {
+        (**self).set_carrier(carrier, duty_cycle)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        (**self).clock()
+    }
 }
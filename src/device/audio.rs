@@ -0,0 +1,203 @@
+//! # Audio output
+//!
+//! The classic trick for sending IR without IR hardware: two IR LEDs wired anti-parallel across
+//! the left and right channels of a headphone jack. Sound cards can't play a 38 kHz tone, so both
+//! channels play half the carrier frequency in opposite phase. Each LED lights up during its half
+//! of every period, so together they flash at the full carrier frequency.
+//!
+//! `render_audio` turns pulses into such interleaved stereo samples, `write_wav` saves them for
+//! analysis in an audio editor, and `AudioPulseTransmitter` streams them as raw PCM, e.g. into
+//! `aplay`.
+
+use crate::clock::{Clock, SystemClock};
+use crate::device::PulseTransmitter;
+use crate::protocols::timing::CARRIER_HZ;
+use crate::Result;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The sample rate nearly every sound card supports. It needs to be above the carrier frequency;
+/// 96 kHz gives a cleaner waveform where available.
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+/// The peak amplitude of the marks, leaving a little headroom below full scale.
+const AMPLITUDE: f64 = 30_000.0;
+
+/// Renders the pulses as interleaved stereo 16-bit samples, the right channel inverting the left,
+/// with each channel at half the `carrier` frequency during the marks and silent in the spaces.
+pub fn render_audio(pulses: &[u32], carrier: u32, sample_rate: u32) -> Vec<i16> {
+    let sample_at = |micros: u64| (micros * u64::from(sample_rate) + 500_000) / 1_000_000;
+    let step = PI * f64::from(carrier) / f64::from(sample_rate);
+    let mut samples = Vec::new();
+    let mut elapsed = 0u64;
+    for (index, &pulse) in pulses.iter().enumerate() {
+        let start = sample_at(elapsed);
+        elapsed += u64::from(pulse);
+        for sample in 0..sample_at(elapsed) - start {
+            let value = if index % 2 == 0 {
+                ((sample as f64 * step).sin() * AMPLITUDE).round() as i16
+            } else {
+                0
+            };
+            samples.extend_from_slice(&[value, -value]);
+        }
+    }
+    samples
+}
+
+/// Writes the pulses, modulated with the PF carrier, as a stereo 16-bit WAV file.
+pub fn write_wav(path: impl AsRef<Path>, pulses: &[u32], sample_rate: u32) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_wav_to(&mut file, pulses, sample_rate)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes the pulses, modulated with the PF carrier, as a stereo 16-bit WAV stream.
+pub fn write_wav_to(mut writer: impl Write, pulses: &[u32], sample_rate: u32) -> Result<()> {
+    let samples = render_audio(pulses, CARRIER_HZ, sample_rate);
+    let data_size = (samples.len() * 2) as u32;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_size).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes()); // format chunk size
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&2u16.to_le_bytes()); // channels
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * 4).to_le_bytes()); // bytes per second
+    header.extend_from_slice(&4u16.to_le_bytes()); // bytes per frame
+    header.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+    writer.write_all(&header)?;
+    writer.write_all(&pcm_bytes(&samples))?;
+    Ok(())
+}
+
+fn pcm_bytes(samples: &[i16]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect()
+}
+
+/// Streams the pulses as raw stereo 16-bit little-endian PCM for the headphone jack trick.
+///
+/// The stream has no header, so the player has to be told the format. Only the pulses are
+/// written, and a player plays whatever it gets back to back, so `send_pulses` returns only once
+/// the samples have had time to play, waiting for them on the clock set with `with_clock`. This
+/// keeps the pauses between messages: the player runs out of samples in them and resumes playing
+/// with the next message.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::{AudioPulseTransmitter, BrickBeam, Result, DEFAULT_SAMPLE_RATE};
+/// use std::process::{Command, Stdio};
+///
+/// fn main() -> Result<()> {
+///     let aplay = Command::new("aplay")
+///         .args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "2", "-r", "48000"])
+///         .stdin(Stdio::piped())
+///         .spawn()?;
+///     let transmitter = AudioPulseTransmitter::new(aplay.stdin.unwrap(), DEFAULT_SAMPLE_RATE);
+///     let brick_beam = BrickBeam::from_transmitter(transmitter);
+///     Ok(())
+/// }
+/// ```
+pub struct AudioPulseTransmitter<W: Write> {
+    output: Mutex<W>,
+    sample_rate: u32,
+    carrier: Mutex<u32>,
+    clock: Box<dyn Clock>,
+}
+
+impl<W: Write> AudioPulseTransmitter<W> {
+    /// Creates a transmitter writing to `output`, which plays at `sample_rate`.
+    pub fn new(output: W, sample_rate: u32) -> Self {
+        Self {
+            output: Mutex::new(output),
+            sample_rate,
+            carrier: Mutex::new(CARRIER_HZ),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Sets the clock the transmitter waits for the samples to play on.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+}
+
+impl<W: Write> PulseTransmitter for AudioPulseTransmitter<W> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let carrier = *self.carrier.lock().unwrap_or_else(|e| e.into_inner());
+        let samples = render_audio(pulses, carrier, self.sample_rate);
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        output.write_all(&pcm_bytes(&samples))?;
+        output.flush()?;
+        let frames = (samples.len() / 2) as u64;
+        self.clock.sleep(Duration::from_micros(
+            frames * 1_000_000 / u64::from(self.sample_rate),
+        ));
+        Ok(())
+    }
+
+    /// Sets the carrier frequency; the duty cycle is given by the LEDs and can't be changed.
+    fn set_carrier(&self, carrier: u32, _duty_cycle: u32) -> Result<()> {
+        *self.carrier.lock().unwrap_or_else(|e| e.into_inner()) = carrier;
+        Ok(())
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_marks_rendered_in_opposite_phase() {
+        // 157 µs at 96 kHz are 15 samples, 263 µs 25 samples.
+        let samples = render_audio(&[157, 263, 157], 38_000, 96_000);
+        assert_eq!(samples.len(), (15 + 25 + 15) * 2);
+        let (mark, rest) = samples.split_at(30);
+        assert!(mark.chunks(2).all(|frame| frame[0] == -frame[1]));
+        assert!(mark.iter().any(|&sample| sample > 20_000));
+        assert!(rest[..50].iter().all(|&sample| sample == 0));
+        assert_eq!(rest[50..], mark[..]);
+    }
+
+    #[test]
+    fn test_wav_header() {
+        let mut wav = Vec::new();
+        write_wav_to(&mut wav, &[157, 263], DEFAULT_SAMPLE_RATE).unwrap();
+        // 157 µs + 263 µs at 48 kHz are 8 + 12 stereo samples of 4 bytes.
+        assert_eq!(wav.len(), 44 + 80);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(wav[24..28], 48_000u32.to_le_bytes());
+        assert_eq!(wav[40..44], 80u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_transmitter_streams_pcm() {
+        let clock = Arc::new(MockClock::new());
+        let transmitter = AudioPulseTransmitter::new(Vec::new(), DEFAULT_SAMPLE_RATE)
+            .with_clock(Arc::clone(&clock));
+        transmitter.send_pulses(&[157, 263]).unwrap();
+        // 20 stereo samples at 48 kHz play for 416 µs.
+        assert_eq!(clock.slept(), Duration::from_micros(416));
+        let output = transmitter.output.into_inner().unwrap();
+        assert_eq!(output.len(), 80);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::clock::Clock;
+use crate::device::PulseTransmitter;
+use crate::{Error, Result};
+use std::sync::Mutex;
+
+/// Corrects the pulse lengths for the skew of an emitter's driver stage.
+///
+/// Every duration is first scaled, then the mark or space offset is added. Transistor drivers
+/// with slow turn-off stretch the marks and shorten the spaces by a fixed amount, which an
+/// offset pair undoes; an emitter running on a clock that is off stretches everything by the
+/// same factor, which the scale undoes.
+///
+/// `new` rejects scales that aren't finite numbers above 0, so a calibration can't turn the
+/// frames into garbage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PulseCalibration {
+    scale: f32,
+    mark_offset: i32,
+    space_offset: i32,
+}
+
+impl Default for PulseCalibration {
+    /// Leaves the pulses as they are.
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            mark_offset: 0,
+            space_offset: 0,
+        }
+    }
+}
+
+impl PulseCalibration {
+    /// Creates a calibration multiplying every duration with `scale`, 1.0 to keep them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if `scale` is not a finite number above 0, which would
+    /// destroy every frame.
+    pub fn new(scale: f32) -> Result<Self> {
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(Error::ProtocolError(format!(
+                "Calibration scale {} is not a finite number above 0",
+                scale
+            )));
+        }
+        Ok(Self {
+            scale,
+            ..Self::default()
+        })
+    }
+
+    /// Adds `mark_offset` µs to every mark and `space_offset` µs to every space after scaling,
+    /// negative to shorten them.
+    pub fn with_offsets(mut self, mark_offset: i32, space_offset: i32) -> Self {
+        self.mark_offset = mark_offset;
+        self.space_offset = space_offset;
+        self
+    }
+
+    /// Returns the factor every duration is multiplied with.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Returns the µs added to every mark.
+    pub fn mark_offset(&self) -> i32 {
+        self.mark_offset
+    }
+
+    /// Returns the µs added to every space.
+    pub fn space_offset(&self) -> i32 {
+        self.space_offset
+    }
+
+    /// Returns the calibrated copy of alternating mark and space durations in µs, starting with
+    /// a mark. No duration drops below 1 µs, so the train keeps its shape.
+    pub fn apply(&self, pulses: &[u32]) -> Vec<u32> {
+        pulses
+            .iter()
+            .enumerate()
+            .map(|(i, &pulse)| {
+                let offset = if i % 2 == 0 {
+                    self.mark_offset
+                } else {
+                    self.space_offset
+                };
+                let scaled = (f64::from(pulse) * f64::from(self.scale)).round();
+                (scaled + f64::from(offset)).clamp(1.0, f64::from(u32::MAX)) as u32
+            })
+            .collect()
+    }
+}
+
+/// Applies a `PulseCalibration` to every pulse train before the wrapped transmitter sends it, so
+/// the timings can be tuned for one emitter without touching the IRPs of the protocols.
+///
+/// The calibration can be changed while sending, e.g. from a UI nudging the offsets until a
+/// receiver at the far end of the layout reacts reliably.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, CalibratedTransmitter, PulseCalibration, PulseTransmitterEmulator};
+///
+/// // The driver stretches marks by about 20 µs.
+/// let calibration = PulseCalibration::default().with_offsets(-20, 20);
+/// let transmitter = CalibratedTransmitter::new(PulseTransmitterEmulator, calibration);
+/// let brick_beam = BrickBeam::from_transmitter(transmitter);
+/// ```
+pub struct CalibratedTransmitter<T: PulseTransmitter> {
+    inner: T,
+    calibration: Mutex<PulseCalibration>,
+}
+
+impl<T: PulseTransmitter> CalibratedTransmitter<T> {
+    /// Wraps `inner`, calibrating the pulses as `calibration` says.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The transmitter that sends the pulses, usually the hardware.
+    /// * `calibration` - The correction for the skew of that hardware.
+    pub fn new(inner: T, calibration: PulseCalibration) -> Self {
+        Self {
+            inner,
+            calibration: Mutex::new(calibration),
+        }
+    }
+
+    /// Returns the calibration applied to the pulses.
+    pub fn calibration(&self) -> PulseCalibration {
+        *self.calibration.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Applies `calibration` to the pulse trains sent from now on.
+    pub fn set_calibration(&self, calibration: PulseCalibration) {
+        *self.calibration.lock().unwrap_or_else(|e| e.into_inner()) = calibration;
+    }
+
+    /// Returns a reference to the wrapped transmitter.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: PulseTransmitter> PulseTransmitter for CalibratedTransmitter<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let calibrated = self.calibration().apply(pulses);
+        self.inner.send_pulses(&calibrated)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn settle(&self) {
+        self.inner.settle()
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.inner.set_carrier(carrier, duty_cycle)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+
+    #[test]
+    fn test_scale_then_offsets_per_mark_and_space() {
+        let calibration = PulseCalibration::new(1.1).unwrap().with_offsets(-20, 15);
+        assert_eq!(
+            calibration.apply(&[158, 263, 158, 1026, 10]),
+            [154, 304, 154, 1144, 1]
+        );
+        assert_eq!(PulseCalibration::default().apply(&[158, 263]), [158, 263]);
+    }
+
+    #[test]
+    fn test_invalid_scale_rejected() {
+        for scale in [f32::NAN, f32::INFINITY, 0.0, -1.0] {
+            assert!(
+                matches!(PulseCalibration::new(scale), Err(Error::ProtocolError(_))),
+                "{}",
+                scale
+            );
+        }
+    }
+
+    #[test]
+    fn test_calibration_changed_while_sending() {
+        let transmitter = CalibratedTransmitter::new(
+            MockTransmitterRecorder::default(),
+            PulseCalibration::default(),
+        );
+        transmitter.send_pulses(&[158, 263, 158]).unwrap();
+        transmitter.set_calibration(transmitter.calibration().with_offsets(10, 0));
+        transmitter.send_pulses(&[158, 263, 158]).unwrap();
+
+        assert_eq!(
+            transmitter.inner().sent(),
+            [vec![158, 263, 158], vec![168, 263, 168]]
+        );
+    }
+}
@@ -1,17 +1,32 @@
+use crate::clock::SystemClock;
+use crate::device::info::{transmitter_info, TransmitterInfo};
+use crate::device::registry::DeviceRegistry;
+use crate::device::retry::{retry_transient, RetryPolicy};
+use crate::device::writer::{Device, DeviceWriter};
 use crate::device::PulseTransmitter;
+use crate::protocols::timing::{CARRIER_HZ, DUTY_CYCLE};
 use crate::{Error, Result};
 use cir::lirc::Lirc;
-use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// All LIRC devices currently open in this process, keyed by canonical path.
+static LIRC_DEVICES: DeviceRegistry<DeviceWriter> = DeviceRegistry::new();
 
 /// Transmits pulses to the kernel's /dev/lircX device using the cir library.
 /// See README.md for information how to enable /dev/lircX device in the Linux kernel.
+///
+/// All instances opened on the same device path share one device handle, owned by a writer
+/// thread, so their transmissions are serialized in submission order even if they belong to
+/// different `BrickBeam` instances.
 pub struct CirPulseTransmitter {
-    tx_device: Arc<Mutex<Lirc>>,
+    tx_device: Arc<DeviceWriter>,
+    tx_device_path: PathBuf,
 }
 
 impl CirPulseTransmitter {
-    /// Creates a new CirPulseTransmitter instance.
+    /// Creates a new CirPulseTransmitter instance, set to the PF carrier of 38 kHz at 33% duty
+    /// cycle, as not every driver defaults to it.
     ///
     /// # Arguments
     ///
@@ -21,16 +36,69 @@ impl CirPulseTransmitter {
     ///
     /// * `Result<Self>` - A result containing the new CirPulseTransmitter instance or an error.
     pub fn new(tx_device_path: impl AsRef<Path>) -> Result<Self> {
-        let tx_device = cir::lirc::open(tx_device_path)?;
+        Self::with_carrier(tx_device_path, CARRIER_HZ, DUTY_CYCLE)
+    }
+
+    /// Creates a new CirPulseTransmitter instance that modulates the IR bursts with the given
+    /// carrier frequency in Hz and duty cycle in percent instead of the PF carrier.
+    ///
+    /// Drivers that can't change the carrier or the duty cycle keep their fixed setting. The
+    /// carrier applies to all instances sharing the device and can be changed later with
+    /// `PulseTransmitter::set_carrier`.
+    pub fn with_carrier(
+        tx_device_path: impl AsRef<Path>,
+        carrier: u32,
+        duty_cycle: u32,
+    ) -> Result<Self> {
+        let tx_device = LIRC_DEVICES.get_or_open(tx_device_path.as_ref(), |path| {
+            DeviceWriter::spawn(cir::lirc::open(path)?)
+        })?;
+        tx_device.set_carrier(carrier, duty_cycle)?;
         Ok(Self {
-            tx_device: Arc::new(Mutex::new(tx_device)),
+            tx_device,
+            tx_device_path: tx_device_path.as_ref().to_path_buf(),
         })
     }
+
+    /// Returns the driver name and the features of the device, e.g. to show device info or to
+    /// adapt to drivers that can't change the carrier.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Io` if the device can't be opened to query its features.
+    pub fn info(&self) -> Result<TransmitterInfo> {
+        Ok(transmitter_info(&self.tx_device_path)?)
+    }
+
+    /// Selects which emitters of a multi-output IR blaster fire, one bit per emitter starting
+    /// with bit 0 for the first, e.g. `0b10` for only the second one pointing at the layout.
+    ///
+    /// The mask applies to all instances sharing the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Transmitting` if the driver can't select emitters or has fewer emitters
+    /// than the mask addresses.
+    pub fn set_transmitter_mask(&self, mask: u32) -> Result<()> {
+        self.tx_device.set_transmitter_mask(mask)
+    }
+
+    /// Sets how often and with which backoff writes failing with `EINTR`, `EAGAIN` or `EBUSY` are
+    /// retried before `send_pulses` reports the error. `RetryPolicy { retries: 0, .. }` surfaces
+    /// them right away.
+    ///
+    /// The policy applies to all instances sharing the device.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) -> Result<()> {
+        self.tx_device.set_retry_policy(policy)
+    }
 }
 
 impl PulseTransmitter for CirPulseTransmitter {
     /// Sends pulses to the transmission device.
     ///
+    /// Writes interrupted by a signal (`EINTR`) or rejected as busy (`EAGAIN`, `EBUSY`) are retried
+    /// with a short backoff before the error is reported, see `set_retry_policy`.
+    ///
     /// # Arguments
     ///
     /// * `pulses` - A slice of unsigned 32-bit integers representing the pulses to be sent.
@@ -39,16 +107,48 @@ impl PulseTransmitter for CirPulseTransmitter {
     ///
     /// * `Result<()>` - A result indicating success or failure.
     fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
-        let mut tx_device = self
-            .tx_device
-            .lock()
-            .map_err(|e| Error::Transmitting(format!("Lock error: {}", e)))?;
-
-        tx_device
-            .send(pulses)
-            .map_err(|e| Error::Transmitting(e.to_string()))?;
+        self.tx_device.send(pulses)
+    }
+
+    /// Waits until any transmission submitted to the shared device so far has completed.
+    ///
+    /// The LIRC `write` blocks until the IR has been transmitted, and the writer thread performs
+    /// the writes in order, so its reaching the flush is sufficient as a completion barrier.
+    fn flush(&self) -> Result<()> {
+        self.tx_device.flush()
+    }
+
+    /// Issues `LIRC_SET_SEND_CARRIER` and `LIRC_SET_SEND_DUTY_CYCLE` for whichever of the two the
+    /// driver supports.
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.tx_device.set_carrier(carrier, duty_cycle)
+    }
+}
+
+impl Device for Lirc {
+    fn send(&mut self, pulses: &[u32], retry: RetryPolicy) -> Result<()> {
+        retry_transient(retry, &SystemClock, || Lirc::send(self, pulses))
+            .map_err(|e| Error::Transmitting(e.to_string()))
+    }
+
+    fn set_carrier(&mut self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        if self.can_set_send_carrier() {
+            self.set_send_carrier(carrier)?;
+        }
+        if self.can_set_send_duty_cycle() {
+            self.set_send_duty_cycle(duty_cycle)?;
+        }
         Ok(())
     }
+
+    fn set_transmitter_mask(&mut self, mask: u32) -> Result<()> {
+        if !self.can_set_send_transmitter_mask() {
+            return Err(Error::Transmitting(
+                "The driver can't select transmitters".into(),
+            ));
+        }
+        Lirc::set_transmitter_mask(self, mask).map_err(|e| Error::Transmitting(e.to_string()))
+    }
 }
 
 #[cfg(test)]
@@ -73,4 +173,22 @@ mod tests {
         let result = CirPulseTransmitter::new("/invalid/path");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_cir_transmitter_with_carrier() {
+        // This test requires a valid /dev/lirc0 device.
+        let transmitter = CirPulseTransmitter::with_carrier("/dev/lirc0", 36_000, 50)
+            .expect("Should open /dev/lirc0");
+        assert!(transmitter.set_carrier(38_000, 33).is_ok());
+        assert!(CirPulseTransmitter::with_carrier("/invalid/path", 38_000, 33).is_err());
+    }
+
+    #[test]
+    fn test_cir_transmitter_info() {
+        // This test requires a valid /dev/lirc0 device.
+        let transmitter = CirPulseTransmitter::new("/dev/lirc0").expect("Should open /dev/lirc0");
+        let info = transmitter.info().unwrap();
+        assert!(info.device.can_send);
+        assert_eq!(info.max_pulses, 1024);
+    }
 }
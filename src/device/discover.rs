@@ -0,0 +1,191 @@
+//! # Device discovery
+//!
+//! On a Raspberry Pi with both the `gpio-ir` (receive) and `gpio-ir-tx` overlays, there are two
+//! LIRC devices and their numbering depends on the probe order, so `/dev/lirc0` may well be the
+//! receiver. Sending on it fails with an error that doesn't say why.
+//!
+//! Instead of relying on names, the helpers here open every `/dev/lirc*` and ask its driver for
+//! its features, so only devices that can actually transmit are picked. Among several of them,
+//! the rc-core metadata of the `sysfs` helpers breaks the tie.
+
+use crate::device::lirc::{
+    self, LIRC_CAN_REC_MASK, LIRC_CAN_SEND_PULSE, LIRC_CAN_SET_SEND_CARRIER,
+    LIRC_CAN_SET_SEND_DUTY_CYCLE, LIRC_CAN_SET_TRANSMITTER_MASK,
+};
+use crate::device::sysfs::{select_transmitter, RcDevice};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The directory holding the LIRC character devices.
+pub(crate) const DEV_ROOT: &str = "/dev";
+
+/// A LIRC character device and the features its driver reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LircDevice {
+    /// The path of the character device, e.g. `/dev/lirc1`.
+    pub path: PathBuf,
+    /// Whether the device can transmit pulses.
+    pub can_send: bool,
+    /// Whether the device can receive, in any mode.
+    pub can_receive: bool,
+    /// Whether the carrier frequency can be changed.
+    pub can_set_carrier: bool,
+    /// Whether the duty cycle of the carrier can be changed.
+    pub can_set_duty_cycle: bool,
+    /// Whether the emitters of multi-output hardware can be selected.
+    pub can_set_transmitter_mask: bool,
+}
+
+impl LircDevice {
+    fn from_features(path: PathBuf, features: u32) -> Self {
+        Self {
+            path,
+            can_send: features & LIRC_CAN_SEND_PULSE != 0,
+            can_receive: features & LIRC_CAN_REC_MASK != 0,
+            can_set_carrier: features & LIRC_CAN_SET_SEND_CARRIER != 0,
+            can_set_duty_cycle: features & LIRC_CAN_SET_SEND_DUTY_CYCLE != 0,
+            can_set_transmitter_mask: features & LIRC_CAN_SET_TRANSMITTER_MASK != 0,
+        }
+    }
+
+    /// Opens the device and queries its features.
+    pub(crate) fn probe(path: &Path) -> io::Result<Self> {
+        let features = lirc::get_features(&File::open(path)?)?;
+        Ok(Self::from_features(path.to_owned(), features))
+    }
+}
+
+/// Lists the `/dev/lirc*` devices and what they can do, sorted by number.
+///
+/// Devices that can't be opened, e.g. for lack of permissions, are left out.
+pub fn discover_lirc_devices() -> io::Result<Vec<LircDevice>> {
+    Ok(probe_lirc_devices_in(Path::new(DEV_ROOT))?
+        .into_iter()
+        .filter_map(|(_, device)| device.ok())
+        .collect())
+}
+
+/// Picks the device in `dir` best suited for transmitting, preferring the transmitters of
+/// `rc_devices` as `select_transmitter` does.
+///
+/// # Errors
+///
+/// Returns `ErrorKind::NotFound` if no device can transmit, naming why each one was skipped.
+pub(crate) fn find_transmitter_in(dir: &Path, rc_devices: &[RcDevice]) -> io::Result<PathBuf> {
+    let probed = probe_lirc_devices_in(dir)?;
+    let senders: Vec<&PathBuf> = probed
+        .iter()
+        .filter_map(|(_, device)| device.as_ref().ok())
+        .filter(|device| device.can_send)
+        .map(|device| &device.path)
+        .collect();
+    let Some(&first) = senders.first() else {
+        let reasons: Vec<String> = probed
+            .iter()
+            .map(|(path, device)| match device {
+                Ok(_) => format!("{} can only receive", path.display()),
+                Err(e) => format!("{}: {}", path.display(), e),
+            })
+            .collect();
+        let message = if reasons.is_empty() {
+            format!("no LIRC device found in {}", dir.display())
+        } else {
+            format!("no LIRC device can transmit ({})", reasons.join("; "))
+        };
+        return Err(io::Error::new(io::ErrorKind::NotFound, message));
+    };
+    let candidates: Vec<RcDevice> = rc_devices
+        .iter()
+        .filter(|rc| {
+            rc.lirc_device
+                .as_ref()
+                .is_some_and(|lirc| senders.contains(&lirc))
+        })
+        .cloned()
+        .collect();
+    Ok(select_transmitter(&candidates).unwrap_or_else(|| first.clone()))
+}
+
+/// Probes every LIRC device in `dir`, keeping the reason of those that can't be queried.
+fn probe_lirc_devices_in(dir: &Path) -> io::Result<Vec<(PathBuf, io::Result<LircDevice>)>> {
+    Ok(lirc_paths_in(dir)?
+        .into_iter()
+        .map(|path| {
+            let device = LircDevice::probe(&path);
+            (path, device)
+        })
+        .collect())
+}
+
+/// Returns the `lircN` entries of `dir`, sorted by `N`.
+fn lirc_paths_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut numbered = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let number = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("lirc"))
+            .and_then(|number| number.parse::<u32>().ok());
+        if let Some(number) = number {
+            numbered.push((number, entry.path()));
+        }
+    }
+    numbered.sort();
+    Ok(numbered.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_dev(test: &str, names: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "brickbeam-discover-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for name in names {
+            fs::write(root.join(name), "").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_lirc_paths_sorted_by_number() {
+        let root = fake_dev("paths", &["lirc10", "lirc2", "lircd", "lirc0", "null"]);
+        let names: Vec<String> = lirc_paths_in(&root)
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["lirc0", "lirc2", "lirc10"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_features_decoded() {
+        let tx = LircDevice::from_features("/dev/lirc1".into(), 0x0000_0702);
+        assert!(tx.can_send && tx.can_set_carrier && tx.can_set_transmitter_mask);
+        assert!(!tx.can_receive);
+        let rx = LircDevice::from_features("/dev/lirc0".into(), 0x0002_0000);
+        assert!(rx.can_receive && !rx.can_send && !rx.can_set_duty_cycle);
+    }
+
+    #[test]
+    fn test_find_transmitter_explains_failure() {
+        let root = fake_dev("none", &["lirc0"]);
+        let err = find_transmitter_in(&root, &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("lirc0: not a LIRC device"));
+        fs::remove_dir_all(&root).unwrap();
+
+        let empty = fake_dev("empty", &[]);
+        let err = find_transmitter_in(&empty, &[]).unwrap_err();
+        assert!(err.to_string().starts_with("no LIRC device found"));
+        fs::remove_dir_all(&empty).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+//! # embedded-hal transmitter
+//!
+//! `EmbeddedHalTransmitter` drives an IR LED through the `embedded-hal` 1.0 traits, so any board
+//! with a HAL crate (other SBCs, or a Linux host talking to GPIO and PWM through
+//! `linux-embedded-hal`) can reuse brickbeam's encoders without LIRC.
+//!
+//! The marks and spaces are timed with a `DelayNs`, so the timing is only as good as the HAL's
+//! delay and the scheduling of the thread calling it.
+
+use crate::device::PulseTransmitter;
+use crate::protocols::timing::DUTY_CYCLE;
+use crate::{Error, Result};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use std::fmt::Debug;
+use std::sync::Mutex;
+
+/// Switches the IR LED on (modulated) and off for the marks and spaces.
+pub trait IrEmitter {
+    /// Starts (`true`) or ends (`false`) a mark.
+    fn set_emitting(&mut self, on: bool) -> Result<()>;
+
+    /// Sets the duty cycle of the carrier in percent. Emitters without a carrier of their own
+    /// ignore it, which is the default.
+    fn set_duty_cycle(&mut self, _percent: u8) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Modulates the IR LED with a PWM channel, which has to run at the carrier frequency (38 kHz
+/// for PF), as `embedded-hal` can't set the frequency.
+pub struct PwmEmitter<P> {
+    pwm: P,
+    duty_cycle: u8,
+}
+
+impl<P: SetDutyCycle> PwmEmitter<P> {
+    /// Wraps a PWM channel running at the carrier frequency, emitting at the PF duty cycle of
+    /// 33%.
+    pub fn new(pwm: P) -> Self {
+        Self {
+            pwm,
+            duty_cycle: DUTY_CYCLE as u8,
+        }
+    }
+}
+
+impl<P: SetDutyCycle> IrEmitter for PwmEmitter<P> {
+    fn set_emitting(&mut self, on: bool) -> Result<()> {
+        if on {
+            self.pwm.set_duty_cycle_percent(self.duty_cycle)
+        } else {
+            self.pwm.set_duty_cycle_fully_off()
+        }
+        .map_err(hal_error)
+    }
+
+    fn set_duty_cycle(&mut self, percent: u8) -> Result<()> {
+        self.duty_cycle = percent.min(100);
+        Ok(())
+    }
+}
+
+/// Switches an output pin high for the marks, for LEDs behind a driver that modulates them
+/// itself, e.g. a 38 kHz oscillator gated by the pin.
+pub struct PinEmitter<P> {
+    pin: P,
+}
+
+impl<P: OutputPin> PinEmitter<P> {
+    /// Wraps the pin gating the modulated LED.
+    pub fn new(pin: P) -> Self {
+        Self { pin }
+    }
+}
+
+impl<P: OutputPin> IrEmitter for PinEmitter<P> {
+    fn set_emitting(&mut self, on: bool) -> Result<()> {
+        if on {
+            self.pin.set_high()
+        } else {
+            self.pin.set_low()
+        }
+        .map_err(hal_error)
+    }
+}
+
+fn hal_error(e: impl Debug) -> Error {
+    Error::Transmitting(format!("embedded-hal: {:?}", e))
+}
+
+/// Transmits pulses with an `IrEmitter` timed by an `embedded-hal` delay.
+///
+/// # Example
+/// ```rust,ignore
+/// use brickbeam::{BrickBeam, EmbeddedHalTransmitter};
+///
+/// // `pwm` is a PWM channel of your HAL set up at 38 kHz, `delay` its `DelayNs`.
+/// let transmitter = EmbeddedHalTransmitter::pwm(pwm, delay);
+/// let brick_beam = BrickBeam::from_transmitter(transmitter);
+/// ```
+pub struct EmbeddedHalTransmitter<E, D> {
+    hal: Mutex<(E, D)>,
+}
+
+impl<E: IrEmitter, D: DelayNs> EmbeddedHalTransmitter<E, D> {
+    /// Creates a transmitter emitting with `emitter` and timing the pulses with `delay`.
+    pub fn new(emitter: E, delay: D) -> Self {
+        Self {
+            hal: Mutex::new((emitter, delay)),
+        }
+    }
+}
+
+impl<P: SetDutyCycle, D: DelayNs> EmbeddedHalTransmitter<PwmEmitter<P>, D> {
+    /// Creates a transmitter modulating the LED with a PWM channel running at the carrier
+    /// frequency.
+    pub fn pwm(pwm: P, delay: D) -> Self {
+        Self::new(PwmEmitter::new(pwm), delay)
+    }
+}
+
+impl<P: OutputPin, D: DelayNs> EmbeddedHalTransmitter<PinEmitter<P>, D> {
+    /// Creates a transmitter gating an externally modulated LED with an output pin.
+    pub fn pin(pin: P, delay: D) -> Self {
+        Self::new(PinEmitter::new(pin), delay)
+    }
+}
+
+impl<E: IrEmitter, D: DelayNs> PulseTransmitter for EmbeddedHalTransmitter<E, D> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let mut hal = self.hal.lock().unwrap_or_else(|e| e.into_inner());
+        let (emitter, delay) = &mut *hal;
+        let result = pulses.iter().enumerate().try_for_each(|(index, &pulse)| {
+            emitter.set_emitting(index % 2 == 0)?;
+            delay.delay_us(pulse);
+            Ok(())
+        });
+        // Never leave the LED on, even if a mark failed halfway.
+        let off = emitter.set_emitting(false);
+        result.and(off)
+    }
+
+    /// Sets the duty cycle of a `PwmEmitter`; the carrier frequency is the one the PWM channel was
+    /// set up with.
+    fn set_carrier(&self, _carrier: u32, duty_cycle: u32) -> Result<()> {
+        let mut hal = self.hal.lock().unwrap_or_else(|e| e.into_inner());
+        hal.0.set_duty_cycle(duty_cycle.min(100) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::pwm::ErrorType;
+    use std::convert::Infallible;
+    use std::sync::Arc;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    struct FakePwm(Log);
+
+    impl ErrorType for FakePwm {
+        type Error = Infallible;
+    }
+
+    impl SetDutyCycle for FakePwm {
+        fn max_duty_cycle(&self) -> u16 {
+            100
+        }
+
+        fn set_duty_cycle(&mut self, duty: u16) -> std::result::Result<(), Infallible> {
+            self.0.lock().unwrap().push(format!("duty {}", duty));
+            Ok(())
+        }
+    }
+
+    struct FakeDelay(Log);
+
+    impl DelayNs for FakeDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.0.lock().unwrap().push(format!("wait {}", ns / 1000));
+        }
+    }
+
+    #[test]
+    fn test_pwm_modulated_marks() {
+        let log = Log::default();
+        let transmitter =
+            EmbeddedHalTransmitter::pwm(FakePwm(Arc::clone(&log)), FakeDelay(Arc::clone(&log)));
+        transmitter.send_pulses(&[157, 1026, 157]).unwrap();
+        transmitter.set_carrier(38_000, 50).unwrap();
+        transmitter.send_pulses(&[157]).unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "duty 33",
+                "wait 157",
+                "duty 0",
+                "wait 1026",
+                "duty 33",
+                "wait 157",
+                "duty 0",
+                "duty 50",
+                "wait 157",
+                "duty 0",
+            ]
+        );
+    }
+
+    struct FailingPin(Log);
+
+    impl embedded_hal::digital::ErrorType for FailingPin {
+        type Error = embedded_hal::digital::ErrorKind;
+    }
+
+    impl OutputPin for FailingPin {
+        fn set_low(&mut self) -> std::result::Result<(), Self::Error> {
+            self.0.lock().unwrap().push("low".into());
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> std::result::Result<(), Self::Error> {
+            Err(embedded_hal::digital::ErrorKind::Other)
+        }
+    }
+
+    #[test]
+    fn test_pin_failure_leaves_led_off() {
+        let log = Log::default();
+        let transmitter =
+            EmbeddedHalTransmitter::pin(FailingPin(Arc::clone(&log)), FakeDelay(Arc::clone(&log)));
+        assert!(matches!(
+            transmitter.send_pulses(&[157, 1026]),
+            Err(Error::Transmitting(_))
+        ));
+        assert_eq!(*log.lock().unwrap(), ["low"]);
+    }
+}
@@ -2,6 +2,11 @@ use crate::device::PulseTransmitter;
 use crate::Result;
 
 // Note: PulseTransmitterEmulator is for development/testing on non-Linux platforms only.
+/// Prints every pulse train instead of transmitting it.
+///
+/// It is the default transmitter when the `cir` feature is disabled, and a handy mirror
+/// (see `MirrorTransmitter`) to trace what is sent to the real hardware.
+#[derive(Debug, Clone, Copy, Default)]
 pub struct PulseTransmitterEmulator;
 
 impl PulseTransmitter for PulseTransmitterEmulator {
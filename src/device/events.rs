@@ -0,0 +1,297 @@
+//! # Transmission events
+//!
+//! `EventTransmitter` wraps a transmitter and reports the outcome of every pulse train as a
+//! `TransmissionEvent`, so servers and dashboards can forward live activity instead of polling.
+//!
+//! Subscribers either receive the events on a standard channel (`subscribe`) or, with the `async`
+//! feature, as a `futures_core::Stream` (`events`) that works with any async runtime. Events are
+//! buffered per subscriber until consumed; a dropped subscriber is removed with the next event.
+
+use crate::clock::Clock;
+use crate::device::PulseTransmitter;
+use crate::protocols::duration_of;
+use crate::Result;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+pub use stream::EventStream;
+
+/// The outcome of a pulse train reported by `EventTransmitter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TransmissionEvent {
+    /// The pulse train was handed to the wrapped transmitter.
+    Sent {
+        /// The airtime of the pulse train.
+        airtime: Duration,
+    },
+    /// The wrapped transmitter failed to send the pulse train.
+    Failed {
+        /// The error of the wrapped transmitter.
+        error: String,
+    },
+}
+
+enum Subscriber {
+    Channel(Sender<TransmissionEvent>),
+    #[cfg(feature = "async")]
+    Stream(std::sync::Arc<stream::Queue>),
+}
+
+impl Subscriber {
+    /// Delivers an event, returning `false` once the subscriber is gone.
+    fn deliver(&self, event: &TransmissionEvent) -> bool {
+        match self {
+            Subscriber::Channel(sender) => sender.send(event.clone()).is_ok(),
+            #[cfg(feature = "async")]
+            Subscriber::Stream(queue) => queue.push(event.clone()),
+        }
+    }
+}
+
+/// A `PulseTransmitter` that reports every transmission to its subscribers.
+///
+/// Subscribe before handing the transmitter to `BrickBeam::from_transmitter`, or later through
+/// `BrickBeam::transmitter`.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{
+///     BrickBeam, EventTransmitter, PulseTransmitter, PulseTransmitterEmulator, TransmissionEvent,
+/// };
+///
+/// let brick_beam = BrickBeam::from_transmitter(EventTransmitter::new(PulseTransmitterEmulator));
+/// let events = brick_beam.transmitter().subscribe();
+/// brick_beam.transmitter().send_pulses(&[157, 1026]).unwrap();
+/// assert!(matches!(events.recv().unwrap(), TransmissionEvent::Sent { .. }));
+/// ```
+pub struct EventTransmitter<T: PulseTransmitter> {
+    inner: T,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl<T: PulseTransmitter> EventTransmitter<T> {
+    /// Wraps `inner`, reporting every pulse train it sends.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a receiver for all transmission events from now on.
+    pub fn subscribe(&self) -> Receiver<TransmissionEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.add(Subscriber::Channel(sender));
+        receiver
+    }
+
+    /// Returns a stream of all transmission events from now on.
+    ///
+    /// The stream ends when the transmitter is dropped.
+    #[cfg(feature = "async")]
+    pub fn events(&self) -> EventStream {
+        let queue = std::sync::Arc::new(stream::Queue::default());
+        self.add(Subscriber::Stream(std::sync::Arc::clone(&queue)));
+        EventStream { queue }
+    }
+
+    fn add(&self, subscriber: Subscriber) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(subscriber);
+        }
+    }
+
+    fn emit(&self, event: TransmissionEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|subscriber| subscriber.deliver(&event));
+        }
+    }
+}
+
+impl<T: PulseTransmitter> PulseTransmitter for EventTransmitter<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let result = self.inner.send_pulses(pulses);
+        self.emit(match &result {
+            Ok(()) => TransmissionEvent::Sent {
+                airtime: duration_of(pulses),
+            },
+            Err(e) => TransmissionEvent::Failed {
+                error: e.to_string(),
+            },
+        });
+        result
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn settle(&self) {
+        self.inner.settle()
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.inner.set_carrier(carrier, duty_cycle)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: PulseTransmitter> Drop for EventTransmitter<T> {
+    fn drop(&mut self) {
+        if let Ok(subscribers) = self.subscribers.lock() {
+            for subscriber in subscribers.iter() {
+                if let Subscriber::Stream(queue) = subscriber {
+                    queue.close();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+mod stream {
+    use super::TransmissionEvent;
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+
+    #[derive(Default)]
+    struct State {
+        events: VecDeque<TransmissionEvent>,
+        waker: Option<Waker>,
+        closed: bool,
+    }
+
+    /// The events not yet polled by an `EventStream`.
+    #[derive(Default)]
+    pub(super) struct Queue(Mutex<State>);
+
+    impl Queue {
+        /// Queues an event, returning `false` once the stream is gone.
+        pub(super) fn push(self: &Arc<Self>, event: TransmissionEvent) -> bool {
+            if Arc::strong_count(self) == 1 {
+                return false;
+            }
+            self.update(|state| state.events.push_back(event));
+            true
+        }
+
+        pub(super) fn close(&self) {
+            self.update(|state| state.closed = true);
+        }
+
+        fn update(&self, change: impl FnOnce(&mut State)) {
+            let waker = {
+                let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+                change(&mut state);
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    /// A stream of `TransmissionEvent`s, see `EventTransmitter::events`.
+    pub struct EventStream {
+        pub(super) queue: Arc<Queue>,
+    }
+
+    impl futures_core::Stream for EventStream {
+        type Item = TransmissionEvent;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let mut state = self.queue.0.lock().unwrap_or_else(|e| e.into_inner());
+            match state.events.pop_front() {
+                Some(event) => Poll::Ready(Some(event)),
+                None if state.closed => Poll::Ready(None),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[derive(Default)]
+    struct MockTransmitterFlaky {
+        fail: AtomicBool,
+    }
+
+    impl PulseTransmitter for MockTransmitterFlaky {
+        fn send_pulses(&self, _pulses: &[u32]) -> Result<()> {
+            if self.fail.load(Ordering::SeqCst) {
+                Err(Error::Transmitting("Mock failure".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_events_report_outcome() {
+        let transmitter = EventTransmitter::new(MockTransmitterFlaky::default());
+        let events = transmitter.subscribe();
+        transmitter.send_pulses(&[100, 200, 300]).unwrap();
+        transmitter.inner.fail.store(true, Ordering::SeqCst);
+        assert!(transmitter.send_pulses(&[100]).is_err());
+
+        assert_eq!(
+            events.recv().unwrap(),
+            TransmissionEvent::Sent {
+                airtime: Duration::from_micros(600)
+            }
+        );
+        assert!(matches!(
+            events.recv().unwrap(),
+            TransmissionEvent::Failed { error } if error.contains("Mock failure")
+        ));
+    }
+
+    #[test]
+    fn test_dropped_subscribers_are_removed() {
+        let transmitter = EventTransmitter::new(MockTransmitterFlaky::default());
+        drop(transmitter.subscribe());
+        let events = transmitter.subscribe();
+        transmitter.send_pulses(&[100]).unwrap();
+        assert_eq!(transmitter.subscribers.lock().unwrap().len(), 1);
+        assert!(events.try_recv().is_ok());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_event_stream() {
+        use futures_core::Stream;
+        use std::pin::Pin;
+        use std::task::{Context, Poll, Waker};
+
+        let transmitter = EventTransmitter::new(MockTransmitterFlaky::default());
+        let mut events = transmitter.events();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut poll = |events: &mut EventStream| Pin::new(events).poll_next(&mut cx);
+
+        assert_eq!(poll(&mut events), Poll::Pending);
+        transmitter.send_pulses(&[100]).unwrap();
+        assert!(matches!(
+            poll(&mut events),
+            Poll::Ready(Some(TransmissionEvent::Sent { .. }))
+        ));
+        drop(transmitter);
+        assert_eq!(poll(&mut events), Poll::Ready(None));
+    }
+}
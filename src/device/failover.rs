@@ -0,0 +1,187 @@
+use crate::clock::Clock;
+use crate::device::PulseTransmitter;
+use crate::{Error, Result};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+type FailoverHandler = Box<dyn Fn(&Error) + Send + Sync>;
+
+/// Sends with a primary transmitter and switches to a backup once the primary fails, so a show
+/// keeps running when the wiring of one emitter breaks.
+///
+/// The pulse train that failed on the primary is sent again on the backup, so no message is
+/// lost. From then on everything goes to the backup until `restore_primary` is called, e.g. after
+/// the primary was repaired. The carrier is set on both, so the backup is ready when needed.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, FailoverTransmitter, PulseTransmitterEmulator};
+///
+/// let transmitter = FailoverTransmitter::new(PulseTransmitterEmulator, PulseTransmitterEmulator)
+///     .with_failover_handler(|error| eprintln!("Primary emitter failed: {}", error));
+/// let brick_beam = BrickBeam::from_transmitter(transmitter);
+/// ```
+pub struct FailoverTransmitter<P: PulseTransmitter, B: PulseTransmitter> {
+    primary: P,
+    backup: B,
+    failure_threshold: u32,
+    failures: AtomicU32,
+    failed_over: AtomicBool,
+    handler: Option<FailoverHandler>,
+}
+
+impl<P: PulseTransmitter, B: PulseTransmitter> FailoverTransmitter<P, B> {
+    /// Creates a transmitter failing over to `backup` on the first error of `primary`.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The transmitter used while it works, usually the main emitter.
+    /// * `backup` - The transmitter taking over once the primary fails.
+    pub fn new(primary: P, backup: B) -> Self {
+        Self {
+            primary,
+            backup,
+            failure_threshold: 1,
+            failures: AtomicU32::new(0),
+            failed_over: AtomicBool::new(false),
+            handler: None,
+        }
+    }
+
+    /// Sets how many consecutive errors of the primary trigger the failover. Below the threshold,
+    /// the errors are returned to the caller. Values below 1 are treated as 1.
+    pub fn with_failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// Sets a handler called with the error of the primary when failing over.
+    pub fn with_failover_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&Error) + Send + Sync + 'static,
+    {
+        self.handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns `true` once the backup has taken over.
+    pub fn is_failed_over(&self) -> bool {
+        self.failed_over.load(Ordering::Relaxed)
+    }
+
+    /// Switches back to the primary transmitter.
+    pub fn restore_primary(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        self.failed_over.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns a reference to the primary transmitter.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Returns a reference to the backup transmitter.
+    pub fn backup(&self) -> &B {
+        &self.backup
+    }
+}
+
+impl<P: PulseTransmitter, B: PulseTransmitter> PulseTransmitter for FailoverTransmitter<P, B> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        if self.is_failed_over() {
+            return self.backup.send_pulses(pulses);
+        }
+        match self.primary.send_pulses(pulses) {
+            Ok(()) => {
+                self.failures.store(0, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(e) => {
+                if self.failures.fetch_add(1, Ordering::Relaxed) + 1 < self.failure_threshold {
+                    return Err(e);
+                }
+                if !self.failed_over.swap(true, Ordering::Relaxed) {
+                    if let Some(handler) = &self.handler {
+                        handler(&e);
+                    }
+                }
+                self.backup.send_pulses(pulses)
+            }
+        }
+    }
+
+    fn flush(&self) -> Result<()> {
+        if self.is_failed_over() {
+            self.backup.flush()
+        } else {
+            self.primary.flush()
+        }
+    }
+
+    fn settle(&self) {
+        self.primary.settle();
+        self.backup.settle();
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        let primary = self.primary.set_carrier(carrier, duty_cycle);
+        let backup = self.backup.set_carrier(carrier, duty_cycle);
+        if self.is_failed_over() {
+            backup
+        } else {
+            primary
+        }
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.primary.clock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_backup_takes_over_failed_message() {
+        let notified = Arc::new(Mutex::new(Vec::new()));
+        let handler_log = Arc::clone(&notified);
+        let transmitter = FailoverTransmitter::new(
+            MockTransmitterRecorder::default(),
+            MockTransmitterRecorder::default(),
+        )
+        .with_failover_handler(move |error| handler_log.lock().unwrap().push(error.to_string()));
+
+        transmitter.send_pulses(&[1]).unwrap();
+        transmitter.primary().set_failing(true);
+        transmitter.send_pulses(&[2]).unwrap();
+        transmitter.primary().set_failing(false);
+        transmitter.send_pulses(&[3]).unwrap();
+
+        assert!(transmitter.is_failed_over());
+        assert_eq!(transmitter.primary().sent(), [vec![1]]);
+        assert_eq!(transmitter.backup().sent(), [vec![2], vec![3]]);
+        assert_eq!(notified.lock().unwrap().len(), 1);
+
+        transmitter.restore_primary();
+        transmitter.send_pulses(&[4]).unwrap();
+        assert_eq!(transmitter.primary().sent(), [vec![1], vec![4]]);
+    }
+
+    #[test]
+    fn test_failures_below_threshold_returned() {
+        let transmitter = FailoverTransmitter::new(
+            MockTransmitterRecorder::default(),
+            MockTransmitterRecorder::default(),
+        )
+        .with_failure_threshold(2);
+        transmitter.primary().set_failing(true);
+
+        assert!(transmitter.send_pulses(&[1]).is_err());
+        assert!(!transmitter.is_failed_over());
+        transmitter.send_pulses(&[2]).unwrap();
+        assert!(transmitter.is_failed_over());
+        assert_eq!(transmitter.backup().sent(), [vec![2]]);
+    }
+}
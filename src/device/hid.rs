@@ -0,0 +1,257 @@
+//! # HID blasters
+//!
+//! `HidPulseTransmitter` drives a USB IR blaster built on a microcontroller board running
+//! custom firmware that enumerates as a HID device, which Windows, macOS and Linux all talk to
+//! without drivers, so a development machine can send real IR instead of only printing the
+//! pulses.
+//!
+//! The report protocol is brickbeam's own. Commercial HID blasters each speak an undocumented
+//! protocol of their vendor and are not supported; the firmware has to implement the one
+//! documented with `HidPulseTransmitter`.
+
+use crate::device::PulseTransmitter;
+use crate::protocols::timing::{CARRIER_HZ, DUTY_CYCLE};
+use crate::{Error, Result};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The size of every report, in bytes.
+const REPORT_SIZE: usize = 64;
+
+/// The most pulses one `0x02` report carries.
+const PULSES_PER_REPORT: usize = (REPORT_SIZE - 2) / 2;
+
+const CMD_SET_CARRIER: u8 = 0x01;
+const CMD_APPEND: u8 = 0x02;
+const CMD_TRANSMIT: u8 = 0x03;
+
+/// How long the blaster may take to answer beyond the airtime of the pulses.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Exchanges reports with a HID device.
+pub(crate) trait ReportChannel: Send {
+    /// Writes one output report.
+    fn write_report(&self, report: &[u8]) -> Result<()>;
+
+    /// Reads one input report, returning its length, or 0 if none arrived in time.
+    fn read_report(&self, report: &mut [u8], timeout: Duration) -> Result<usize>;
+}
+
+impl ReportChannel for hidapi::HidDevice {
+    fn write_report(&self, report: &[u8]) -> Result<()> {
+        // hidapi expects the report ID first, 0 for devices without IDs.
+        let mut data = Vec::with_capacity(report.len() + 1);
+        data.push(0);
+        data.extend_from_slice(report);
+        self.write(&data).map_err(hid_error)?;
+        Ok(())
+    }
+
+    fn read_report(&self, report: &mut [u8], timeout: Duration) -> Result<usize> {
+        let millis = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+        self.read_timeout(report, millis).map_err(hid_error)
+    }
+}
+
+fn hid_error(e: hidapi::HidError) -> Error {
+    Error::Transmitting(format!("HID blaster: {}", e))
+}
+
+/// Transmits pulses with a custom-firmware USB HID IR blaster, on any platform hidapi supports.
+///
+/// No off-the-shelf blaster speaks this protocol. It is defined by brickbeam, simple enough for a
+/// few lines of custom firmware on any USB microcontroller board (Pro Micro, RP2040, ...) with an
+/// IR LED. Every output report is 64 bytes, without report ID, starting with a command byte:
+///
+/// | Command | Arguments                                   | Effect                             |
+/// |---------|---------------------------------------------|------------------------------------|
+/// | `0x01`  | carrier (Hz) as `u32`, duty cycle (%) as u8 | sets the modulation                |
+/// | `0x02`  | count (≤ 31), then that many `u16` pulses   | appends marks and spaces (µs)      |
+/// | `0x03`  |                                             | transmits the appended pulses      |
+///
+/// Numbers are little-endian. A mark of 0 µs keeps the LED off, so spaces longer than a `u16`
+/// are sent as several spaces joined by empty marks. After transmitting, the blaster answers with
+/// an input report whose first byte is 0 on success, or the number of an error, e.g. 1 if the
+/// pulses overflowed its buffer. The pulses of a transmission start with a mark.
+///
+/// The blaster is set to the PF carrier of 38 kHz at 33% duty cycle when opening it.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::{BrickBeam, HidPulseTransmitter, Result};
+///
+/// fn main() -> Result<()> {
+///     // The vendor and product ID the blaster's firmware enumerates with.
+///     let transmitter = HidPulseTransmitter::open(0x1209, 0x4242)?;
+///     let brick_beam = BrickBeam::from_transmitter(transmitter);
+///     Ok(())
+/// }
+/// ```
+pub struct HidPulseTransmitter {
+    channel: Mutex<Box<dyn ReportChannel>>,
+}
+
+impl HidPulseTransmitter {
+    /// Opens the first HID device with the given vendor and product ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Transmitting` if no such device can be opened, e.g. because it isn't
+    /// plugged in or, on Linux, its hidraw node isn't accessible.
+    pub fn open(vendor_id: u16, product_id: u16) -> Result<Self> {
+        let api = hidapi::HidApi::new().map_err(hid_error)?;
+        let device = api.open(vendor_id, product_id).map_err(|e| {
+            Error::Transmitting(format!(
+                "Can't open the HID blaster {:04x}:{:04x}: {}",
+                vendor_id, product_id, e
+            ))
+        })?;
+        Self::from_channel(Box::new(device))
+    }
+
+    pub(crate) fn from_channel(channel: Box<dyn ReportChannel>) -> Result<Self> {
+        let transmitter = Self {
+            channel: Mutex::new(channel),
+        };
+        transmitter.set_carrier(CARRIER_HZ, DUTY_CYCLE)?;
+        Ok(transmitter)
+    }
+}
+
+impl PulseTransmitter for HidPulseTransmitter {
+    /// Transmits the pulses, returning once the blaster reports them sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Transmitting` if the blaster reports an error or doesn't answer.
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let units = split_long_pulses(pulses);
+        let channel = self.channel.lock().unwrap_or_else(|e| e.into_inner());
+        for chunk in units.chunks(PULSES_PER_REPORT) {
+            let mut report = [0u8; REPORT_SIZE];
+            report[0] = CMD_APPEND;
+            report[1] = chunk.len() as u8;
+            for (bytes, pulse) in report[2..].chunks_exact_mut(2).zip(chunk) {
+                bytes.copy_from_slice(&pulse.to_le_bytes());
+            }
+            channel.write_report(&report)?;
+        }
+        let mut report = [0u8; REPORT_SIZE];
+        report[0] = CMD_TRANSMIT;
+        channel.write_report(&report)?;
+
+        let airtime: u64 = pulses.iter().map(|&pulse| u64::from(pulse)).sum();
+        let mut reply = [0u8; REPORT_SIZE];
+        match channel.read_report(&mut reply, Duration::from_micros(airtime) + REPLY_TIMEOUT)? {
+            0 => Err(Error::Transmitting(
+                "The HID blaster did not confirm the transmission".into(),
+            )),
+            _ if reply[0] == 0 => Ok(()),
+            _ => Err(Error::Transmitting(format!(
+                "The HID blaster failed with error {}",
+                reply[0]
+            ))),
+        }
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        let mut report = [0u8; REPORT_SIZE];
+        report[0] = CMD_SET_CARRIER;
+        report[1..5].copy_from_slice(&carrier.to_le_bytes());
+        report[5] = duty_cycle.min(100) as u8;
+        let channel = self.channel.lock().unwrap_or_else(|e| e.into_inner());
+        channel.write_report(&report)
+    }
+}
+
+/// Converts the pulses to `u16`, splitting longer spaces (and marks) into several joined by
+/// empty pulses of the other kind.
+fn split_long_pulses(pulses: &[u32]) -> Vec<u16> {
+    let mut units = Vec::with_capacity(pulses.len());
+    for &pulse in pulses {
+        let mut rest = pulse;
+        while rest > u32::from(u16::MAX) {
+            units.extend_from_slice(&[u16::MAX, 0]);
+            rest -= u32::from(u16::MAX);
+        }
+        units.push(rest as u16);
+    }
+    units
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A blaster answering every transmission with `status`, recording the reports written.
+    struct FakeBlaster {
+        written: Arc<Mutex<Vec<Vec<u8>>>>,
+        status: Option<u8>,
+    }
+
+    impl ReportChannel for FakeBlaster {
+        fn write_report(&self, report: &[u8]) -> Result<()> {
+            self.written.lock().unwrap().push(report.to_vec());
+            Ok(())
+        }
+
+        fn read_report(&self, report: &mut [u8], _timeout: Duration) -> Result<usize> {
+            match self.status {
+                Some(status) => {
+                    report[0] = status;
+                    Ok(REPORT_SIZE)
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    fn blaster(status: Option<u8>) -> (HidPulseTransmitter, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let channel = FakeBlaster {
+            written: Arc::clone(&written),
+            status,
+        };
+        (
+            HidPulseTransmitter::from_channel(Box::new(channel)).unwrap(),
+            written,
+        )
+    }
+
+    #[test]
+    fn test_pulses_sent_in_reports() {
+        let (transmitter, written) = blaster(Some(0));
+        let pulses = vec![157u32; 35];
+        transmitter.send_pulses(&pulses).unwrap();
+
+        let written = written.lock().unwrap();
+        assert_eq!(written[0][..6], [0x01, 0x70, 0x94, 0x00, 0x00, 33]);
+        assert_eq!(written[1][..4], [0x02, 31, 157, 0]);
+        assert_eq!(written[2][..2], [0x02, 4]);
+        assert_eq!(written[3][0], 0x03);
+        assert!(written.iter().all(|report| report.len() == REPORT_SIZE));
+    }
+
+    #[test]
+    fn test_blaster_errors_reported() {
+        let (overflowing, _) = blaster(Some(1));
+        assert!(matches!(
+            overflowing.send_pulses(&[157, 1026]),
+            Err(Error::Transmitting(_))
+        ));
+        let (silent, _) = blaster(None);
+        assert!(matches!(
+            silent.send_pulses(&[157, 1026]),
+            Err(Error::Transmitting(_))
+        ));
+    }
+
+    #[test]
+    fn test_long_spaces_split() {
+        assert_eq!(
+            split_long_pulses(&[157, 140_000, 157]),
+            [157, 65535, 0, 65535, 0, 8930, 157]
+        );
+    }
+}
@@ -0,0 +1,608 @@
+//! # Hotplug monitoring
+//!
+//! A LIRC device node can appear after the application started (the `gpio-ir-tx` overlay is still
+//! initializing when a boot-time daemon launches) or come and go at runtime (a USB blaster being
+//! unplugged). `HotplugTransmitter` watches the device node from a background thread, opens the
+//! wrapped transmitter once the node shows up, drops it once it disappears, and reports every
+//! transition as a `DeviceEvent` to any subscriber.
+//!
+//! The watcher polls the device path instead of listening on a udev netlink socket, which keeps
+//! the crate free of libudev and works identically for device nodes created by overlays, udev
+//! rules, or `mknod`. It compares the device and inode numbers of the node between polls, so a
+//! blaster unplugged and replugged within one poll interval is still reopened, and a failed write
+//! reopens the device at once.
+//!
+//! A replug also leaves the send that hit the stale descriptor failed. With a `ReconnectPolicy`
+//! (`with_reconnect_policy`), the transmitter keeps reopening the device for a while and retries
+//! that send, so a running show survives the hiccup. A carrier set with `set_carrier` is applied
+//! again whenever the device is reopened.
+//!
+//! The poll interval and the pauses between reconnect attempts are taken on a `Clock`
+//! (`with_clock`), so tests drive the transmitter with a `MockClock` instead of waiting for it.
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::device::PulseTransmitter;
+use crate::{Error, Result};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A change of the watched device reported by `HotplugTransmitter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// The device node appeared and the transmitter was (re)bound to it.
+    Bound(PathBuf),
+    /// The device node appeared but opening the transmitter failed.
+    BindFailed(PathBuf, String),
+    /// The device node disappeared and the transmitter was released.
+    Unbound(PathBuf),
+}
+
+/// How persistently `HotplugTransmitter` reopens its device to retry a failed send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// How often reopening is tried before the send fails. 0 disables retrying.
+    pub attempts: u32,
+    /// The pause before every attempt, giving the device node time to come back.
+    pub delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    /// Tries for 5 seconds, which covers re-plugging a USB blaster and reloading an overlay.
+    fn default() -> Self {
+        Self {
+            attempts: 10,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+type Opener<T> = Box<dyn Fn(&Path) -> Result<T> + Send + Sync>;
+
+/// The device and inode numbers of a device node, which change when the node is recreated.
+type NodeId = (u64, u64);
+
+fn node_id(path: &Path) -> Option<NodeId> {
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+/// What the watcher thread is told between polls, guarded by `Shared::watch`.
+struct Watch {
+    clock: Arc<dyn Clock>,
+    // Set when the clock is replaced, so the running interval is taken on the new one.
+    restart: bool,
+    stop: bool,
+}
+
+struct Shared<T> {
+    path: PathBuf,
+    open: Opener<T>,
+    transmitter: Mutex<Option<T>>,
+    // The node the transmitter was opened on, guarded by the `transmitter` lock.
+    bound_node: Mutex<Option<NodeId>>,
+    bind_failed: AtomicBool,
+    carrier: Mutex<Option<(u32, u32)>>,
+    subscribers: Mutex<Vec<Sender<DeviceEvent>>>,
+    watch: Mutex<Watch>,
+    changed: Condvar,
+}
+
+impl<T: PulseTransmitter> Shared<T> {
+    fn watch(&self) -> MutexGuard<'_, Watch> {
+        self.watch.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.watch().clock)
+    }
+
+    /// Refreshes every `poll_interval` on the clock of the watch until it is stopped.
+    fn run_watcher(&self, poll_interval: Duration) {
+        let mut watch = self.watch();
+        loop {
+            let clock = Arc::clone(&watch.clock);
+            let deadline = clock.now() + poll_interval;
+            while !watch.stop && !watch.restart && clock.now() < deadline {
+                watch = clock::wait_until(&*clock, &self.changed, watch, deadline);
+            }
+            if watch.stop {
+                return;
+            }
+            if std::mem::take(&mut watch.restart) {
+                continue;
+            }
+            drop(watch);
+            self.refresh();
+            watch = self.watch();
+        }
+    }
+
+    fn emit(&self, event: DeviceEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Binds or releases the transmitter according to whether the device node exists.
+    ///
+    /// A node recreated since the transmitter was opened is released and bound again, with the
+    /// carrier set last. A failed bind is retried on every refresh but reported only once per
+    /// appearance.
+    fn refresh(&self) {
+        let node = node_id(&self.path);
+        let Ok(mut transmitter) = self.transmitter.lock() else {
+            return;
+        };
+        let mut bound_node = self.bound_node.lock().unwrap_or_else(|e| e.into_inner());
+        if transmitter.is_some() && node != *bound_node {
+            *transmitter = None;
+            self.emit(DeviceEvent::Unbound(self.path.clone()));
+        }
+        match (node, transmitter.is_some()) {
+            (Some(node), false) => match self.open_with_carrier() {
+                Ok(opened) => {
+                    *transmitter = Some(opened);
+                    *bound_node = Some(node);
+                    self.bind_failed.store(false, Ordering::Relaxed);
+                    self.emit(DeviceEvent::Bound(self.path.clone()));
+                }
+                Err(e) => {
+                    if !self.bind_failed.swap(true, Ordering::Relaxed) {
+                        self.emit(DeviceEvent::BindFailed(self.path.clone(), e.to_string()));
+                    }
+                }
+            },
+            (None, _) => self.bind_failed.store(false, Ordering::Relaxed),
+            (Some(_), true) => (),
+        }
+    }
+
+    /// Opens the transmitter and applies the carrier set last, if any.
+    fn open_with_carrier(&self) -> Result<T> {
+        let opened = (self.open)(&self.path)?;
+        let carrier = *self.carrier.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((carrier, duty_cycle)) = carrier {
+            opened.set_carrier(carrier, duty_cycle)?;
+        }
+        Ok(opened)
+    }
+
+    /// Sends on the bound transmitter, failing if the device is absent.
+    fn send(&self, pulses: &[u32]) -> Result<()> {
+        let transmitter = self
+            .transmitter
+            .lock()
+            .map_err(|e| Error::Transmitting(format!("Lock error: {}", e)))?;
+        match transmitter.as_ref() {
+            Some(transmitter) => transmitter.send_pulses(pulses),
+            None => Err(Error::Transmitting(format!(
+                "Device {} is not available",
+                self.path.display()
+            ))),
+        }
+    }
+
+    /// Releases the transmitter after a failed write and binds it again if the node is there.
+    fn rebind(&self) {
+        if let Ok(mut transmitter) = self.transmitter.lock() {
+            if transmitter.take().is_some() {
+                self.emit(DeviceEvent::Unbound(self.path.clone()));
+            }
+        }
+        self.refresh();
+    }
+}
+
+/// A `PulseTransmitter` that follows a device node as it appears and disappears.
+///
+/// Sending while the device is absent fails with `Error::Transmitting` instead of blocking, so the
+/// application decides whether to drop or retry the message. Subscribe to `DeviceEvent`s to learn
+/// when the transmitter becomes usable again.
+///
+/// A write that fails with `Error::Io` or `Error::Transmitting` releases the transmitter and
+/// opens the device again right away, so a descriptor left stale by a replug doesn't wait for the
+/// next poll. The failed message is only repeated with a `ReconnectPolicy`, which then also
+/// retries sends while the device is absent.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, HotplugTransmitter, PulseTransmitter, Result};
+/// use std::time::Duration;
+///
+/// struct Printer;
+/// impl PulseTransmitter for Printer {
+///     fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+///         println!("{:?}", pulses);
+///         Ok(())
+///     }
+/// }
+///
+/// let transmitter =
+///     HotplugTransmitter::new("/dev/lirc0", Duration::from_millis(500), |_| Ok(Printer));
+/// let events = transmitter.subscribe();
+/// let brick_beam = BrickBeam::from_transmitter(transmitter);
+/// ```
+pub struct HotplugTransmitter<T: PulseTransmitter + Send + 'static> {
+    shared: Arc<Shared<T>>,
+    watcher: Option<JoinHandle<()>>,
+    reconnect: Option<ReconnectPolicy>,
+}
+
+impl<T: PulseTransmitter + Send + 'static> HotplugTransmitter<T> {
+    /// Creates a new transmitter watching `tx_device_path` every `poll_interval`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_device_path` - The device node to follow, e.g. /dev/lirc0.
+    /// * `poll_interval` - How often the watcher checks for the device node.
+    /// * `open` - Opens the wrapped transmitter once the device node is present,
+    ///   e.g. `CirPulseTransmitter::new`.
+    pub fn new<F>(tx_device_path: impl AsRef<Path>, poll_interval: Duration, open: F) -> Self
+    where
+        F: Fn(&Path) -> Result<T> + Send + Sync + 'static,
+    {
+        let shared = Arc::new(Shared {
+            path: tx_device_path.as_ref().to_path_buf(),
+            open: Box::new(open),
+            transmitter: Mutex::new(None),
+            bound_node: Mutex::new(None),
+            bind_failed: AtomicBool::new(false),
+            carrier: Mutex::new(None),
+            subscribers: Mutex::new(Vec::new()),
+            watch: Mutex::new(Watch {
+                clock: Arc::new(SystemClock),
+                restart: false,
+                stop: false,
+            }),
+            changed: Condvar::new(),
+        });
+        shared.refresh();
+
+        let watcher = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || shared.run_watcher(poll_interval))
+        };
+
+        Self {
+            shared,
+            watcher: Some(watcher),
+            reconnect: None,
+        }
+    }
+
+    /// Retries a failed send according to `policy`, reopening the device before every attempt.
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Sets the clock the poll interval and the pauses between reconnect attempts are taken on.
+    pub fn with_clock(self, clock: impl Clock + 'static) -> Self {
+        {
+            let mut watch = self.shared.watch();
+            watch.clock = Arc::new(clock);
+            watch.restart = true;
+        }
+        self.shared.changed.notify_all();
+        self
+    }
+
+    /// Returns a receiver for all device events from now on.
+    pub fn subscribe(&self) -> Receiver<DeviceEvent> {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut subscribers) = self.shared.subscribers.lock() {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    /// Returns `true` if the device is currently present and bound.
+    pub fn is_bound(&self) -> bool {
+        self.shared
+            .transmitter
+            .lock()
+            .map(|transmitter| transmitter.is_some())
+            .unwrap_or(false)
+    }
+}
+
+impl<T: PulseTransmitter + Send + 'static> PulseTransmitter for HotplugTransmitter<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let bound = self.is_bound();
+        let error = match self.shared.send(pulses) {
+            Err(e @ (Error::Io(_) | Error::Transmitting(_))) => e,
+            result => return result,
+        };
+        if bound {
+            self.shared.rebind();
+        }
+        let Some(policy) = self.reconnect else {
+            return Err(error);
+        };
+        let clock = self.shared.clock();
+        for _ in 0..policy.attempts {
+            clock.sleep(policy.delay);
+            self.shared.refresh();
+            match self.shared.send(pulses) {
+                Err(Error::Io(_) | Error::Transmitting(_)) => self.shared.rebind(),
+                result => return result,
+            }
+        }
+        Err(error)
+    }
+
+    fn flush(&self) -> Result<()> {
+        let transmitter = self
+            .shared
+            .transmitter
+            .lock()
+            .map_err(|e| Error::Transmitting(format!("Lock error: {}", e)))?;
+        match transmitter.as_ref() {
+            Some(transmitter) => transmitter.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn settle(&self) {
+        if let Ok(transmitter) = self.shared.transmitter.lock() {
+            if let Some(transmitter) = transmitter.as_ref() {
+                transmitter.settle();
+            }
+        }
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        *self
+            .shared
+            .carrier
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some((carrier, duty_cycle));
+        let transmitter = self
+            .shared
+            .transmitter
+            .lock()
+            .map_err(|e| Error::Transmitting(format!("Lock error: {}", e)))?;
+        match transmitter.as_ref() {
+            Some(transmitter) => transmitter.set_carrier(carrier, duty_cycle),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: PulseTransmitter + Send + 'static> Drop for HotplugTransmitter<T> {
+    fn drop(&mut self) {
+        self.shared.watch().stop = true;
+        self.shared.changed.notify_all();
+        if let Some(watcher) = self.watcher.take() {
+            let _ = watcher.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::sync::atomic::AtomicU32;
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+    struct MockTransmitterSuccess;
+    impl PulseTransmitter for MockTransmitterSuccess {
+        fn send_pulses(&self, _pulses: &[u32]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn fake_device(test: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("brickbeam-hotplug-{}-{}", test, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// Polls the watcher on `clock` until it reports the next event.
+    fn next_event(clock: &MockClock, events: &Receiver<DeviceEvent>) -> DeviceEvent {
+        loop {
+            if let Ok(event) = events.try_recv() {
+                return event;
+            }
+            clock.advance(POLL_INTERVAL);
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_hotplug_binds_and_unbinds() {
+        let path = fake_device("bind");
+        let clock = Arc::new(MockClock::new());
+        let transmitter =
+            HotplugTransmitter::new(&path, POLL_INTERVAL, |_| Ok(MockTransmitterSuccess))
+                .with_clock(Arc::clone(&clock));
+        let events = transmitter.subscribe();
+        assert!(!transmitter.is_bound());
+        assert!(transmitter.send_pulses(&[157, 1026]).is_err());
+
+        fs::write(&path, "").unwrap();
+        let event = next_event(&clock, &events);
+        assert_eq!(event, DeviceEvent::Bound(path.clone()));
+        assert!(transmitter.send_pulses(&[157, 1026]).is_ok());
+
+        fs::remove_file(&path).unwrap();
+        let event = next_event(&clock, &events);
+        assert_eq!(event, DeviceEvent::Unbound(path.clone()));
+        assert!(transmitter.send_pulses(&[157, 1026]).is_err());
+    }
+
+    #[test]
+    fn test_hotplug_rebinds_replaced_node() {
+        let path = fake_device("replace");
+        fs::write(&path, "").unwrap();
+        let clock = Arc::new(MockClock::new());
+        let transmitter =
+            HotplugTransmitter::new(&path, POLL_INTERVAL, |_| Ok(MockTransmitterSuccess))
+                .with_clock(Arc::clone(&clock));
+        let events = transmitter.subscribe();
+        assert!(transmitter.is_bound());
+
+        // A new node at the same path, without a poll ever seeing the path missing.
+        let replacement = path.with_extension("new");
+        fs::write(&replacement, "").unwrap();
+        fs::rename(&replacement, &path).unwrap();
+        let event = next_event(&clock, &events);
+        assert_eq!(event, DeviceEvent::Unbound(path.clone()));
+        let event = next_event(&clock, &events);
+        assert_eq!(event, DeviceEvent::Bound(path.clone()));
+        drop(transmitter);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_hotplug_reopens_after_write_error() {
+        struct MockTransmitterFail;
+        impl PulseTransmitter for MockTransmitterFail {
+            fn send_pulses(&self, _pulses: &[u32]) -> Result<()> {
+                Err(Error::Transmitting("Mock failure".to_string()))
+            }
+        }
+
+        let path = fake_device("reopen");
+        fs::write(&path, "").unwrap();
+        let opened = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&opened);
+        let transmitter = HotplugTransmitter::new(&path, POLL_INTERVAL, move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Ok(MockTransmitterFail)
+        })
+        .with_clock(MockClock::new());
+        let events = transmitter.subscribe();
+
+        assert!(transmitter.send_pulses(&[157, 1026]).is_err());
+        assert_eq!(opened.load(Ordering::Relaxed), 2);
+        assert_eq!(events.try_recv(), Ok(DeviceEvent::Unbound(path.clone())));
+        assert_eq!(events.try_recv(), Ok(DeviceEvent::Bound(path.clone())));
+        assert!(transmitter.is_bound());
+        drop(transmitter);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_hotplug_reports_bind_failure() {
+        let path = fake_device("fail");
+        let clock = Arc::new(MockClock::new());
+        let transmitter = HotplugTransmitter::new(
+            &path,
+            POLL_INTERVAL,
+            |_| -> Result<MockTransmitterSuccess> {
+                Err(Error::Transmitting("Mock failure".to_string()))
+            },
+        )
+        .with_clock(Arc::clone(&clock));
+        let events = transmitter.subscribe();
+
+        fs::write(&path, "").unwrap();
+        match next_event(&clock, &events) {
+            DeviceEvent::BindFailed(failed, msg) => {
+                assert_eq!(failed, path);
+                assert!(msg.contains("Mock failure"));
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+        drop(transmitter);
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// A device whose handles break when it is unplugged, like a descriptor left stale by a
+    /// replug, and which records what its handles send.
+    #[derive(Default)]
+    struct FakeDevice {
+        generation: AtomicU32,
+        sent: Mutex<Vec<Vec<u32>>>,
+        carriers: Mutex<Vec<u32>>,
+    }
+
+    struct Handle {
+        device: Arc<FakeDevice>,
+        generation: u32,
+    }
+
+    impl PulseTransmitter for Handle {
+        fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+            if self.generation != self.device.generation.load(Ordering::Relaxed) {
+                return Err(Error::Transmitting("No such device".to_string()));
+            }
+            self.device.sent.lock().unwrap().push(pulses.to_vec());
+            Ok(())
+        }
+
+        fn set_carrier(&self, carrier: u32, _duty_cycle: u32) -> Result<()> {
+            self.device.carriers.lock().unwrap().push(carrier);
+            Ok(())
+        }
+    }
+
+    fn reconnecting(
+        path: &Path,
+        device: &Arc<FakeDevice>,
+        clock: &Arc<MockClock>,
+    ) -> HotplugTransmitter<Handle> {
+        let opened = Arc::clone(device);
+        HotplugTransmitter::new(path, POLL_INTERVAL, move |_| {
+            Ok(Handle {
+                device: Arc::clone(&opened),
+                generation: opened.generation.load(Ordering::Relaxed),
+            })
+        })
+        .with_reconnect_policy(ReconnectPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(100),
+        })
+        .with_clock(Arc::clone(clock))
+    }
+
+    #[test]
+    fn test_reconnect_retries_send_after_replug() {
+        let path = fake_device("replug");
+        fs::write(&path, "").unwrap();
+        let device = Arc::new(FakeDevice::default());
+        let clock = Arc::new(MockClock::new());
+        let transmitter = reconnecting(&path, &device, &clock);
+        transmitter.set_carrier(36_000, 33).unwrap();
+        transmitter.send_pulses(&[1]).unwrap();
+
+        device.generation.fetch_add(1, Ordering::Relaxed);
+        transmitter.send_pulses(&[2]).unwrap();
+
+        assert!(transmitter.is_bound());
+        assert_eq!(*device.sent.lock().unwrap(), [vec![1], vec![2]]);
+        assert_eq!(*device.carriers.lock().unwrap(), [36_000, 36_000]);
+        drop(transmitter);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reconnect_attempts_bounded_and_retried_later() {
+        let path = fake_device("unplug");
+        let device = Arc::new(FakeDevice::default());
+        let clock = Arc::new(MockClock::new());
+        let transmitter = reconnecting(&path, &device, &clock);
+
+        let error = transmitter.send_pulses(&[1]).unwrap_err();
+        assert!(error.to_string().contains("not available"));
+        assert_eq!(clock.slept(), Duration::from_millis(300));
+        assert!(!transmitter.is_bound());
+
+        fs::write(&path, "").unwrap();
+        transmitter.send_pulses(&[2]).unwrap();
+        assert_eq!(*device.sent.lock().unwrap(), [vec![2]]);
+        drop(transmitter);
+        fs::remove_file(&path).unwrap();
+    }
+}
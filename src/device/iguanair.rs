@@ -0,0 +1,159 @@
+use crate::clock::Clock;
+use crate::device::{
+    enumerate_rc_devices, open_default, DefaultPulseTransmitter, PulseTransmitter, RcDevice,
+};
+use crate::Result;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The rc-core driver name of IguanaWorks USB IR transceivers.
+pub const IGUANAIR_DRIVER: &str = "iguanair";
+
+/// Spaces at least this long (µs) end a burst the transceiver is given on its own. PF symbols are
+/// at most 1.2 ms, so only the gaps between frames and messages qualify.
+const SPLIT_SPACE_MICROS: u32 = 2_000;
+
+/// Transmits pulses with an IguanaWorks USB IR transceiver.
+///
+/// The kernel's `iguanair` driver exposes the transceiver as a `/dev/lircN` device, which the
+/// wrapped transmitter (usually the `DefaultPulseTransmitter`) sends to. The `igdaemon` of the
+/// IguanaWorks user space tools claims the USB device for itself, so it must not be running.
+///
+/// The transceiver buffers a whole transmission before sending it, and its buffer is too small
+/// for a message with its repeats. The pulses are therefore handed over one burst at a time,
+/// split at the gaps between frames, which are waited out on the transmitter's clock.
+///
+/// Models with several emitters select them with `set_transmitter_mask` of the wrapped
+/// transmitter, e.g. `inner().set_transmitter_mask(0b0011)` for the first two.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::{BrickBeam, IguanaIrTransmitter, Result};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::from_transmitter(IguanaIrTransmitter::open()?);
+///     Ok(())
+/// }
+/// ```
+pub struct IguanaIrTransmitter<T: PulseTransmitter> {
+    inner: T,
+}
+
+impl<T: PulseTransmitter> IguanaIrTransmitter<T> {
+    /// Wraps the transmitter of an already opened IguanaWorks transceiver.
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a reference to the wrapped transmitter.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl IguanaIrTransmitter<DefaultPulseTransmitter> {
+    /// Opens the first IguanaWorks transceiver listed in `/sys/class/rc`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Io` with `ErrorKind::NotFound` if no transceiver is bound to the
+    /// `iguanair` driver.
+    pub fn open() -> Result<Self> {
+        let devices = enumerate_rc_devices()?;
+        let tx_device_path = find_iguanair(&devices).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no IguanaWorks transceiver found, is the iguanair module loaded?",
+            )
+        })?;
+        Self::open_device(tx_device_path)
+    }
+
+    /// Opens the IguanaWorks transceiver at the given LIRC device path, e.g. `/dev/lirc1`.
+    pub fn open_device(tx_device_path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(open_default(tx_device_path)?))
+    }
+}
+
+impl<T: PulseTransmitter> PulseTransmitter for IguanaIrTransmitter<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let clock = self.inner.clock();
+        let mut start = 0;
+        // Spaces are at odd indices; the last pulse goes out with the final burst.
+        for space in (1..pulses.len().saturating_sub(1)).step_by(2) {
+            if pulses[space] >= SPLIT_SPACE_MICROS {
+                self.inner.send_pulses(&pulses[start..space])?;
+                clock.sleep(Duration::from_micros(u64::from(pulses[space])));
+                start = space + 1;
+            }
+        }
+        self.inner.send_pulses(&pulses[start..])
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn settle(&self) {
+        self.inner.settle()
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.inner.set_carrier(carrier, duty_cycle)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock()
+    }
+}
+
+/// Returns the LIRC device of the first IguanaWorks transceiver among `devices`.
+pub(crate) fn find_iguanair(devices: &[RcDevice]) -> Option<PathBuf> {
+    devices
+        .iter()
+        .find(|device| device.driver == IGUANAIR_DRIVER)
+        .and_then(|device| device.lirc_device.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use crate::protocols::timing::PulseTiming;
+
+    #[test]
+    fn test_pulses_sent_burst_by_burst() {
+        let transmitter = IguanaIrTransmitter::new(MockTransmitterRecorder::default());
+        let frame = PulseTiming::STANDARD.encode_frame(0x4D53);
+        let mut pulses = frame[..35].to_vec();
+        pulses.push(16_000);
+        pulses.extend_from_slice(&frame);
+        transmitter.send_pulses(&pulses).unwrap();
+
+        let sent = transmitter.inner().sent();
+        assert_eq!(*sent, [frame[..35].to_vec(), frame.to_vec()]);
+        assert_eq!(transmitter.inner().clock.slept(), Duration::from_millis(16));
+    }
+
+    #[test]
+    fn test_find_iguanair_by_driver() {
+        let devices = [
+            RcDevice {
+                name: "rc0".into(),
+                driver: "gpio-ir-tx".into(),
+                lirc_device: Some("/dev/lirc0".into()),
+                ..RcDevice::default()
+            },
+            RcDevice {
+                name: "rc1".into(),
+                driver: IGUANAIR_DRIVER.into(),
+                lirc_device: Some("/dev/lirc1".into()),
+                has_input: true,
+                ..RcDevice::default()
+            },
+        ];
+        assert_eq!(find_iguanair(&devices), Some(PathBuf::from("/dev/lirc1")));
+        assert_eq!(find_iguanair(&devices[..1]), None);
+    }
+}
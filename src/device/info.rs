@@ -0,0 +1,110 @@
+use crate::device::discover::LircDevice;
+use crate::device::sysfs::{enumerate_rc_devices, RcDevice};
+use std::io;
+use std::path::Path;
+
+/// The most values the kernel accepts in one LIRC write (`LIRCBUF_SIZE` of `lirc_dev.c`).
+pub const LIRC_MAX_PULSES: usize = 1024;
+
+/// What an open LIRC transmitter is and can do, e.g. to show device info or to adapt to drivers
+/// that can't change the carrier.
+///
+/// The features come from the driver, the names from the rc-core metadata in `/sys/class/rc`,
+/// which isn't available on every system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransmitterInfo {
+    /// The path and features of the LIRC device.
+    pub device: LircDevice,
+    /// The kernel driver name, e.g. `gpio-ir-tx`, if found in sysfs.
+    pub driver: Option<String>,
+    /// The human readable device name reported by the driver, if found in sysfs.
+    pub device_name: Option<String>,
+    /// The most marks and spaces one message may have. Longer pulse trains are rejected by the
+    /// kernel.
+    pub max_pulses: usize,
+}
+
+impl TransmitterInfo {
+    /// Returns whether the duty cycle of the carrier can be changed.
+    pub fn can_set_duty_cycle(&self) -> bool {
+        self.device.can_set_duty_cycle
+    }
+
+    /// Returns whether the carrier frequency can be changed.
+    pub fn can_set_carrier(&self) -> bool {
+        self.device.can_set_carrier
+    }
+
+    /// Combines the features of `device` with the names of its entry in `rc_devices`.
+    fn new(device: LircDevice, rc_devices: &[RcDevice]) -> Self {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let path = canonical(&device.path);
+        let rc = rc_devices.iter().find(|rc| {
+            rc.lirc_device
+                .as_deref()
+                .is_some_and(|lirc| canonical(lirc) == path)
+        });
+        Self {
+            driver: rc
+                .map(|rc| rc.driver.clone())
+                .filter(|name| !name.is_empty()),
+            device_name: rc
+                .map(|rc| rc.device_name.clone())
+                .filter(|name| !name.is_empty()),
+            device,
+            max_pulses: LIRC_MAX_PULSES,
+        }
+    }
+}
+
+/// Queries the driver of the LIRC device at `path` and looks up its names in sysfs.
+pub(crate) fn transmitter_info(path: &Path) -> io::Result<TransmitterInfo> {
+    let device = LircDevice::probe(path)?;
+    let rc_devices = enumerate_rc_devices().unwrap_or_default();
+    Ok(TransmitterInfo::new(device, &rc_devices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn lirc(path: &str, can_set_duty_cycle: bool) -> LircDevice {
+        LircDevice {
+            path: PathBuf::from(path),
+            can_send: true,
+            can_receive: false,
+            can_set_carrier: true,
+            can_set_duty_cycle,
+            can_set_transmitter_mask: false,
+        }
+    }
+
+    #[test]
+    fn test_info_names_from_sysfs() {
+        let rc_devices = [
+            RcDevice {
+                name: "rc0".into(),
+                driver: "gpio_ir_recv".into(),
+                lirc_device: Some("/dev/lirc0".into()),
+                ..RcDevice::default()
+            },
+            RcDevice {
+                name: "rc1".into(),
+                device_name: "gpio-ir-tx".into(),
+                driver: "gpio-ir-tx".into(),
+                lirc_device: Some("/dev/lirc1".into()),
+                ..RcDevice::default()
+            },
+        ];
+        let info = TransmitterInfo::new(lirc("/dev/lirc1", false), &rc_devices);
+        assert_eq!(info.driver.as_deref(), Some("gpio-ir-tx"));
+        assert_eq!(info.device_name.as_deref(), Some("gpio-ir-tx"));
+        assert!(info.can_set_carrier() && !info.can_set_duty_cycle());
+        assert_eq!(info.max_pulses, 1024);
+
+        let unknown = TransmitterInfo::new(lirc("/dev/lirc7", true), &rc_devices);
+        assert_eq!((&unknown.driver, &unknown.device_name), (&None, &None));
+        assert!(unknown.can_set_duty_cycle());
+    }
+}
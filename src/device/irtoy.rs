@@ -0,0 +1,308 @@
+//! # Serial IR dongles
+//!
+//! `IrToyTransmitter` drives the Dangerous Prototypes USB IR Toy and the IRdroid, which speaks
+//! the same protocol, through their USB serial port (usually `/dev/ttyACM0`). They work on any
+//! Linux box with a USB port, without GPIO IR hardware or a LIRC driver.
+//!
+//! The dongle is switched into its sampling mode once, after which every transmission is:
+//! - the transmit command, followed by the marks and spaces as big-endian 16-bit counts of
+//!   21.33 µs ticks and the `0xFFFF` terminator,
+//! - sent in packets of at most 62 bytes, each one after the dongle's handshake asking for it,
+//! - and answered with the number of bytes transmitted and `C` on completion, or `F` if the
+//!   dongle ran out of data.
+
+use crate::device::PulseTransmitter;
+use crate::protocols::timing::CARRIER_HZ;
+use crate::{Error, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The serial port the USB IR Toy usually appears as.
+pub const DEFAULT_IRTOY_PORT: &str = "/dev/ttyACM0";
+
+/// The packet size the dongle asks for with each handshake, which is also the handshake byte.
+const PACKET_SIZE: u8 = 62;
+
+const CMD_RESET: u8 = 0x00;
+const CMD_SAMPLE_MODE: u8 = b'S';
+const CMD_TRANSMIT: u8 = 0x03;
+const CMD_SET_MODULATION: u8 = 0x06;
+const CMD_NOTIFY_COMPLETE: u8 = 0x24;
+const CMD_REPORT_BYTE_COUNT: u8 = 0x25;
+const CMD_HANDSHAKE: u8 = 0x26;
+
+/// Ends the pulses of a transmission.
+const TERMINATOR: u16 = 0xFFFF;
+
+/// The oscillator driving the carrier PWM, in Hz.
+const OSCILLATOR_HZ: u32 = 48_000_000;
+
+/// Transmits pulses with a USB IR Toy or IRdroid.
+///
+/// The dongle's carrier is set to the PF carrier of 38 kHz when opening it. Its duty cycle is
+/// fixed, so the duty cycle of `set_carrier` is ignored.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::{BrickBeam, IrToyTransmitter, Result, DEFAULT_IRTOY_PORT};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::from_transmitter(IrToyTransmitter::open(DEFAULT_IRTOY_PORT)?);
+///     Ok(())
+/// }
+/// ```
+pub struct IrToyTransmitter<P = File> {
+    port: Mutex<P>,
+}
+
+impl IrToyTransmitter {
+    /// Opens the serial port of the dongle, e.g. `/dev/ttyACM0`, and switches it into sampling
+    /// mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Io` if the port can't be opened or configured, and
+    /// `Error::Transmitting` if the device doesn't answer like an IR Toy.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let port = OpenOptions::new().read(true).write(true).open(path)?;
+        make_raw(&port)?;
+        Self::from_port(port)
+    }
+}
+
+impl<P: Read + Write> IrToyTransmitter<P> {
+    /// Uses an already opened serial port, switching the dongle into sampling mode.
+    ///
+    /// The port must be in raw mode, and reads should time out rather than block forever if the
+    /// dongle stops answering.
+    pub fn from_port(mut port: P) -> Result<Self> {
+        port.write_all(&[CMD_RESET; 5])?;
+        port.write_all(&[CMD_SAMPLE_MODE])?;
+        port.flush()?;
+        let mut version = [0u8; 3];
+        read_reply(&mut port, &mut version)?;
+        if version[0] != b'S' {
+            return Err(Error::Transmitting(format!(
+                "Not an IR Toy, it answered {:?} to the sampling mode",
+                String::from_utf8_lossy(&version)
+            )));
+        }
+        let transmitter = Self {
+            port: Mutex::new(port),
+        };
+        transmitter.set_modulation(CARRIER_HZ)?;
+        Ok(transmitter)
+    }
+
+    fn set_modulation(&self, carrier: u32) -> Result<()> {
+        let period = u8::try_from((OSCILLATOR_HZ / 64 + carrier.max(1) / 2) / carrier.max(1))
+            .ok()
+            .and_then(|period| period.checked_sub(1))
+            .ok_or_else(|| {
+                Error::Transmitting(format!("The IR Toy can't modulate at {} Hz", carrier))
+            })?;
+        let mut port = self.port.lock().unwrap_or_else(|e| e.into_inner());
+        port.write_all(&[CMD_SET_MODULATION, period, 0])?;
+        port.flush()?;
+        Ok(())
+    }
+}
+
+impl<P: Read + Write> PulseTransmitter for IrToyTransmitter<P> {
+    /// Transmits the pulses, returning once the dongle reports them sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Transmitting` if a pulse is too long for the dongle (about 1.4 s), or if
+    /// it stops answering or reports an incomplete transmission.
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let data = encode(pulses)?;
+        let mut port = self.port.lock().unwrap_or_else(|e| e.into_inner());
+        port.write_all(&[
+            CMD_HANDSHAKE,
+            CMD_REPORT_BYTE_COUNT,
+            CMD_NOTIFY_COMPLETE,
+            CMD_TRANSMIT,
+        ])?;
+        port.flush()?;
+        for packet in data.chunks(usize::from(PACKET_SIZE)) {
+            expect_handshake(&mut *port)?;
+            port.write_all(packet)?;
+            port.flush()?;
+        }
+        expect_handshake(&mut *port)?;
+
+        let mut report = [0u8; 4];
+        read_reply(&mut *port, &mut report)?;
+        let sent = usize::from(u16::from_be_bytes([report[1], report[2]]));
+        match report {
+            [b't', .., b'C'] if sent == data.len() => Ok(()),
+            [b't', .., b'F'] => Err(Error::Transmitting(
+                "The IR Toy ran out of data while transmitting".into(),
+            )),
+            _ => Err(Error::Transmitting(format!(
+                "The IR Toy sent {} of {} bytes",
+                sent,
+                data.len()
+            ))),
+        }
+    }
+
+    /// Sets the carrier frequency; the dongle's duty cycle is fixed.
+    fn set_carrier(&self, carrier: u32, _duty_cycle: u32) -> Result<()> {
+        self.set_modulation(carrier)
+    }
+}
+
+/// Converts the pulses into 21.33 µs ticks, each a big-endian `u16`, followed by the terminator.
+fn encode(pulses: &[u32]) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(pulses.len() * 2 + 2);
+    for &pulse in pulses {
+        let ticks = u16::try_from((u64::from(pulse) * 3 + 32) / 64)
+            .ok()
+            .filter(|&ticks| ticks != TERMINATOR)
+            .ok_or_else(|| {
+                Error::Transmitting(format!(
+                    "A pulse of {} µs is too long for the IR Toy",
+                    pulse
+                ))
+            })?;
+        data.extend_from_slice(&ticks.max(1).to_be_bytes());
+    }
+    data.extend_from_slice(&TERMINATOR.to_be_bytes());
+    Ok(data)
+}
+
+fn expect_handshake(port: &mut impl Read) -> Result<()> {
+    let mut handshake = [0u8];
+    read_reply(port, &mut handshake)?;
+    if handshake[0] != PACKET_SIZE {
+        return Err(Error::Transmitting(format!(
+            "Unexpected IR Toy handshake {:#04x}",
+            handshake[0]
+        )));
+    }
+    Ok(())
+}
+
+/// Reads a reply of the dongle, treating a timeout as the dongle not answering.
+fn read_reply(port: &mut impl Read, reply: &mut [u8]) -> Result<()> {
+    port.read_exact(reply).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::TimedOut => {
+            Error::Transmitting("The IR Toy did not answer".into())
+        }
+        _ => Error::Io(e),
+    })
+}
+
+/// Puts the serial port into raw mode, with reads timing out after a second.
+fn make_raw(port: &File) -> io::Result<()> {
+    // SAFETY: termios is plain data, filled in by tcgetattr before it is used.
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    // SAFETY: The descriptor is open and the pointers outlive the calls.
+    unsafe {
+        if libc::tcgetattr(port.as_raw_fd(), &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        termios.c_cc[libc::VMIN] = 0;
+        termios.c_cc[libc::VTIME] = 10;
+        if libc::tcsetattr(port.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// A serial port with scripted replies, recording what is written to it.
+    #[derive(Default)]
+    struct FakePort {
+        replies: VecDeque<u8>,
+        written: Vec<u8>,
+    }
+
+    impl FakePort {
+        fn replying(replies: &[u8]) -> Self {
+            Self {
+                replies: replies.iter().copied().collect(),
+                written: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for FakePort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for FakePort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn opened(replies: &[u8]) -> IrToyTransmitter<FakePort> {
+        let mut script = b"S01".to_vec();
+        script.extend_from_slice(replies);
+        IrToyTransmitter::from_port(FakePort::replying(&script)).unwrap()
+    }
+
+    fn written(transmitter: IrToyTransmitter<FakePort>) -> Vec<u8> {
+        transmitter.port.into_inner().unwrap().written
+    }
+
+    #[test]
+    fn test_open_sets_sampling_mode_and_carrier() {
+        let transmitter = opened(&[]);
+        // 48 MHz / 64 / 38 kHz rounds to a period of 20 ticks, written as 19.
+        assert_eq!(written(transmitter), [0, 0, 0, 0, 0, b'S', 0x06, 19, 0]);
+
+        let err = IrToyTransmitter::from_port(FakePort::replying(b"?")).err();
+        assert!(matches!(err, Some(Error::Transmitting(_))));
+    }
+
+    #[test]
+    fn test_pulses_sent_in_packets() {
+        let pulses = vec![157u32; 40];
+        // 40 pulses and the terminator are 82 bytes, two packets.
+        let transmitter = opened(&[62, 62, 62, b't', 0, 82, b'C']);
+        transmitter.send_pulses(&pulses).unwrap();
+        let written = written(transmitter);
+        let data = &written[9..];
+        assert_eq!(data[..4], [0x26, 0x25, 0x24, 0x03]);
+        assert_eq!(data[4..6], [0, 7]);
+        assert_eq!(data.len(), 4 + 82);
+        assert_eq!(data[data.len() - 2..], [0xFF, 0xFF]);
+
+        let underrun = opened(&[62, 62, 62, b't', 0, 62, b'F']);
+        assert!(matches!(
+            underrun.send_pulses(&pulses),
+            Err(Error::Transmitting(_))
+        ));
+        let silent = opened(&[62]);
+        assert!(matches!(
+            silent.send_pulses(&pulses),
+            Err(Error::Transmitting(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_rejects_long_pulses() {
+        assert_eq!(encode(&[0, 1026]).unwrap(), [0, 1, 0, 48, 0xFF, 0xFF]);
+        assert!(encode(&[2_000_000]).is_err());
+    }
+}
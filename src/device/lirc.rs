@@ -0,0 +1,79 @@
+//! # LIRC ioctls
+//!
+//! The handful of requests of `include/uapi/linux/lirc.h` brickbeam issues itself, with plain
+//! `libc::ioctl` calls: querying the features of a device, and the `u32` setters of the
+//! transmit side. Each request is encoded as `_IOR`/`_IOW` of a `u32` with the magic 'i'.
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+#[cfg(feature = "lirc-raw")]
+pub(crate) const LIRC_SET_SEND_CARRIER: u32 = ioc(IOC_WRITE, 0x13);
+#[cfg(feature = "lirc-raw")]
+pub(crate) const LIRC_SET_SEND_DUTY_CYCLE: u32 = ioc(IOC_WRITE, 0x15);
+#[cfg(feature = "lirc-raw")]
+pub(crate) const LIRC_SET_TRANSMITTER_MASK: u32 = ioc(IOC_WRITE, 0x17);
+const LIRC_GET_FEATURES: u32 = ioc(IOC_READ, 0x00);
+
+pub(crate) const LIRC_CAN_SEND_PULSE: u32 = 0x2;
+pub(crate) const LIRC_CAN_SET_SEND_CARRIER: u32 = 0x100;
+pub(crate) const LIRC_CAN_SET_SEND_DUTY_CYCLE: u32 = 0x200;
+pub(crate) const LIRC_CAN_SET_TRANSMITTER_MASK: u32 = 0x400;
+/// Any of the receive modes (raw, mode2, scancode, ...).
+pub(crate) const LIRC_CAN_REC_MASK: u32 = 0x003F_0000;
+
+const IOC_WRITE: u32 = 1;
+const IOC_READ: u32 = 2;
+
+/// Encodes an ioctl request number of the LIRC magic with a `u32` argument, as the `_IOC` macro
+/// of the generic Linux ioctl layout does.
+const fn ioc(direction: u32, number: u32) -> u32 {
+    (direction << 30) | ((std::mem::size_of::<u32>() as u32) << 16) | ((b'i' as u32) << 8) | number
+}
+
+/// Returns the `LIRC_CAN_*` feature bits of an open LIRC device.
+///
+/// Files that aren't a LIRC device are rejected with `ErrorKind::NotFound`.
+pub(crate) fn get_features(file: &impl AsRawFd) -> io::Result<u32> {
+    let mut features = 0u32;
+    // SAFETY: LIRC_GET_FEATURES writes one u32 to the pointer, which outlives the call.
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), LIRC_GET_FEATURES as _, &mut features) };
+    if ret != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "not a LIRC device".to_string(),
+        ));
+    }
+    Ok(features)
+}
+
+/// Issues an ioctl that takes a `u32` argument and returns its non-negative result.
+#[cfg(feature = "lirc-raw")]
+pub(crate) fn set(file: &impl AsRawFd, request: u32, value: u32) -> io::Result<i32> {
+    // SAFETY: The LIRC setters read one u32 from the pointer, which outlives the call.
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), request as _, &value) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ioctl_numbers_match_linux() {
+        assert_eq!(LIRC_GET_FEATURES, 0x8004_6900);
+        assert_eq!(LIRC_SET_SEND_CARRIER, 0x4004_6913);
+        assert_eq!(LIRC_SET_SEND_DUTY_CYCLE, 0x4004_6915);
+        assert_eq!(LIRC_SET_TRANSMITTER_MASK, 0x4004_6917);
+    }
+
+    #[test]
+    fn test_get_features_rejects_other_files() {
+        let file = std::fs::File::open("/dev/null").unwrap();
+        let err = get_features(&file).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
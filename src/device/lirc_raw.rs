@@ -0,0 +1,220 @@
+use crate::clock::SystemClock;
+use crate::device::info::{transmitter_info, TransmitterInfo};
+use crate::device::lirc::{
+    self, LIRC_CAN_SEND_PULSE, LIRC_CAN_SET_SEND_CARRIER, LIRC_CAN_SET_SEND_DUTY_CYCLE,
+    LIRC_CAN_SET_TRANSMITTER_MASK, LIRC_SET_SEND_CARRIER, LIRC_SET_SEND_DUTY_CYCLE,
+    LIRC_SET_TRANSMITTER_MASK,
+};
+use crate::device::registry::DeviceRegistry;
+use crate::device::retry::{retry_transient, RetryPolicy};
+use crate::device::writer::{Device, DeviceWriter};
+use crate::device::PulseTransmitter;
+use crate::protocols::timing::{CARRIER_HZ, DUTY_CYCLE};
+use crate::{Error, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// All LIRC devices currently open in this process by this backend, keyed by canonical path.
+static LIRC_DEVICES: DeviceRegistry<DeviceWriter> = DeviceRegistry::new();
+
+/// Transmits pulses to the kernel's /dev/lircX device with plain `ioctl` and `write` calls.
+/// See README.md for information how to enable /dev/lircX device in the Linux kernel.
+///
+/// It does the same as `CirPulseTransmitter` for transmitting, but only needs `libc`, so builds
+/// with the `lirc-raw` feature instead of `cir` don't need LLVM and cross-compile easily.
+///
+/// All instances opened on the same device path share one device handle, owned by a writer
+/// thread, so their transmissions are serialized in submission order even if they belong to
+/// different `BrickBeam` instances.
+pub struct LircRawPulseTransmitter {
+    tx_device: Arc<DeviceWriter>,
+    tx_device_path: PathBuf,
+}
+
+impl LircRawPulseTransmitter {
+    /// Creates a new LircRawPulseTransmitter instance, set to the PF carrier of 38 kHz at 33% duty
+    /// cycle, as not every driver defaults to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_device_path` - A reference to the path of the transmission device. (e.g. /dev/lirc0)
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self>` - A result containing the new instance, or an error if the device can't
+    ///   be opened, isn't a LIRC device or can't transmit.
+    pub fn new(tx_device_path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_carrier(tx_device_path, CARRIER_HZ, DUTY_CYCLE)
+    }
+
+    /// Creates a new LircRawPulseTransmitter instance that modulates the IR bursts with the given
+    /// carrier frequency in Hz and duty cycle in percent instead of the PF carrier.
+    ///
+    /// Drivers that can't change the carrier or the duty cycle keep their fixed setting. The
+    /// carrier applies to all instances sharing the device and can be changed later with
+    /// `PulseTransmitter::set_carrier`.
+    pub fn with_carrier(
+        tx_device_path: impl AsRef<Path>,
+        carrier: u32,
+        duty_cycle: u32,
+    ) -> Result<Self> {
+        let tx_device = LIRC_DEVICES.get_or_open(tx_device_path.as_ref(), |path| {
+            DeviceWriter::spawn(RawLirc::open(path)?)
+        })?;
+        tx_device.set_carrier(carrier, duty_cycle)?;
+        Ok(Self {
+            tx_device,
+            tx_device_path: tx_device_path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Returns the driver name and the features of the device, e.g. to show device info or to
+    /// adapt to drivers that can't change the carrier.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Io` if the device can't be opened to query its features.
+    pub fn info(&self) -> Result<TransmitterInfo> {
+        Ok(transmitter_info(&self.tx_device_path)?)
+    }
+
+    /// Selects which emitters of a multi-output IR blaster fire, one bit per emitter starting
+    /// with bit 0 for the first, e.g. `0b10` for only the second one pointing at the layout.
+    ///
+    /// The mask applies to all instances sharing the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Transmitting` if the driver can't select emitters or has fewer emitters
+    /// than the mask addresses.
+    pub fn set_transmitter_mask(&self, mask: u32) -> Result<()> {
+        self.tx_device.set_transmitter_mask(mask)
+    }
+
+    /// Sets how often and with which backoff writes failing with `EINTR`, `EAGAIN` or `EBUSY` are
+    /// retried before `send_pulses` reports the error. `RetryPolicy { retries: 0, .. }` surfaces
+    /// them right away.
+    ///
+    /// The policy applies to all instances sharing the device.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) -> Result<()> {
+        self.tx_device.set_retry_policy(policy)
+    }
+}
+
+impl PulseTransmitter for LircRawPulseTransmitter {
+    /// Sends pulses to the transmission device.
+    ///
+    /// Writes interrupted by a signal (`EINTR`) or rejected as busy (`EAGAIN`, `EBUSY`) are retried
+    /// with a short backoff before the error is reported, see `set_retry_policy`.
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        self.tx_device.send(pulses)
+    }
+
+    /// Waits until any transmission submitted to the shared device so far has completed.
+    fn flush(&self) -> Result<()> {
+        self.tx_device.flush()
+    }
+
+    /// Issues `LIRC_SET_SEND_CARRIER` and `LIRC_SET_SEND_DUTY_CYCLE` for whichever of the two the
+    /// driver supports.
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.tx_device.set_carrier(carrier, duty_cycle)
+    }
+}
+
+/// An open LIRC character device and the features its driver reports.
+struct RawLirc {
+    file: File,
+    features: u32,
+}
+
+impl RawLirc {
+    /// Opens a LIRC device, rejecting files that aren't one or drivers that can't transmit.
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let features = lirc::get_features(&file)
+            .map_err(|e| io::Error::new(e.kind(), format!("{} is {}", path.display(), e)))?;
+        if features & LIRC_CAN_SEND_PULSE == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} does not support sending", path.display()),
+            ));
+        }
+        Ok(Self { file, features })
+    }
+
+    /// Issues an ioctl that takes a `u32` argument and returns its non-negative result.
+    fn set(&self, request: u32, value: u32) -> io::Result<i32> {
+        lirc::set(&self.file, request, value)
+    }
+}
+
+/// Returns the bytes written for the pulses: the kernel takes an odd number of native-endian
+/// `u32`s starting and ending with a pulse, so a trailing space is dropped.
+fn payload(pulses: &[u32]) -> Vec<u8> {
+    let len = pulses.len() - (1 - pulses.len() % 2);
+    pulses[..len]
+        .iter()
+        .flat_map(|pulse| pulse.to_ne_bytes())
+        .collect()
+}
+
+impl Device for RawLirc {
+    fn send(&mut self, pulses: &[u32], retry: RetryPolicy) -> Result<()> {
+        if pulses.is_empty() {
+            return Ok(());
+        }
+        let payload = payload(pulses);
+        // The write blocks until the IR has been transmitted; the driver sends all of it or fails.
+        retry_transient(retry, &SystemClock, || self.file.write_all(&payload))
+            .map_err(|e| Error::Transmitting(e.to_string()))
+    }
+
+    fn set_carrier(&mut self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        // Old kernels return the new setting instead of 0, so only errors matter.
+        if self.features & LIRC_CAN_SET_SEND_CARRIER != 0 {
+            self.set(LIRC_SET_SEND_CARRIER, carrier)?;
+        }
+        if self.features & LIRC_CAN_SET_SEND_DUTY_CYCLE != 0 {
+            self.set(LIRC_SET_SEND_DUTY_CYCLE, duty_cycle)?;
+        }
+        Ok(())
+    }
+
+    fn set_transmitter_mask(&mut self, mask: u32) -> Result<()> {
+        if self.features & LIRC_CAN_SET_TRANSMITTER_MASK == 0 {
+            return Err(Error::Transmitting(
+                "The driver can't select transmitters".into(),
+            ));
+        }
+        // A mask beyond the emitters is rejected with their number instead of 0.
+        match self.set(LIRC_SET_TRANSMITTER_MASK, mask)? {
+            0 => Ok(()),
+            count => Err(Error::Transmitting(format!(
+                "Transmitter mask {:#b} exceeds the {} transmitters of the device",
+                mask, count
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_drops_trailing_space() {
+        assert_eq!(payload(&[157, 1026, 157, 263]).len(), 3 * 4);
+        assert_eq!(payload(&[157, 1026, 157]).len(), 3 * 4);
+        assert_eq!(payload(&[157, 1026])[..4], 157u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_lirc_raw_rejects_non_lirc_files() {
+        assert!(LircRawPulseTransmitter::new("/invalid/path").is_err());
+        assert!(LircRawPulseTransmitter::new("/dev/null").is_err());
+        assert!(LircRawPulseTransmitter::with_carrier("/dev/null", 36_000, 50).is_err());
+    }
+}
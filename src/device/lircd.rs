@@ -0,0 +1,280 @@
+//! # lircd backend
+//!
+//! Where the lircd daemon already owns `/dev/lirc0`, brickbeam can't open the device itself.
+//! `LircdTransmitter` sends through the daemon's socket instead.
+//!
+//! lircd only transmits the named codes of its configured remotes, not raw pulses. The PF
+//! messages are therefore described as a remote with 16-bit space-encoded codes, one per frame
+//! with a valid LRC, named `F` followed by the four hex digits of the frame (e.g. `F4D53`).
+//! `LircdTransmitter::remote_config` generates that remote for the lircd configuration; the
+//! transmitter decodes every frame of the pulses it is given and sends it with `SEND_ONCE`.
+
+use crate::device::PulseTransmitter;
+use crate::protocols::decode::find_frames;
+use crate::protocols::timing::{
+    CARRIER_HZ, DUTY_CYCLE, MARK_MICROS, MAX_MESSAGE_DURATION, ONE_SPACE_MICROS,
+    START_STOP_SPACE_MICROS, ZERO_SPACE_MICROS,
+};
+use crate::protocols::{compute_lrc, verify_lrc};
+use crate::{Error, Result};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The socket lircd listens on by default.
+pub const DEFAULT_LIRCD_SOCKET: &str = "/var/run/lirc/lircd";
+
+/// The name of the remote `remote_config` describes unless told otherwise.
+pub const DEFAULT_LIRCD_REMOTE: &str = "brickbeam-pf";
+
+/// Transmits PF messages through a running lircd daemon.
+///
+/// The daemon needs the remote generated by `remote_config` in its configuration, e.g. as
+/// `/etc/lirc/lircd.conf.d/brickbeam-pf.lircd.conf`. Only PF frames with a valid LRC can be sent;
+/// other pulse trains, such as RCX or NEC messages, are rejected. The carrier is the one of the
+/// remote, so `set_carrier` has no effect.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::{BrickBeam, LircdTransmitter, Result, DEFAULT_LIRCD_REMOTE};
+///
+/// fn main() -> Result<()> {
+///     std::fs::write(
+///         "/etc/lirc/lircd.conf.d/brickbeam-pf.lircd.conf",
+///         LircdTransmitter::remote_config(DEFAULT_LIRCD_REMOTE),
+///     )?;
+///     // After restarting lircd:
+///     let brick_beam = BrickBeam::from_transmitter(LircdTransmitter::new()?);
+///     Ok(())
+/// }
+/// ```
+pub struct LircdTransmitter {
+    connection: Mutex<Connection>,
+    remote: String,
+}
+
+struct Connection {
+    writer: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl LircdTransmitter {
+    /// Connects to lircd on its default socket, sending with the default remote name.
+    pub fn new() -> Result<Self> {
+        Self::connect(DEFAULT_LIRCD_SOCKET, DEFAULT_LIRCD_REMOTE)
+    }
+
+    /// Connects to the lircd socket at `socket`, sending the codes of the remote named `remote`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Io` if the socket can't be connected.
+    pub fn connect(socket: impl AsRef<Path>, remote: &str) -> Result<Self> {
+        let writer = UnixStream::connect(socket)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self {
+            connection: Mutex::new(Connection { writer, reader }),
+            remote: remote.to_owned(),
+        })
+    }
+
+    /// Returns the lircd configuration of a remote named `name` with every PF frame that has a
+    /// valid LRC.
+    pub fn remote_config(name: &str) -> String {
+        let mut config = format!(
+            "# PF frames for brickbeam's LircdTransmitter.\n\
+             begin remote\n\
+             \x20 name       {name}\n\
+             \x20 bits       16\n\
+             \x20 flags      SPACE_ENC|CONST_LENGTH\n\
+             \x20 eps        30\n\
+             \x20 aeps       100\n\
+             \x20 header     {MARK_MICROS} {START_STOP_SPACE_MICROS}\n\
+             \x20 one        {MARK_MICROS} {ONE_SPACE_MICROS}\n\
+             \x20 zero       {MARK_MICROS} {ZERO_SPACE_MICROS}\n\
+             \x20 ptrail     {MARK_MICROS}\n\
+             \x20 gap        {}\n\
+             \x20 frequency  {CARRIER_HZ}\n\
+             \x20 duty_cycle {DUTY_CYCLE}\n\
+             \x20 begin codes\n",
+            MAX_MESSAGE_DURATION.as_micros()
+        );
+        for payload in 0..0x1000u16 {
+            let nibbles = [
+                (payload >> 8) as u8,
+                (payload >> 4) as u8 & 0xF,
+                payload as u8 & 0xF,
+            ];
+            let frame = payload << 4 | u16::from(compute_lrc(nibbles));
+            let _ = writeln!(config, "    {} {:#06X}", code_name(frame), frame);
+        }
+        config.push_str("  end codes\nend remote\n");
+        config
+    }
+}
+
+impl PulseTransmitter for LircdTransmitter {
+    /// Sends every PF frame of the pulses with `SEND_ONCE`, returning once lircd has sent them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Transmitting` if the pulses aren't whole PF frames with a valid LRC, or
+    /// if lircd fails to send one, e.g. because it lacks the remote.
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let frames = find_frames(pulses);
+        if frames.is_empty() {
+            return Err(Error::Transmitting(
+                "lircd can only send PF frames, none found in the pulses".into(),
+            ));
+        }
+        let mut codes = Vec::with_capacity(frames.len());
+        for frame in frames {
+            let bits = frame
+                .bits
+                .map_err(|e| Error::Transmitting(format!("lircd can't send the pulses: {}", e)))?;
+            if !verify_lrc(bits) {
+                return Err(Error::Transmitting(format!(
+                    "Frame {:#06X} has a wrong LRC and no lircd code",
+                    bits
+                )));
+            }
+            codes.push(code_name(bits));
+        }
+        let mut connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        for code in codes {
+            connection.command(&format!("SEND_ONCE {} {}", self.remote, code))?;
+        }
+        Ok(())
+    }
+}
+
+impl Connection {
+    /// Sends a command and waits for its reply, skipping broadcasts such as `SIGHUP`.
+    fn command(&mut self, command: &str) -> Result<()> {
+        self.writer.write_all(format!("{}\n", command).as_bytes())?;
+        loop {
+            let packet = self.read_packet()?;
+            if packet.first().map(String::as_str) != Some(command) {
+                continue;
+            }
+            return match packet.get(1).map(String::as_str) {
+                Some("SUCCESS") => Ok(()),
+                _ => {
+                    // The message follows `DATA` and the number of its lines.
+                    let message = packet.get(4..).unwrap_or_default().join(" ");
+                    Err(Error::Transmitting(format!(
+                        "lircd failed to {}: {}",
+                        command, message
+                    )))
+                }
+            };
+        }
+    }
+
+    /// Reads the lines of one reply packet, between `BEGIN` and `END`.
+    fn read_packet(&mut self) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+        let mut started = false;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(Error::Transmitting("lircd closed the connection".into()));
+            }
+            match line.trim_end() {
+                "BEGIN" => started = true,
+                "END" if started => return Ok(lines),
+                line if started => lines.push(line.to_owned()),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Returns the name of the lircd code of a frame.
+fn code_name(frame: u16) -> String {
+    format!("F{:04X}", frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::timing::PulseTiming;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Answers like lircd, failing the code `F000F`, and reports the commands received.
+    fn fake_lircd(test: &str) -> (PathBuf, mpsc::Receiver<String>) {
+        let path =
+            std::env::temp_dir().join(format!("brickbeam-lircd-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let (commands, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            writer.write_all(b"BEGIN\nSIGHUP\nEND\n").unwrap();
+            for command in BufReader::new(stream).lines().map_while(|line| line.ok()) {
+                let reply = if command.ends_with("F000F") {
+                    format!("BEGIN\n{}\nERROR\nDATA\n1\nunknown code\nEND\n", command)
+                } else {
+                    format!("BEGIN\n{}\nSUCCESS\nEND\n", command)
+                };
+                let _ = commands.send(command);
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        (path, received)
+    }
+
+    #[test]
+    fn test_remote_config_lists_valid_frames() {
+        let config = LircdTransmitter::remote_config("pf");
+        assert!(config.contains("  name       pf\n"));
+        assert!(config.contains("  header     157 1026\n"));
+        assert!(config.contains("    F4D53 0x4D53\n"));
+        assert_eq!(config.matches("\n    F").count(), 4096);
+    }
+
+    #[test]
+    fn test_lircd_sends_every_frame() {
+        let (path, received) = fake_lircd("send");
+        let transmitter = LircdTransmitter::connect(&path, "pf").unwrap();
+        let mut pulses = PulseTiming::STANDARD.encode_frame(0x4D53).to_vec();
+        let frame = 0x1230 | u16::from(compute_lrc([1, 2, 3]));
+        pulses.extend(PulseTiming::STANDARD.encode_frame(frame));
+        transmitter.send_pulses(&pulses).unwrap();
+        let commands: Vec<String> = received.try_iter().collect();
+        assert_eq!(
+            commands,
+            [
+                "SEND_ONCE pf F4D53".to_string(),
+                format!("SEND_ONCE pf F{:04X}", frame)
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_lircd_rejects_what_it_cant_send() {
+        let (path, _received) = fake_lircd("errors");
+        let transmitter = LircdTransmitter::connect(&path, "pf").unwrap();
+        assert!(matches!(
+            transmitter.send_pulses(&[9000, 4500, 560]),
+            Err(Error::Transmitting(_))
+        ));
+        let wrong_lrc = PulseTiming::STANDARD.encode_frame(0x4D50);
+        assert!(matches!(
+            transmitter.send_pulses(&wrong_lrc),
+            Err(Error::Transmitting(_))
+        ));
+        let err = transmitter
+            .send_pulses(&PulseTiming::STANDARD.encode_frame(0x000F))
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown code"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use crate::clock::Clock;
+use crate::device::PulseTransmitter;
+use crate::Result;
+
+/// Sends every pulse train to a primary transmitter and, in addition, to a secondary "mirror".
+///
+/// The typical setup pairs the hardware transmitter with `PulseTransmitterEmulator`, which gives a
+/// live, human-readable trace of exactly what went to the IR LED during a real run.
+///
+/// The primary transmitter is authoritative: its result is returned to the caller. The mirror is
+/// best effort; it receives every pulse train (even when the primary failed) and its errors are
+/// ignored so that tracing can never break the actual transmission.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, MirrorTransmitter, PulseTransmitterEmulator};
+///
+/// let transmitter = MirrorTransmitter::new(PulseTransmitterEmulator, PulseTransmitterEmulator);
+/// let brick_beam = BrickBeam::from_transmitter(transmitter);
+/// ```
+pub struct MirrorTransmitter<P: PulseTransmitter, M: PulseTransmitter> {
+    primary: P,
+    mirror: M,
+}
+
+impl<P: PulseTransmitter, M: PulseTransmitter> MirrorTransmitter<P, M> {
+    /// Creates a new mirroring transmitter.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The transmitter whose result is reported, usually the hardware.
+    /// * `mirror` - The transmitter that receives a copy of every pulse train, e.g. an emulator.
+    pub fn new(primary: P, mirror: M) -> Self {
+        Self { primary, mirror }
+    }
+
+    /// Returns a reference to the primary transmitter.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Returns a reference to the mirror transmitter.
+    pub fn mirror(&self) -> &M {
+        &self.mirror
+    }
+}
+
+impl<P: PulseTransmitter, M: PulseTransmitter> PulseTransmitter for MirrorTransmitter<P, M> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let result = self.primary.send_pulses(pulses);
+        let _ = self.mirror.send_pulses(pulses);
+        result
+    }
+
+    fn flush(&self) -> Result<()> {
+        let result = self.primary.flush();
+        let _ = self.mirror.flush();
+        result
+    }
+
+    fn settle(&self) {
+        self.primary.settle();
+        self.mirror.settle();
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        let result = self.primary.set_carrier(carrier, duty_cycle);
+        let _ = self.mirror.set_carrier(carrier, duty_cycle);
+        result
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.primary.clock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use crate::Error;
+
+    struct MockTransmitterFail;
+
+    impl PulseTransmitter for MockTransmitterFail {
+        fn send_pulses(&self, _pulses: &[u32]) -> Result<()> {
+            Err(Error::Transmitting("Mock failure".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_mirror_receives_every_pulse_train() {
+        let transmitter = MirrorTransmitter::new(
+            MockTransmitterRecorder::default(),
+            MockTransmitterRecorder::default(),
+        );
+        transmitter.send_pulses(&[157, 1026]).unwrap();
+        transmitter.send_pulses(&[157, 263]).unwrap();
+        assert_eq!(transmitter.primary().sent(), transmitter.mirror().sent());
+    }
+
+    #[test]
+    fn test_mirror_errors_are_ignored() {
+        let transmitter =
+            MirrorTransmitter::new(MockTransmitterRecorder::default(), MockTransmitterFail);
+        assert!(transmitter.send_pulses(&[157, 1026]).is_ok());
+    }
+
+    #[test]
+    fn test_primary_errors_are_reported_and_still_mirrored() {
+        let transmitter =
+            MirrorTransmitter::new(MockTransmitterFail, MockTransmitterRecorder::default());
+        assert!(transmitter.send_pulses(&[157, 1026]).is_err());
+        assert_eq!(transmitter.mirror().sent().len(), 1);
+    }
+}
@@ -2,37 +2,216 @@
 //!
 //! This module deals with transmitting the raw IR pulses to the hardware.
 //! - On Linux with the `cir` feature, `CirPulseTransmitter` uses `/dev/lirc<X>`.
-//! - On other platforms (or if `cir` is disabled), it uses `PulseTransmitterEmulator`,
+//! - With the `lirc-raw` feature, `LircRawPulseTransmitter` uses `/dev/lirc<X>` with plain
+//!   `ioctl` and `write` calls instead, so no LLVM is needed to build.
+//! - On other platforms (or if both are disabled), it uses `PulseTransmitterEmulator`,
 //!   which simply prints pulses for testing or development.
 //!
+//! - With the `pigpiod` feature, `PigpiodTransmitter` modulates an IR LED on any GPIO through the
+//!   pigpio daemon, unprivileged and without kernel LIRC support.
+//! - With the `lircd` feature, `LircdTransmitter` sends PF frames through a running lircd daemon
+//!   that owns the device.
+//! - `IguanaIrTransmitter` sends to IguanaWorks USB transceivers through their LIRC device, in
+//!   bursts small enough for their buffer.
+//! - With the `audio` feature, `AudioPulseTransmitter` plays the pulses through IR LEDs on a
+//!   headphone jack, and `write_wav` saves them as a WAV file for analysis.
+//! - With the `embedded-hal` feature, `EmbeddedHalTransmitter` drives an IR LED through the PWM or
+//!   GPIO traits of any `embedded-hal` implementation.
+//! - With the `hid` feature, `HidPulseTransmitter` drives USB HID IR blasters running custom
+//!   firmware on any platform.
+//! - With the `irtoy` feature, `IrToyTransmitter` uses a USB IR Toy or IRdroid on its serial port.
+//! - With the `network` feature, `NetworkPulseTransmitter` sends the pulses over TCP to a
+//!   `PulseAgent`, which transmits them on the hardware of another machine.
+//! - `FailoverTransmitter` switches to a backup transmitter once the primary one fails.
+//! - `RateLimitTransmitter` enforces a gap between messages and an air time budget.
+//! - `QueuedTransmitter` transmits on a background worker by priority, so sending never blocks.
+//! - `MirrorTransmitter` sends every pulse train to a primary and a secondary transmitter.
+//! - `SettleTransmitter` keeps the medium quiet for a while after opening and after state changes.
+//! - `CalibratedTransmitter` corrects the pulse lengths for the skew of an emitter.
+//! - `HotplugTransmitter` follows a device node that appears or disappears at runtime, and with a
+//!   `ReconnectPolicy` reopens it to retry failed sends.
+//! - `PulseReceiver` yields the bursts seen by an IR receiver, `CirPulseReceiver` reads them from
+//!   `/dev/lirc<X>` with the `cir` feature.
+//! - `EventTransmitter` reports the outcome of every transmission to subscribers.
+//! - With the `test-util` feature, `MockTransmitterRecorder` records what it is asked to send, on
+//!   a `MockClock`.
+//! - A process-wide registry makes all transmitters opened on the same device path share one
+//!   handle, so separate `BrickBeam` instances never interleave their writes. A writer thread
+//!   owns each handle and transmits in submission order.
+//! - The `sysfs` helpers read rc-core metadata from `/sys/class/rc` to pick the right device.
+//! - `discover_lirc_devices` asks every `/dev/lirc*` driver what it can do, so a receiver is
+//!   never opened for transmitting by mistake.
+//!
 //! `DefaultPulseTransmitter` is aliased to whichever implementation is active
 //! on your platform/features.
 
 mod api;
+#[cfg(feature = "audio")]
+mod audio;
+mod calibrate;
+mod emulator;
+mod events;
+mod failover;
+#[cfg(feature = "hid")]
+mod hid;
+mod hotplug;
+mod mirror;
+mod queued;
+mod rate_limit;
+mod receiver;
+#[cfg(any(test, feature = "test-util"))]
+mod recorder;
+mod settle;
+mod sysfs;
 
 #[cfg(feature = "cir")]
 mod cir;
-#[cfg(not(feature = "cir"))]
-mod emulator;
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+mod discover;
+#[cfg(feature = "embedded-hal")]
+mod embedded;
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+mod iguanair;
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+mod info;
+#[cfg(all(unix, feature = "irtoy"))]
+mod irtoy;
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+mod lirc;
+#[cfg(feature = "lirc-raw")]
+mod lirc_raw;
+#[cfg(all(unix, feature = "lircd"))]
+mod lircd;
+#[cfg(feature = "network")]
+mod network;
+#[cfg(feature = "pigpiod")]
+mod pigpiod;
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+mod registry;
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+mod retry;
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+mod writer;
 
 /// On non–Linux platforms, the `send_pulses` functions simply print the encoded pulse sequence, acting as a development/testing emulator.
 /// The library abstracts the underlying hardware differences by using the `DefaultPulseTransmitter`:
 ///
-/// • On Linux, this corresponds to the `CirPulseTransmitter`, which uses the `/dev/lirc0` interface.
+/// • On Linux, this corresponds to the `CirPulseTransmitter`, which uses the `/dev/lirc0` interface,
+/// or to the `LircRawPulseTransmitter` if only the `lirc-raw` feature is enabled.
 ///
 /// • On other platforms, it uses an emulator (`PulseTransmitterEmulator`) that mimics the interface while doing nothing.
 ///
-pub use api::PulseTransmitter;
+pub use api::{DynPulseTransmitter, PulseTransmitter};
+#[cfg(feature = "audio")]
+pub use audio::{
+    render_audio, write_wav, write_wav_to, AudioPulseTransmitter, DEFAULT_SAMPLE_RATE,
+};
+pub use calibrate::{CalibratedTransmitter, PulseCalibration};
+#[cfg(feature = "async")]
+pub use events::EventStream;
+pub use events::{EventTransmitter, TransmissionEvent};
+pub use failover::FailoverTransmitter;
+#[cfg(feature = "hid")]
+pub use hid::HidPulseTransmitter;
+pub use hotplug::{DeviceEvent, HotplugTransmitter, ReconnectPolicy};
+pub use mirror::MirrorTransmitter;
+pub use queued::{Priority, QueuePolicy, QueueSender, QueuedTransmitter};
+pub use rate_limit::RateLimitTransmitter;
+#[cfg(feature = "cir")]
+pub use receiver::CirPulseReceiver;
+pub use receiver::{PulseReceiver, DEFAULT_BURST_GAP};
+#[cfg(any(test, feature = "test-util"))]
+pub use recorder::MockTransmitterRecorder;
+pub use settle::SettleTransmitter;
+pub use sysfs::{
+    enumerate_rc_devices, enumerate_rc_devices_in, select_transmitter, RcDevice, SYSFS_RC_ROOT,
+};
 
 #[cfg(feature = "cir")]
 pub use cir::CirPulseTransmitter; // See note below.
-#[cfg(not(feature = "cir"))]
-// Note: PulseTransmitterEmulator is for development/testing on non-Linux platforms only.
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+pub use discover::{discover_lirc_devices, LircDevice};
+#[cfg(feature = "embedded-hal")]
+pub use embedded::{EmbeddedHalTransmitter, IrEmitter, PinEmitter, PwmEmitter};
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+pub use iguanair::{IguanaIrTransmitter, IGUANAIR_DRIVER};
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+pub use info::{TransmitterInfo, LIRC_MAX_PULSES};
+#[cfg(all(unix, feature = "irtoy"))]
+pub use irtoy::{IrToyTransmitter, DEFAULT_IRTOY_PORT};
+#[cfg(feature = "lirc-raw")]
+pub use lirc_raw::LircRawPulseTransmitter;
+#[cfg(all(unix, feature = "lircd"))]
+pub use lircd::{LircdTransmitter, DEFAULT_LIRCD_REMOTE, DEFAULT_LIRCD_SOCKET};
+#[cfg(feature = "network")]
+pub use network::{NetworkPulseTransmitter, PulseAgent, DEFAULT_AGENT_PORT};
+#[cfg(feature = "pigpiod")]
+pub use pigpiod::{PigpiodTransmitter, DEFAULT_PIGPIOD_ADDR};
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+pub use retry::RetryPolicy;
+// Note: PulseTransmitterEmulator is for development/testing (or as a mirror) only.
 pub use emulator::PulseTransmitterEmulator;
 
 /// Default PulseTransmitter implementation.
 /// On Linux, this is the actual IR transmitter; on other platforms, it is simulated.
 #[cfg(feature = "cir")]
 pub type DefaultPulseTransmitter = crate::device::CirPulseTransmitter;
-#[cfg(not(feature = "cir"))]
+#[cfg(all(not(feature = "cir"), feature = "lirc-raw"))]
+pub type DefaultPulseTransmitter = crate::device::LircRawPulseTransmitter;
+#[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
 pub type DefaultPulseTransmitter = crate::device::PulseTransmitterEmulator;
+
+/// Opens the `DefaultPulseTransmitter` for the given device path.
+#[cfg(feature = "cir")]
+pub(crate) fn open_default(
+    tx_device_path: impl AsRef<std::path::Path>,
+) -> crate::Result<DefaultPulseTransmitter> {
+    CirPulseTransmitter::new(tx_device_path)
+}
+
+/// Opens the `DefaultPulseTransmitter` for the given device path.
+#[cfg(all(not(feature = "cir"), feature = "lirc-raw"))]
+pub(crate) fn open_default(
+    tx_device_path: impl AsRef<std::path::Path>,
+) -> crate::Result<DefaultPulseTransmitter> {
+    LircRawPulseTransmitter::new(tx_device_path)
+}
+
+/// Opens the `DefaultPulseTransmitter`; the emulator ignores the device path.
+#[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+pub(crate) fn open_default(
+    _tx_device_path: impl AsRef<std::path::Path>,
+) -> crate::Result<DefaultPulseTransmitter> {
+    Ok(PulseTransmitterEmulator)
+}
+
+/// Opens the `DefaultPulseTransmitter` on the most suitable device according to rc-core sysfs metadata.
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+pub(crate) fn open_auto() -> crate::Result<DefaultPulseTransmitter> {
+    let devices = enumerate_rc_devices()?;
+    let tx_device_path = select_transmitter(&devices)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no LIRC device found"))?;
+    open_default(tx_device_path)
+}
+
+/// Opens the `DefaultPulseTransmitter`; the emulator needs no device.
+#[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+pub(crate) fn open_auto() -> crate::Result<DefaultPulseTransmitter> {
+    Ok(PulseTransmitterEmulator)
+}
+
+/// Opens the `DefaultPulseTransmitter` on a device whose driver reports it can transmit.
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+pub(crate) fn open_discovered() -> crate::Result<DefaultPulseTransmitter> {
+    // Without sysfs, the first device that can transmit is taken.
+    let rc_devices = enumerate_rc_devices().unwrap_or_default();
+    let tx_device_path =
+        discover::find_transmitter_in(std::path::Path::new(discover::DEV_ROOT), &rc_devices)?;
+    open_default(tx_device_path)
+}
+
+/// Opens the `DefaultPulseTransmitter`; the emulator needs no device.
+#[cfg(not(any(feature = "cir", feature = "lirc-raw")))]
+pub(crate) fn open_discovered() -> crate::Result<DefaultPulseTransmitter> {
+    Ok(PulseTransmitterEmulator)
+}
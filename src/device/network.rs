@@ -0,0 +1,325 @@
+//! # Network transmitter
+//!
+//! Splits brickbeam across two machines: the control logic runs on a laptop or server with a
+//! `NetworkPulseTransmitter`, while a `PulseAgent` on a small Pi next to the track forwards the
+//! pulses to its local IR LED.
+//!
+//! The two talk a small binary protocol over TCP. Every request is a one byte opcode, the
+//! payload length as a big-endian `u32` and the payload:
+//!
+//! | Opcode | Payload                          | Request                          |
+//! |--------|----------------------------------|----------------------------------|
+//! | `A`    | the token, UTF-8                 | authenticate (see `Auth`)        |
+//! | `P`    | the pulses, big-endian `u32`s    | `send_pulses`                    |
+//! | `F`    |                                  | `flush`                          |
+//! | `C`    | carrier and duty cycle, `u32`s   | `set_carrier`                    |
+//!
+//! The agent answers every request once the local transmitter has returned, with a status byte,
+//! the length of a message and the UTF-8 message explaining a failure. So `send_pulses` returns
+//! once the pulses are on air, as with a local transmitter.
+
+use crate::auth::Auth;
+use crate::device::PulseTransmitter;
+use crate::{Error, Result};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+
+/// The TCP port a `PulseAgent` is usually bound to.
+pub const DEFAULT_AGENT_PORT: u16 = 3838;
+
+/// The largest request payload the agent accepts, far above any IR message.
+const MAX_PAYLOAD: u32 = 1 << 20;
+
+const OP_AUTH: u8 = b'A';
+const OP_PULSES: u8 = b'P';
+const OP_FLUSH: u8 = b'F';
+const OP_CARRIER: u8 = b'C';
+
+const STATUS_OK: u8 = 0;
+const STATUS_TRANSMITTING: u8 = 1;
+const STATUS_UNAUTHORIZED: u8 = 2;
+const STATUS_PROTOCOL: u8 = 3;
+
+/// Sends the pulses to a remote `PulseAgent`, which transmits them on its own hardware.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::{BrickBeam, NetworkPulseTransmitter, Result};
+///
+/// fn main() -> Result<()> {
+///     let transmitter = NetworkPulseTransmitter::connect("trackside-pi.local:3838")?;
+///     let brick_beam = BrickBeam::from_transmitter(transmitter);
+///     Ok(())
+/// }
+/// ```
+pub struct NetworkPulseTransmitter {
+    connection: Mutex<Connection>,
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl NetworkPulseTransmitter {
+    /// Connects to an agent that accepts every client.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Io` if the agent can't be reached.
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            connection: Mutex::new(Connection {
+                reader: BufReader::new(stream.try_clone()?),
+                writer: BufWriter::new(stream),
+            }),
+        })
+    }
+
+    /// Connects to an agent and authenticates with `token`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unauthorized` if the agent rejects the token.
+    pub fn connect_with_token(address: impl ToSocketAddrs, token: &str) -> Result<Self> {
+        let transmitter = Self::connect(address)?;
+        transmitter.request(OP_AUTH, token.as_bytes())?;
+        Ok(transmitter)
+    }
+
+    fn request(&self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        write_message(&mut connection.writer, opcode, payload)?;
+        connection.writer.flush()?;
+        let (status, message) = read_message(&mut connection.reader)?;
+        let message = String::from_utf8_lossy(&message).into_owned();
+        match status {
+            STATUS_OK => Ok(()),
+            STATUS_UNAUTHORIZED => Err(Error::Unauthorized(message)),
+            STATUS_PROTOCOL => Err(Error::ProtocolError(message)),
+            _ => Err(Error::Transmitting(format!("Agent failed: {}", message))),
+        }
+    }
+}
+
+impl PulseTransmitter for NetworkPulseTransmitter {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        self.request(OP_PULSES, &encode_u32s(pulses))
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.request(OP_FLUSH, &[])
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.request(OP_CARRIER, &encode_u32s(&[carrier, duty_cycle]))
+    }
+}
+
+/// Accepts `NetworkPulseTransmitter` clients and forwards their requests to a local transmitter.
+///
+/// Every client is served on its own thread; the local transmitter keeps their messages apart.
+///
+/// # Example
+/// ```rust,no_run
+/// use brickbeam::{BrickBeam, PulseAgent, Result};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     PulseAgent::bind("0.0.0.0:3838")?.serve(brick_beam.transmitter())
+/// }
+/// ```
+pub struct PulseAgent {
+    listener: TcpListener,
+    auth: Auth,
+}
+
+impl PulseAgent {
+    /// Binds the agent to a TCP address, e.g. `0.0.0.0:3838`.
+    pub fn bind(address: impl ToSocketAddrs) -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            auth: Auth::Open,
+        })
+    }
+
+    /// Sets the access policy; by default every client is accepted.
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Returns the address the agent is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serves clients until accepting a connection fails.
+    ///
+    /// A client that sends a malformed request or fails authorization is disconnected, without
+    /// affecting the others.
+    pub fn serve<T: PulseTransmitter + Sync>(&self, transmitter: &T) -> Result<()> {
+        thread::scope(|scope| loop {
+            let (stream, _) = self.listener.accept()?;
+            scope.spawn(move || {
+                let _ = self.handle_client(stream, transmitter);
+            });
+        })
+    }
+
+    /// Answers the requests of one client until it disconnects.
+    fn handle_client<T: PulseTransmitter>(&self, stream: TcpStream, transmitter: &T) -> Result<()> {
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        let mut authorized = !self.auth.is_required();
+        loop {
+            let (opcode, payload) = match read_message(&mut reader) {
+                Ok(request) => request,
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let result = match opcode {
+                OP_AUTH => std::str::from_utf8(&payload)
+                    .map_err(|_| Error::Unauthorized("Invalid token".to_string()))
+                    .and_then(|token| self.auth.authorize(Some(token))),
+                _ if !authorized => Err(Error::Unauthorized("Missing token".to_string())),
+                OP_PULSES => {
+                    decode_u32s(&payload).and_then(|pulses| transmitter.send_pulses(&pulses))
+                }
+                OP_FLUSH => transmitter.flush(),
+                OP_CARRIER => match decode_u32s(&payload).as_deref() {
+                    Ok(&[carrier, duty_cycle]) => transmitter.set_carrier(carrier, duty_cycle),
+                    _ => Err(malformed("the carrier takes two u32 values")),
+                },
+                _ => Err(malformed(&format!("unknown opcode {:#04x}", opcode))),
+            };
+            let (status, message) = match &result {
+                Ok(()) => (STATUS_OK, String::new()),
+                Err(Error::Unauthorized(message)) => (STATUS_UNAUTHORIZED, message.clone()),
+                Err(Error::ProtocolError(message)) => (STATUS_PROTOCOL, message.clone()),
+                Err(e) => (STATUS_TRANSMITTING, e.to_string()),
+            };
+            write_message(&mut writer, status, message.as_bytes())?;
+            writer.flush()?;
+            match result {
+                Ok(()) if opcode == OP_AUTH => authorized = true,
+                Err(e @ (Error::Unauthorized(_) | Error::ProtocolError(_))) => return Err(e),
+                _ => (),
+            }
+        }
+    }
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::ProtocolError(format!("Malformed agent request: {}", reason))
+}
+
+/// Writes a request or reply: the tag byte, the payload length and the payload.
+fn write_message(writer: &mut impl Write, tag: u8, payload: &[u8]) -> Result<()> {
+    let length = u32::try_from(payload.len())
+        .ok()
+        .filter(|&length| length <= MAX_PAYLOAD)
+        .ok_or_else(|| Error::ProtocolError("Message too long for the agent".to_string()))?;
+    writer.write_all(&[tag])?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(payload)?;
+    Ok(())
+}
+
+/// Reads a request or reply written by `write_message`.
+fn read_message(reader: &mut impl Read) -> Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    reader.read_exact(&mut header)?;
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    if length > MAX_PAYLOAD {
+        return Err(malformed(&format!("payload of {} bytes", length)));
+    }
+    let mut payload = vec![0u8; length as usize];
+    reader.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+fn encode_u32s(values: &[u32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+fn decode_u32s(bytes: &[u8]) -> Result<Vec<u32>> {
+    if bytes.len() % 4 != 0 {
+        return Err(malformed("payload is not a sequence of u32"));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use std::sync::Arc;
+
+    /// Starts an agent on a free port, serving until the test process ends.
+    fn spawn_agent(auth: Auth) -> (SocketAddr, Arc<MockTransmitterRecorder>) {
+        let agent = PulseAgent::bind("127.0.0.1:0").unwrap().with_auth(auth);
+        let address = agent.local_addr().unwrap();
+        let transmitter = Arc::new(MockTransmitterRecorder::default());
+        let local = Arc::clone(&transmitter);
+        thread::spawn(move || agent.serve(&local));
+        (address, transmitter)
+    }
+
+    #[test]
+    fn test_pulses_forwarded_to_agent() {
+        let (address, local) = spawn_agent(Auth::Open);
+        let transmitter = NetworkPulseTransmitter::connect(address).unwrap();
+        transmitter.send_pulses(&[157, 1026, 157, 263]).unwrap();
+        transmitter.set_carrier(38_000, 33).unwrap();
+        transmitter.flush().unwrap();
+        assert_eq!(local.sent(), vec![vec![157, 1026, 157, 263]]);
+        assert_eq!(local.carriers(), [(38_000, 33)]);
+
+        local.set_failing(true);
+        let err = transmitter.send_pulses(&[157]).unwrap_err();
+        assert!(
+            matches!(err, Error::Transmitting(ref message) if message.contains("Mock failure"))
+        );
+    }
+
+    #[test]
+    fn test_agent_requires_token() {
+        let (address, local) = spawn_agent(Auth::token("s3cret"));
+        let anonymous = NetworkPulseTransmitter::connect(address).unwrap();
+        assert!(matches!(
+            anonymous.send_pulses(&[157]),
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(
+            NetworkPulseTransmitter::connect_with_token(address, "guess"),
+            Err(Error::Unauthorized(_))
+        ));
+
+        let client = NetworkPulseTransmitter::connect_with_token(address, "s3cret").unwrap();
+        client.send_pulses(&[157]).unwrap();
+        assert_eq!(local.sent(), vec![vec![157]]);
+    }
+
+    #[test]
+    fn test_malformed_requests_rejected() {
+        assert!(decode_u32s(&[0, 0, 1]).is_err());
+        let mut oversized = vec![OP_PULSES];
+        oversized.extend_from_slice(&(MAX_PAYLOAD + 1).to_be_bytes());
+        assert!(matches!(
+            read_message(&mut oversized.as_slice()),
+            Err(Error::ProtocolError(_))
+        ));
+    }
+}
@@ -0,0 +1,396 @@
+//! # pigpiod backend
+//!
+//! The pigpio daemon times GPIO waveforms with DMA, accurately enough to modulate the IR carrier
+//! in software on any GPIO, without the `gpio-ir-tx` overlay or any kernel LIRC support. Talking
+//! to it over its socket needs no root privileges, as the daemon owns the hardware.
+//!
+//! Every message is built as a waveform of carrier cycles and sent with the commands of the
+//! socket interface: each is 16 bytes (command, two parameters and the length of an optional
+//! extension, little-endian `u32`s), answered by 16 bytes ending in the result.
+
+use crate::clock::{Clock, SystemClock};
+use crate::device::PulseTransmitter;
+use crate::protocols::{
+    duration_of,
+    timing::{CARRIER_HZ, DUTY_CYCLE},
+};
+use crate::{Error, Result};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The address pigpiod listens on by default.
+pub const DEFAULT_PIGPIOD_ADDR: &str = "127.0.0.1:8888";
+
+const CMD_MODES: u32 = 0;
+const CMD_WRITE: u32 = 4;
+const CMD_WVAG: u32 = 28;
+const CMD_WVBSY: u32 = 32;
+const CMD_WVCRE: u32 = 49;
+const CMD_WVDEL: u32 = 50;
+const CMD_WVTX: u32 = 51;
+const CMD_WVNEW: u32 = 53;
+
+const MODE_OUTPUT: u32 = 1;
+
+/// The most pulses sent with one `WVAG` command, well within the 64 KiB command extension.
+const PULSES_PER_COMMAND: usize = 4000;
+
+/// How long to wait for a waveform to finish beyond its airtime before giving up.
+const WAVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// One step of a pigpio waveform: the GPIOs switched on, those switched off, then the delay in
+/// µs until the next step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WavePulse {
+    on: u32,
+    off: u32,
+    delay: u32,
+}
+
+/// Transmits pulses through the pigpio daemon on an IR LED connected to a GPIO.
+///
+/// The carrier is generated as a waveform, so it can be changed freely with `set_carrier`; it
+/// starts at the PF carrier of 38 kHz at 33% duty cycle. `send_pulses` returns once the waveform
+/// has been transmitted, waiting for it on the clock set with `with_clock`.
+///
+/// # Example
+/// ```no_run
+/// use brickbeam::{BrickBeam, PigpiodTransmitter, Result};
+///
+/// fn main() -> Result<()> {
+///     // An IR LED on GPIO 18 of the Pi running pigpiod.
+///     let transmitter = PigpiodTransmitter::connect("raspberrypi.local:8888", 18)?;
+///     let brick_beam = BrickBeam::from_transmitter(transmitter);
+///     Ok(())
+/// }
+/// ```
+pub struct PigpiodTransmitter {
+    connection: Mutex<Connection>,
+    clock: Box<dyn Clock>,
+}
+
+struct Connection {
+    stream: TcpStream,
+    gpio: u32,
+    carrier: (u32, u32),
+}
+
+impl PigpiodTransmitter {
+    /// Connects to pigpiod on this machine, see `connect`.
+    pub fn new(gpio: u32) -> Result<Self> {
+        Self::connect(DEFAULT_PIGPIOD_ADDR, gpio)
+    }
+
+    /// Connects to pigpiod at `addr` and sets `gpio` (a Broadcom number from 0 to 31) up as the
+    /// output of the IR LED.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` for a GPIO above 31, `Error::Io` if the daemon can't be
+    /// reached and `Error::Transmitting` if it rejects the GPIO.
+    pub fn connect(addr: impl ToSocketAddrs, gpio: u32) -> Result<Self> {
+        if gpio > 31 {
+            return Err(Error::ProtocolError(format!(
+                "GPIO {} is outside 0 to 31",
+                gpio
+            )));
+        }
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            stream,
+            gpio,
+            carrier: (CARRIER_HZ, DUTY_CYCLE),
+        };
+        connection.command(CMD_MODES, gpio, MODE_OUTPUT, &[])?;
+        connection.command(CMD_WRITE, gpio, 0, &[])?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            clock: Box::new(SystemClock),
+        })
+    }
+
+    /// Sets the clock the transmitter waits for its waveforms on.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+}
+
+impl PulseTransmitter for PigpiodTransmitter {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        connection.transmit(pulses, &*self.clock)
+    }
+
+    /// Modulates subsequent messages with the given carrier; a carrier of 0 sends unmodulated
+    /// bursts.
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        connection.carrier = (carrier, duty_cycle.min(100));
+        Ok(())
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+}
+
+impl Connection {
+    /// Builds the waveform of the pulses, transmits it and waits until it is done.
+    fn transmit(&mut self, pulses: &[u32], clock: &dyn Clock) -> Result<()> {
+        let (carrier, duty_cycle) = self.carrier;
+        let wave = modulate(pulses, 1 << self.gpio, carrier, duty_cycle);
+        if wave.is_empty() {
+            return Ok(());
+        }
+        self.command(CMD_WVNEW, 0, 0, &[])?;
+        let mut offset = 0;
+        for chunk in wave.chunks(PULSES_PER_COMMAND) {
+            // Added pulses are merged into the waveform from its start, so later chunks are
+            // preceded by a delay up to where the previous ones end.
+            let lead = (offset > 0).then_some(WavePulse {
+                on: 0,
+                off: 0,
+                delay: offset,
+            });
+            let mut extension = Vec::with_capacity((chunk.len() + 1) * 12);
+            for pulse in lead.iter().chain(chunk) {
+                for value in [pulse.on, pulse.off, pulse.delay] {
+                    extension.extend(value.to_le_bytes());
+                }
+            }
+            self.command(CMD_WVAG, 0, 0, &extension)?;
+            offset += chunk.iter().map(|pulse| pulse.delay).sum::<u32>();
+        }
+        let wave_id = self.command(CMD_WVCRE, 0, 0, &[])? as u32;
+        let sent = self.send_wave(wave_id, duration_of(pulses), clock);
+        let deleted = self.command(CMD_WVDEL, wave_id, 0, &[]);
+        sent.and(deleted.map(|_| ()))
+    }
+
+    fn send_wave(&mut self, wave_id: u32, airtime: Duration, clock: &dyn Clock) -> Result<()> {
+        self.command(CMD_WVTX, wave_id, 0, &[])?;
+        let started = clock.now();
+        clock.sleep(airtime);
+        while self.command(CMD_WVBSY, 0, 0, &[])? != 0 {
+            if clock.now() - started > airtime + WAVE_TIMEOUT {
+                return Err(Error::Transmitting(
+                    "pigpiod didn't finish the waveform in time".into(),
+                ));
+            }
+            clock.sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+
+    /// Sends one command and returns its non-negative result.
+    fn command(&mut self, cmd: u32, p1: u32, p2: u32, extension: &[u8]) -> Result<i32> {
+        let mut request = Vec::with_capacity(16 + extension.len());
+        for value in [cmd, p1, p2, extension.len() as u32] {
+            request.extend(value.to_le_bytes());
+        }
+        request.extend(extension);
+        self.stream.write_all(&request)?;
+        let mut response = [0u8; 16];
+        self.stream.read_exact(&mut response)?;
+        let result = i32::from_le_bytes([response[12], response[13], response[14], response[15]]);
+        if result < 0 {
+            return Err(Error::Transmitting(format!(
+                "pigpiod command {} failed with error {}",
+                cmd, result
+            )));
+        }
+        Ok(result)
+    }
+}
+
+/// Turns alternating marks and spaces in µs into waveform steps of the GPIOs in `mask`.
+///
+/// Every mark is split into whole carrier cycles, each switched on for `duty_cycle` percent of
+/// it. The cycle edges are rounded from their exact positions, so the rounding errors don't add
+/// up along a mark, and every mark ends exactly where the pulses say.
+fn modulate(pulses: &[u32], mask: u32, carrier: u32, duty_cycle: u32) -> Vec<WavePulse> {
+    let mut wave: Vec<WavePulse> = Vec::new();
+    let mut push = |pulse: WavePulse| match wave.last_mut() {
+        // Merge consecutive spaces.
+        Some(last) if pulse.on == 0 && last.on == 0 && pulse.off == last.off => {
+            last.delay += pulse.delay
+        }
+        _ => wave.push(pulse),
+    };
+    for (index, &length) in pulses.iter().enumerate() {
+        if length == 0 {
+            continue;
+        }
+        let space = WavePulse {
+            on: 0,
+            off: mask,
+            delay: length,
+        };
+        if index % 2 == 1 {
+            push(space);
+            continue;
+        }
+        if carrier == 0 {
+            push(WavePulse {
+                on: mask,
+                off: 0,
+                delay: length,
+            });
+            continue;
+        }
+        let (length, carrier) = (u64::from(length), u64::from(carrier));
+        let cycles = ((length * carrier + 500_000) / 1_000_000).max(1);
+        // The position in µs of `hundredths` of a cycle into the mark.
+        let at = |hundredths: u64| (hundredths * 10_000 + carrier / 2) / carrier;
+        for cycle in 0..cycles {
+            let start = at(cycle * 100);
+            let on_end = at(cycle * 100 + u64::from(duty_cycle)).min(length);
+            let end = if cycle + 1 == cycles {
+                length
+            } else {
+                at((cycle + 1) * 100)
+            };
+            push(WavePulse {
+                on: mask,
+                off: 0,
+                delay: (on_end - start) as u32,
+            });
+            push(WavePulse {
+                on: 0,
+                off: mask,
+                delay: (end - on_end) as u32,
+            });
+        }
+    }
+    wave
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::net::TcpListener;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+
+    /// Answers commands like pigpiod, failing `fail_cmd` and reporting a waveform that never
+    /// finishes if `stuck`, and reports the commands received.
+    fn fake_pigpiod(fail_cmd: Option<u32>, stuck: bool) -> (String, mpsc::Receiver<(u32, usize)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (commands, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 16];
+            while stream.read_exact(&mut header).is_ok() {
+                let word = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+                let mut extension = vec![0u8; word(12) as usize];
+                stream.read_exact(&mut extension).unwrap();
+                let result: i32 = match word(0) {
+                    cmd if Some(cmd) == fail_cmd => -2,
+                    CMD_WVCRE => 7,
+                    CMD_WVBSY => i32::from(stuck),
+                    _ => 0,
+                };
+                let _ = commands.send((word(0), extension.len() / 12));
+                header[12..].copy_from_slice(&result.to_le_bytes());
+                stream.write_all(&header).unwrap();
+            }
+        });
+        (addr, received)
+    }
+
+    #[test]
+    fn test_modulate_splits_marks_into_cycles() {
+        let wave = modulate(&[157, 263, 157], 1 << 18, 38_000, 33);
+        // Six cycles per mark, the last off-time merged with the space.
+        assert_eq!(wave.len(), 24);
+        assert_eq!(
+            wave[0],
+            WavePulse {
+                on: 1 << 18,
+                off: 0,
+                delay: 9
+            }
+        );
+        assert_eq!(wave[1].delay, 17);
+        assert_eq!(wave[11].delay, 157 - 140 + 263);
+        let total: u32 = wave.iter().map(|pulse| pulse.delay).sum();
+        assert_eq!(total, 157 + 263 + 157);
+
+        let unmodulated = modulate(&[500, 1000, 500], 1, 0, 33);
+        assert_eq!(unmodulated.len(), 3);
+        assert_eq!(
+            unmodulated[1],
+            WavePulse {
+                on: 0,
+                off: 1,
+                delay: 1000
+            }
+        );
+    }
+
+    #[test]
+    fn test_pigpiod_transmits_waveform() {
+        let (addr, received) = fake_pigpiod(None, false);
+        let clock = Arc::new(MockClock::new());
+        let transmitter = PigpiodTransmitter::connect(addr, 18)
+            .unwrap()
+            .with_clock(Arc::clone(&clock));
+        transmitter.send_pulses(&[157, 1026, 157, 263]).unwrap();
+        assert_eq!(clock.slept(), duration_of(&[157, 1026, 157, 263]));
+        let commands: Vec<u32> = received.try_iter().map(|(cmd, _)| cmd).collect();
+        assert_eq!(
+            commands,
+            [
+                CMD_MODES, CMD_WRITE, CMD_WVNEW, CMD_WVAG, CMD_WVCRE, CMD_WVTX, CMD_WVBSY,
+                CMD_WVDEL
+            ]
+        );
+
+        transmitter.set_carrier(0, 50).unwrap();
+        transmitter.send_pulses(&[157, 1026, 157, 263]).unwrap();
+        let added: Vec<usize> = received
+            .try_iter()
+            .filter(|&(cmd, _)| cmd == CMD_WVAG)
+            .map(|(_, pulses)| pulses)
+            .collect();
+        assert_eq!(added, [4]);
+    }
+
+    #[test]
+    fn test_pigpiod_reports_errors() {
+        assert!(matches!(
+            PigpiodTransmitter::connect("127.0.0.1:1", 32),
+            Err(Error::ProtocolError(_))
+        ));
+        let (addr, received) = fake_pigpiod(Some(CMD_WVCRE), false);
+        let transmitter = PigpiodTransmitter::connect(addr, 18)
+            .unwrap()
+            .with_clock(MockClock::new());
+        assert!(matches!(
+            transmitter.send_pulses(&[157, 1026]),
+            Err(Error::Transmitting(_))
+        ));
+        // Nothing was created, so nothing is sent or deleted.
+        let last = received.try_iter().last().unwrap();
+        assert_eq!(last.0, CMD_WVCRE);
+
+        let (addr, received) = fake_pigpiod(None, true);
+        let clock = Arc::new(MockClock::new());
+        let transmitter = PigpiodTransmitter::connect(addr, 18)
+            .unwrap()
+            .with_clock(Arc::clone(&clock));
+        assert!(matches!(
+            transmitter.send_pulses(&[157, 1026]),
+            Err(Error::Transmitting(message)) if message.contains("in time")
+        ));
+        assert!(clock.slept() > WAVE_TIMEOUT);
+        // The waveform is deleted even though it didn't finish.
+        assert_eq!(received.try_iter().last().unwrap().0, CMD_WVDEL);
+    }
+}
@@ -0,0 +1,522 @@
+//! # Queued transmission
+//!
+//! A LIRC write blocks for the whole air time of a message, tens of milliseconds for a frame with
+//! its repeats. `QueuedTransmitter` moves the writes to a background worker: `send_pulses` only
+//! queues the pulses and returns, so a UI thread driving a controller never waits for the IR
+//! medium. Messages of a higher `Priority`, e.g. an emergency stop, overtake queued ones of a
+//! lower priority.
+//!
+//! As sending returns before the pulses are on air, transmission errors can't be returned by it.
+//! The worker keeps the first error, and `flush`, which waits until everything queued so far is
+//! transmitted, returns it.
+//!
+//! A joystick or slider produces speed updates faster than the medium can carry them. With
+//! `QueuePolicy::CoalescePwm`, a PWM message replaces a still queued PWM message for the same
+//! receiver output, so the worker sends the latest speed instead of every intermediate step.
+
+use crate::clock::Clock;
+use crate::device::PulseTransmitter;
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+use crate::protocols::decode::{decode, DecodedCommand};
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+use crate::{Channel, Output, SingleOutputCommand};
+use crate::{Error, Result};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+/// The urgency of queued messages; the worker always serves the highest priority first, and
+/// messages of the same priority in order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Background traffic such as keepalives or light effects.
+    Low,
+    /// Regular commands.
+    #[default]
+    Normal,
+    /// Commands that must not wait behind others, e.g. stopping all trains.
+    High,
+}
+
+/// How `QueuedTransmitter` treats a message queued behind others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Every message is transmitted, in order.
+    #[default]
+    Fifo,
+    /// A PWM message (Combo PWM or Single Output PWM) replaces a queued PWM message of the same
+    /// priority for the same receiver and outputs, keeping its place in the queue. The search
+    /// stops at any other message for that receiver and at carrier changes, so the order of
+    /// different commands is kept.
+    #[cfg(all(
+        feature = "single-output",
+        feature = "combo-direct",
+        feature = "combo-pwm",
+        feature = "extended"
+    ))]
+    CoalescePwm,
+}
+
+enum Job {
+    Send(Vec<u32>),
+    SetCarrier(u32, u32),
+    Settle,
+}
+
+#[derive(Default)]
+struct State {
+    // One queue per priority, indexed by `Priority as usize`.
+    queues: [VecDeque<Job>; 3],
+    busy: bool,
+    closed: bool,
+    error: Option<Error>,
+    policy: QueuePolicy,
+}
+
+impl State {
+    fn is_idle(&self) -> bool {
+        !self.busy && self.queues.iter().all(VecDeque::is_empty)
+    }
+}
+
+struct Shared<T> {
+    transmitter: T,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A `PulseTransmitter` handing the pulses to a worker thread that transmits them by priority.
+///
+/// Sending through the transmitter itself queues at `Priority::Normal`; `sender` creates handles
+/// for other priorities, which can be passed to further `BrickBeam` instances sharing the worker.
+/// The queue is unbounded, so producers faster than the medium should coalesce their updates,
+/// e.g. with `QueuePolicy::CoalescePwm`.
+///
+/// Only the air time moves to the worker. Controllers still wait between the copies a
+/// `RepeatPolicy` asks for, so keep it at `RepeatPolicy::single()` for callers that must not
+/// block.
+///
+/// Dropping the transmitter waits until the worker has transmitted everything queued.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, Priority, PulseTransmitterEmulator, QueuedTransmitter};
+///
+/// let queued = QueuedTransmitter::new(PulseTransmitterEmulator);
+/// let emergency = BrickBeam::from_transmitter(queued.sender(Priority::High));
+/// let brick_beam = BrickBeam::from_transmitter(queued);
+/// ```
+pub struct QueuedTransmitter<T: PulseTransmitter + Send + Sync + 'static> {
+    sender: QueueSender<T>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<T: PulseTransmitter + Send + Sync + 'static> QueuedTransmitter<T> {
+    /// Starts a worker thread transmitting on `transmitter`.
+    pub fn new(transmitter: T) -> Self {
+        let shared = Arc::new(Shared {
+            transmitter,
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        });
+        let worker = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(&shared))
+        };
+        Self {
+            sender: QueueSender {
+                shared,
+                priority: Priority::Normal,
+            },
+            worker: Some(worker),
+        }
+    }
+
+    /// Sets how messages queued behind others are treated, see `QueuePolicy`.
+    pub fn with_policy(self, policy: QueuePolicy) -> Self {
+        self.sender.shared.lock().policy = policy;
+        self
+    }
+
+    /// Returns a handle queuing its messages at `priority` on this transmitter's worker.
+    pub fn sender(&self, priority: Priority) -> QueueSender<T> {
+        QueueSender {
+            shared: Arc::clone(&self.sender.shared),
+            priority,
+        }
+    }
+
+    /// Returns the number of queued messages and settings the worker has not started yet.
+    pub fn len(&self) -> usize {
+        self.sender
+            .shared
+            .lock()
+            .queues
+            .iter()
+            .map(VecDeque::len)
+            .sum()
+    }
+
+    /// Returns `true` if nothing is waiting for the worker.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a reference to the wrapped transmitter.
+    pub fn inner(&self) -> &T {
+        &self.sender.shared.transmitter
+    }
+}
+
+/// Stops accepting messages and waits until the worker has transmitted the queued ones.
+impl<T: PulseTransmitter + Send + Sync + 'static> Drop for QueuedTransmitter<T> {
+    fn drop(&mut self) {
+        self.sender.shared.lock().closed = true;
+        self.sender.shared.changed.notify_all();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<T: PulseTransmitter + Send + Sync + 'static> PulseTransmitter for QueuedTransmitter<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        self.sender.send_pulses(pulses)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.sender.flush()
+    }
+
+    fn settle(&self) {
+        self.sender.settle()
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.sender.set_carrier(carrier, duty_cycle)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.sender.clock()
+    }
+}
+
+/// A handle queuing messages at one priority on the worker of a `QueuedTransmitter`.
+///
+/// Once the `QueuedTransmitter` is dropped, sending fails with `Error::Transmitting`.
+pub struct QueueSender<T: PulseTransmitter + Send + Sync + 'static> {
+    shared: Arc<Shared<T>>,
+    priority: Priority,
+}
+
+impl<T: PulseTransmitter + Send + Sync + 'static> QueueSender<T> {
+    /// Returns the priority the handle queues at.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    fn enqueue(&self, job: Job) -> Result<()> {
+        let mut state = self.shared.lock();
+        if state.closed {
+            return Err(Error::Transmitting("The queue is closed".into()));
+        }
+        let policy = state.policy;
+        let queue = &mut state.queues[self.priority as usize];
+        match policy {
+            QueuePolicy::Fifo => queue.push_back(job),
+            #[cfg(all(
+                feature = "single-output",
+                feature = "combo-direct",
+                feature = "combo-pwm",
+                feature = "extended"
+            ))]
+            QueuePolicy::CoalescePwm => coalesce_pwm(queue, job),
+        }
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+}
+
+impl<T: PulseTransmitter + Send + Sync + 'static> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            priority: self.priority,
+        }
+    }
+}
+
+impl<T: PulseTransmitter + Send + Sync + 'static> PulseTransmitter for QueueSender<T> {
+    /// Queues the pulses and returns without waiting for them to be transmitted.
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        self.enqueue(Job::Send(pulses.to_vec()))
+    }
+
+    /// Waits until everything queued so far, at any priority, has been transmitted.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error the worker ran into since the last flush.
+    fn flush(&self) -> Result<()> {
+        let mut state = self.shared.lock();
+        while !state.is_idle() {
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        match state.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn settle(&self) {
+        let _ = self.enqueue(Job::Settle);
+    }
+
+    /// Queues the carrier change, so it takes effect for the messages queued after it.
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.enqueue(Job::SetCarrier(carrier, duty_cycle))
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.shared.transmitter.clock()
+    }
+}
+
+/// The receiver outputs a PWM message sets: its channel, address bit and the single output, or
+/// `None` for both outputs of Combo PWM.
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+type PwmTarget = (Channel, bool, Option<Output>);
+
+/// Returns the target of a message that is PWM only, and the receiver of other PF messages in
+/// the `Err` variant; `None` if the pulses aren't PF messages for a single receiver.
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+fn pwm_target(pulses: &[u32]) -> Option<std::result::Result<PwmTarget, (Channel, bool)>> {
+    let mut target = None;
+    for message in decode(pulses) {
+        let message = message.ok()?;
+        let output = match message.command {
+            DecodedCommand::ComboPwm(_) => Ok(None),
+            DecodedCommand::SingleOutput(output, SingleOutputCommand::PWM(_)) => Ok(Some(output)),
+            _ => Err(()),
+        };
+        let this = output
+            .map(|output| (message.channel, message.address, output))
+            .map_err(|()| (message.channel, message.address));
+        // Repeats of the same frame are one message; mixed frames aren't coalesced.
+        match target {
+            Some(known) if known != this => return None,
+            _ => target = Some(this),
+        }
+    }
+    target
+}
+
+/// Queues `job`, replacing a queued PWM message for the same target if there is one before any
+/// other message for that receiver.
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+fn coalesce_pwm(queue: &mut VecDeque<Job>, job: Job) {
+    let Job::Send(pulses) = &job else {
+        return queue.push_back(job);
+    };
+    let Some(Ok(target)) = pwm_target(pulses) else {
+        return queue.push_back(job);
+    };
+    let receiver = (target.0, target.1);
+    for queued in queue.iter_mut().rev() {
+        let Job::Send(queued_pulses) = queued else {
+            break;
+        };
+        match pwm_target(queued_pulses) {
+            Some(Ok(queued_target)) if queued_target == target => {
+                *queued = job;
+                return;
+            }
+            Some(Ok((channel, address, _))) | Some(Err((channel, address)))
+                if (channel, address) != receiver => {}
+            _ => break,
+        }
+    }
+    queue.push_back(job);
+}
+
+fn run<T: PulseTransmitter>(shared: &Shared<T>) {
+    loop {
+        let job = {
+            let mut state = shared.lock();
+            loop {
+                if let Some(job) = state.queues.iter_mut().rev().find_map(VecDeque::pop_front) {
+                    state.busy = true;
+                    break job;
+                }
+                if state.closed {
+                    return;
+                }
+                state = shared
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+        };
+        let transmitter = &shared.transmitter;
+        let result = match job {
+            Job::Send(pulses) => transmitter.send_pulses(&pulses),
+            Job::SetCarrier(carrier, duty_cycle) => transmitter.set_carrier(carrier, duty_cycle),
+            Job::Settle => {
+                transmitter.settle();
+                Ok(())
+            }
+        };
+        let mut state = shared.lock();
+        state.busy = false;
+        if let Err(e) = result {
+            state.error.get_or_insert(e);
+        }
+        shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    /// Records the pulses and blocks every transmission until released.
+    struct MockTransmitterGated {
+        sent: Mutex<Vec<Vec<u32>>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl PulseTransmitter for MockTransmitterGated {
+        fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+            let _ = self.release.lock().unwrap().recv();
+            if pulses.is_empty() {
+                return Err(Error::Transmitting("Mock failure".to_string()));
+            }
+            self.sent.lock().unwrap().push(pulses.to_vec());
+            Ok(())
+        }
+    }
+
+    fn gated() -> (QueuedTransmitter<MockTransmitterGated>, Sender<()>) {
+        let (release, release_rx) = mpsc::channel();
+        let transmitter = MockTransmitterGated {
+            sent: Mutex::new(Vec::new()),
+            release: Mutex::new(release_rx),
+        };
+        (QueuedTransmitter::new(transmitter), release)
+    }
+
+    #[test]
+    fn test_send_returns_before_transmission() {
+        let (queued, release) = gated();
+        queued.send_pulses(&[1]).unwrap();
+        queued.send_pulses(&[2]).unwrap();
+        assert!(queued.inner().sent.lock().unwrap().is_empty());
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        queued.flush().unwrap();
+        assert_eq!(*queued.inner().sent.lock().unwrap(), [[1], [2]]);
+    }
+
+    #[test]
+    fn test_higher_priority_overtakes() {
+        let (queued, release) = gated();
+        let urgent = queued.sender(Priority::High);
+        let background = queued.sender(Priority::Low);
+        queued.send_pulses(&[1]).unwrap();
+        // Wait until the worker is blocked on the first message.
+        while !queued.is_empty() {
+            thread::yield_now();
+        }
+        background.send_pulses(&[2]).unwrap();
+        queued.send_pulses(&[3]).unwrap();
+        urgent.send_pulses(&[4]).unwrap();
+
+        (0..4).for_each(|_| release.send(()).unwrap());
+        urgent.flush().unwrap();
+        assert_eq!(*queued.inner().sent.lock().unwrap(), [[1], [4], [3], [2]]);
+    }
+
+    #[test]
+    fn test_errors_reported_on_flush() {
+        let (queued, release) = gated();
+        queued.send_pulses(&[]).unwrap();
+        queued.send_pulses(&[1]).unwrap();
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        assert!(matches!(queued.flush(), Err(Error::Transmitting(_))));
+        assert!(queued.flush().is_ok());
+        assert_eq!(*queued.inner().sent.lock().unwrap(), [[1]]);
+    }
+
+    #[cfg(all(
+        feature = "single-output",
+        feature = "combo-direct",
+        feature = "combo-pwm",
+        feature = "extended"
+    ))]
+    #[test]
+    fn test_pwm_updates_coalesced() {
+        use crate::protocols::timing::PulseTiming;
+        let frame = |frame: u16| PulseTiming::STANDARD.encode_frame(frame).to_vec();
+        let (queued, release) = gated();
+        let queued = queued.with_policy(QueuePolicy::CoalescePwm);
+        queued.send_pulses(&[1]).unwrap();
+        while !queued.is_empty() {
+            thread::yield_now();
+        }
+        // Combo PWM on channel 1: float/forward 1, then forward 2/forward 3.
+        queued.send_pulses(&frame(0x401A)).unwrap();
+        // Combo PWM on channel 2, which doesn't stop the search.
+        queued.send_pulses(&frame(0x501B)).unwrap();
+        queued.send_pulses(&frame(0x423A)).unwrap();
+        // Single Output PWM forward 1 on the red output of channel 1 stops it.
+        queued.send_pulses(&frame(0x041A)).unwrap();
+        queued.send_pulses(&frame(0x4F04)).unwrap();
+        assert_eq!(queued.len(), 4);
+
+        (0..5).for_each(|_| release.send(()).unwrap());
+        queued.flush().unwrap();
+        let sent = queued.inner().sent.lock().unwrap();
+        assert_eq!(
+            sent[1..],
+            [frame(0x423A), frame(0x501B), frame(0x041A), frame(0x4F04)]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use crate::clock::Clock;
+use crate::device::PulseTransmitter;
+use crate::protocols::duration_of;
+use crate::Result;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// When a transmission ended and how long it was on air.
+type Transmission = (Instant, Duration);
+
+struct Budget {
+    max_air_time: Duration,
+    window: Duration,
+}
+
+/// Paces the messages of all controllers sharing a transmitter: every message waits for a
+/// minimum gap after the previous one, and the IR air time within a sliding window stays within a
+/// budget.
+///
+/// PF receivers lose track when frames of different controllers follow each other back to back,
+/// and an emitter that is never off heats its LED and floods the room for other remotes. The
+/// limits apply across all senders, which are served one at a time.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, PulseTransmitterEmulator, RateLimitTransmitter};
+/// use std::time::Duration;
+///
+/// // At least 2 ms between messages, on air at most half of every second.
+/// let transmitter = RateLimitTransmitter::new(PulseTransmitterEmulator, Duration::from_millis(2))
+///     .with_duty_budget(Duration::from_millis(500), Duration::from_secs(1));
+/// let brick_beam = BrickBeam::from_transmitter(transmitter);
+/// ```
+pub struct RateLimitTransmitter<T: PulseTransmitter> {
+    inner: T,
+    min_gap: Duration,
+    budget: Option<Budget>,
+    history: Mutex<VecDeque<Transmission>>,
+}
+
+impl<T: PulseTransmitter> RateLimitTransmitter<T> {
+    /// Wraps `inner`, keeping the medium quiet for `min_gap` between any two messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The transmitter that sends the pulses, usually the hardware.
+    /// * `min_gap` - The quiet time from the end of a message to the start of the next one.
+    pub fn new(inner: T, min_gap: Duration) -> Self {
+        Self {
+            inner,
+            min_gap,
+            budget: None,
+            history: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Limits the air time within any `window` to `max_air_time`, delaying messages that would
+    /// exceed it. A single message longer than the budget is sent once the window is clear.
+    pub fn with_duty_budget(mut self, max_air_time: Duration, window: Duration) -> Self {
+        self.budget = Some(Budget {
+            max_air_time,
+            window,
+        });
+        self
+    }
+
+    /// Returns a reference to the wrapped transmitter.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns when a message of `air_time` may start at the earliest.
+    fn earliest_start(&self, history: &VecDeque<Transmission>, air_time: Duration) -> Instant {
+        let now = self.inner.clock().now();
+        let mut start = match history.back() {
+            Some(&(ended, _)) => now.max(ended + self.min_gap),
+            None => now,
+        };
+        if let Some(budget) = &self.budget {
+            let mut used: Duration = history.iter().map(|&(_, air_time)| air_time).sum();
+            // Oldest first, drop transmissions that will have left the window at the start.
+            for &(ended, past) in history.iter() {
+                if ended + budget.window > start {
+                    if used + air_time <= budget.max_air_time {
+                        break;
+                    }
+                    start = ended + budget.window;
+                }
+                used -= past;
+            }
+        }
+        start
+    }
+}
+
+impl<T: PulseTransmitter> PulseTransmitter for RateLimitTransmitter<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        // Held while waiting and sending, so concurrent senders are spaced out one by one.
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let clock = self.inner.clock();
+        let air_time = duration_of(pulses);
+        let start = self.earliest_start(&history, air_time);
+        clock.sleep(start.saturating_duration_since(clock.now()));
+
+        let result = self.inner.send_pulses(pulses);
+        let ended = clock.now().max(start + air_time);
+        history.push_back((ended, air_time));
+        let forget_before = ended
+            .checked_sub(self.budget.as_ref().map_or(Duration::ZERO, |b| b.window))
+            .unwrap_or(ended);
+        while history.len() > 1 && history.front().is_some_and(|&(e, _)| e < forget_before) {
+            history.pop_front();
+        }
+        result
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn settle(&self) {
+        self.inner.settle()
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.inner.set_carrier(carrier, duty_cycle)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+
+    #[test]
+    fn test_min_gap_between_messages() {
+        let transmitter =
+            RateLimitTransmitter::new(MockTransmitterRecorder::default(), Duration::from_millis(2));
+        let opened = transmitter.clock().now();
+        transmitter.send_pulses(&[5000, 5000]).unwrap();
+        transmitter.send_pulses(&[5000, 5000]).unwrap();
+        transmitter.inner().clock.advance(Duration::from_millis(50));
+        transmitter.send_pulses(&[5000, 5000]).unwrap();
+
+        let sent = transmitter.inner().sent_at();
+        assert_eq!(sent[0], opened);
+        assert_eq!(sent[1] - sent[0], Duration::from_millis(12));
+        assert_eq!(sent[2] - sent[1], Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_duty_budget_delays_messages() {
+        let transmitter =
+            RateLimitTransmitter::new(MockTransmitterRecorder::default(), Duration::ZERO)
+                .with_duty_budget(Duration::from_millis(20), Duration::from_millis(100));
+        let opened = transmitter.clock().now();
+        for _ in 0..3 {
+            transmitter.send_pulses(&[5000, 5000]).unwrap();
+        }
+
+        let sent = transmitter.inner().sent_at();
+        assert_eq!(sent[1] - opened, Duration::from_millis(10));
+        // The first message has to leave the window before the third fits into the budget.
+        assert_eq!(sent[2] - opened, Duration::from_millis(110));
+    }
+}
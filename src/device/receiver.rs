@@ -0,0 +1,255 @@
+//! # Receiving pulses
+//!
+//! `PulseReceiver` is the receive counterpart of `PulseTransmitter`: it yields the IR activity
+//! seen by a receiver, such as a `gpio-ir-recv` overlay, as bursts of alternating mark and space
+//! durations in microseconds. Every burst starts and ends with a mark; a space of at least the
+//! burst gap (or a receiver timeout) separates consecutive bursts.
+//!
+//! With the `cir` feature, `CirPulseReceiver` reads the bursts from `/dev/lircX` in LIRC mode2.
+
+use crate::Result;
+use std::collections::VecDeque;
+
+/// The default silence after which a burst is complete, in microseconds.
+///
+/// The longest space within a PF frame is its 1 ms start space, and consecutive copies of a
+/// message are at least a few milliseconds apart, so every frame becomes a burst of its own.
+pub const DEFAULT_BURST_GAP: u32 = 5_000;
+
+/// A trait representing the ability to receive IR pulses.
+///
+/// # Example
+///
+/// ```
+/// use brickbeam::{PulseReceiver, Result};
+///
+/// struct Replay(Vec<Vec<u32>>);
+///
+/// impl PulseReceiver for Replay {
+///     fn receive_pulses(&mut self) -> Result<Vec<u32>> {
+///         Ok(self.0.pop().unwrap_or_default())
+///     }
+/// }
+///
+/// let mut receiver = Replay(vec![vec![158, 1026, 158]]);
+/// assert_eq!(receiver.receive_pulses().unwrap().len(), 3);
+/// ```
+pub trait PulseReceiver {
+    /// Blocks until a complete burst was received and returns its alternating mark and space
+    /// durations (in microseconds), starting and ending with a mark.
+    fn receive_pulses(&mut self) -> crate::Result<Vec<u32>>;
+}
+
+impl<R: PulseReceiver + ?Sized> PulseReceiver for Box<R> {
+    fn receive_pulses(&mut self) -> Result<Vec<u32>> {
+        (**self).receive_pulses()
+    }
+}
+
+/// A LIRC mode2 event, as far as bursts are concerned.
+#[cfg_attr(not(feature = "cir"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode2 {
+    Pulse(u32),
+    Space(u32),
+    /// The receiver saw no IR for its timeout.
+    Timeout,
+    /// The receiver lost data, so the current burst is incomplete.
+    Overflow,
+}
+
+/// Assembles mode2 events into bursts.
+#[cfg_attr(not(feature = "cir"), allow(dead_code))]
+#[derive(Debug)]
+pub(crate) struct BurstAssembler {
+    gap: u32,
+    current: Vec<u32>,
+    complete: VecDeque<Vec<u32>>,
+}
+
+#[cfg_attr(not(feature = "cir"), allow(dead_code))]
+impl BurstAssembler {
+    pub(crate) fn new(gap: u32) -> Self {
+        Self {
+            gap,
+            current: Vec::new(),
+            complete: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, event: Mode2) {
+        let marks = self.current.len() % 2 == 1;
+        match event {
+            Mode2::Pulse(duration) if marks => *self.current.last_mut().unwrap() += duration,
+            Mode2::Pulse(duration) => self.current.push(duration),
+            // Leading silence belongs to no burst.
+            Mode2::Space(_) if self.current.is_empty() => {}
+            Mode2::Space(duration) if marks => {
+                self.current.push(duration);
+                self.end_on_gap();
+            }
+            Mode2::Space(duration) => {
+                *self.current.last_mut().unwrap() += duration;
+                self.end_on_gap();
+            }
+            Mode2::Timeout => self.finish(),
+            Mode2::Overflow => self.current.clear(),
+        }
+    }
+
+    /// Returns the oldest complete burst, if any.
+    pub(crate) fn pop(&mut self) -> Option<Vec<u32>> {
+        self.complete.pop_front()
+    }
+
+    fn end_on_gap(&mut self) {
+        if self.current.last().is_some_and(|&space| space >= self.gap) {
+            self.finish();
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.current.len() % 2 == 0 {
+            self.current.pop();
+        }
+        if !self.current.is_empty() {
+            self.complete.push_back(std::mem::take(&mut self.current));
+        }
+    }
+}
+
+#[cfg(feature = "cir")]
+pub use cir_receiver::CirPulseReceiver;
+
+#[cfg(feature = "cir")]
+mod cir_receiver {
+    use super::{BurstAssembler, Mode2, PulseReceiver, DEFAULT_BURST_GAP};
+    use crate::{Error, Result};
+    use cir::lirc::{Lirc, LircRaw};
+    use std::path::Path;
+
+    /// Receives pulses from the kernel's /dev/lircX device using the cir library.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use brickbeam::{CirPulseReceiver, PulseReceiver};
+    ///
+    /// let mut receiver = CirPulseReceiver::new("/dev/lirc1").unwrap();
+    /// loop {
+    ///     println!("{:?}", receiver.receive_pulses().unwrap());
+    /// }
+    /// ```
+    pub struct CirPulseReceiver {
+        rx_device: Lirc,
+        buffer: Vec<LircRaw>,
+        bursts: BurstAssembler,
+    }
+
+    impl CirPulseReceiver {
+        /// Opens the receive device (e.g. /dev/lirc1) in mode2.
+        ///
+        /// The receiver timeout is set to `DEFAULT_BURST_GAP` where the driver allows it, so a
+        /// burst is reported as soon as the IR stops.
+        ///
+        /// # Errors
+        ///
+        /// Returns `Error::Receiving` if the device can't receive raw pulses.
+        pub fn new(rx_device_path: impl AsRef<Path>) -> Result<Self> {
+            let mut rx_device = cir::lirc::open(rx_device_path.as_ref())?;
+            if !rx_device.can_receive_raw() {
+                return Err(Error::Receiving(format!(
+                    "{} can't receive raw pulses",
+                    rx_device_path.as_ref().display()
+                )));
+            }
+            if rx_device.can_set_timeout() {
+                let timeouts = rx_device.get_min_max_timeout()?;
+                let timeout = DEFAULT_BURST_GAP.clamp(timeouts.start, timeouts.end);
+                rx_device.set_timeout(timeout)?;
+            }
+            Ok(Self {
+                rx_device,
+                buffer: Vec::with_capacity(1024),
+                bursts: BurstAssembler::new(DEFAULT_BURST_GAP),
+            })
+        }
+    }
+
+    impl PulseReceiver for CirPulseReceiver {
+        fn receive_pulses(&mut self) -> Result<Vec<u32>> {
+            loop {
+                if let Some(burst) = self.bursts.pop() {
+                    return Ok(burst);
+                }
+                self.rx_device
+                    .receive_raw(&mut self.buffer)
+                    .map_err(|e| Error::Receiving(e.to_string()))?;
+                for raw in &self.buffer {
+                    let event = if raw.is_pulse() {
+                        Mode2::Pulse(raw.value())
+                    } else if raw.is_space() {
+                        Mode2::Space(raw.value())
+                    } else if raw.is_timeout() {
+                        Mode2::Timeout
+                    } else if raw.is_overflow() {
+                        Mode2::Overflow
+                    } else {
+                        continue;
+                    };
+                    self.bursts.push(event);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(events: &[Mode2]) -> Vec<Vec<u32>> {
+        let mut bursts = BurstAssembler::new(DEFAULT_BURST_GAP);
+        for &event in events {
+            bursts.push(event);
+        }
+        std::iter::from_fn(|| bursts.pop()).collect()
+    }
+
+    #[test]
+    fn test_bursts_end_on_timeout_and_gap() {
+        use Mode2::*;
+        let bursts = assemble(&[
+            Space(100_000),
+            Pulse(158),
+            Space(1026),
+            Pulse(158),
+            Timeout,
+            Pulse(158),
+            Space(263),
+            Pulse(158),
+            Space(9000),
+            Pulse(158),
+        ]);
+        assert_eq!(bursts, vec![vec![158, 1026, 158], vec![158, 263, 158]]);
+    }
+
+    #[test]
+    fn test_bursts_merge_repeated_events_and_drop_overflow() {
+        use Mode2::*;
+        let bursts = assemble(&[
+            Pulse(100),
+            Pulse(58),
+            Space(200),
+            Space(63),
+            Pulse(158),
+            Timeout,
+            Pulse(158),
+            Overflow,
+            Pulse(158),
+            Space(263),
+            Timeout,
+            Timeout,
+        ]);
+        assert_eq!(bursts, vec![vec![158, 263, 158], vec![158]]);
+    }
+}
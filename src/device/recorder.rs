@@ -0,0 +1,104 @@
+use crate::clock::{Clock, MockClock};
+use crate::device::PulseTransmitter;
+use crate::{Error, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+/// A `PulseTransmitter` for tests that records what it is asked to send.
+///
+/// It paces everything built on top of it with its `MockClock`, so sleeps take no real time and
+/// every pulse train is recorded with the virtual time it was sent at. `set_failing` makes it
+/// fail sends like a vanished device.
+///
+/// # Example
+#[cfg_attr(all(feature = "test-util", feature = "single-output"), doc = "```rust")]
+#[cfg_attr(
+    not(all(feature = "test-util", feature = "single-output")),
+    doc = "```ignore"
+)]
+/// use brickbeam::{BrickBeam, Channel, MockTransmitterRecorder, Output, RepeatPolicy};
+/// use brickbeam::SingleOutputCommand;
+///
+/// let brick_beam = BrickBeam::from_transmitter(MockTransmitterRecorder::new())
+///     .with_repeat_policy(RepeatPolicy::single());
+/// let mut motor = brick_beam.create_speed_remote_controller(Channel::One, Output::RED)?;
+/// motor.send(SingleOutputCommand::PWM(5))?;
+/// assert_eq!(brick_beam.transmitter().sent().len(), 1);
+/// # Ok::<(), brickbeam::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct MockTransmitterRecorder {
+    /// The clock of the transmitter, which stamps the recorded pulse trains.
+    pub clock: MockClock,
+    sent: Mutex<Vec<(Instant, Vec<u32>)>>,
+    carriers: Mutex<Vec<(u32, u32)>>,
+    failing: AtomicBool,
+}
+
+impl MockTransmitterRecorder {
+    /// Creates a recorder that has sent nothing yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the pulse trains sent so far.
+    pub fn sent(&self) -> Vec<Vec<u32>> {
+        lock(&self.sent)
+            .iter()
+            .map(|(_, pulses)| pulses.clone())
+            .collect()
+    }
+
+    /// Returns the times of the clock at which the pulse trains were sent.
+    pub fn sent_at(&self) -> Vec<Instant> {
+        lock(&self.sent).iter().map(|(at, _)| *at).collect()
+    }
+
+    /// Returns the pulse trains sent so far, each with the time it was sent at.
+    pub fn timeline(&self) -> Vec<(Instant, Vec<u32>)> {
+        lock(&self.sent).clone()
+    }
+
+    /// Returns the carriers and duty cycles set so far.
+    pub fn carriers(&self) -> Vec<(u32, u32)> {
+        lock(&self.carriers).clone()
+    }
+
+    /// Blocks until at least `count` pulse trains have been sent, e.g. by a background thread
+    /// woken up by `clock.advance`.
+    pub fn wait_until_sent(&self, count: usize) {
+        while lock(&self.sent).len() < count {
+            std::thread::yield_now();
+        }
+    }
+
+    /// Makes the following sends fail with `Error::Transmitting` without recording them, or
+    /// succeed again.
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::Relaxed);
+    }
+}
+
+impl PulseTransmitter for MockTransmitterRecorder {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        if self.failing.load(Ordering::Relaxed) {
+            return Err(Error::Transmitting("Mock failure".to_string()));
+        }
+        lock(&self.sent).push((self.clock.now(), pulses.to_vec()));
+        Ok(())
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        lock(&self.carriers).push((carrier, duty_cycle));
+        Ok(())
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,94 @@
+//! # Process-wide device registry
+//!
+//! Two `BrickBeam` instances opened on the same device path must not interleave their writes,
+//! even when they live in unrelated parts of a program (for example in separately loaded
+//! plugins). Transmitters therefore obtain their device handle from a registry keyed by the
+//! canonical device path: every opener of the same path shares one handle, and with it one
+//! writer thread that serializes all transmissions to that device (see `writer`).
+//!
+//! The registry only holds weak references, so a device is closed as soon as its last user is
+//! dropped and reopened on the next request.
+
+use crate::Result;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Shares one handle per device path between all users in the process.
+pub(crate) struct DeviceRegistry<D> {
+    devices: Mutex<Vec<(PathBuf, Weak<D>)>>,
+}
+
+impl<D> DeviceRegistry<D> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            devices: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the shared handle for `path`, opening the device with `open` if nobody holds it.
+    ///
+    /// Paths are compared after canonicalization, so `/dev/lirc0` and a symlink to it share a
+    /// handle. The registry lock is held while opening, so a device is never opened twice.
+    pub(crate) fn get_or_open(
+        &self,
+        path: &Path,
+        open: impl FnOnce(&Path) -> Result<D>,
+    ) -> Result<Arc<D>> {
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        devices.retain(|(_, device)| device.strong_count() > 0);
+        if let Some(device) = devices
+            .iter()
+            .find(|(known, _)| *known == key)
+            .and_then(|(_, device)| device.upgrade())
+        {
+            return Ok(device);
+        }
+        let device = Arc::new(open(path)?);
+        devices.push((key, Arc::downgrade(&device)));
+        Ok(device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_registry_shares_handle_per_path() {
+        let registry = DeviceRegistry::new();
+        let mut opened = 0;
+        let mut open = |_: &Path| -> Result<u32> {
+            opened += 1;
+            Ok(opened)
+        };
+
+        let first = registry
+            .get_or_open(Path::new("/dev/lirc0"), &mut open)
+            .unwrap();
+        let second = registry
+            .get_or_open(Path::new("/dev/lirc0"), &mut open)
+            .unwrap();
+        let other = registry
+            .get_or_open(Path::new("/dev/lirc1"), &mut open)
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &other));
+
+        drop((first, second));
+        let reopened = registry
+            .get_or_open(Path::new("/dev/lirc0"), &mut open)
+            .unwrap();
+        assert_eq!(*reopened, 3);
+    }
+
+    #[test]
+    fn test_registry_propagates_open_errors() {
+        let registry: DeviceRegistry<u32> = DeviceRegistry::new();
+        let result = registry.get_or_open(Path::new("/dev/lirc0"), |_| {
+            Err(Error::Transmitting("Mock failure".to_string()))
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! # Retrying interrupted writes
+//!
+//! A LIRC `write` blocks until the IR has been transmitted. If the process receives a signal in
+//! the meantime (a timer, `SIGCHLD`, ...), the call fails with `EINTR`; a device that is busy
+//! with another writer may answer `EAGAIN` or `EBUSY`. All are transient, so the device layer
+//! retries them a bounded number of times, backing off between the attempts, before reporting an
+//! error. A ramping loop thus keeps running through a momentary hiccup.
+
+use crate::clock::Clock;
+use std::io;
+use std::time::Duration;
+
+/// How the LIRC transmitters retry writes failing with a transient error.
+///
+/// A write interrupted by a signal is repeated right away. After `EAGAIN` or `EBUSY`, the
+/// transmitter waits `backoff` before the first retry and twice as long before every further one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How often a transient write error is retried before it is surfaced.
+    pub retries: u32,
+    /// The wait before the first retry of a busy device.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Retries 5 times, backing off 1, 2, 4, 8 and 16 ms: 31 ms in total, about two PF messages.
+    fn default() -> Self {
+        Self {
+            retries: 5,
+            backoff: Duration::from_millis(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the wait before the retry following the given number of earlier retries.
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.min(16))
+    }
+}
+
+/// Runs `op`, retrying according to `policy` while it fails with `Interrupted` (`EINTR`),
+/// `WouldBlock` (`EAGAIN`) or `ResourceBusy` (`EBUSY`), backing off on `clock`. Any other error, or
+/// the last transient one, is returned as is.
+pub(crate) fn retry_transient<T>(
+    policy: RetryPolicy,
+    clock: &dyn Clock,
+    mut op: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(e) if attempt < policy.retries && is_transient(&e) => {
+                if e.kind() != io::ErrorKind::Interrupted {
+                    clock.sleep(policy.backoff(attempt));
+                }
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::ResourceBusy
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn test_transient_errors_are_retried() {
+        let clock = MockClock::new();
+        let mut calls = 0;
+        let result = retry_transient(RetryPolicy::default(), &clock, || {
+            calls += 1;
+            match calls {
+                1 => Err(io::Error::from(io::ErrorKind::Interrupted)),
+                2 => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+                3 => Err(io::Error::from(io::ErrorKind::ResourceBusy)),
+                _ => Ok(calls),
+            }
+        });
+        assert_eq!(result.unwrap(), 4);
+        // The interrupted write is repeated at once, the busy ones as the second and third retry.
+        assert_eq!(clock.slept(), Duration::from_millis(2 + 4));
+    }
+
+    #[test]
+    fn test_retries_are_bounded() {
+        let mut calls = 0;
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: Duration::ZERO,
+        };
+        let result: io::Result<()> = retry_transient(policy, &MockClock::new(), || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::Interrupted))
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Interrupted);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_other_errors_are_not_retried() {
+        let mut calls = 0;
+        let result: io::Result<()> =
+            retry_transient(RetryPolicy::default(), &MockClock::new(), || {
+                calls += 1;
+                Err(io::Error::from(io::ErrorKind::InvalidInput))
+            });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(1));
+        assert_eq!(policy.backoff(4), Duration::from_millis(16));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::clock::Clock;
+use crate::device::PulseTransmitter;
+use crate::Result;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keeps the IR medium quiet for a settle time after opening the device and after receiver state
+/// changes, delaying the next transmission until the settle time has passed.
+///
+/// Some receivers ignore the very first message after power-up, or a message that arrives right
+/// after they switched their address (`ExtendedCommand::ToggleAddress`). The quiet period starts
+/// when the transmitter is created and is restarted by every call to `settle`, which the remote
+/// controllers issue after such state changes.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{BrickBeam, PulseTransmitterEmulator, SettleTransmitter};
+/// use std::time::Duration;
+///
+/// let transmitter = SettleTransmitter::new(PulseTransmitterEmulator, Duration::from_millis(200));
+/// let brick_beam = BrickBeam::from_transmitter(transmitter);
+/// ```
+pub struct SettleTransmitter<T: PulseTransmitter> {
+    inner: T,
+    settle_time: Duration,
+    quiet_until: Mutex<Instant>,
+}
+
+impl<T: PulseTransmitter> SettleTransmitter<T> {
+    /// Wraps `inner`, starting the first quiet period right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The transmitter that sends the pulses, usually the hardware.
+    /// * `settle_time` - How long the medium is kept quiet after opening and after state changes.
+    pub fn new(inner: T, settle_time: Duration) -> Self {
+        let quiet_until = Mutex::new(inner.clock().now() + settle_time);
+        Self {
+            inner,
+            settle_time,
+            quiet_until,
+        }
+    }
+
+    /// Returns a reference to the wrapped transmitter.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the configured settle time.
+    pub fn settle_time(&self) -> Duration {
+        self.settle_time
+    }
+}
+
+impl<T: PulseTransmitter> PulseTransmitter for SettleTransmitter<T> {
+    fn send_pulses(&self, pulses: &[u32]) -> Result<()> {
+        let quiet_until = *self.quiet_until.lock().unwrap_or_else(|e| e.into_inner());
+        let clock = self.inner.clock();
+        clock.sleep(quiet_until.saturating_duration_since(clock.now()));
+        self.inner.send_pulses(pulses)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn settle(&self) {
+        *self.quiet_until.lock().unwrap_or_else(|e| e.into_inner()) =
+            self.inner.clock().now() + self.settle_time;
+        self.inner.settle();
+    }
+
+    fn set_carrier(&self, carrier: u32, duty_cycle: u32) -> Result<()> {
+        self.inner.set_carrier(carrier, duty_cycle)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.inner.clock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+
+    #[test]
+    fn test_settle_delays_first_and_post_settle_messages() {
+        let settle_time = Duration::from_millis(30);
+        let recorder = MockTransmitterRecorder::default();
+        let opened = recorder.clock.now();
+        let transmitter = SettleTransmitter::new(recorder, settle_time);
+        transmitter.send_pulses(&[157, 1026]).unwrap();
+        transmitter.send_pulses(&[157, 1026]).unwrap();
+        transmitter.inner().clock.advance(Duration::from_millis(10));
+        let settled = transmitter.clock().now();
+        transmitter.settle();
+        transmitter.send_pulses(&[157, 1026]).unwrap();
+
+        let sent = transmitter.inner().sent_at();
+        assert_eq!(sent[0] - opened, settle_time);
+        assert_eq!(sent[1], sent[0]);
+        assert_eq!(sent[2] - settled, settle_time);
+    }
+}
@@ -0,0 +1,209 @@
+//! # rc-core sysfs metadata
+//!
+//! Every remote controller registered with the kernel's rc-core subsystem shows up under
+//! `/sys/class/rc/rcN`. Its `uevent` file names the driver (e.g. `gpio-ir-tx`), a `lircN`
+//! subdirectory links it to its `/dev/lircN` character device, and receivers additionally expose
+//! a `protocols` attribute and an `inputN` device. Transmit-only drivers have neither.
+//!
+//! Blindly opening the first `/dev/lirc*` picks the wrong device on systems with several IR
+//! adapters (for example a TV-card receiver next to a `gpio-ir-tx` overlay). The helpers here read
+//! that metadata so the transmitter can be chosen deliberately.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The default location of rc-core devices in sysfs.
+pub const SYSFS_RC_ROOT: &str = "/sys/class/rc";
+
+/// Drivers known to be dedicated IR transmitters. These are preferred by [`select_transmitter`].
+const TRANSMITTER_DRIVERS: [&str; 3] = ["gpio-ir-tx", "pwm-ir-tx", "ir-spi"];
+
+/// Metadata about a single rc-core device as exposed in sysfs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RcDevice {
+    /// The sysfs name of the device, e.g. `rc0`.
+    pub name: String,
+    /// The human readable device name reported by the driver.
+    pub device_name: String,
+    /// The kernel driver name, e.g. `gpio-ir-tx`.
+    pub driver: String,
+    /// The associated LIRC character device, e.g. `/dev/lirc0`, if any.
+    pub lirc_device: Option<PathBuf>,
+    /// Whether the device registered an input device (only receivers do).
+    pub has_input: bool,
+    /// Decoder protocols supported by the device (empty for transmit-only devices).
+    pub protocols: Vec<String>,
+}
+
+impl RcDevice {
+    /// Returns `true` if this device is driven by a known dedicated transmitter driver.
+    pub fn is_known_transmitter(&self) -> bool {
+        TRANSMITTER_DRIVERS.contains(&self.driver.as_str())
+    }
+
+    /// Returns `true` if the device looks transmit-only, i.e. it exposes neither decoder
+    /// protocols nor an input device.
+    pub fn is_transmit_only(&self) -> bool {
+        self.protocols.is_empty() && !self.has_input
+    }
+
+    /// Ranks how suitable the device is for transmitting; higher is better.
+    fn transmitter_score(&self) -> u8 {
+        match (self.is_known_transmitter(), self.is_transmit_only()) {
+            (true, _) => 2,
+            (false, true) => 1,
+            (false, false) => 0,
+        }
+    }
+}
+
+/// Lists all rc-core devices found under `/sys/class/rc`, sorted by name.
+pub fn enumerate_rc_devices() -> io::Result<Vec<RcDevice>> {
+    enumerate_rc_devices_in(SYSFS_RC_ROOT)
+}
+
+/// Lists all rc-core devices found under the given sysfs root, sorted by name.
+///
+/// This is mostly useful for testing against a fake sysfs tree.
+pub fn enumerate_rc_devices_in(root: impl AsRef<Path>) -> io::Result<Vec<RcDevice>> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            devices.push(read_rc_device(&path)?);
+        }
+    }
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(devices)
+}
+
+/// Picks the LIRC device best suited for transmitting, if any.
+///
+/// Known transmitter drivers (`gpio-ir-tx`, `pwm-ir-tx`, `ir-spi`) win over other transmit-only
+/// devices, which in turn win over receivers. Ties go to the device listed first. Devices without
+/// a LIRC character device are ignored.
+pub fn select_transmitter(devices: &[RcDevice]) -> Option<PathBuf> {
+    devices
+        .iter()
+        .filter(|device| device.lirc_device.is_some())
+        .rev()
+        .max_by_key(|device| device.transmitter_score())
+        .and_then(|device| device.lirc_device.clone())
+}
+
+fn read_rc_device(path: &Path) -> io::Result<RcDevice> {
+    let mut device = RcDevice {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        ..RcDevice::default()
+    };
+
+    for (key, value) in read_uevent(path)? {
+        match key.as_str() {
+            "DRV_NAME" => device.driver = value,
+            "DEV_NAME" => device.device_name = value,
+            _ => (),
+        }
+    }
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with("lirc") {
+            let dev_name = read_uevent(&entry.path())?
+                .into_iter()
+                .find(|(key, _)| key == "DEVNAME")
+                .map(|(_, value)| value)
+                .unwrap_or(file_name);
+            device.lirc_device = Some(Path::new("/dev").join(dev_name));
+        } else if file_name.starts_with("input") {
+            device.has_input = true;
+        } else if file_name == "protocols" {
+            device.protocols = fs::read_to_string(entry.path())?
+                .split_whitespace()
+                .map(|protocol| protocol.trim_matches(|c| c == '[' || c == ']').to_owned())
+                .filter(|protocol| protocol != "lirc")
+                .collect();
+        }
+    }
+
+    Ok(device)
+}
+
+fn read_uevent(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let content = match fs::read_to_string(path.join("uevent")) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_rc(root: &Path, name: &str, driver: &str, lirc: &str, receiver: bool) {
+        let dir = root.join(name);
+        fs::create_dir_all(dir.join(lirc)).unwrap();
+        fs::write(
+            dir.join("uevent"),
+            format!("DEV_NAME={}\nDRV_NAME={}\n", driver, driver),
+        )
+        .unwrap();
+        fs::write(dir.join(lirc).join("uevent"), format!("DEVNAME={}\n", lirc)).unwrap();
+        if receiver {
+            fs::create_dir_all(dir.join("input3")).unwrap();
+            fs::write(dir.join("protocols"), "rc-5 [nec] lirc\n").unwrap();
+        }
+    }
+
+    fn fake_root(test: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("brickbeam-sysfs-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_enumerate_reads_driver_lirc_and_protocols() {
+        let root = fake_root("enumerate");
+        fake_rc(&root, "rc0", "cx88xx", "lirc0", true);
+        fake_rc(&root, "rc1", "gpio-ir-tx", "lirc1", false);
+
+        let devices = enumerate_rc_devices_in(&root).unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].driver, "cx88xx");
+        assert_eq!(devices[0].protocols, vec!["rc-5", "nec"]);
+        assert!(devices[0].has_input);
+        assert_eq!(devices[1].lirc_device, Some(PathBuf::from("/dev/lirc1")));
+        assert!(devices[1].is_known_transmitter());
+        assert!(devices[1].is_transmit_only());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_select_transmitter_prefers_tx_driver_over_receiver() {
+        let root = fake_root("select");
+        fake_rc(&root, "rc0", "cx88xx", "lirc0", true);
+        fake_rc(&root, "rc1", "gpio-ir-tx", "lirc1", false);
+
+        let devices = enumerate_rc_devices_in(&root).unwrap();
+        assert_eq!(
+            select_transmitter(&devices),
+            Some(PathBuf::from("/dev/lirc1"))
+        );
+        assert_eq!(select_transmitter(&[]), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
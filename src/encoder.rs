@@ -0,0 +1,241 @@
+//! # Pulse Encoder
+//!
+//! The remote controllers encode and transmit in one step, on a `PulseTransmitter`. Applications
+//! with their own transmission stack (a microcontroller on a serial line, a network service, a
+//! test bench) only need the first half. `PulseEncoder` encodes the commands of every protocol
+//! into pulse lengths in microseconds, without ever opening a device, so the protocol layer can
+//! be used alone, e.g. when cross-compiling on macOS.
+//!
+//! Like `BrickBeam::speed` and `BrickBeam::extended`, the encoder keeps one toggle state per
+//! receiver, so consecutive messages to the same receiver are told apart as on a real remote.
+
+#[cfg(any(feature = "single-output", feature = "extended"))]
+use crate::controller::registry::ControllerRegistry;
+#[cfg(feature = "combo-direct")]
+use crate::protocols::{ComboDirectCommand, ComboDirectProtocol};
+#[cfg(feature = "combo-pwm")]
+use crate::protocols::{ComboPwmCommand, ComboPwmProtocol};
+#[cfg(feature = "extended")]
+use crate::protocols::{ExtendedCommand, ExtendedProtocol};
+#[cfg(feature = "single-output")]
+use crate::protocols::{Output, SingleOutputCommand, SingleOutputProtocol};
+#[cfg(any(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+use crate::LogicalChannel;
+use crate::{protocols::timing::PulseTiming, Lrc, Result};
+
+/// Encodes commands of all protocols into pulses, without a transmitter.
+///
+/// # Example
+#[cfg_attr(feature = "single-output", doc = "```rust")]
+#[cfg_attr(not(feature = "single-output"), doc = "```ignore")]
+/// use brickbeam::{Channel, Output, PulseEncoder, Result, SingleOutputCommand};
+///
+/// fn main() -> Result<()> {
+///     let mut encoder = PulseEncoder::new()?;
+///     let pulses: Vec<u32> =
+///         encoder.encode_single_output(Channel::One, Output::RED, SingleOutputCommand::PWM(5))?;
+///     assert_eq!(pulses.len(), 36);
+///     Ok(())
+/// }
+/// ```
+pub struct PulseEncoder {
+    #[cfg(feature = "single-output")]
+    single_output: SingleOutputProtocol,
+    #[cfg(feature = "combo-pwm")]
+    combo_pwm: ComboPwmProtocol,
+    #[cfg(feature = "combo-direct")]
+    combo_direct: ComboDirectProtocol,
+    #[cfg(feature = "extended")]
+    extended: ExtendedProtocol,
+    #[cfg(any(feature = "single-output", feature = "extended"))]
+    registry: ControllerRegistry,
+}
+
+impl PulseEncoder {
+    pub fn new() -> Result<Self> {
+        Self::with_timing(PulseTiming::STANDARD)
+    }
+
+    /// Creates an encoder with adjusted symbol lengths.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the IRP for `timing` can't be built.
+    #[cfg_attr(
+        not(any(
+            feature = "single-output",
+            feature = "combo-direct",
+            feature = "combo-pwm",
+            feature = "extended"
+        )),
+        allow(unused_variables)
+    )]
+    pub fn with_timing(timing: PulseTiming) -> Result<Self> {
+        Ok(Self {
+            #[cfg(feature = "single-output")]
+            single_output: SingleOutputProtocol::with_timing(timing)?,
+            #[cfg(feature = "combo-pwm")]
+            combo_pwm: ComboPwmProtocol::with_timing(timing)?,
+            #[cfg(feature = "combo-direct")]
+            combo_direct: ComboDirectProtocol::with_timing(timing)?,
+            #[cfg(feature = "extended")]
+            extended: ExtendedProtocol::with_timing(timing)?,
+            #[cfg(any(feature = "single-output", feature = "extended"))]
+            registry: ControllerRegistry::default(),
+        })
+    }
+
+    /// Encodes subsequent messages with the given LRC, e.g. a wrong one to check that receivers
+    /// ignore corrupted messages.
+    #[cfg_attr(
+        not(any(
+            feature = "single-output",
+            feature = "combo-direct",
+            feature = "combo-pwm",
+            feature = "extended"
+        )),
+        allow(unused_mut, unused_variables)
+    )]
+    pub fn with_lrc(mut self, lrc: Lrc) -> Self {
+        #[cfg(feature = "single-output")]
+        self.single_output.set_lrc(lrc);
+        #[cfg(feature = "combo-pwm")]
+        self.combo_pwm.set_lrc(lrc);
+        #[cfg(feature = "combo-direct")]
+        self.combo_direct.set_lrc(lrc);
+        #[cfg(feature = "extended")]
+        self.extended.set_lrc(lrc);
+        self
+    }
+
+    /// Encodes a Single Output command for an output of a receiver.
+    #[cfg(feature = "single-output")]
+    pub fn encode_single_output(
+        &mut self,
+        channel: impl Into<LogicalChannel>,
+        output: Output,
+        cmd: SingleOutputCommand,
+    ) -> Result<Vec<u32>> {
+        let channel = channel.into();
+        self.single_output.set_address(channel.address());
+        self.single_output
+            .share_toggle(self.registry.toggle(channel, Some(output)));
+        self.single_output
+            .encode_cmd(channel.channel(), output, cmd)
+    }
+
+    /// Encodes a Combo PWM command for both outputs of a receiver.
+    #[cfg(feature = "combo-pwm")]
+    pub fn encode_combo_pwm(
+        &mut self,
+        channel: impl Into<LogicalChannel>,
+        cmd: ComboPwmCommand,
+    ) -> Result<Vec<u32>> {
+        let channel = channel.into();
+        self.combo_pwm.set_address(channel.address());
+        self.combo_pwm.encode_cmd(channel.channel(), cmd)
+    }
+
+    /// Encodes a Combo Direct command for both outputs of a receiver.
+    #[cfg(feature = "combo-direct")]
+    pub fn encode_combo_direct(
+        &mut self,
+        channel: impl Into<LogicalChannel>,
+        cmd: ComboDirectCommand,
+    ) -> Result<Vec<u32>> {
+        let channel = channel.into();
+        self.combo_direct.set_address(channel.address());
+        self.combo_direct.encode_cmd(channel.channel(), cmd)
+    }
+
+    /// Encodes an Extended command for a receiver.
+    ///
+    /// After a `ToggleAddress`, subsequent Extended messages for `channel` follow the receiver
+    /// to its new address, as with `BrickBeam::extended`.
+    #[cfg(feature = "extended")]
+    pub fn encode_extended(
+        &mut self,
+        channel: impl Into<LogicalChannel>,
+        cmd: ExtendedCommand,
+    ) -> Result<Vec<u32>> {
+        let channel = channel.into();
+        self.extended
+            .share_toggle(self.registry.toggle(channel, None));
+        self.extended.encode_cmd(channel.channel(), cmd)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+mod tests {
+    use super::*;
+    use crate::protocols::{Channel, DirectState};
+
+    // Spaces longer than this are 1 bits.
+    const ONE_SPACE: u32 = 400;
+
+    #[test]
+    fn test_encoder_toggles_per_receiver() {
+        let mut encoder = PulseEncoder::new().unwrap();
+        let cmd = SingleOutputCommand::PWM(3);
+        let red = encoder
+            .encode_single_output(Channel::One, Output::RED, cmd)
+            .unwrap();
+        let blue = encoder
+            .encode_single_output(Channel::One, Output::BLUE, cmd)
+            .unwrap();
+        let red_again = encoder
+            .encode_single_output(Channel::One, Output::RED, cmd)
+            .unwrap();
+        // The first message to each output starts with the toggle bit cleared.
+        assert!(red[3] < ONE_SPACE && blue[3] < ONE_SPACE);
+        assert!(red_again[3] > ONE_SPACE);
+
+        let mut protocol = SingleOutputProtocol::new().unwrap();
+        assert_eq!(
+            red,
+            protocol.encode_cmd(Channel::One, Output::RED, cmd).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encoder_addresses_logical_channels() {
+        let mut encoder = PulseEncoder::new().unwrap();
+        let pwm = encoder
+            .encode_combo_pwm(LogicalChannel::Five, ComboPwmCommand::new(1, 2).unwrap())
+            .unwrap();
+        assert!(pwm[3] > ONE_SPACE);
+        let direct = encoder
+            .encode_combo_direct(
+                Channel::One,
+                ComboDirectCommand {
+                    red: DirectState::Forward,
+                    blue: DirectState::Float,
+                },
+            )
+            .unwrap();
+        assert!(direct[11] < ONE_SPACE);
+
+        encoder
+            .encode_extended(Channel::Two, ExtendedCommand::ToggleAddress)
+            .unwrap();
+        let extended = encoder
+            .encode_extended(Channel::Two, ExtendedCommand::AlignToggle)
+            .unwrap();
+        assert!(extended[11] > ONE_SPACE);
+        let other = encoder
+            .encode_extended(Channel::Three, ExtendedCommand::AlignToggle)
+            .unwrap();
+        assert!(other[11] < ONE_SPACE);
+    }
+}
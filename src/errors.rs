@@ -3,7 +3,7 @@ use thiserror::Error;
 /// The library’s specialized `Result` type.
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// Possible errors while encoding commands or transmitting pulses.
+/// Possible errors while encoding commands or transmitting and receiving pulses.
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("IO error: {0}")]
@@ -14,6 +14,15 @@ pub enum Error {
 
     #[error("Pulse sending error: {0}")]
     Transmitting(String),
+
+    #[error("Pulse receiving error: {0}")]
+    Receiving(String),
+
+    #[error("Invalid speed {0}: expected a value from -7 to 8")]
+    InvalidSpeed(i8),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
 }
 
 #[cfg(test)]
@@ -22,11 +31,20 @@ mod tests {
     use std::io;
 
     #[test]
+    #[allow(clippy::io_other_error)]
     fn test_error_display_io() {
         let io_err = Error::Io(io::Error::new(io::ErrorKind::Other, "test error"));
         assert!(io_err.to_string().contains("IO error"));
     }
 
+    #[test]
+    fn test_error_from_io() {
+        let convert = || -> Result<()> { Err(io::Error::other("flush failed"))? };
+        let io_err = convert().unwrap_err();
+        assert!(matches!(io_err, Error::Io(_)));
+        assert!(io_err.to_string().contains("flush failed"));
+    }
+
     #[test]
     fn test_error_display_protocol() {
         let proto_err = Error::ProtocolError("encoding failed".to_string());
@@ -38,4 +56,22 @@ mod tests {
         let tx_err = Error::Transmitting("transmission failed".to_string());
         assert!(tx_err.to_string().contains("Pulse sending error"));
     }
+
+    #[test]
+    fn test_error_display_receiving() {
+        let rx_err = Error::Receiving("receiving failed".to_string());
+        assert!(rx_err.to_string().contains("Pulse receiving error"));
+    }
+
+    #[test]
+    fn test_error_display_invalid_speed() {
+        let speed_err = Error::InvalidSpeed(9);
+        assert!(speed_err.to_string().contains("Invalid speed 9"));
+    }
+
+    #[test]
+    fn test_error_display_unauthorized() {
+        let auth_err = Error::Unauthorized("Missing token".to_string());
+        assert!(auth_err.to_string().contains("Unauthorized"));
+    }
 }
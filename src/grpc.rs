@@ -0,0 +1,528 @@
+//! # gRPC Control
+//!
+//! A gRPC server (feature `grpc`) implementing the `brickbeam.v1.BrickBeam` service of
+//! `proto/brickbeam.proto`, for layout software written in other languages. Like the `osc`
+//! module, it turns every request into sequence steps and plays them through a `SequencePlayer`
+//! of the given `BrickBeam`, so the toggle bits and the repeat policy are shared with the
+//! controllers of that instance.
+//!
+//! | RPC       | Action                                                                   |
+//! |-----------|--------------------------------------------------------------------------|
+//! | `Send`    | Transmits one command and returns its airtime                            |
+//! | `StopAll` | Sends Combo PWM float on every channel, one message slot apart           |
+//! | `Status`  | Returns the outputs of every channel, as a `VirtualReceiver` tracks them |
+//! | `Events`  | Streams every command transmitted from now on, whichever client sent it  |
+//!
+//! Requests are transmitted one after another. Invalid channels, speeds or commands are
+//! rejected with `INVALID_ARGUMENT`, transmitter failures reported as `UNAVAILABLE`.
+//!
+//! A server created `with_auth(Auth::token(..))` expects the token in the `authorization`
+//! metadata of every call, as `Bearer <token>`, and rejects calls without it as
+//! `UNAUTHENTICATED`.
+
+// tonic's `Status` is what the handlers return anyway; boxing it in the helpers gains nothing.
+#![allow(clippy::result_large_err)]
+
+use crate::auth::Auth;
+use crate::device::PulseTransmitter;
+use crate::protocols::MAX_MESSAGE_DURATION;
+use crate::sandbox::{OutputState, VirtualReceiver};
+use crate::sequence::{Sequence, SequenceAction, SequenceStep};
+use crate::{
+    BrickBeam, Channel, ComboDirectCommand, ComboPwmCommand, DirectState, Error, ExtendedCommand,
+    Output, Result, SingleOutputCommand,
+};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+
+/// The messages and the client and server stubs generated from `proto/brickbeam.proto`.
+pub mod proto {
+    tonic::include_proto!("brickbeam.v1");
+}
+
+use proto::brick_beam_server::{BrickBeam as BrickBeamRpc, BrickBeamServer};
+use proto::command::Action;
+
+/// How many events a slow `Events` subscriber may fall behind before it misses some.
+const EVENT_BUFFER: usize = 64;
+
+/// The metadata key carrying the token of a client.
+const AUTHORIZATION: &str = "authorization";
+
+/// Serves the `BrickBeam` gRPC service for one `BrickBeam` instance.
+///
+/// # Example
+/// ```rust,no_run
+/// use brickbeam::{auth::Auth, grpc::GrpcServer, BrickBeam};
+/// use std::sync::Arc;
+///
+/// #[tokio::main]
+/// async fn main() -> brickbeam::Result<()> {
+///     let brick_beam = Arc::new(BrickBeam::new("/dev/lirc0")?);
+///     GrpcServer::new(brick_beam)
+///         .with_auth(Auth::token("s3cret"))
+///         .serve("0.0.0.0:50051".parse().unwrap())
+///         .await
+/// }
+/// ```
+pub struct GrpcServer<T: PulseTransmitter> {
+    brick_beam: Arc<BrickBeam<T>>,
+    // Locked while transmitting, so requests go out one after another.
+    receivers: Arc<Mutex<[VirtualReceiver; 4]>>,
+    events: broadcast::Sender<proto::Event>,
+    auth: Auth,
+}
+
+impl<T: PulseTransmitter + Send + Sync + 'static> GrpcServer<T> {
+    /// Creates a server transmitting through `brick_beam`.
+    pub fn new(brick_beam: Arc<BrickBeam<T>>) -> Self {
+        Self {
+            brick_beam,
+            receivers: Arc::default(),
+            events: broadcast::channel(EVENT_BUFFER).0,
+            auth: Auth::Open,
+        }
+    }
+
+    /// Sets the access policy; by default every client is accepted.
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Returns the tonic service, checking the token of every call, e.g. to add it to a
+    /// `tonic::transport::Server` with other services or a listener of its own.
+    pub fn into_service(self) -> InterceptedService<BrickBeamServer<Self>, AuthInterceptor> {
+        let interceptor = AuthInterceptor {
+            auth: self.auth.clone(),
+        };
+        BrickBeamServer::with_interceptor(self, interceptor)
+    }
+
+    /// Serves the service on `address` until the server fails.
+    pub async fn serve(self, address: SocketAddr) -> Result<()> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_service())
+            .serve(address)
+            .await
+            .map_err(|e| Error::Io(io::Error::other(e)))
+    }
+
+    /// Serves the service on a bound `listener` until `shutdown` completes or the server fails.
+    pub async fn serve_with_shutdown(
+        self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()> {
+        let incoming = TcpIncoming::from_listener(listener, true, None)
+            .map_err(|e| Error::Io(io::Error::other(e)))?;
+        tonic::transport::Server::builder()
+            .add_service(self.into_service())
+            .serve_with_incoming_shutdown(incoming, shutdown)
+            .await
+            .map_err(|e| Error::Io(io::Error::other(e)))
+    }
+
+    /// Plays `steps` on a blocking thread, updating the receivers and publishing an event per
+    /// step. Returns the summed airtime.
+    async fn play(&self, steps: Vec<SequenceStep>) -> std::result::Result<Duration, Status> {
+        let brick_beam = Arc::clone(&self.brick_beam);
+        let receivers = Arc::clone(&self.receivers);
+        let events = self.events.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut receivers = receivers.lock().unwrap_or_else(|e| e.into_inner());
+            let mut player = brick_beam.create_sequence_player(Sequence::new())?;
+            let mut airtime = Duration::ZERO;
+            for step in steps {
+                airtime += player.play_step(step)?;
+                receivers[step.channel as usize].apply(step.action);
+                // Nobody may be listening, which is fine.
+                let _ = events.send(proto::Event {
+                    channel: u32::from(step.channel as u8) + 1,
+                    command: Some(command_of(step.action)),
+                    sent_at_us: micros_since_epoch(),
+                });
+            }
+            Ok(airtime)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(status_of)
+    }
+}
+
+/// Checks the `authorization` metadata of every call against the `Auth` of a `GrpcServer`.
+#[derive(Debug, Clone)]
+pub struct AuthInterceptor {
+    auth: Auth,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        let credential = request
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.strip_prefix("Bearer ").unwrap_or(value));
+        self.auth.authorize(credential).map_err(status_of)?;
+        Ok(request)
+    }
+}
+
+#[tonic::async_trait]
+impl<T: PulseTransmitter + Send + Sync + 'static> BrickBeamRpc for GrpcServer<T> {
+    async fn send(
+        &self,
+        request: Request<proto::SendRequest>,
+    ) -> std::result::Result<Response<proto::SendReply>, Status> {
+        let request = request.into_inner();
+        let step = SequenceStep {
+            channel: channel_of(request.channel)?,
+            action: action_of(request.command)?,
+            wait: Duration::ZERO,
+        };
+        let airtime = self.play(vec![step]).await?;
+        Ok(Response::new(reply(airtime)))
+    }
+
+    async fn stop_all(
+        &self,
+        _request: Request<proto::StopAllRequest>,
+    ) -> std::result::Result<Response<proto::SendReply>, Status> {
+        let steps = Channel::iter()
+            .map(|channel| SequenceStep {
+                channel,
+                action: SequenceAction::ComboPwm(ComboPwmCommand::stopped()),
+                // Give each channel its own slot, like `Broadcast` does.
+                wait: if channel == Channel::Four {
+                    Duration::ZERO
+                } else {
+                    MAX_MESSAGE_DURATION
+                },
+            })
+            .collect();
+        let airtime = self.play(steps).await?;
+        Ok(Response::new(reply(airtime)))
+    }
+
+    async fn status(
+        &self,
+        _request: Request<proto::StatusRequest>,
+    ) -> std::result::Result<Response<proto::StatusReply>, Status> {
+        let receivers = *self.receivers.lock().unwrap_or_else(|e| e.into_inner());
+        let channels = Channel::iter()
+            .map(|channel| {
+                let receiver = receivers[channel as usize];
+                proto::ChannelStatus {
+                    channel: u32::from(channel as u8) + 1,
+                    red: speed_of(receiver.output(Output::RED)),
+                    blue: speed_of(receiver.output(Output::BLUE)),
+                }
+            })
+            .collect();
+        Ok(Response::new(proto::StatusReply { channels }))
+    }
+
+    type EventsStream =
+        Pin<Box<dyn Stream<Item = std::result::Result<proto::Event, Status>> + Send>>;
+
+    async fn events(
+        &self,
+        _request: Request<proto::EventsRequest>,
+    ) -> std::result::Result<Response<Self::EventsStream>, Status> {
+        // A subscriber that fell behind skips the events it missed rather than failing.
+        let stream = BroadcastStream::new(self.events.subscribe())
+            .filter_map(|event| event.ok())
+            .map(Ok);
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+fn reply(airtime: Duration) -> proto::SendReply {
+    proto::SendReply {
+        airtime_us: u64::try_from(airtime.as_micros()).unwrap_or(u64::MAX),
+    }
+}
+
+fn micros_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| {
+            u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX)
+        })
+}
+
+fn status_of(error: Error) -> Status {
+    match error {
+        Error::ProtocolError(_) | Error::InvalidSpeed(_) => {
+            Status::invalid_argument(error.to_string())
+        }
+        Error::Unauthorized(_) => Status::unauthenticated(error.to_string()),
+        Error::Io(_) | Error::Transmitting(_) | Error::Receiving(_) => {
+            Status::unavailable(error.to_string())
+        }
+    }
+}
+
+fn invalid(what: &str, value: impl std::fmt::Display) -> Status {
+    Status::invalid_argument(format!("Invalid {} {}", what, value))
+}
+
+fn channel_of(number: u32) -> std::result::Result<Channel, Status> {
+    usize::try_from(number)
+        .ok()
+        .and_then(|number| Channel::ALL.get(number.wrapping_sub(1)).copied())
+        .ok_or_else(|| invalid("channel", number))
+}
+
+fn speed(value: i32) -> std::result::Result<i8, Status> {
+    i8::try_from(value).map_err(|_| status_of(Error::InvalidSpeed(i8::MAX)))
+}
+
+fn direct_state(value: i32) -> std::result::Result<DirectState, Status> {
+    u8::try_from(value)
+        .ok()
+        .and_then(|value| DirectState::try_from(value).ok())
+        .ok_or_else(|| invalid("direct state", value))
+}
+
+/// Converts a command of the contract into the action it stands for.
+fn action_of(command: Option<proto::Command>) -> std::result::Result<SequenceAction, Status> {
+    let action = command
+        .and_then(|command| command.action)
+        .ok_or_else(|| Status::invalid_argument("Missing command"))?;
+    Ok(match action {
+        Action::SingleOutput(pwm) => {
+            let output = match proto::Output::try_from(pwm.output) {
+                Ok(proto::Output::Red) => Output::RED,
+                Ok(proto::Output::Blue) => Output::BLUE,
+                Err(_) => return Err(invalid("output", pwm.output)),
+            };
+            let speed = crate::protocols::check_speed(speed(pwm.speed)?).map_err(status_of)?;
+            SequenceAction::SingleOutput(output, SingleOutputCommand::PWM(speed))
+        }
+        Action::ComboPwm(pwm) => SequenceAction::ComboPwm(
+            ComboPwmCommand::new(speed(pwm.red)?, speed(pwm.blue)?).map_err(status_of)?,
+        ),
+        Action::ComboDirect(direct) => SequenceAction::ComboDirect(ComboDirectCommand::from((
+            direct_state(direct.red)?,
+            direct_state(direct.blue)?,
+        ))),
+        Action::Extended(command) => SequenceAction::Extended(
+            u8::try_from(command)
+                .ok()
+                .and_then(|command| ExtendedCommand::try_from(command).ok())
+                .ok_or_else(|| invalid("extended command", command))?,
+        ),
+    })
+}
+
+/// Converts an action into the command of the contract, for the events.
+fn command_of(action: SequenceAction) -> proto::Command {
+    let action = match action {
+        SequenceAction::SingleOutput(output, cmd) => Action::SingleOutput(proto::SingleOutputPwm {
+            output: match output {
+                Output::RED => proto::Output::Red,
+                Output::BLUE => proto::Output::Blue,
+            } as i32,
+            speed: match cmd {
+                SingleOutputCommand::PWM(speed) => i32::from(speed),
+                // Not produced by requests, which only carry PWM speeds.
+                _ => 0,
+            },
+        }),
+        SequenceAction::ComboPwm(cmd) => Action::ComboPwm(proto::ComboPwm {
+            red: i32::from(cmd.speed_red),
+            blue: i32::from(cmd.speed_blue),
+        }),
+        SequenceAction::ComboDirect(cmd) => Action::ComboDirect(proto::ComboDirect {
+            red: cmd.red as i32,
+            blue: cmd.blue as i32,
+        }),
+        SequenceAction::Extended(cmd) => Action::Extended(cmd as i32),
+    };
+    proto::Command {
+        action: Some(action),
+    }
+}
+
+/// Returns the speed the contract reports for an output: the PWM step, 0 when floating and 8
+/// when braking.
+fn speed_of(state: OutputState) -> i32 {
+    match state {
+        OutputState::Float => 0,
+        OutputState::Brake => 8,
+        OutputState::Pwm(step) => i32::from(step),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use crate::RepeatPolicy;
+    use proto::brick_beam_client::BrickBeamClient;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tonic::transport::server::TcpIncoming;
+
+    #[test]
+    fn test_loopback_send_stop_all_status_and_events() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let brick_beam = Arc::new(
+                BrickBeam::from_transmitter(MockTransmitterRecorder::default())
+                    .with_repeat_policy(RepeatPolicy::single()),
+            );
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+            let service = GrpcServer::new(Arc::clone(&brick_beam)).into_service();
+            tokio::spawn(
+                tonic::transport::Server::builder()
+                    .add_service(service)
+                    .serve_with_incoming(incoming),
+            );
+
+            let mut client = BrickBeamClient::connect(format!("http://{}", address))
+                .await
+                .unwrap();
+            let mut events = client
+                .events(proto::EventsRequest {})
+                .await
+                .unwrap()
+                .into_inner();
+
+            let combo = proto::Command {
+                action: Some(Action::ComboPwm(proto::ComboPwm { red: 5, blue: -3 })),
+            };
+            let reply = client
+                .send(proto::SendRequest {
+                    channel: 2,
+                    command: Some(combo),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(reply.airtime_us > 0);
+            let event = events.next().await.unwrap().unwrap();
+            assert_eq!((event.channel, event.command), (2, Some(combo)));
+
+            let status = client
+                .status(proto::StatusRequest {})
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(
+                status.channels[1],
+                proto::ChannelStatus {
+                    channel: 2,
+                    red: 5,
+                    blue: -3
+                }
+            );
+
+            let rejected = client
+                .send(proto::SendRequest {
+                    channel: 5,
+                    command: None,
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(rejected.code(), tonic::Code::InvalidArgument);
+
+            // The channels are a message slot apart on the clock of the transmitter, which only
+            // moves on when told to.
+            let stopped = Arc::new(AtomicBool::new(false));
+            let ticker = {
+                let brick_beam = Arc::clone(&brick_beam);
+                let stopped = Arc::clone(&stopped);
+                std::thread::spawn(move || {
+                    while !stopped.load(Ordering::Relaxed) {
+                        brick_beam
+                            .transmitter()
+                            .clock
+                            .advance(Duration::from_millis(1));
+                        std::thread::yield_now();
+                    }
+                })
+            };
+            client.stop_all(proto::StopAllRequest {}).await.unwrap();
+            stopped.store(true, Ordering::Relaxed);
+            ticker.join().unwrap();
+            let status = client
+                .status(proto::StatusRequest {})
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(status
+                .channels
+                .iter()
+                .all(|channel| channel.red == 0 && channel.blue == 0));
+            assert_eq!(brick_beam.transmitter().sent().len(), 5);
+        });
+    }
+
+    #[test]
+    fn test_server_requires_token() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let brick_beam = Arc::new(
+                BrickBeam::from_transmitter(MockTransmitterRecorder::default())
+                    .with_repeat_policy(RepeatPolicy::single()),
+            );
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = format!("http://{}", listener.local_addr().unwrap());
+            let server = GrpcServer::new(Arc::clone(&brick_beam)).with_auth(Auth::token("s3cret"));
+            tokio::spawn(server.serve_with_shutdown(listener, std::future::pending()));
+            let channel = tonic::transport::Endpoint::from_shared(address)
+                .unwrap()
+                .connect()
+                .await
+                .unwrap();
+            let with_token = |token: &'static str| {
+                move |mut request: Request<()>| {
+                    let value = format!("Bearer {}", token).parse().unwrap();
+                    request.metadata_mut().insert(AUTHORIZATION, value);
+                    Ok(request)
+                }
+            };
+
+            let mut anonymous = BrickBeamClient::new(channel.clone());
+            let rejected = anonymous.status(proto::StatusRequest {}).await.unwrap_err();
+            assert_eq!(rejected.code(), tonic::Code::Unauthenticated);
+            let mut guessing =
+                BrickBeamClient::with_interceptor(channel.clone(), with_token("guess"));
+            let rejected = guessing
+                .stop_all(proto::StopAllRequest {})
+                .await
+                .unwrap_err();
+            assert_eq!(rejected.code(), tonic::Code::Unauthenticated);
+            assert!(brick_beam.transmitter().sent().is_empty());
+
+            let mut client = BrickBeamClient::with_interceptor(channel, with_token("s3cret"));
+            let combo = proto::Command {
+                action: Some(Action::ComboPwm(proto::ComboPwm { red: 5, blue: -3 })),
+            };
+            client
+                .send(proto::SendRequest {
+                    channel: 1,
+                    command: Some(combo),
+                })
+                .await
+                .unwrap();
+            assert_eq!(brick_beam.transmitter().sent().len(), 1);
+        });
+    }
+}
@@ -20,8 +20,10 @@ The crate implements multiple protocols as separate modules, including Extended,
 
 ## Usage Example
 
-```rust
-use brickbeam::{BrickBeam, Channel, Output, SingleOutputCommand, Result};
+"#]
+#![cfg_attr(feature = "single-output", doc = "```rust")]
+#![cfg_attr(not(feature = "single-output"), doc = "```ignore")]
+#![doc = r#"use brickbeam::{BrickBeam, Channel, Output, SingleOutputCommand, Result};
 
 fn main() -> Result<()> {
     // Initialize the library with the IR transmit device path.
@@ -82,7 +84,7 @@ See the project's [README for hardware setup instructions](https://github.com/az
 
         ```ignore
         [dependencies]
-        brickbeam = { version = "0.1.0", default-features = false}
+        brickbeam = { version = "0.1.0", default-features = false, features = ["single-output", "combo-direct", "combo-pwm", "extended"]}
         ```
         > **NOTE:**
         > Use the IR transmission emulator for **development** only (e.g., on macOS).
@@ -91,6 +93,18 @@ See the project's [README for hardware setup instructions](https://github.com/az
 
 For more complete examples, see the [examples](https://github.com/azachar/brickbeam/tree/main/examples) directory.
 
+## `no_std`
+
+Everything but the PF frame encoder needs the default `std` feature. Without it, the crate is
+`no_std` and allocation-free, and exports only `timing::PulseTiming::encode_frame`, the LRC
+helpers, the timing constants and the channel and output types, for firmware that builds its
+frames itself:
+
+```ignore
+[dependencies]
+brickbeam = { version = "0.1.0", default-features = false }
+```
+
 > **Disclaimer:**
 > This project is **not** sponsored, authorized, or endorsed by the LEGO Group.
 > “LEGO”® is a trademark of the LEGO Group. LEGO® and Power Functions™ are trademarks of the LEGO Group.
@@ -102,21 +116,149 @@ For more complete examples, see the [examples](https://github.com/azachar/brickb
 > **Acknowledgements:**
 > Special thanks to my brother for his unwavering support throughout this project.
 "#]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 #[doc = include_str!("../README.md")]
-#[cfg(doctest)]
+#[cfg(all(
+    doctest,
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
 pub struct ReadmeDoctests;
 
+#[cfg(feature = "std")]
+pub mod auth;
+#[cfg(feature = "std")]
+pub mod ble;
+#[cfg(feature = "std")]
+mod clock;
+#[cfg(feature = "std")]
 mod controller;
+#[cfg(feature = "std")]
 mod device;
+#[cfg(feature = "std")]
+mod encoder;
+#[cfg(feature = "std")]
 mod errors;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+#[cfg(feature = "std")]
+mod motor;
+#[cfg(feature = "osc")]
+pub mod osc;
 mod protocols;
-
+#[cfg(feature = "async")]
+mod queue;
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+pub mod sandbox;
+#[cfg(feature = "std")]
+mod scheduler;
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+mod sequence;
+
+#[cfg(feature = "test-util")]
+pub use clock::MockClock;
+#[cfg(feature = "std")]
+pub use clock::{Clock, SystemClock};
+#[cfg(feature = "std")]
 pub use controller::*;
-pub use device::{DefaultPulseTransmitter, PulseTransmitter};
+#[cfg(feature = "cir")]
+pub use device::CirPulseReceiver;
+#[cfg(feature = "async")]
+pub use device::EventStream;
+#[cfg(feature = "hid")]
+pub use device::HidPulseTransmitter;
+#[cfg(feature = "lirc-raw")]
+pub use device::LircRawPulseTransmitter;
+#[cfg(feature = "test-util")]
+pub use device::MockTransmitterRecorder;
+#[cfg(any(feature = "cir", feature = "lirc-raw"))]
+pub use device::{
+    discover_lirc_devices, IguanaIrTransmitter, LircDevice, RetryPolicy, TransmitterInfo,
+    IGUANAIR_DRIVER, LIRC_MAX_PULSES,
+};
+#[cfg(feature = "std")]
+pub use device::{
+    enumerate_rc_devices, enumerate_rc_devices_in, select_transmitter, CalibratedTransmitter,
+    DefaultPulseTransmitter, DeviceEvent, DynPulseTransmitter, EventTransmitter,
+    FailoverTransmitter, HotplugTransmitter, MirrorTransmitter, Priority, PulseCalibration,
+    PulseReceiver, PulseTransmitter, PulseTransmitterEmulator, QueuePolicy, QueueSender,
+    QueuedTransmitter, RateLimitTransmitter, RcDevice, ReconnectPolicy, SettleTransmitter,
+    TransmissionEvent, DEFAULT_BURST_GAP, SYSFS_RC_ROOT,
+};
+#[cfg(feature = "audio")]
+pub use device::{
+    render_audio, write_wav, write_wav_to, AudioPulseTransmitter, DEFAULT_SAMPLE_RATE,
+};
+#[cfg(feature = "embedded-hal")]
+pub use device::{EmbeddedHalTransmitter, IrEmitter, PinEmitter, PwmEmitter};
+#[cfg(all(unix, feature = "irtoy"))]
+pub use device::{IrToyTransmitter, DEFAULT_IRTOY_PORT};
+#[cfg(all(unix, feature = "lircd"))]
+pub use device::{LircdTransmitter, DEFAULT_LIRCD_REMOTE, DEFAULT_LIRCD_SOCKET};
+#[cfg(feature = "network")]
+pub use device::{NetworkPulseTransmitter, PulseAgent, DEFAULT_AGENT_PORT};
+#[cfg(feature = "pigpiod")]
+pub use device::{PigpiodTransmitter, DEFAULT_PIGPIOD_ADDR};
+#[cfg(feature = "std")]
+pub use encoder::PulseEncoder;
+#[cfg(feature = "std")]
 pub use errors::{Error, Result};
+#[cfg(feature = "std")]
+pub use motor::{BrakeBehavior, MotorControl, MotorProfile, StepCurve, StepRounding, TrainControl};
 
+#[cfg(feature = "combo-pwm")]
+pub use protocols::ComboPwmCommand;
+#[cfg(feature = "extended")]
+pub use protocols::ExtendedCommand;
 pub use protocols::{
-    Channel, ComboDirectCommand, ComboPwmCommand, DirectState, ExtendedCommand, Output,
-    SingleOutputCommand, SingleOutputDiscrete,
+    compute_lrc, duration_of, timing, verify_lrc, Address, Channel, LogicalChannel, Lrc, Output,
+    OutputSelector,
+};
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+pub use protocols::{decode, scancode};
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+pub use protocols::{map_speed, try_map_speed, unmap_speed, Speed, SpeedStep};
+#[cfg(feature = "combo-direct")]
+pub use protocols::{ComboDirectCommand, DirectState, ToggleMode};
+#[cfg(feature = "generic")]
+pub use protocols::{CustomIrpProtocol, GenericCode};
+#[cfg(feature = "std")]
+pub use protocols::{FrameBreakdown, ProtocolKind, PulseTrain, RawMessageFields, RawPfMessage};
+#[cfg(feature = "rcx")]
+pub use protocols::{RcxCommand, RcxDirection, RcxMotorState, RcxMotors, RCX_BAUD, RCX_CARRIER};
+#[cfg(feature = "single-output")]
+pub use protocols::{SingleOutputCommand, SingleOutputDiscrete};
+#[cfg(feature = "async")]
+pub use queue::{OverflowPolicy, Submission, Transmission, TransmitQueue};
+#[cfg(feature = "std")]
+pub use scheduler::{FairnessPolicy, Scheduler};
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+pub use sequence::{
+    PauseBehavior, Sequence, SequenceAction, SequenceControl, SequencePlayer, SequenceStep,
+    StepParser, ValidationIssue,
 };
@@ -0,0 +1,167 @@
+//! # mDNS / DNS-SD Advertisement
+//!
+//! Advertises brickbeam's network servers (feature `mdns`) so throttle apps and control surfaces
+//! find them on the LAN without typing IP addresses.
+//!
+//! Linux systems already run an mDNS responder (Avahi), which owns UDP port 5353. Instead of
+//! competing with it, brickbeam hands the service to Avahi by writing a static service file into
+//! `/etc/avahi/services`, which Avahi picks up immediately. The file is removed again when the
+//! returned `Registration` is dropped.
+
+use crate::Result;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The directory Avahi watches for static service definitions.
+pub const AVAHI_SERVICES_DIR: &str = "/etc/avahi/services";
+
+/// The DNS-SD service type of the OSC server.
+pub const OSC_SERVICE_TYPE: &str = "_osc._udp";
+
+/// The DNS-SD service type of the gRPC server, which has no registered type of its own.
+pub const GRPC_SERVICE_TYPE: &str = "_brickbeam._tcp";
+
+/// A DNS-SD service to advertise.
+///
+/// # Example
+/// ```rust,no_run
+/// use brickbeam::mdns::{ServiceAdvertisement, OSC_SERVICE_TYPE};
+///
+/// fn main() -> brickbeam::Result<()> {
+///     let _registration = ServiceAdvertisement::new("Layout", OSC_SERVICE_TYPE, 9000)
+///         .with_txt("channels", "1-4")
+///         .publish()?;
+///     // ... serve until done; the advertisement ends when `_registration` is dropped.
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceAdvertisement {
+    name: String,
+    service_type: String,
+    port: u16,
+    txt: Vec<(String, String)>,
+}
+
+impl ServiceAdvertisement {
+    /// Creates an advertisement for a service instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The instance name shown to users, e.g. `Layout`.
+    /// * `service_type` - The DNS-SD service type, e.g. `_osc._udp`.
+    /// * `port` - The port the server listens on.
+    pub fn new(name: impl Into<String>, service_type: impl Into<String>, port: u16) -> Self {
+        Self {
+            name: name.into(),
+            service_type: service_type.into(),
+            port,
+            txt: Vec::new(),
+        }
+    }
+
+    /// Adds a `key=value` TXT record.
+    pub fn with_txt(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.txt.push((key.into(), value.into()));
+        self
+    }
+
+    /// Renders the Avahi static service definition.
+    pub fn to_avahi_xml(&self) -> String {
+        let mut xml = String::new();
+        let _ = writeln!(xml, "<?xml version=\"1.0\" standalone=\"no\"?>");
+        let _ = writeln!(xml, "<!DOCTYPE service-group SYSTEM \"avahi-service.dtd\">");
+        let _ = writeln!(xml, "<service-group>");
+        let _ = writeln!(xml, "  <name>{}</name>", escape(&self.name));
+        let _ = writeln!(xml, "  <service>");
+        let _ = writeln!(xml, "    <type>{}</type>", escape(&self.service_type));
+        let _ = writeln!(xml, "    <port>{}</port>", self.port);
+        for (key, value) in &self.txt {
+            let _ = writeln!(
+                xml,
+                "    <txt-record>{}={}</txt-record>",
+                escape(key),
+                escape(value)
+            );
+        }
+        let _ = writeln!(xml, "  </service>");
+        let _ = writeln!(xml, "</service-group>");
+        xml
+    }
+
+    /// Publishes the service through the system's Avahi daemon.
+    ///
+    /// Writing to `/etc/avahi/services` usually requires root.
+    pub fn publish(&self) -> Result<Registration> {
+        self.publish_in(AVAHI_SERVICES_DIR)
+    }
+
+    /// Publishes the service by writing its definition into the given directory.
+    pub fn publish_in(&self, dir: impl AsRef<Path>) -> Result<Registration> {
+        let file_name: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let path = dir
+            .as_ref()
+            .join(format!("brickbeam-{}-{}.service", file_name, self.port));
+        fs::write(&path, self.to_avahi_xml())?;
+        Ok(Registration { path })
+    }
+}
+
+/// A published advertisement, withdrawn when dropped.
+#[derive(Debug)]
+pub struct Registration {
+    path: PathBuf,
+}
+
+impl Registration {
+    /// Returns the path of the service definition file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_avahi_xml() {
+        let xml = ServiceAdvertisement::new("Trains & Co", OSC_SERVICE_TYPE, 9000)
+            .with_txt("channels", "1-4")
+            .to_avahi_xml();
+        assert!(xml.contains("<name>Trains &amp; Co</name>"));
+        assert!(xml.contains("<type>_osc._udp</type>"));
+        assert!(xml.contains("<port>9000</port>"));
+        assert!(xml.contains("<txt-record>channels=1-4</txt-record>"));
+    }
+
+    #[test]
+    fn test_registration_is_withdrawn_on_drop() {
+        let dir = std::env::temp_dir();
+        let registration = ServiceAdvertisement::new("Test Layout", OSC_SERVICE_TYPE, 9123)
+            .publish_in(&dir)
+            .unwrap();
+        let path = registration.path().to_path_buf();
+        assert!(path.ends_with("brickbeam-Test-Layout-9123.service"));
+        assert!(path.exists());
+        drop(registration);
+        assert!(!path.exists());
+    }
+}
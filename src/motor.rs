@@ -0,0 +1,433 @@
+//! # Motor Control
+//!
+//! `MotorControl` is the transport-agnostic, high-level view of a single motor. It is implemented
+//! by the IR Power Functions controllers of this crate and by the optional Bluetooth LE backends
+//! (e.g. Powered Up hubs behind the `powered-up` feature), so mixed fleets of old PF trains and new
+//! City trains can be driven through one API.
+//!
+//! Power is expressed in percent, from -100 (full reverse) to 100 (full forward), which is the
+//! common denominator of the PF 7-step PWM and the Powered Up ±100 power range.
+//!
+//! `TrainControl` is the train-level vocabulary on top of it, implemented for every `MotorControl`.
+//!
+//! `MotorProfile` adapts the mapping from percent to PF steps to the motor on the output, since
+//! e.g. step 1 moves an XL motor but not a train.
+//!
+//! ## Adapting other crates
+//!
+//! brickbeam stays the orchestration layer; other transports can be plugged in by implementing
+//! `MotorControl`. The motors of the `lego-powered-up` crate come with the
+//! `ble::lego::LegoPoweredUpMotor` adapter behind the feature of the same name. For any other
+//! crate, both the trait and its motor type are foreign to your application, so wrap the motor in
+//! a newtype:
+//!
+//! ```rust
+//! use brickbeam::{MotorControl, Result, TrainControl};
+//!
+//! # struct ForeignMotor;
+//! # impl ForeignMotor { fn start_power(&mut self, _power: i8) {} }
+//! struct Adapter(ForeignMotor);
+//!
+//! impl MotorControl for Adapter {
+//!     fn set_power(&mut self, percent: i8) -> Result<()> {
+//!         self.0.start_power(percent);
+//!         Ok(())
+//!     }
+//!
+//!     fn brake(&mut self) -> Result<()> {
+//!         self.0.start_power(127);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! fn main() -> Result<()> {
+//!     let mut train = Adapter(ForeignMotor);
+//!     train.set_speed(40)?;
+//!     train.emergency_stop()?;
+//!     Ok(())
+//! }
+//! ```
+
+#[cfg(feature = "single-output")]
+use crate::{device::PulseTransmitter, SingleOutputCommand, SpeedRemoteController};
+use crate::{Error, Result};
+use std::str::FromStr;
+
+/// A single motor that can be driven forward or backward, floated, and braked.
+pub trait MotorControl {
+    /// Sets the motor power in percent, from -100 (full reverse) to 100 (full forward).
+    ///
+    /// A value of 0 lets the motor float. Values outside the range are clamped.
+    fn set_power(&mut self, percent: i8) -> Result<()>;
+
+    /// Lets the motor float (coast to a stop).
+    fn stop(&mut self) -> Result<()> {
+        self.set_power(0)
+    }
+
+    /// Actively brakes the motor.
+    fn brake(&mut self) -> Result<()>;
+}
+
+/// A train, driven by a single motor (or a group of motors wired to one output).
+///
+/// Every `MotorControl` is a `TrainControl`, so fleet code can accept `&mut dyn TrainControl`
+/// regardless of whether the train is an IR Power Functions or a Bluetooth LE model.
+pub trait TrainControl {
+    /// Sets the train speed in percent, from -100 (full reverse) to 100 (full forward).
+    fn set_speed(&mut self, percent: i8) -> Result<()>;
+
+    /// Lets the train coast to a stop.
+    ///
+    /// Named differently from `MotorControl::stop` so both traits can be in scope together.
+    fn coast(&mut self) -> Result<()>;
+
+    /// Stops the train as quickly as possible by braking its motor.
+    fn emergency_stop(&mut self) -> Result<()>;
+}
+
+impl<M: MotorControl + ?Sized> TrainControl for M {
+    fn set_speed(&mut self, percent: i8) -> Result<()> {
+        self.set_power(percent)
+    }
+
+    fn coast(&mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn emergency_stop(&mut self) -> Result<()> {
+        self.brake()
+    }
+}
+
+/// How the PF steps between the minimum step and 7 are spread over the percent range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepCurve {
+    /// Equal percent ranges per step.
+    Linear,
+    /// Grows with the square of the percentage, leaving more of the range to the low steps.
+    Quadratic,
+}
+
+/// How a percentage between two PF steps is rounded by `MotorProfile::step_percent`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StepRounding {
+    /// Rounds to the nearest step, halves away from 0.
+    #[default]
+    Nearest,
+    /// Rounds toward 0, so a slider reaches a step only once fully past it.
+    TowardZero,
+    /// Rounds away from 0, so any movement of a slider moves the motor.
+    AwayFromZero,
+}
+
+impl StepRounding {
+    fn round(self, magnitude: f32) -> f32 {
+        match self {
+            StepRounding::Nearest => magnitude.round(),
+            StepRounding::TowardZero => magnitude.floor(),
+            StepRounding::AwayFromZero => magnitude.ceil(),
+        }
+    }
+}
+
+/// What `MotorControl::brake` does with the motor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrakeBehavior {
+    /// Short-circuits the motor (PWM step 8), stopping it quickly.
+    Brake,
+    /// Lets the motor float, for motors where braking is pointless, such as the servo.
+    Float,
+}
+
+/// How power percentages map onto the PF PWM steps of a particular motor.
+///
+/// Any percentage other than 0 maps to at least `min_step`, the lowest step that moves the motor
+/// under a typical load; 100% maps to step 7. The presets are starting points, which different
+/// loads and battery levels may call for adjusting:
+///
+/// | Name     | Motor              | Minimum step | Curve       | Brake   |
+/// |----------|--------------------|--------------|-------------|---------|
+/// | `linear` | any (the default)  | 0            | `Linear`    | `Brake` |
+/// | `train`  | 88002 train motor  | 2            | `Quadratic` | `Brake` |
+/// | `m`      | 8883 M motor       | 2            | `Linear`    | `Brake` |
+/// | `l`      | 88003 L motor      | 1            | `Linear`    | `Brake` |
+/// | `xl`     | 8882 XL motor      | 1            | `Quadratic` | `Brake` |
+/// | `servo`  | 88004 servo motor  | 0            | `Linear`    | `Float` |
+///
+/// With the `linear` profile, percentages round to the nearest step, so below 8% the motor
+/// floats.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{MotorProfile, Result};
+///
+/// fn main() -> Result<()> {
+///     let train: MotorProfile = "train".parse()?;
+///     assert_eq!(train.step(5), 2);
+///     assert_eq!(train.step(-100), -7);
+///     assert_eq!(MotorProfile::linear().step(5), 0);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotorProfile {
+    /// The lowest step a percentage other than 0 maps to, from 0 to 7.
+    pub min_step: i8,
+    /// How the remaining steps are spread over the percent range.
+    pub curve: StepCurve,
+    /// What braking does.
+    pub brake: BrakeBehavior,
+}
+
+impl MotorProfile {
+    /// The names accepted by `FromStr`.
+    pub const NAMES: [&'static str; 6] = ["linear", "train", "m", "l", "xl", "servo"];
+
+    /// Rounds to the nearest step; suits motors without load.
+    pub const fn linear() -> Self {
+        Self {
+            min_step: 0,
+            curve: StepCurve::Linear,
+            brake: BrakeBehavior::Brake,
+        }
+    }
+
+    /// A loaded train doesn't start below step 2, and the slow steps matter most for shunting.
+    pub const fn train() -> Self {
+        Self {
+            min_step: 2,
+            curve: StepCurve::Quadratic,
+            ..Self::linear()
+        }
+    }
+
+    /// The M motor is geared fast with little torque, so it stalls on step 1 under load.
+    pub const fn m() -> Self {
+        Self {
+            min_step: 2,
+            ..Self::linear()
+        }
+    }
+
+    /// The L motor turns from step 1.
+    pub const fn l() -> Self {
+        Self {
+            min_step: 1,
+            ..Self::linear()
+        }
+    }
+
+    /// The XL motor has torque to spare and is slow, so the low steps get more of the range.
+    pub const fn xl() -> Self {
+        Self {
+            min_step: 1,
+            curve: StepCurve::Quadratic,
+            ..Self::linear()
+        }
+    }
+
+    /// Steps are servo positions, so percentages map proportionally; floating centers the servo.
+    pub const fn servo() -> Self {
+        Self {
+            brake: BrakeBehavior::Float,
+            ..Self::linear()
+        }
+    }
+
+    /// Returns the profile with a different minimum step, clamped to 0 to 7.
+    pub const fn with_min_step(mut self, min_step: i8) -> Self {
+        self.min_step = if min_step < 0 {
+            0
+        } else if min_step > 7 {
+            7
+        } else {
+            min_step
+        };
+        self
+    }
+
+    /// Maps a power percentage (-100 to 100, clamped) onto a PWM step (-7 to 7).
+    pub fn step(&self, percent: i8) -> i8 {
+        let percent = i32::from(percent.clamp(-100, 100));
+        if percent == 0 {
+            return 0;
+        }
+        let min_step = i32::from(self.min_step.clamp(0, 7));
+        let (fraction, scale) = match self.curve {
+            StepCurve::Linear => (percent.abs(), 100),
+            StepCurve::Quadratic => (percent * percent, 10_000),
+        };
+        let magnitude = min_step + (fraction * (7 - min_step) + scale / 2) / scale;
+        (percent.signum() * magnitude) as i8
+    }
+
+    /// Maps a fractional power percentage (-100.0 to 100.0, clamped) onto a PWM step (-7 to 7),
+    /// rounding as `rounding` says, e.g. for the position of a slider.
+    ///
+    /// NaN maps to 0, like 0%.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::{MotorProfile, StepRounding};
+    ///
+    /// let linear = MotorProfile::linear();
+    /// assert_eq!(linear.step_percent(50.0, StepRounding::Nearest), 4);
+    /// assert_eq!(linear.step_percent(50.0, StepRounding::TowardZero), 3);
+    /// assert_eq!(linear.step_percent(-1.0, StepRounding::AwayFromZero), -1);
+    /// ```
+    pub fn step_percent(&self, percent: f32, rounding: StepRounding) -> i8 {
+        if percent.is_nan() || percent == 0.0 {
+            return 0;
+        }
+        let min_step = f32::from(self.min_step.clamp(0, 7));
+        let fraction = percent.abs().min(100.0) / 100.0;
+        let fraction = match self.curve {
+            StepCurve::Linear => fraction,
+            StepCurve::Quadratic => fraction * fraction,
+        };
+        let magnitude = rounding.round(min_step + fraction * (7.0 - min_step));
+        (percent.signum() * magnitude.clamp(0.0, 7.0)) as i8
+    }
+}
+
+impl Default for MotorProfile {
+    fn default() -> Self {
+        Self::linear()
+    }
+}
+
+impl FromStr for MotorProfile {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "linear" => Ok(Self::linear()),
+            "train" => Ok(Self::train()),
+            "m" => Ok(Self::m()),
+            "l" => Ok(Self::l()),
+            "xl" => Ok(Self::xl()),
+            "servo" => Ok(Self::servo()),
+            other => Err(Error::ProtocolError(format!(
+                "Unknown motor profile '{}', expected one of {}",
+                other,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// Maps percentages through the controller's `MotorProfile` (see `with_motor_profile`).
+#[cfg(feature = "single-output")]
+impl<T: PulseTransmitter> MotorControl for SpeedRemoteController<'_, T> {
+    fn set_power(&mut self, percent: i8) -> Result<()> {
+        let step = self.motor_profile().step(percent);
+        self.send(SingleOutputCommand::PWM(step)).map(|_| ())
+    }
+
+    fn brake(&mut self) -> Result<()> {
+        let step = match self.motor_profile().brake {
+            BrakeBehavior::Brake => 8,
+            BrakeBehavior::Float => 0,
+        };
+        self.send(SingleOutputCommand::PWM(step)).map(|_| ())
+    }
+}
+
+#[cfg(all(test, feature = "single-output"))]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use crate::{Channel, Output};
+
+    #[test]
+    fn test_percent_to_step_rounds_to_nearest() {
+        let percent_to_step = |percent| MotorProfile::linear().step(percent);
+        assert_eq!(percent_to_step(0), 0);
+        assert_eq!(percent_to_step(100), 7);
+        assert_eq!(percent_to_step(-100), -7);
+        assert_eq!(percent_to_step(50), 4);
+        assert_eq!(percent_to_step(-50), -4);
+        assert_eq!(percent_to_step(7), 0);
+        assert_eq!(percent_to_step(8), 1);
+        assert_eq!(percent_to_step(127), 7);
+        assert_eq!(percent_to_step(-128), -7);
+    }
+
+    #[test]
+    fn test_fractional_percent_to_step_rounding() {
+        let linear = MotorProfile::linear();
+        for percent in -100..=100i8 {
+            assert_eq!(
+                linear.step_percent(f32::from(percent), StepRounding::Nearest),
+                linear.step(percent),
+                "{}%",
+                percent
+            );
+        }
+        assert_eq!(linear.step_percent(20.0, StepRounding::TowardZero), 1);
+        assert_eq!(linear.step_percent(20.0, StepRounding::AwayFromZero), 2);
+        assert_eq!(linear.step_percent(-0.5, StepRounding::AwayFromZero), -1);
+        assert_eq!(linear.step_percent(-0.5, StepRounding::TowardZero), 0);
+        assert_eq!(linear.step_percent(250.0, StepRounding::Nearest), 7);
+        assert_eq!(linear.step_percent(f32::NAN, StepRounding::Nearest), 0);
+        assert_eq!(
+            MotorProfile::train().step_percent(0.1, StepRounding::TowardZero),
+            2
+        );
+    }
+
+    #[test]
+    fn test_motor_profiles() {
+        let train = MotorProfile::train();
+        assert_eq!(train.step(1), 2);
+        assert_eq!(train.step(50), 3);
+        assert_eq!(train.step(-100), -7);
+        assert_eq!(MotorProfile::xl().step(50), 3);
+        assert_eq!(MotorProfile::l().step(50), 4);
+        assert_eq!(MotorProfile::linear().with_min_step(9).step(1), 7);
+        for name in MotorProfile::NAMES {
+            assert!(name.parse::<MotorProfile>().is_ok(), "{}", name);
+        }
+        assert!("technic".parse::<MotorProfile>().is_err());
+    }
+
+    #[test]
+    fn test_servo_brake_floats() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut servo = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .unwrap()
+            .with_motor_profile(MotorProfile::servo());
+        servo.brake().unwrap();
+        let mut reference =
+            SpeedRemoteController::new(&transmitter, Channel::One, Output::RED).unwrap();
+        reference.send(SingleOutputCommand::PWM(0)).unwrap();
+
+        let sent = transmitter.sent();
+        assert_eq!(sent[0], sent[1]);
+    }
+
+    #[test]
+    fn test_speed_remote_controller_as_motor() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut motor = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        let motor: &mut dyn MotorControl = &mut motor;
+        motor.set_power(100).unwrap();
+        motor.stop().unwrap();
+        motor.brake().unwrap();
+        assert_eq!(transmitter.sent().len(), 3);
+    }
+
+    #[test]
+    fn test_motor_is_a_train() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut motor = SpeedRemoteController::new(&transmitter, Channel::One, Output::RED)
+            .expect("Should create SpeedRemoteController");
+        let train: &mut dyn TrainControl = &mut motor;
+        train.set_speed(60).unwrap();
+        train.coast().unwrap();
+        train.emergency_stop().unwrap();
+        assert_eq!(transmitter.sent().len(), 3);
+    }
+}
@@ -0,0 +1,478 @@
+//! # OSC Control
+//!
+//! An Open Sound Control server (feature `osc`) for show-control software such as QLab or
+//! TouchOSC. Every OSC message received over UDP is translated into sequence steps and played
+//! through a `SequencePlayer`, so OSC shares the toggle and pause handling of the other
+//! front ends.
+//!
+//! ## Addresses
+//!
+//! | Address                          | Arguments    | Command                               |
+//! |----------------------------------|--------------|---------------------------------------|
+//! | `/train/<1-4>/<red\|blue>/speed` | speed        | Single Output PWM on one output       |
+//! | `/train/<1-4>/<red\|blue>/brake` |              | Single Output brake on one output     |
+//! | `/train/<1-4>/speed`             | red, blue    | Combo PWM on both outputs             |
+//! | `/train/<1-4>/stop`              |              | Combo PWM float on both outputs       |
+//! | `/all/stop`                      |              | Combo PWM float on every channel      |
+//!
+//! Speeds may be sent as integers or floats; floats are rounded to the nearest step. Bundles are
+//! unpacked and their messages executed in order, ignoring the time tag.
+//!
+//! A server created `with_auth(Auth::token(..))` expects the token as the first (string) argument
+//! of every message, e.g. `/train/1/stop "s3cret"`, and drops packets containing any message
+//! without it.
+
+use crate::auth::Auth;
+use crate::device::PulseTransmitter;
+use crate::protocols::{check_speed, MAX_MESSAGE_DURATION};
+use crate::sequence::{SequenceAction, SequencePlayer, SequenceStep};
+use crate::{Channel, ComboPwmCommand, Error, Output, Result, SingleOutputCommand};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How often `OscServer::serve_until` checks its stop flag while no packets arrive.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The largest datagram the server accepts.
+const MAX_PACKET_SIZE: usize = 1536;
+
+/// An argument of an OSC message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+/// A decoded OSC message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+/// Decodes an OSC packet into its messages, unpacking (nested) bundles.
+pub fn decode_packet(packet: &[u8]) -> Result<Vec<OscMessage>> {
+    let mut messages = Vec::new();
+    decode_into(packet, &mut messages)?;
+    Ok(messages)
+}
+
+fn decode_into(packet: &[u8], messages: &mut Vec<OscMessage>) -> Result<()> {
+    let mut reader = Reader {
+        packet,
+        position: 0,
+    };
+    let address = reader.string()?;
+    if address == "#bundle" {
+        reader.take(8)?; // time tag
+        while reader.position < packet.len() {
+            let size = reader.int()?;
+            let size = usize::try_from(size).map_err(|_| malformed("negative bundle element"))?;
+            decode_into(reader.take(size)?, messages)?;
+        }
+        return Ok(());
+    }
+
+    let tags = reader.string()?;
+    let tags = tags
+        .strip_prefix(',')
+        .ok_or_else(|| malformed("missing type tags"))?;
+    let args = tags
+        .chars()
+        .map(|tag| match tag {
+            'i' => reader.int().map(OscArg::Int),
+            'f' => reader
+                .int()
+                .map(|bits| OscArg::Float(f32::from_bits(bits as u32))),
+            's' => reader.string().map(OscArg::String),
+            _ => Err(malformed(&format!("unsupported type tag '{}'", tag))),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    messages.push(OscMessage { address, args });
+    Ok(())
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::ProtocolError(format!("Malformed OSC packet: {}", reason))
+}
+
+/// Reads the 4-byte aligned fields of an OSC packet.
+struct Reader<'a> {
+    packet: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .packet
+            .get(self.position..self.position + size)
+            .ok_or_else(|| malformed("truncated"))?;
+        self.position += size;
+        Ok(bytes)
+    }
+
+    fn int(&mut self) -> Result<i32> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let rest = &self.packet[self.position.min(self.packet.len())..];
+        let length = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| malformed("unterminated string"))?;
+        let string = String::from_utf8(rest[..length].to_vec())
+            .map_err(|_| malformed("string is not UTF-8"))?;
+        self.take((length + 4) & !3)?;
+        Ok(string)
+    }
+}
+
+/// Translates an OSC message into the steps that execute it (see the module documentation).
+pub fn message_to_steps(message: &OscMessage) -> Result<Vec<SequenceStep>> {
+    let parts: Vec<&str> = message.address.trim_matches('/').split('/').collect();
+    let unknown = || Error::ProtocolError(format!("Unknown OSC address '{}'", message.address));
+    let step = |channel, action| SequenceStep {
+        channel,
+        action,
+        wait: Duration::ZERO,
+    };
+
+    let steps = match parts.as_slice() {
+        ["all", "stop"] => Channel::iter()
+            .map(|channel| SequenceStep {
+                // Give each channel its own slot, like `Broadcast` does.
+                wait: if channel == Channel::Four {
+                    Duration::ZERO
+                } else {
+                    MAX_MESSAGE_DURATION
+                },
+                ..step(
+                    channel,
+                    SequenceAction::ComboPwm(ComboPwmCommand::stopped()),
+                )
+            })
+            .collect(),
+        ["train", channel, rest @ ..] => {
+            let channel = channel
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| Channel::ALL.get(number.wrapping_sub(1)).copied())
+                .ok_or_else(unknown)?;
+            let action = match rest {
+                [output, "speed"] => SequenceAction::SingleOutput(
+                    parse_output(output).ok_or_else(unknown)?,
+                    SingleOutputCommand::PWM(speed_arg(message, 0)?),
+                ),
+                [output, "brake"] => SequenceAction::SingleOutput(
+                    parse_output(output).ok_or_else(unknown)?,
+                    SingleOutputCommand::PWM(8),
+                ),
+                ["speed"] => SequenceAction::ComboPwm(ComboPwmCommand::new(
+                    speed_arg(message, 0)?,
+                    speed_arg(message, 1)?,
+                )?),
+                ["stop"] => SequenceAction::ComboPwm(ComboPwmCommand::stopped()),
+                _ => return Err(unknown()),
+            };
+            vec![step(channel, action)]
+        }
+        _ => return Err(unknown()),
+    };
+    Ok(steps)
+}
+
+fn parse_output(word: &str) -> Option<Output> {
+    match word {
+        "red" => Some(Output::RED),
+        "blue" => Some(Output::BLUE),
+        _ => None,
+    }
+}
+
+fn speed_arg(message: &OscMessage, index: usize) -> Result<i8> {
+    let speed = match message.args.get(index) {
+        Some(OscArg::Int(speed)) => *speed,
+        Some(OscArg::Float(speed)) => speed.round() as i32,
+        _ => {
+            return Err(Error::ProtocolError(format!(
+                "{} expects a numeric argument at position {}",
+                message.address,
+                index + 1
+            )))
+        }
+    };
+    check_speed(i8::try_from(speed).unwrap_or(i8::MAX))
+}
+
+/// A UDP server executing OSC messages.
+///
+/// # Example
+/// ```rust,no_run
+/// use brickbeam::{osc::OscServer, BrickBeam, Result, Sequence};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     let mut player = brick_beam.create_sequence_player(Sequence::new())?;
+///     OscServer::bind("0.0.0.0:9000")?.serve(&mut player)
+/// }
+/// ```
+pub struct OscServer {
+    socket: UdpSocket,
+    auth: Auth,
+}
+
+impl OscServer {
+    /// Binds the server to a UDP address, e.g. `0.0.0.0:9000`.
+    pub fn bind(address: impl ToSocketAddrs) -> Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(address)?,
+            auth: Auth::Open,
+        })
+    }
+
+    /// Sets the access policy; by default every client is accepted.
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Returns the address the server is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Receives one packet and plays its messages.
+    ///
+    /// Returns the summed airtime of all transmitted messages.
+    pub fn handle_next<T: PulseTransmitter>(
+        &self,
+        player: &mut SequencePlayer<'_, T>,
+    ) -> Result<Duration> {
+        let mut packet = [0u8; MAX_PACKET_SIZE];
+        let (size, _) = self.socket.recv_from(&mut packet)?;
+        let mut steps = Vec::new();
+        for mut message in decode_packet(&packet[..size])? {
+            if self.auth.is_required() {
+                let credential = match message.args.first() {
+                    Some(OscArg::String(credential)) => Some(credential.as_str()),
+                    _ => None,
+                };
+                self.auth.authorize(credential)?;
+                message.args.remove(0);
+            }
+            steps.extend(message_to_steps(&message)?);
+        }
+        let mut airtime = Duration::ZERO;
+        for step in steps {
+            airtime += player.play_step(step)?;
+        }
+        Ok(airtime)
+    }
+
+    /// Serves packets until receiving or transmitting fails.
+    ///
+    /// Packets that cannot be decoded, address nothing known or fail authorization are ignored,
+    /// so a misconfigured control surface cannot stop the server.
+    pub fn serve<T: PulseTransmitter>(&self, player: &mut SequencePlayer<'_, T>) -> Result<()> {
+        loop {
+            match self.handle_next(player) {
+                Ok(_)
+                | Err(Error::ProtocolError(_))
+                | Err(Error::InvalidSpeed(_))
+                | Err(Error::Unauthorized(_)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Serves packets like `serve` until `stop` is set, e.g. by a signal handler, and then
+    /// returns `Ok(())`. The flag is checked at least every 100 ms.
+    pub fn serve_until<T: PulseTransmitter>(
+        &self,
+        player: &mut SequencePlayer<'_, T>,
+        stop: &AtomicBool,
+    ) -> Result<()> {
+        self.socket.set_read_timeout(Some(STOP_POLL_INTERVAL))?;
+        let result = loop {
+            if stop.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            match self.handle_next(player) {
+                Ok(_)
+                | Err(Error::ProtocolError(_))
+                | Err(Error::InvalidSpeed(_))
+                | Err(Error::Unauthorized(_)) => (),
+                // Timeouts let the flag be checked; signals setting it interrupt the receive.
+                Err(Error::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.socket.set_read_timeout(None)?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use crate::sequence::Sequence;
+
+    fn pad(bytes: &mut Vec<u8>, text: &str) {
+        bytes.extend_from_slice(text.as_bytes());
+        bytes.resize((bytes.len() + 4) & !3, 0);
+    }
+
+    fn encode(address: &str, args: &[OscArg]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        pad(&mut packet, address);
+        let tags: String = args
+            .iter()
+            .map(|arg| match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+            })
+            .collect();
+        pad(&mut packet, &format!(",{}", tags));
+        for arg in args {
+            match arg {
+                OscArg::Int(value) => packet.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Float(value) => packet.extend_from_slice(&value.to_be_bytes()),
+                OscArg::String(value) => pad(&mut packet, value),
+            }
+        }
+        packet
+    }
+
+    #[test]
+    fn test_decode_message_and_bundle() {
+        let message = encode("/train/1/red/speed", &[OscArg::Float(4.6)]);
+        assert_eq!(
+            decode_packet(&message).unwrap(),
+            vec![OscMessage {
+                address: "/train/1/red/speed".to_string(),
+                args: vec![OscArg::Float(4.6)],
+            }]
+        );
+
+        let mut bundle = Vec::new();
+        pad(&mut bundle, "#bundle");
+        bundle.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        for _ in 0..2 {
+            bundle.extend_from_slice(&(message.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(&message);
+        }
+        assert_eq!(decode_packet(&bundle).unwrap().len(), 2);
+        assert!(decode_packet(&message[..message.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_message_to_steps() {
+        let message = OscMessage {
+            address: "/train/2/blue/speed".to_string(),
+            args: vec![OscArg::Float(-3.4)],
+        };
+        let steps = message_to_steps(&message).unwrap();
+        assert_eq!(steps[0].channel, Channel::Two);
+        assert!(matches!(
+            steps[0].action,
+            SequenceAction::SingleOutput(Output::BLUE, SingleOutputCommand::PWM(-3))
+        ));
+
+        let all_stop = OscMessage {
+            address: "/all/stop".to_string(),
+            args: Vec::new(),
+        };
+        assert_eq!(message_to_steps(&all_stop).unwrap().len(), 4);
+
+        let too_fast = OscMessage {
+            address: "/train/1/speed".to_string(),
+            args: vec![OscArg::Int(9), OscArg::Int(0)],
+        };
+        assert!(matches!(
+            message_to_steps(&too_fast),
+            Err(Error::InvalidSpeed(9))
+        ));
+        let unknown = OscMessage {
+            address: "/train/5/stop".to_string(),
+            args: Vec::new(),
+        };
+        assert!(message_to_steps(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_server_plays_received_message() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut player = SequencePlayer::new(&transmitter, Sequence::new()).unwrap();
+        let server = OscServer::bind("127.0.0.1:0").unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .send_to(
+                &encode("/train/1/speed", &[OscArg::Int(5), OscArg::Int(-5)]),
+                server.local_addr().unwrap(),
+            )
+            .unwrap();
+
+        assert!(server.handle_next(&mut player).unwrap() > Duration::ZERO);
+        assert_eq!(transmitter.sent().len(), 1);
+    }
+
+    #[test]
+    fn test_serve_until_stops_on_flag() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut player = SequencePlayer::new(&transmitter, Sequence::new()).unwrap();
+        let server = OscServer::bind("127.0.0.1:0").unwrap();
+        let stop = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                stop.store(true, Ordering::Relaxed);
+            });
+            server.serve_until(&mut player, &stop).unwrap();
+        });
+        assert!(transmitter.sent().is_empty());
+    }
+
+    #[test]
+    fn test_server_requires_token() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut player = SequencePlayer::new(&transmitter, Sequence::new()).unwrap();
+        let server = OscServer::bind("127.0.0.1:0")
+            .unwrap()
+            .with_auth(Auth::token("s3cret"));
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+
+        client
+            .send_to(&encode("/train/1/stop", &[]), address)
+            .unwrap();
+        assert!(matches!(
+            server.handle_next(&mut player),
+            Err(Error::Unauthorized(_))
+        ));
+        client
+            .send_to(
+                &encode(
+                    "/train/1/red/speed",
+                    &[OscArg::String("s3cret".to_string()), OscArg::Int(3)],
+                ),
+                address,
+            )
+            .unwrap();
+        assert!(server.handle_next(&mut player).is_ok());
+        assert_eq!(transmitter.sent().len(), 1);
+    }
+}
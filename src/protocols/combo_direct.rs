@@ -1,12 +1,17 @@
 //! # Combo Direct Protocol
 //!
-//! We reuse the `LEGO_EXTENDED_IRP` constant (from extended.rs) because the
+//! We reuse the Extended IRP (`extended_irp` in extended.rs) because the
 //! base waveform timing is the same. The relevant bits for Combo Direct are
 //! encoded as (Mode=1), toggling the F nibble for the two outputs, etc.
 
-use super::Channel;
+use super::{
+    lrc::Lrc,
+    timing::{frame_air_time, PfIrp, PulseTiming},
+    Address, Channel, ToggleState,
+};
 use crate::{Error, Result};
-use irp::{Irp, Vartable};
+use irp::Vartable;
+use std::{fmt, str::FromStr, time::Duration};
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,9 +22,62 @@ pub enum DirectState {
     Brake = 0b11,
 }
 
+impl DirectState {
+    fn name(self) -> &'static str {
+        match self {
+            DirectState::Float => "float",
+            DirectState::Forward => "forward",
+            DirectState::Backward => "backward",
+            DirectState::Brake => "brake",
+        }
+    }
+}
+
+/// Formats the state as its lowercase name, e.g. `forward`, which `FromStr` parses back.
+impl fmt::Display for DirectState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses a state from its name, ignoring case and surrounding whitespace.
+impl FromStr for DirectState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        [
+            DirectState::Float,
+            DirectState::Forward,
+            DirectState::Backward,
+            DirectState::Brake,
+        ]
+        .into_iter()
+        .find(|state| state.name().eq_ignore_ascii_case(s.trim()))
+        .ok_or_else(|| Error::ProtocolError(format!("Unknown direct state '{}'", s)))
+    }
+}
+
+/// Converts the 2-bit wire value of a state (0 to 3) back into a `DirectState`.
+impl TryFrom<u8> for DirectState {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0b00 => Ok(DirectState::Float),
+            0b01 => Ok(DirectState::Forward),
+            0b10 => Ok(DirectState::Backward),
+            0b11 => Ok(DirectState::Brake),
+            _ => Err(Error::ProtocolError(format!(
+                "Invalid direct state value {}",
+                value
+            ))),
+        }
+    }
+}
+
 /// Represents a Combo Direct command used to control two outputs simultaneously
 /// via the Combo Direct protocol.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ComboDirectCommand {
     /// The state for output A (red).
     /// Controls the forward, reverse, brake or float actions for the A output.
@@ -30,44 +88,137 @@ pub struct ComboDirectCommand {
     pub blue: DirectState,
 }
 
+impl ComboDirectCommand {
+    /// Returns an upper bound of how long `repeats` copies of the command occupy the IR medium,
+    /// e.g. to schedule the next message: every copy but the last takes its 16 ms message slot.
+    ///
+    /// The channel, toggle, address and LRC bits the command doesn't fix are counted as 1 bits,
+    /// which last longest.
+    pub fn air_time(&self, repeats: u32) -> Duration {
+        // Toggle, channel and address bits set, mode Combo Direct.
+        let data = ((self.blue as u16) << 2) | self.red as u16;
+        frame_air_time(0xB90F | (data << 4), repeats)
+    }
+}
+
+/// Builds a command from a `(red, blue)` pair.
+impl From<(DirectState, DirectState)> for ComboDirectCommand {
+    fn from((red, blue): (DirectState, DirectState)) -> Self {
+        Self { red, blue }
+    }
+}
+
+/// Selects the toggle bit of Combo Direct messages.
+///
+/// The IR Remote Control 8885 always sends toggle bit 0, since its messages describe a state
+/// rather than a step. Some receivers verify the toggle anyway and ignore a message repeating
+/// the previous one, which `Alternate` avoids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToggleMode {
+    /// Sends the given toggle bit with every message, `false` like the 8885.
+    Fixed(bool),
+    /// Flips the toggle bit after every message, starting with 0.
+    Alternate,
+}
+
+impl Default for ToggleMode {
+    fn default() -> Self {
+        ToggleMode::Fixed(false)
+    }
+}
+
 struct ComboDirectMessage {
+    toggle: u8,
     channel: u8,
+    address: u8,
     data: u8,
 }
 
 pub struct ComboDirectProtocol {
-    irp: Irp,
+    irp: PfIrp,
+    lrc: Lrc,
+    address: Address,
+    toggle_mode: ToggleMode,
+    toggle: ToggleState,
 }
 
-use crate::protocols::extended::LEGO_EXTENDED_IRP;
+use crate::protocols::extended::extended_irp;
 
 impl ComboDirectProtocol {
     pub fn new() -> Result<Self> {
-        let irp = Irp::parse(LEGO_EXTENDED_IRP).map_err(Error::ProtocolError)?;
-        Ok(Self { irp })
+        Self::with_timing(PulseTiming::STANDARD)
+    }
+
+    /// Creates the protocol with adjusted symbol lengths.
+    pub fn with_timing(timing: PulseTiming) -> Result<Self> {
+        Ok(Self {
+            irp: extended_irp(timing)?,
+            lrc: Lrc::Computed,
+            address: Address::Default,
+            toggle_mode: ToggleMode::default(),
+            toggle: ToggleState::default(),
+        })
+    }
+
+    /// Changes the symbol lengths of subsequent messages.
+    pub fn set_timing(&mut self, timing: PulseTiming) -> Result<()> {
+        self.irp = Self::with_timing(timing)?.irp;
+        Ok(())
+    }
+
+    /// Selects the LRC sent with subsequent messages.
+    pub fn set_lrc(&mut self, lrc: Lrc) {
+        self.lrc = lrc;
+    }
+
+    /// Selects the address space of subsequent messages.
+    pub fn set_address(&mut self, address: Address) {
+        self.address = address;
+    }
+
+    /// Returns the address space of subsequent messages.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Selects how the toggle bit of subsequent messages is chosen.
+    pub fn set_toggle_mode(&mut self, mode: ToggleMode) {
+        self.toggle_mode = mode;
+    }
+
+    /// Returns how the toggle bit of subsequent messages is chosen.
+    pub fn toggle_mode(&self) -> ToggleMode {
+        self.toggle_mode
     }
 
     fn encode_msg(&self, msg: ComboDirectMessage) -> Result<Vec<u32>> {
         let mut vars = Vartable::new();
-        vars.set("T".into(), 0u8.into());
+        vars.set("T".into(), msg.toggle.into());
         vars.set("E".into(), 0u8.into());
         vars.set("C".into(), msg.channel.into());
-        vars.set("a".into(), 0u8.into());
+        vars.set("a".into(), msg.address.into());
         vars.set("M".into(), 1u8.into());
         vars.set("F".into(), msg.data.into());
-        self.irp
-            .encode_raw(vars, 1)
-            .map(|res| res.raw)
-            .map_err(Error::ProtocolError)
+        self.irp.encode(vars, self.lrc)
     }
 
     /// Encodes a Combo Direct command.
     pub fn encode_cmd(&self, channel: Channel, cmd: ComboDirectCommand) -> Result<Vec<u32>> {
+        let toggle = match self.toggle_mode {
+            ToggleMode::Fixed(toggle) => toggle as u8,
+            ToggleMode::Alternate => self.toggle.toggle(),
+        };
         let msg = ComboDirectMessage {
+            toggle,
             channel: channel as u8,
+            address: self.address as u8,
             data: ((cmd.blue as u8) << 2) | (cmd.red as u8),
         };
-        self.encode_msg(msg)
+        let pulses = self.encode_msg(msg)?;
+        if self.toggle_mode == ToggleMode::Alternate {
+            self.toggle.flip_toggle();
+        }
+        Ok(pulses)
     }
 }
 
@@ -120,4 +271,45 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_combo_direct_toggle_modes() {
+        let cmd = ComboDirectCommand::from((DirectState::Forward, DirectState::Float));
+        // The toggle bit is the first bit of the frame, so its space is the fourth pulse.
+        let toggle_bits = |proto: &ComboDirectProtocol| -> Vec<bool> {
+            (0..3)
+                .map(|_| proto.encode_cmd(Channel::One, cmd).unwrap()[3] > 400)
+                .collect()
+        };
+
+        let mut proto = ComboDirectProtocol::new().unwrap();
+        assert_eq!(proto.toggle_mode(), ToggleMode::Fixed(false));
+        assert_eq!(toggle_bits(&proto), [false, false, false]);
+        proto.set_toggle_mode(ToggleMode::Fixed(true));
+        assert_eq!(toggle_bits(&proto), [true, true, true]);
+        proto.set_toggle_mode(ToggleMode::Alternate);
+        assert_eq!(toggle_bits(&proto), [false, true, false]);
+    }
+
+    #[test]
+    fn test_direct_state_string_round_trip() {
+        for value in 0..4u8 {
+            let state = DirectState::try_from(value).unwrap();
+            assert_eq!(state as u8, value);
+            assert_eq!(state.to_string().parse::<DirectState>().unwrap(), state);
+        }
+        assert_eq!(
+            " Brake ".parse::<DirectState>().unwrap(),
+            DirectState::Brake
+        );
+        assert!("reverse".parse::<DirectState>().is_err());
+        assert!(DirectState::try_from(4).is_err());
+    }
+
+    #[test]
+    fn test_combo_direct_command_from_pair() {
+        let cmd = ComboDirectCommand::from((DirectState::Forward, DirectState::Brake));
+        assert_eq!(cmd.red, DirectState::Forward);
+        assert_eq!(cmd.blue, DirectState::Brake);
+    }
 }
@@ -1,7 +1,8 @@
 //! # Combo PWM Protocol
 //!
 //! Combo PWM allows simultaneously controlling both outputs (A/B) at various
-//! speed steps. Internally, we define the IRP string `LEGO_COMBO_PWM_IRP`:
+//! speed steps. Internally, we build the IRP from `LEGO_COMBO_PWM_FIELDS`, which with the
+//! default `PulseTiming` reads:
 //! ```ignore
 //! {38k,33%,26.3157894737,msb}
 //! <6,-10|6,-21>
@@ -12,13 +13,20 @@
 //! We then map user-friendly `ComboPwmCommand` speeds (e.g. `speed_red=5`)
 //! to the correct nibble for each output.
 
-use super::{map_speed, Channel};
-use crate::{Error, Result};
-use irp::{Irp, Vartable};
+use super::{
+    check_speed,
+    lrc::Lrc,
+    map_speed,
+    timing::{frame_air_time, PfIrp, PulseTiming},
+    try_map_speed, Address, Channel, Speed,
+};
+use crate::Result;
+use irp::Vartable;
+use std::time::Duration;
 
 /// Represents a Combo PWM command used for simultaneous control of two outputs
 /// via the Combo PWM protocol.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ComboPwmCommand {
     /// PWM speed for output A (red). Valid range is from -7 to 8.
     ///
@@ -33,6 +41,69 @@ pub struct ComboPwmCommand {
     pub speed_blue: i8,
 }
 
+impl ComboPwmCommand {
+    /// Creates a command for both outputs, rejecting speeds outside -7 to 8 with
+    /// `Error::InvalidSpeed` instead of clamping them at encode time.
+    pub fn new(speed_red: i8, speed_blue: i8) -> Result<Self> {
+        Ok(Self {
+            speed_red: check_speed(speed_red)?,
+            speed_blue: check_speed(speed_blue)?,
+        })
+    }
+
+    /// Creates a command for both outputs from typed speeds, which are always valid.
+    pub const fn from_speeds(red: Speed, blue: Speed) -> Self {
+        Self {
+            speed_red: red.step(),
+            speed_blue: blue.step(),
+        }
+    }
+
+    /// Returns an upper bound of how long `repeats` copies of the command occupy the IR medium,
+    /// e.g. to schedule the next message: every copy but the last takes its 16 ms message slot.
+    ///
+    /// The channel, toggle, address and LRC bits the command doesn't fix are counted as 1 bits,
+    /// which last longest.
+    pub fn air_time(&self, repeats: u32) -> Duration {
+        // Address and channel bits set, escape bit set.
+        let (blue, red) = (map_speed(self.speed_blue), map_speed(self.speed_red));
+        frame_air_time(
+            0xF00F | (u16::from(blue) << 8) | (u16::from(red) << 4),
+            repeats,
+        )
+    }
+
+    /// Floats both outputs.
+    pub const fn stopped() -> Self {
+        Self {
+            speed_red: 0,
+            speed_blue: 0,
+        }
+    }
+
+    /// Brakes both outputs, then lets them float.
+    pub const fn brake_both() -> Self {
+        Self {
+            speed_red: 8,
+            speed_blue: 8,
+        }
+    }
+
+    /// Returns a copy of the command with the red output speed replaced, rejecting speeds
+    /// outside -7 to 8 with `Error::InvalidSpeed` like `new`.
+    pub fn with_red(mut self, speed_red: i8) -> Result<Self> {
+        self.speed_red = check_speed(speed_red)?;
+        Ok(self)
+    }
+
+    /// Returns a copy of the command with the blue output speed replaced, rejecting speeds
+    /// outside -7 to 8 with `Error::InvalidSpeed` like `new`.
+    pub fn with_blue(mut self, speed_blue: i8) -> Result<Self> {
+        self.speed_blue = check_speed(speed_blue)?;
+        Ok(self)
+    }
+}
+
 struct ComboPwmMessage {
     address: u8,
     channel: u8,
@@ -41,21 +112,73 @@ struct ComboPwmMessage {
 }
 
 pub struct ComboPwmProtocol {
-    irp: Irp,
+    irp: PfIrp,
+    lrc: Lrc,
+    address: Address,
+    strict: bool,
 }
 
-const LEGO_COMBO_PWM_IRP: &str = "\
-{38k,33%,26.3157894737,msb}\
-<6,-10|6,-21>\
-(6,-39, a:1, 1:1, C:2, B:4, A:4, L:4, 6,-39)\
-{L = 0xF^( ( (a<<3) | (1<<2) | C ) ^ B ^ A )}\
-[a:0..1,C:0..3,B:0..15,A:0..15]\
-";
+/// The payload of a Combo PWM message, framed by start and stop bits in the IRP.
+const LEGO_COMBO_PWM_FIELDS: &str = "a:1, 1:1, C:2, B:4, A:4, L:4";
+
+const LEGO_COMBO_PWM_LRC: &str = "0xF^( ( (a<<3) | (1<<2) | C ) ^ B ^ A )";
+
+const LEGO_COMBO_PWM_PARAMETERS: &str = "a:0..1,C:0..3,B:0..15,A:0..15";
 
 impl ComboPwmProtocol {
     pub fn new() -> Result<Self> {
-        let irp = Irp::parse(LEGO_COMBO_PWM_IRP).map_err(Error::ProtocolError)?;
-        Ok(Self { irp })
+        Self::with_timing(PulseTiming::STANDARD)
+    }
+
+    /// Creates the protocol with adjusted symbol lengths.
+    pub fn with_timing(timing: PulseTiming) -> Result<Self> {
+        let irp = PfIrp::new(
+            timing,
+            LEGO_COMBO_PWM_FIELDS,
+            LEGO_COMBO_PWM_LRC,
+            LEGO_COMBO_PWM_PARAMETERS,
+        )?;
+        Ok(Self {
+            irp,
+            lrc: Lrc::Computed,
+            address: Address::Default,
+            strict: false,
+        })
+    }
+
+    /// Changes the symbol lengths of subsequent messages.
+    pub fn set_timing(&mut self, timing: PulseTiming) -> Result<()> {
+        self.irp = Self::with_timing(timing)?.irp;
+        Ok(())
+    }
+
+    /// Selects the LRC sent with subsequent messages.
+    pub fn set_lrc(&mut self, lrc: Lrc) {
+        self.lrc = lrc;
+    }
+
+    /// Selects the address space of subsequent messages.
+    pub fn set_address(&mut self, address: Address) {
+        self.address = address;
+    }
+
+    /// Returns the address space of subsequent messages.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Rejects PWM speeds outside -7 to 8 with `Error::InvalidSpeed` instead of clamping them.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Maps a PWM speed, clamping it unless strict.
+    fn map_speed(&self, speed: i8) -> Result<u8> {
+        if self.strict {
+            try_map_speed(speed)
+        } else {
+            Ok(map_speed(speed))
+        }
     }
 
     fn encode_msg(&self, msg: ComboPwmMessage) -> Result<Vec<u32>> {
@@ -64,19 +187,16 @@ impl ComboPwmProtocol {
         vars.set("C".into(), msg.channel.into());
         vars.set("B".into(), msg.output_b.into());
         vars.set("A".into(), msg.output_a.into());
-        self.irp
-            .encode_raw(vars, 1)
-            .map(|res| res.raw)
-            .map_err(Error::ProtocolError)
+        self.irp.encode(vars, self.lrc)
     }
 
     /// Encodes a Combo PWM command.
     pub fn encode_cmd(&self, channel: Channel, cmd: ComboPwmCommand) -> Result<Vec<u32>> {
         let msg = ComboPwmMessage {
-            address: 0,
+            address: self.address as u8,
             channel: channel as u8,
-            output_b: map_speed(cmd.speed_blue),
-            output_a: map_speed(cmd.speed_red),
+            output_b: self.map_speed(cmd.speed_blue)?,
+            output_a: self.map_speed(cmd.speed_red)?,
         };
         self.encode_msg(msg)
     }
@@ -85,7 +205,9 @@ impl ComboPwmProtocol {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::protocols::Channel;
+    use crate::protocols::{timing::PulseRounding, Channel};
+    use crate::Error;
+
     #[test]
     fn test_combo_pwm_encode_cmd() {
         let proto = ComboPwmProtocol::new().unwrap();
@@ -105,4 +227,129 @@ mod tests {
         ];
         assert_eq!(pulses, expected, "Pulse sequence does not match expected");
     }
+
+    #[test]
+    fn test_combo_pwm_strict_speeds() {
+        let mut proto = ComboPwmProtocol::new().unwrap();
+        let unchecked = ComboPwmCommand {
+            speed_red: -100,
+            speed_blue: 0,
+        };
+        let clamped = proto.encode_cmd(Channel::One, unchecked).unwrap();
+        assert_eq!(
+            clamped,
+            proto
+                .encode_cmd(Channel::One, ComboPwmCommand::new(-7, 0).unwrap())
+                .unwrap()
+        );
+        proto.set_strict(true);
+        assert!(matches!(
+            proto.encode_cmd(Channel::One, unchecked),
+            Err(Error::InvalidSpeed(-100))
+        ));
+    }
+
+    #[test]
+    fn test_combo_pwm_command_builders() {
+        let cmd = ComboPwmCommand::new(5, -3).unwrap();
+        assert_eq!((cmd.speed_red, cmd.speed_blue), (5, -3));
+        assert!(matches!(
+            ComboPwmCommand::new(9, 0),
+            Err(Error::InvalidSpeed(9))
+        ));
+        assert!(matches!(
+            ComboPwmCommand::new(0, -8),
+            Err(Error::InvalidSpeed(-8))
+        ));
+
+        let cmd = ComboPwmCommand::from_speeds(Speed::BrakeThenFloat, Speed::Reverse(4));
+        assert_eq!((cmd.speed_red, cmd.speed_blue), (8, -4));
+
+        let cmd = ComboPwmCommand::stopped()
+            .with_red(3)
+            .and_then(|cmd| cmd.with_blue(-2))
+            .unwrap();
+        assert_eq!((cmd.speed_red, cmd.speed_blue), (3, -2));
+        assert!(matches!(
+            ComboPwmCommand::stopped().with_blue(9),
+            Err(Error::InvalidSpeed(9))
+        ));
+        let cmd = ComboPwmCommand::brake_both();
+        assert_eq!((cmd.speed_red, cmd.speed_blue), (8, 8));
+    }
+
+    #[test]
+    fn test_combo_pwm_clone_receiver_timing() {
+        let cmd = ComboPwmCommand::new(5, -3).unwrap();
+        let standard = ComboPwmProtocol::new()
+            .unwrap()
+            .encode_cmd(Channel::One, cmd)
+            .unwrap();
+        let clone = ComboPwmProtocol::with_timing(PulseTiming::CLONE_RECEIVER)
+            .unwrap()
+            .encode_cmd(Channel::One, cmd)
+            .unwrap();
+
+        assert_eq!(&clone[..4], &[173, 1010, 173, 247]);
+        assert_eq!(clone.len(), standard.len());
+        // Each mark/space pair keeps its length, give or take the truncation to whole µs.
+        for (clone, standard) in clone.chunks(2).zip(standard.chunks(2)) {
+            let (clone, standard) = (clone[0] + clone[1], standard[0] + standard[1]);
+            assert!(clone.abs_diff(standard) <= 1, "{} vs {}", clone, standard);
+        }
+    }
+
+    #[test]
+    fn test_combo_pwm_rounding_golden() {
+        let cmd = ComboPwmCommand::new(5, -3).unwrap();
+        let encode = |rounding| {
+            ComboPwmProtocol::with_timing(PulseTiming::STANDARD.with_rounding(rounding))
+                .unwrap()
+                .encode_cmd(Channel::One, cmd)
+                .unwrap()
+        };
+
+        let nearest = encode(PulseRounding::Nearest);
+        let expected: Vec<u32> = vec![
+            158, 1026, 158, 263, 158, 553, 158, 263, 158, 263, 158, 553, 158, 553, 158, 263, 158,
+            553, 158, 263, 158, 553, 158, 263, 158, 553, 158, 263, 158, 263, 158, 553, 158, 553,
+            158, 1026,
+        ];
+        assert_eq!(nearest, expected);
+
+        let accumulated = encode(PulseRounding::Accumulated);
+        let expected: Vec<u32> = vec![
+            158, 1026, 158, 263, 158, 553, 158, 263, 158, 263, 158, 552, 158, 553, 158, 263, 158,
+            553, 157, 264, 157, 553, 158, 263, 158, 553, 158, 263, 158, 263, 158, 552, 158, 553,
+            158, 1026,
+        ];
+        assert_eq!(accumulated, expected);
+
+        // The exact message length is 11421.05 µs; truncating every pulse loses 23 µs.
+        let total = |pulses: &[u32]| pulses.iter().sum::<u32>();
+        assert_eq!(total(&encode(PulseRounding::Floor)), 11398);
+        assert_eq!(total(&nearest), 11424);
+        assert_eq!(total(&accumulated), 11421);
+    }
+
+    #[test]
+    fn test_combo_pwm_matches_frame_encoder() {
+        let cmd = ComboPwmCommand::new(5, -3).unwrap();
+        // Nibbles 0100 (channel 1), 1101 (blue -3), 0101 (red 5) and the LRC 0011.
+        let frame = 0x4D53;
+        for timing in [PulseTiming::STANDARD, PulseTiming::CLONE_RECEIVER] {
+            for rounding in [
+                PulseRounding::Floor,
+                PulseRounding::Nearest,
+                PulseRounding::Accumulated,
+            ] {
+                let timing = timing.with_rounding(rounding);
+                let pulses = ComboPwmProtocol::with_timing(timing)
+                    .unwrap()
+                    .encode_cmd(Channel::One, cmd)
+                    .unwrap();
+                assert_eq!(pulses, timing.encode_frame(frame), "{:?}", timing);
+            }
+        }
+    }
 }
@@ -0,0 +1,376 @@
+//! # Decoding
+//!
+//! Turns received pulses back into PF messages, the reverse of the protocol encoders.
+//!
+//! `find_frames` demodulates the frames in a pulse sequence, e.g. a burst from a
+//! `PulseReceiver`: each is a start symbol, 16 bits and a stop mark. The bits are told apart by
+//! the length of mark plus space, with the bounds halfway between the nominal symbol lengths, so
+//! receivers stretching the marks or shortening the spaces decode fine.
+//!
+//! `decode_frame` then interprets the 16 bits as a typed command with its channel, toggle and
+//! address bits. A wrong LRC doesn't reject the message, it is reported in
+//! `DecodedMessage::lrc_valid`, as a receiver would drop the message but a tool may still want to
+//! show what it was meant to be.
+//!
+//! # Example
+//! ```rust
+//! use brickbeam::decode::{decode, DecodedCommand};
+//! use brickbeam::{timing::PulseTiming, Channel, ComboPwmCommand, Result};
+//!
+//! fn main() -> Result<()> {
+//!     // Combo PWM on channel 1: red forward 5, blue backward 3.
+//!     let pulses = PulseTiming::STANDARD.encode_frame(0x4D53);
+//!     let message = decode(&pulses).remove(0)?;
+//!     assert_eq!(message.channel, Channel::One);
+//!     assert!(message.lrc_valid);
+//!     assert_eq!(
+//!         message.command,
+//!         DecodedCommand::ComboPwm(ComboPwmCommand::new(5, -3)?)
+//!     );
+//!     Ok(())
+//! }
+//! ```
+
+use super::{
+    unmap_speed, verify_lrc, Channel, ComboDirectCommand, ComboPwmCommand, DirectState,
+    ExtendedCommand, FrameBreakdown, Output, ProtocolKind, SingleOutputCommand,
+    SingleOutputDiscrete,
+};
+use crate::{Error, Result};
+
+/// The upper bound (µs) of mark plus space of a 0 bit, whose nominal length is 421 µs.
+pub const MAX_ZERO_BIT: u32 = 566;
+
+/// The upper bound (µs) of mark plus space of a 1 bit, whose nominal length is 711 µs.
+pub const MAX_ONE_BIT: u32 = 947;
+
+/// The upper bound (µs) of mark plus space of the start symbol, whose nominal length is 1184 µs.
+pub const MAX_START: u32 = 1600;
+
+/// One frame located in a pulse sequence.
+#[derive(Debug)]
+pub struct Frame<'a> {
+    /// Offset of the frame's start mark from the beginning of the sequence, in µs.
+    pub offset: u32,
+    /// The marks and spaces of the frame, from the start mark up to the stop mark.
+    pub pulses: &'a [u32],
+    /// The demodulated 16 bits, or why demodulation failed.
+    pub bits: Result<u16>,
+}
+
+/// The command carried by a PF message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedCommand {
+    Extended(ExtendedCommand),
+    ComboDirect(ComboDirectCommand),
+    SingleOutput(Output, SingleOutputCommand),
+    ComboPwm(ComboPwmCommand),
+}
+
+impl DecodedCommand {
+    /// Returns the protocol the command belongs to.
+    pub fn kind(&self) -> ProtocolKind {
+        match self {
+            DecodedCommand::Extended(_) => ProtocolKind::Extended,
+            DecodedCommand::ComboDirect(_) => ProtocolKind::ComboDirect,
+            DecodedCommand::SingleOutput(..) => ProtocolKind::SingleOutput,
+            DecodedCommand::ComboPwm(_) => ProtocolKind::ComboPwm,
+        }
+    }
+}
+
+/// A PF message decoded from its 16-bit frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedMessage {
+    /// The frame as received, including its LRC.
+    pub frame: u16,
+    pub channel: Channel,
+    /// The toggle bit; always `false` for Combo PWM, which has none.
+    pub toggle: bool,
+    /// The address bit, selecting the second address space of the receiver.
+    pub address: bool,
+    pub command: DecodedCommand,
+    /// Whether the LRC matches the payload. Receivers ignore messages where it doesn't.
+    pub lrc_valid: bool,
+}
+
+impl DecodedMessage {
+    /// Splits the frame as received into its fields, for printing.
+    pub fn describe(&self) -> FrameBreakdown {
+        FrameBreakdown::new(self.frame)
+    }
+}
+
+/// Locates the PF frames in `pulses`, alternating mark and space durations starting with a
+/// mark.
+///
+/// Pulses that can't start a frame are skipped. A frame with a symbol that is no bit ends there,
+/// and the search resumes at that symbol, as it may start the next frame.
+pub fn find_frames(pulses: &[u32]) -> Vec<Frame<'_>> {
+    let symbol = |index: usize| pulses[index] + pulses.get(index + 1).copied().unwrap_or(0);
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    let mut index = 0;
+    while index + 1 < pulses.len() {
+        let start = symbol(index);
+        if start <= MAX_ONE_BIT || start > MAX_START {
+            offset += start;
+            index += 2;
+            continue;
+        }
+        // A complete frame spans the start symbol, 16 bits and the stop mark.
+        let mut end = (index + 35).min(pulses.len());
+        let mut bits = Ok(0u16);
+        for bit in 0..16 {
+            let position = index + 2 + 2 * bit;
+            if position + 1 >= pulses.len() {
+                bits = Err(Error::ProtocolError(format!(
+                    "Frame ends after {} bits",
+                    bit
+                )));
+                break;
+            }
+            match symbol(position) {
+                length if length <= MAX_ZERO_BIT => bits = bits.map(|bits| bits << 1),
+                length if length <= MAX_ONE_BIT => bits = bits.map(|bits| (bits << 1) | 1),
+                length => {
+                    bits = Err(Error::ProtocolError(format!(
+                        "Bit {} lasts {} µs",
+                        bit, length
+                    )));
+                    end = position;
+                    break;
+                }
+            }
+        }
+        let next = if bits.is_ok() { end + 1 } else { end };
+        frames.push(Frame {
+            offset,
+            pulses: &pulses[index..end],
+            bits,
+        });
+        offset += pulses[index..next.min(pulses.len())].iter().sum::<u32>();
+        index = next;
+    }
+    frames
+}
+
+/// Interprets a 16-bit PF frame, see `RawMessageFields` for its layout.
+///
+/// # Errors
+///
+/// Returns `Error::ProtocolError` if the frame uses a reserved mode or Extended function.
+pub fn decode_frame(frame: u16) -> Result<DecodedMessage> {
+    let nibble = |shift: u16| ((frame >> shift) & 0xF) as u8;
+    let (nibble1, nibble2, data) = (nibble(12), nibble(8), nibble(4));
+    let channel = Channel::ALL[usize::from(nibble1 & 0b11)];
+    let (toggle, address, command) = if nibble1 & 0b100 != 0 {
+        let command = ComboPwmCommand {
+            speed_red: unmap_speed(data),
+            speed_blue: unmap_speed(nibble2),
+        };
+        (
+            false,
+            nibble1 & 0b1000 != 0,
+            DecodedCommand::ComboPwm(command),
+        )
+    } else {
+        let output = Output::ALL[usize::from(nibble2 & 0b1)];
+        let command = match nibble2 & 0b111 {
+            0b000 => DecodedCommand::Extended(ExtendedCommand::try_from(data)?),
+            0b001 => DecodedCommand::ComboDirect(ComboDirectCommand {
+                red: DirectState::try_from(data & 0b11)?,
+                blue: DirectState::try_from(data >> 2)?,
+            }),
+            0b100 | 0b101 => {
+                DecodedCommand::SingleOutput(output, SingleOutputCommand::PWM(unmap_speed(data)))
+            }
+            0b110 | 0b111 => DecodedCommand::SingleOutput(
+                output,
+                SingleOutputCommand::Discrete(SingleOutputDiscrete::try_from(data)?),
+            ),
+            mode => {
+                return Err(Error::ProtocolError(format!(
+                    "Mode {:#05b} is reserved",
+                    mode
+                )))
+            }
+        };
+        (nibble1 & 0b1000 != 0, nibble2 & 0b1000 != 0, command)
+    };
+    Ok(DecodedMessage {
+        frame,
+        channel,
+        toggle,
+        address,
+        command,
+        lrc_valid: verify_lrc(frame),
+    })
+}
+
+/// Decodes every frame found in `pulses`, in order.
+///
+/// Frames that fail to demodulate or to decode yield an error in their place, so a single bad
+/// frame doesn't hide the repetitions around it.
+pub fn decode(pulses: &[u32]) -> Vec<Result<DecodedMessage>> {
+    find_frames(pulses)
+        .into_iter()
+        .map(|frame| frame.bits.and_then(decode_frame))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::{
+        scancode::{scancode_table, scancode_to_frame},
+        timing::PulseTiming,
+        Address, ComboDirectProtocol, ComboPwmProtocol, ExtendedProtocol, SingleOutputProtocol,
+    };
+
+    fn decode_one(pulses: &[u32]) -> DecodedMessage {
+        let mut messages = decode(pulses);
+        assert_eq!(messages.len(), 1);
+        messages.remove(0).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_single_output() {
+        let mut proto = SingleOutputProtocol::new().unwrap();
+        let pwm = SingleOutputCommand::PWM(-3);
+        let first = decode_one(&proto.encode_cmd(Channel::Two, Output::BLUE, pwm).unwrap());
+        assert_eq!(first.channel, Channel::Two);
+        assert_eq!(
+            first.command,
+            DecodedCommand::SingleOutput(Output::BLUE, pwm)
+        );
+        assert!(first.lrc_valid);
+        assert!(!first.address);
+
+        let discrete = SingleOutputCommand::Discrete(SingleOutputDiscrete::ToggleC2);
+        let second = decode_one(
+            &proto
+                .encode_cmd(Channel::Two, Output::RED, discrete)
+                .unwrap(),
+        );
+        assert_eq!(
+            second.command,
+            DecodedCommand::SingleOutput(Output::RED, discrete)
+        );
+        assert_ne!(first.toggle, second.toggle);
+        assert_eq!(second.command.kind(), ProtocolKind::SingleOutput);
+    }
+
+    #[test]
+    fn test_round_trip_combo_and_extended() {
+        let direct = ComboDirectCommand {
+            red: DirectState::Forward,
+            blue: DirectState::Brake,
+        };
+        let pulses = ComboDirectProtocol::new()
+            .unwrap()
+            .encode_cmd(Channel::Three, direct)
+            .unwrap();
+        let message = decode_one(&pulses);
+        assert_eq!(message.channel, Channel::Three);
+        assert_eq!(message.command, DecodedCommand::ComboDirect(direct));
+
+        let pwm = ComboPwmCommand::new(8, -7).unwrap();
+        let pulses = ComboPwmProtocol::new()
+            .unwrap()
+            .encode_cmd(Channel::Four, pwm)
+            .unwrap();
+        let message = decode_one(&pulses);
+        assert_eq!(message.command, DecodedCommand::ComboPwm(pwm));
+        assert!(!message.toggle);
+
+        let mut extended = ExtendedProtocol::new().unwrap();
+        extended
+            .encode_cmd(Channel::One, ExtendedCommand::ToggleAddress)
+            .unwrap();
+        let pulses = extended
+            .encode_cmd(Channel::One, ExtendedCommand::AlignToggle)
+            .unwrap();
+        let message = decode_one(&pulses);
+        assert_eq!(
+            message.command,
+            DecodedCommand::Extended(ExtendedCommand::AlignToggle)
+        );
+        assert!(message.address);
+        assert!(message.toggle);
+    }
+
+    #[test]
+    fn test_round_trip_extra_address() {
+        let mut single = SingleOutputProtocol::new().unwrap();
+        single.set_address(Address::Extra);
+        let mut direct = ComboDirectProtocol::new().unwrap();
+        direct.set_address(Address::Extra);
+        let mut pwm = ComboPwmProtocol::new().unwrap();
+        pwm.set_address(Address::Extra);
+
+        let pulses = [
+            single
+                .encode_cmd(Channel::Two, Output::RED, SingleOutputCommand::PWM(4))
+                .unwrap(),
+            direct
+                .encode_cmd(
+                    Channel::Two,
+                    (DirectState::Forward, DirectState::Float).into(),
+                )
+                .unwrap(),
+            pwm.encode_cmd(Channel::Two, ComboPwmCommand::new(3, -3).unwrap())
+                .unwrap(),
+        ];
+        for pulses in pulses {
+            let message = decode_one(&pulses);
+            assert!(message.address, "{:?}", message.command);
+            assert!(message.lrc_valid, "{:?}", message.command);
+            assert_eq!(message.channel, Channel::Two);
+            assert_eq!(message.describe().channel(), Channel::Two);
+            assert!(message.describe().to_string().contains("(channel 2)"));
+        }
+    }
+
+    #[test]
+    fn test_every_documented_message_round_trips() {
+        for entry in scancode_table() {
+            let frame = scancode_to_frame(entry.scancode, false);
+            let message = decode_one(&PulseTiming::STANDARD.encode_frame(frame));
+            assert_eq!(message.frame, frame, "{}", entry.name);
+            assert!(message.lrc_valid, "{}", entry.name);
+        }
+    }
+
+    #[test]
+    fn test_lrc_mismatch_and_reserved_values() {
+        let message = decode_frame(0x4D53 ^ 0x1).unwrap();
+        assert!(!message.lrc_valid);
+        assert_eq!(
+            message.command,
+            DecodedCommand::ComboPwm(ComboPwmCommand::new(5, -3).unwrap())
+        );
+
+        // Mode 0b010 and Extended function 0b0011 are reserved.
+        assert!(decode_frame(scancode_to_frame(0x020, false)).is_err());
+        assert!(decode_frame(scancode_to_frame(0x003, false)).is_err());
+    }
+
+    #[test]
+    fn test_find_frames_resynchronizes() {
+        let frame = PulseTiming::STANDARD.encode_frame(0x4D53);
+        // A truncated frame, noise, then a complete frame.
+        let mut pulses = frame[..10].to_vec();
+        pulses.extend([158, 5000]);
+        pulses.extend(frame);
+        let messages = decode(&pulses);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].is_err());
+        assert_eq!(messages[1].as_ref().unwrap().frame, 0x4D53);
+
+        let frames = find_frames(&pulses);
+        let offset: u32 = pulses[..12].iter().sum();
+        assert_eq!(frames[1].offset, offset);
+        assert_eq!(frames[1].pulses.len(), 35);
+    }
+}
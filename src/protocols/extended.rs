@@ -7,10 +7,19 @@
 //!
 //! The protocol supports commands such as braking, toggling, and adjusting speed. The internal state (toggle
 //! and address) is maintained between calls to support multiple commands on the same channel.
+//!
+//! Combo Direct messages share the Extended IRP, so the `combo-direct` feature compiles this module
+//! for `extended_irp` alone; the rest is unused then and left to the linker to drop.
+#![cfg_attr(not(feature = "extended"), allow(dead_code, unused_imports))]
 
-use super::Channel;
+use super::{
+    lrc::Lrc,
+    timing::{frame_air_time, PfIrp, PulseTiming},
+    Address, Channel, ToggleState,
+};
 use crate::{Error, Result};
-use irp::{Irp, Vartable};
+use irp::Vartable;
+use std::time::Duration;
 
 /// Represents an extended command for the Extended protocol.
 #[repr(u8)]
@@ -26,6 +35,38 @@ pub enum ExtendedCommand {
     // Reserved = 0b1000,
 }
 
+impl ExtendedCommand {
+    /// Returns an upper bound of how long `repeats` copies of the command occupy the IR medium,
+    /// e.g. to schedule the next message: every copy but the last takes its 16 ms message slot.
+    ///
+    /// The channel, toggle, address and LRC bits the command doesn't fix are counted as 1 bits,
+    /// which last longest.
+    pub fn air_time(&self, repeats: u32) -> Duration {
+        // Toggle, channel and address bits set, mode Extended.
+        frame_air_time(0xB80F | ((*self as u16) << 4), repeats)
+    }
+}
+
+/// Converts the 4-bit function of an Extended message back into an `ExtendedCommand`.
+impl TryFrom<u8> for ExtendedCommand {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0b0000 => Ok(ExtendedCommand::BrakeThenFloatOnRedOutput),
+            0b0001 => Ok(ExtendedCommand::IncrementSpeedOnRedOutput),
+            0b0010 => Ok(ExtendedCommand::DecrementSpeedOnRedOutput),
+            0b0100 => Ok(ExtendedCommand::ToggleForwardOrFloatOnBlueOutput),
+            0b0110 => Ok(ExtendedCommand::ToggleAddress),
+            0b0111 => Ok(ExtendedCommand::AlignToggle),
+            _ => Err(Error::ProtocolError(format!(
+                "Invalid Extended function value {}",
+                value
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct ExtendedMessage {
     toggle: u8,
@@ -35,12 +76,15 @@ struct ExtendedMessage {
 }
 
 pub struct ExtendedProtocol {
-    irp: Irp,
-    toggle: u8,
-    address: u8, // initial value 0; toggled by ToggleAddress
+    irp: PfIrp,
+    lrc: Lrc,
+    // The address starts at 0, unless set, and is flipped by ToggleAddress.
+    toggle: ToggleState,
 }
 
-/// This IRP string now uses an explicit unit equal to the period of a 38 kHz carrier,
+/// Parses the Extended IRP for the given symbol lengths.
+///
+/// The IRP uses an explicit unit equal to the period of a 38 kHz carrier,
 /// i.e. 1000000/38000 µs (~26.315789 µs). This makes the stream values an exact
 /// multiple of the carrier period:
 ///
@@ -74,24 +118,58 @@ pub struct ExtendedProtocol {
 /// - Parameter spec: [T:0..1,E:0..1,C:0..3,A:0..1,M:0..7,D:0..15]
 ///
 ///  • Note: L is omitted here because it’s computed.
-pub const LEGO_EXTENDED_IRP: &str = "\
-{38k,33%,26.3157894737,msb}\
-<6,-10|6,-21>\
-(6,-39, T:1, E:1, C:2, a:1, M:3, F:4, L:4, 6,-39)\
-{L = 0xF^( (T*8+E*4+C)^(a*8+M)^F )}\
-[T:0..1,E:0..1,C:0..3,a:0..1,M:0..7,F:0..15]\
-";
+///
+/// The bit and start/stop lengths above are the `PulseTiming` defaults.
+pub(crate) fn extended_irp(timing: PulseTiming) -> Result<PfIrp> {
+    PfIrp::new(
+        timing,
+        "T:1, E:1, C:2, a:1, M:3, F:4, L:4",
+        "0xF^( (T*8+E*4+C)^(a*8+M)^F )",
+        "T:0..1,E:0..1,C:0..3,a:0..1,M:0..7,F:0..15",
+    )
+}
 
 impl ExtendedProtocol {
     pub fn new() -> Result<Self> {
-        let irp = Irp::parse(LEGO_EXTENDED_IRP).map_err(Error::ProtocolError)?;
+        Self::with_timing(PulseTiming::STANDARD)
+    }
+
+    /// Creates the protocol with adjusted symbol lengths.
+    pub fn with_timing(timing: PulseTiming) -> Result<Self> {
         Ok(Self {
-            irp,
-            toggle: 0,
-            address: 0,
+            irp: extended_irp(timing)?,
+            lrc: Lrc::Computed,
+            toggle: ToggleState::default(),
         })
     }
 
+    /// Changes the symbol lengths of subsequent messages, keeping the toggle and address.
+    pub fn set_timing(&mut self, timing: PulseTiming) -> Result<()> {
+        self.irp = extended_irp(timing)?;
+        Ok(())
+    }
+
+    /// Selects the LRC sent with subsequent messages.
+    pub fn set_lrc(&mut self, lrc: Lrc) {
+        self.lrc = lrc;
+    }
+
+    /// Sets the address of subsequent messages, until a `ToggleAddress` command flips it.
+    pub fn set_address(&mut self, address: Address) {
+        self.toggle.set_address(address);
+    }
+
+    /// Returns the address of subsequent messages.
+    pub fn address(&self) -> Address {
+        Address::from(self.toggle.address() != 0)
+    }
+
+    /// Shares the toggle bit and address with other protocol instances sending to the same
+    /// channel.
+    pub(crate) fn share_toggle(&mut self, toggle: ToggleState) {
+        self.toggle = toggle;
+    }
+
     fn encode_msg(&self, msg: ExtendedMessage) -> Result<Vec<u32>> {
         let mut vars = Vartable::new();
         vars.set("T".into(), msg.toggle.into());
@@ -100,30 +178,46 @@ impl ExtendedProtocol {
         vars.set("a".into(), msg.address.into());
         vars.set("M".into(), 0u8.into());
         vars.set("F".into(), msg.function.into());
-        self.irp
-            .encode_raw(vars, 1)
-            .map(|res| res.raw)
-            .map_err(Error::ProtocolError)
+        self.irp.encode(vars, self.lrc)
     }
 
     /// Encodes an Extended command.
     pub fn encode_cmd(&mut self, channel: Channel, cmd: ExtendedCommand) -> Result<Vec<u32>> {
+        self.encode_function(channel, cmd as u8)
+    }
+
+    /// Encodes an Extended message with any 4-bit function, including the reserved ones that
+    /// `ExtendedCommand` leaves out (e.g. 0b0011, 0b0101 or 0b1000), for experiments.
+    ///
+    /// The toggle bit flips as for any command, and so does the address for the `ToggleAddress`
+    /// function 0b0110.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if `function` doesn't fit in 4 bits.
+    pub fn encode_function(&mut self, channel: Channel, function: u8) -> Result<Vec<u32>> {
+        if function > 0xF {
+            return Err(Error::ProtocolError(format!(
+                "Extended function {:#b} doesn't fit in 4 bits",
+                function
+            )));
+        }
         let msg = ExtendedMessage {
-            toggle: self.toggle,
+            toggle: self.toggle.toggle(),
             channel: channel as u8,
-            address: self.address,
-            function: cmd as u8,
+            address: self.toggle.address(),
+            function,
         };
         let pulses = self.encode_msg(msg)?;
-        self.toggle ^= 1;
-        if cmd == ExtendedCommand::ToggleAddress {
-            self.address = 1 - self.address;
+        self.toggle.flip_toggle();
+        if function == ExtendedCommand::ToggleAddress as u8 {
+            self.toggle.flip_address();
         }
         Ok(pulses)
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "extended"))]
 mod tests {
     use super::*;
     use crate::protocols::Channel;
@@ -138,7 +232,7 @@ mod tests {
 }
 
 // In brickbeam/src/protocols/extended.rs (or in a dedicated test module)
-#[cfg(test)]
+#[cfg(all(test, feature = "extended"))]
 mod extended_protocol_tests {
     use super::*;
     use crate::protocols::Channel;
@@ -183,10 +277,46 @@ mod extended_protocol_tests {
         assert_eq!(pulses, expected, "Pulse sequence does not match expected");
     }
 
+    #[test]
+    fn test_extended_air_time_bounds_every_encoding() {
+        for cmd in [
+            ExtendedCommand::BrakeThenFloatOnRedOutput,
+            ExtendedCommand::ToggleAddress,
+        ] {
+            let mut proto = ExtendedProtocol::new().unwrap();
+            for channel in Channel::iter() {
+                let pulses = proto.encode_cmd(channel, cmd).unwrap();
+                assert!(crate::duration_of(&pulses) <= cmd.air_time(1));
+            }
+            assert_eq!(cmd.air_time(5) - cmd.air_time(1), Duration::from_millis(64));
+        }
+    }
+
+    #[test]
+    fn test_extended_encode_reserved_function() {
+        let mut proto = ExtendedProtocol::new().unwrap();
+        let pulses = proto.encode_function(Channel::One, 0b1000).unwrap();
+        let frame = pulses[2..34]
+            .chunks(2)
+            .fold(0u16, |frame, bit| (frame << 1) | u16::from(bit[1] > 400));
+        assert_eq!((frame >> 4) & 0xF, 0b1000);
+        assert!(crate::protocols::verify_lrc(frame));
+        assert_eq!(proto.toggle.toggle(), 1);
+
+        assert!(matches!(
+            proto.encode_function(Channel::One, 0x10),
+            Err(Error::ProtocolError(_))
+        ));
+        assert_eq!(proto.toggle.toggle(), 1);
+
+        proto.encode_function(Channel::One, 0b0110).unwrap();
+        assert_eq!(proto.toggle.address(), 1);
+    }
+
     #[test]
     fn test_extended_toggle_address_changes_internal_state() {
         let mut proto = ExtendedProtocol::new().unwrap();
-        let initial_address = proto.address;
+        let initial_address = proto.toggle.address();
         // Invoke ToggleAddress command and verify that internal address is toggled.
         let pulses = proto
             .encode_cmd(Channel::One, ExtendedCommand::ToggleAddress)
@@ -195,7 +325,7 @@ mod extended_protocol_tests {
         assert!(!pulses.is_empty());
         // Check that the address has been toggled.
         assert_eq!(
-            proto.address,
+            proto.toggle.address(),
             1 - initial_address,
             "ToggleAddress should invert the internal address"
         );
@@ -205,7 +335,8 @@ mod extended_protocol_tests {
             .encode_cmd(Channel::One, ExtendedCommand::ToggleAddress)
             .expect("Encoding should succeed");
         assert_eq!(
-            proto.address, initial_address,
+            proto.toggle.address(),
+            initial_address,
             "ToggleAddress should invert the internal address back to its original state"
         );
     }
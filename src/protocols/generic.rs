@@ -0,0 +1,277 @@
+//! # Generic Protocols
+//!
+//! This module encodes the codes of common consumer IR protocols, so the transmitter that drives
+//! the trains can also trigger IR-controlled lights, cameras or other devices of a layout:
+//!
+//! - **NEC**: 38 kHz, pulse distance coded, 8-bit address (or 16-bit for extended NEC) and 8-bit
+//!   command, each sent with a check byte.
+//!
+//! - **RC-5**: 36 kHz, bi-phase (Manchester) coded, 5-bit address and 7-bit command, with a
+//!   toggle bit that tells a new key press from a held key.
+//!
+//! Like the PF protocols, the waveforms are described as IRP and encoded by the `irp` crate.
+//! `CustomIrpProtocol` encodes any other protocol from an IRP string of the caller.
+
+use crate::{Error, Result};
+use irp::{Irp, Vartable};
+use std::collections::BTreeMap;
+
+/// NEC, one message without repeat codes. `S` is the complement of `D` for plain NEC, or the high
+/// byte of a 16-bit address for extended NEC.
+const NEC_IRP: &str =
+    "{38.4k,564}<1,-1|1,-3>(16,-8,D:8,S:8,F:8,~F:8,1,^108m)[D:0..255,S:0..255,F:0..255]";
+
+/// RC-5, one message. Command bit 6 is sent inverted as the second start bit.
+const RC5_IRP: &str =
+    "{36k,msb,889}<1,-1|-1,1>(1,~F:1:6,T:1,D:5,F:6,^114m)[D:0..31,F:0..127,T:0..1]";
+
+/// A code of a generic IR protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GenericCode {
+    /// An NEC code with an 8-bit address, sent with its complement.
+    Nec { address: u8, command: u8 },
+    /// An extended NEC code with a 16-bit address, sent low byte first.
+    NecExtended { address: u16, command: u8 },
+    /// An RC-5 code with a 5-bit address (0 to 31) and a 7-bit command (0 to 127).
+    Rc5 { address: u8, command: u8 },
+}
+
+impl GenericCode {
+    /// Returns the carrier frequency in Hz the code is modulated with.
+    pub const fn carrier(&self) -> u32 {
+        match self {
+            GenericCode::Nec { .. } | GenericCode::NecExtended { .. } => 38_000,
+            GenericCode::Rc5 { .. } => 36_000,
+        }
+    }
+}
+
+pub struct GenericProtocol {
+    nec: Irp,
+    rc5: Irp,
+    // The RC-5 toggle bit, flipped with every RC-5 message.
+    rc5_toggle: bool,
+}
+
+impl GenericProtocol {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            nec: Irp::parse(NEC_IRP).map_err(Error::ProtocolError)?,
+            rc5: Irp::parse(RC5_IRP).map_err(Error::ProtocolError)?,
+            rc5_toggle: false,
+        })
+    }
+
+    /// Encodes a code into pulse lengths in microseconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the address or command of an RC-5 code is out of range.
+    pub fn encode(&mut self, code: GenericCode) -> Result<Vec<u32>> {
+        let mut vars = Vartable::new();
+        let irp = match code {
+            GenericCode::Nec { address, command } => {
+                vars.set("D".into(), address.into());
+                vars.set("S".into(), (!address).into());
+                vars.set("F".into(), command.into());
+                &self.nec
+            }
+            GenericCode::NecExtended { address, command } => {
+                let [low, high] = address.to_le_bytes();
+                vars.set("D".into(), low.into());
+                vars.set("S".into(), high.into());
+                vars.set("F".into(), command.into());
+                &self.nec
+            }
+            GenericCode::Rc5 { address, command } => {
+                if address > 31 || command > 127 {
+                    return Err(Error::ProtocolError(format!(
+                        "RC-5 address {} or command {} is out of range 0 to 31 and 0 to 127",
+                        address, command
+                    )));
+                }
+                vars.set("D".into(), address.into());
+                vars.set("F".into(), command.into());
+                vars.set("T".into(), u8::from(self.rc5_toggle).into());
+                &self.rc5
+            }
+        };
+        let pulses = irp.encode_raw(vars, 0).map_err(Error::ProtocolError)?.raw;
+        if let GenericCode::Rc5 { .. } = code {
+            self.rc5_toggle = !self.rc5_toggle;
+        }
+        Ok(pulses)
+    }
+}
+
+/// A protocol described by a user-supplied IRP string, for IR devices brickbeam has no encoder
+/// for.
+///
+/// The variables of the IRP are set by name; parameters with a default in the IRP may be left
+/// out. The pulses are sent with `GenericRemoteController::send_custom`, or any other
+/// `PulseTransmitter`.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::CustomIrpProtocol;
+///
+/// let sony = CustomIrpProtocol::new(
+///     "{40k,600}<1,-1|2,-1>(4,-1,F:7,D:5,^45m)[D:0..31,F:0..127]",
+/// )
+/// .unwrap()
+/// .with_var("D", 1)
+/// .with_var("F", 21);
+/// let pulses = sony.encode().unwrap();
+/// assert_eq!(pulses[..2], [2400, 600]);
+/// assert_eq!(sony.carrier(), 40_000);
+/// ```
+#[derive(Debug)]
+pub struct CustomIrpProtocol {
+    irp: Irp,
+    vars: BTreeMap<String, i64>,
+    repeats: u64,
+}
+
+impl CustomIrpProtocol {
+    /// Parses an IRP string.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the IRP is malformed.
+    pub fn new(irp: &str) -> Result<Self> {
+        Ok(Self {
+            irp: Irp::parse(irp).map_err(Error::ProtocolError)?,
+            vars: BTreeMap::new(),
+            repeats: 0,
+        })
+    }
+
+    /// Sets a variable of the IRP, replacing its previous value.
+    pub fn set_var(&mut self, name: impl Into<String>, value: i64) {
+        self.vars.insert(name.into(), value);
+    }
+
+    /// Returns the protocol with a variable of the IRP set.
+    pub fn with_var(mut self, name: impl Into<String>, value: i64) -> Self {
+        self.set_var(name, value);
+        self
+    }
+
+    /// Sets how often the repeat part of the IRP (marked with `*` or `+`) is encoded after the
+    /// intro, 0 by default.
+    pub fn set_repeats(&mut self, repeats: u64) {
+        self.repeats = repeats;
+    }
+
+    /// Returns the carrier frequency in Hz of the IRP; 0 means unmodulated.
+    pub fn carrier(&self) -> u32 {
+        u32::try_from(self.irp.carrier()).unwrap_or(0)
+    }
+
+    /// Returns the duty cycle in percent of the IRP, if it specifies one.
+    pub fn duty_cycle(&self) -> Option<u8> {
+        self.irp.duty_cycle()
+    }
+
+    /// Encodes the IRP with the variables set into pulse lengths in microseconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if a parameter is missing or out of range.
+    pub fn encode(&self) -> Result<Vec<u32>> {
+        let mut vars = Vartable::new();
+        for (name, &value) in &self.vars {
+            vars.set(name.clone(), value);
+        }
+        Ok(self
+            .irp
+            .encode_raw(vars, self.repeats)
+            .map_err(Error::ProtocolError)?
+            .raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nec_encode() {
+        let mut proto = GenericProtocol::new().unwrap();
+        let pulses = proto
+            .encode(GenericCode::Nec {
+                address: 0x04,
+                command: 0x08,
+            })
+            .unwrap();
+        // Leader, 32 bits, stop mark and the gap up to 108 ms.
+        assert_eq!(pulses.len(), 68);
+        assert_eq!(pulses[..2], [9024, 4512]);
+        assert_eq!(pulses.iter().sum::<u32>(), 108_000);
+        // The address 0x04 is sent least significant bit first: 0, 0, 1.
+        assert_eq!(pulses[3..8], [564, 564, 564, 564, 1692]);
+
+        let extended = proto
+            .encode(GenericCode::NecExtended {
+                address: 0xFB04,
+                command: 0x08,
+            })
+            .unwrap();
+        assert_eq!(extended, pulses);
+    }
+
+    #[test]
+    fn test_rc5_encode_flips_toggle() {
+        let mut proto = GenericProtocol::new().unwrap();
+        let code = GenericCode::Rc5 {
+            address: 5,
+            command: 53,
+        };
+        let first = proto.encode(code).unwrap();
+        let second = proto.encode(code).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(proto.encode(code).unwrap(), first);
+        assert_eq!(first.iter().sum::<u32>(), 114_000);
+        assert!(first
+            .iter()
+            .all(|&pulse| pulse % 889 == 0 || pulse > 2 * 889));
+
+        assert!(matches!(
+            proto.encode(GenericCode::Rc5 {
+                address: 32,
+                command: 0
+            }),
+            Err(Error::ProtocolError(_))
+        ));
+        assert_eq!(code.carrier(), 36_000);
+    }
+
+    #[test]
+    fn test_custom_irp_matches_builtin_nec() {
+        let custom = CustomIrpProtocol::new(NEC_IRP)
+            .unwrap()
+            .with_var("D", 0x04)
+            .with_var("S", 0xFB)
+            .with_var("F", 0x08);
+        let builtin = GenericProtocol::new()
+            .unwrap()
+            .encode(GenericCode::Nec {
+                address: 0x04,
+                command: 0x08,
+            })
+            .unwrap();
+        assert_eq!(custom.encode().unwrap(), builtin);
+        assert_eq!(custom.carrier(), 38_400);
+
+        assert!(matches!(
+            CustomIrpProtocol::new("{38k}<1,-1|"),
+            Err(Error::ProtocolError(_))
+        ));
+        let mut missing = CustomIrpProtocol::new(NEC_IRP).unwrap();
+        assert!(matches!(missing.encode(), Err(Error::ProtocolError(_))));
+        missing.set_var("D", 0);
+        missing.set_var("S", 0);
+        missing.set_var("F", 256);
+        assert!(matches!(missing.encode(), Err(Error::ProtocolError(_))));
+    }
+}
@@ -0,0 +1,160 @@
+//! # LRC
+//!
+//! Every PF message ends with a longitudinal redundancy check: the XOR of its three payload
+//! nibbles with 0xF. Receivers silently drop messages whose LRC doesn't match, which makes
+//! deliberately broken checksums useful to probe receiver error handling and to generate
+//! negative test vectors for decoders.
+// The IRP helpers are unused when no protocol feature is enabled.
+#![cfg_attr(
+    not(any(
+        feature = "single-output",
+        feature = "combo-direct",
+        feature = "combo-pwm",
+        feature = "extended"
+    )),
+    allow(dead_code)
+)]
+
+#[cfg(feature = "std")]
+use irp::Vartable;
+
+/// IRP parameters selecting the LRC, appended to the parameter spec of every PF message.
+#[cfg(feature = "std")]
+pub(crate) const LRC_PARAMETERS: &str = "lrc_fixed:0..1=0,lrc_value:0..15=0";
+
+/// Computes the LRC of the three payload nibbles of a frame, most significant first.
+///
+/// Only the low four bits of each nibble count.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::compute_lrc;
+///
+/// // Combo PWM on channel 1: red forward 5, blue backward 3.
+/// assert_eq!(compute_lrc([0x4, 0xD, 0x5]), 0x3);
+/// ```
+pub fn compute_lrc(nibbles: [u8; 3]) -> u8 {
+    nibbles.iter().fold(0xF, |lrc, nibble| lrc ^ (nibble & 0xF))
+}
+
+/// Checks the LRC nibble of a 16-bit frame against its payload.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::verify_lrc;
+///
+/// assert!(verify_lrc(0x4D53));
+/// assert!(!verify_lrc(0x4D52));
+/// ```
+pub fn verify_lrc(frame: u16) -> bool {
+    let [high, low] = frame.to_be_bytes();
+    compute_lrc([high >> 4, high, low >> 4]) == low & 0xF
+}
+
+/// Selects the LRC nibble sent with each message.
+///
+/// # Example
+#[cfg_attr(feature = "combo-pwm", doc = "```rust")]
+#[cfg_attr(not(feature = "combo-pwm"), doc = "```ignore")]
+/// use brickbeam::{BrickBeam, Channel, ComboPwmCommand, Lrc, PulseTransmitterEmulator, Result};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::from_transmitter(PulseTransmitterEmulator);
+///     let mut train = brick_beam
+///         .create_combo_speed_remote_controller(Channel::One)?
+///         .with_lrc(Lrc::Flipped(0b0001));
+///     // A receiver must ignore this message.
+///     train.send(ComboPwmCommand::new(5, 0)?)?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lrc {
+    /// Sends the correct checksum.
+    #[default]
+    Computed,
+    /// Sends the given nibble regardless of the payload.
+    Fixed(u8),
+    /// Sends the correct checksum with the bits set in the given mask flipped.
+    Flipped(u8),
+}
+
+impl Lrc {
+    /// Builds the IRP definition of `L` from the expression computing the correct checksum.
+    #[cfg(feature = "std")]
+    pub(crate) fn definition(computed: &str) -> String {
+        // Selects between the two cases arithmetically, as `a ? b : c` would parse `b : c` as a
+        // bit field.
+        format!(
+            "{{L = lrc_fixed*lrc_value + (1-lrc_fixed)*(({})^lrc_value)}}",
+            computed
+        )
+    }
+
+    /// Returns the nibble sent in place of the correct checksum `computed`.
+    pub(crate) fn apply(self, computed: u8) -> u8 {
+        match self {
+            Lrc::Computed => computed & 0xF,
+            Lrc::Fixed(value) => value & 0xF,
+            Lrc::Flipped(mask) => (computed ^ mask) & 0xF,
+        }
+    }
+
+    /// Sets the LRC parameters of a message.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_vars(self, vars: &mut Vartable) {
+        let (fixed, value) = match self {
+            Lrc::Computed => (0, 0),
+            Lrc::Fixed(value) => (1, value & 0xF),
+            Lrc::Flipped(mask) => (0, mask & 0xF),
+        };
+        vars.set("lrc_fixed".into(), fixed);
+        vars.set("lrc_value".into(), value.into());
+    }
+}
+
+#[cfg(all(test, feature = "combo-pwm"))]
+mod tests {
+    use super::*;
+    use crate::protocols::{Channel, ComboPwmCommand, ComboPwmProtocol};
+
+    /// Reads the LRC nibble back from the spaces of its four bits.
+    fn lrc_of(pulses: &[u32]) -> u8 {
+        let bits = &pulses[pulses.len() - 10..pulses.len() - 2];
+        bits.chunks(2)
+            .fold(0, |lrc, bit| (lrc << 1) | u8::from(bit[1] > 400))
+    }
+
+    #[test]
+    fn test_compute_and_verify_lrc() {
+        assert_eq!(compute_lrc([0x0, 0x0, 0x0]), 0xF);
+        assert_eq!(compute_lrc([0x4, 0xD, 0x5]), 0x3);
+        // Only the low nibbles count.
+        assert_eq!(compute_lrc([0x94, 0xFD, 0x15]), 0x3);
+        for frame in [0x4D53u16, 0x0861, 0x0197, 0x9556] {
+            assert!(verify_lrc(frame), "{:#06x}", frame);
+            assert!(!verify_lrc(frame ^ 0x0001), "{:#06x}", frame);
+            assert!(!verify_lrc(frame ^ 0x0100), "{:#06x}", frame);
+        }
+    }
+
+    #[test]
+    fn test_lrc_overrides() {
+        let mut proto = ComboPwmProtocol::new().unwrap();
+        let cmd = ComboPwmCommand::new(5, -3).unwrap();
+        let encode = |proto: &ComboPwmProtocol| proto.encode_cmd(Channel::One, cmd).unwrap();
+        let computed = encode(&proto);
+        assert_eq!(lrc_of(&computed), 0b0011);
+
+        proto.set_lrc(Lrc::Flipped(0b0001));
+        let flipped = encode(&proto);
+        assert_eq!(lrc_of(&flipped), 0b0010);
+        assert_eq!(flipped[..26], computed[..26]);
+
+        proto.set_lrc(Lrc::Fixed(0b1010));
+        assert_eq!(lrc_of(&encode(&proto)), 0b1010);
+
+        proto.set_lrc(Lrc::Computed);
+        assert_eq!(encode(&proto), computed);
+    }
+}
@@ -24,26 +24,118 @@
 //! - **Extended**: Provides extended operations like brake-then-float, toggle address, etc.
 //! - **Single Output**: For the “Speed Remote” behavior on one output (PWM or discrete toggles).
 //!
+//! Behind the `rcx` feature, the `rcx` module encodes the unrelated serial IR protocol of LEGO®
+//! Mindstorms RCX bricks, without IRP. Behind the `generic` feature, the `generic` module encodes
+//! NEC and RC-5 codes for non-LEGO devices.
+//!
+//! `Lrc` selects the checksum sent with each message, e.g. a deliberately wrong one for testing,
+//! and `compute_lrc` and `verify_lrc` compute and check it.
+//! `Speed` names the PWM steps of Single Output and Combo PWM messages.
+//! A `PulseTrain` bundles an encoded message with its repeat count and airtime.
+//! `RawMessageFields` builds a frame of any protocol straight from its bit-level fields, and
+//! `RawPfMessage` any frame at all, down to reserved codes. `FrameBreakdown` prints the fields
+//! and bits of a frame.
+//! The `decode` module turns received pulses back into typed commands.
+//! The `scancode` module maps PF frames onto Linux rc-core scancodes.
+//! The `timing` module converts between `Duration` and the microsecond pulse units on the wire.
+//! A `ToggleState` holds the toggle bit and address, and can be shared by protocol instances
+//! talking to the same receiver.
+//!
+//! Each protocol is behind a cargo feature of the same name (`single-output`, `combo-direct`,
+//! `combo-pwm`, `extended`), all enabled by default, so slim builds compile only what they send.
+//! The Combo Direct protocol reuses the Extended IRP, whose module is compiled for either.
+//!
 //! The main re-exports let you access the command enums (e.g. `ComboPwmCommand`)
 //! and their respective protocols.
 
+#[cfg(feature = "combo-direct")]
 mod combo_direct;
+#[cfg(feature = "combo-pwm")]
 mod combo_pwm;
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+pub mod decode;
+#[cfg(any(feature = "extended", feature = "combo-direct"))]
 mod extended;
+#[cfg(feature = "generic")]
+mod generic;
+mod lrc;
+#[cfg(feature = "std")]
+mod pulse_train;
+#[cfg(feature = "std")]
+mod raw;
+#[cfg(feature = "rcx")]
+mod rcx;
+#[cfg(all(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "combo-pwm",
+    feature = "extended"
+))]
+pub mod scancode;
+#[cfg(feature = "single-output")]
 mod single_output;
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+mod speed;
+pub mod timing;
+#[cfg(any(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "extended"
+))]
+mod toggle;
 
+#[cfg(feature = "combo-direct")]
 pub(crate) use combo_direct::ComboDirectProtocol;
+#[cfg(feature = "combo-pwm")]
 pub(crate) use combo_pwm::ComboPwmProtocol;
+#[cfg(feature = "extended")]
 pub(crate) use extended::ExtendedProtocol;
+#[cfg(feature = "generic")]
+pub(crate) use generic::GenericProtocol;
+#[cfg(feature = "single-output")]
 pub(crate) use single_output::SingleOutputProtocol;
 
-pub use combo_direct::{ComboDirectCommand, DirectState};
+#[cfg(feature = "combo-direct")]
+pub use combo_direct::{ComboDirectCommand, DirectState, ToggleMode};
+#[cfg(feature = "combo-pwm")]
 pub use combo_pwm::ComboPwmCommand;
+#[cfg(feature = "extended")]
 pub use extended::ExtendedCommand;
+#[cfg(feature = "generic")]
+pub use generic::{CustomIrpProtocol, GenericCode};
+pub use lrc::{compute_lrc, verify_lrc, Lrc};
+#[cfg(feature = "std")]
+pub use pulse_train::PulseTrain;
+#[cfg(feature = "std")]
+pub use raw::{FrameBreakdown, ProtocolKind, RawMessageFields, RawPfMessage};
+#[cfg(feature = "rcx")]
+pub(crate) use rcx::RcxProtocol;
+#[cfg(feature = "rcx")]
+pub use rcx::{RcxCommand, RcxDirection, RcxMotorState, RcxMotors, RCX_BAUD, RCX_CARRIER};
+#[cfg(feature = "single-output")]
 pub use single_output::{SingleOutputCommand, SingleOutputDiscrete};
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+pub use speed::{Speed, SpeedStep};
+pub use timing::duration_of;
+#[cfg(feature = "std")]
+pub(crate) use timing::{wait_out_slot, MAX_MESSAGE_DURATION};
+#[cfg(any(
+    feature = "single-output",
+    feature = "combo-direct",
+    feature = "extended"
+))]
+pub(crate) use toggle::ToggleState;
+
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+use crate::{Error, Result};
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Channel {
     One = 0,
     Two = 1,
@@ -51,13 +143,151 @@ pub enum Channel {
     Four = 3,
 }
 
+impl Channel {
+    /// All four channels, in ascending order.
+    pub const ALL: [Channel; 4] = [Channel::One, Channel::Two, Channel::Three, Channel::Four];
+
+    /// Iterates over all four channels, in ascending order.
+    pub fn iter() -> impl Iterator<Item = Channel> {
+        Self::ALL.into_iter()
+    }
+}
+
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Output {
     RED = 0,  // A
     BLUE = 1, // B
 }
 
+impl Output {
+    /// Both outputs, red first.
+    pub const ALL: [Output; 2] = [Output::RED, Output::BLUE];
+
+    /// Iterates over both outputs, red first.
+    pub fn iter() -> impl Iterator<Item = Output> {
+        Self::ALL.into_iter()
+    }
+}
+
+/// Selects the output(s) a high-level command applies to.
+///
+/// APIs taking `impl Into<OutputSelector>` accept a plain `Output` as well. The Single Output
+/// APIs (`SpeedRemoteController::send_to`, `Broadcast::send_single_output`) expand `Both` into
+/// one message per output; the Combo ones (`ComboSpeedRemoteController::set_speed`,
+/// `DirectRemoteController::set_state`) change the selected outputs within one message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputSelector {
+    Red,
+    Blue,
+    Both,
+}
+
+impl OutputSelector {
+    /// Iterates over the selected outputs, red first.
+    pub fn outputs(self) -> impl Iterator<Item = Output> {
+        Output::iter().filter(move |&output| match self {
+            OutputSelector::Red => output == Output::RED,
+            OutputSelector::Blue => output == Output::BLUE,
+            OutputSelector::Both => true,
+        })
+    }
+}
+
+impl From<Output> for OutputSelector {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::RED => OutputSelector::Red,
+            Output::BLUE => OutputSelector::Blue,
+        }
+    }
+}
+
+/// The address bit (`a`) of a message, selecting the address space of the receiver.
+///
+/// Receivers listen on the default address space until an Extended `ToggleAddress` command
+/// switches them to the extra one, which doubles the receivers that can share a channel.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Address {
+    #[default]
+    Default = 0,
+    Extra = 1,
+}
+
+impl From<bool> for Address {
+    /// Maps a set address bit to `Address::Extra`.
+    fn from(extra: bool) -> Self {
+        if extra {
+            Address::Extra
+        } else {
+            Address::Default
+        }
+    }
+}
+
+/// One of eight logical channels: `One` to `Four` are the channels in the default address
+/// space, `Five` to `Eight` the same channels in the extra one.
+///
+/// This lets a layout run eight independent receivers without juggling address bits. APIs
+/// taking `impl Into<LogicalChannel>` accept a plain `Channel` as well.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogicalChannel {
+    One = 0,
+    Two = 1,
+    Three = 2,
+    Four = 3,
+    Five = 4,
+    Six = 5,
+    Seven = 6,
+    Eight = 7,
+}
+
+impl LogicalChannel {
+    /// All eight logical channels, in ascending order.
+    pub const ALL: [LogicalChannel; 8] = [
+        LogicalChannel::One,
+        LogicalChannel::Two,
+        LogicalChannel::Three,
+        LogicalChannel::Four,
+        LogicalChannel::Five,
+        LogicalChannel::Six,
+        LogicalChannel::Seven,
+        LogicalChannel::Eight,
+    ];
+
+    /// Iterates over all eight logical channels, in ascending order.
+    pub fn iter() -> impl Iterator<Item = LogicalChannel> {
+        Self::ALL.into_iter()
+    }
+
+    /// Returns the logical channel of `channel` in the address space `address`.
+    pub const fn new(channel: Channel, address: Address) -> Self {
+        Self::ALL[((address as usize) << 2) | channel as usize]
+    }
+
+    /// Returns the channel sent on the wire.
+    pub const fn channel(self) -> Channel {
+        Channel::ALL[self as usize & 0b11]
+    }
+
+    /// Returns the address sent on the wire.
+    pub const fn address(self) -> Address {
+        if self as u8 & 0b100 != 0 {
+            Address::Extra
+        } else {
+            Address::Default
+        }
+    }
+}
+
+impl From<Channel> for LogicalChannel {
+    fn from(channel: Channel) -> Self {
+        Self::new(channel, Address::Default)
+    }
+}
+
 /// Maps user-specified PWM speeds into protocol-specific command values.
 ///
 /// Acceptable inputs are from -7 to 8.
@@ -66,6 +296,7 @@ pub enum Output {
 ///
 /// Inputs beyond this range are clamped to the nearest valid value.
 /// (e.g., inputs greater than 8 become 7; inputs less than -7 become -7)
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
 pub fn map_speed(speed: i8) -> u8 {
     if speed == 0 || speed == 8 {
         speed as u8
@@ -85,6 +316,63 @@ pub fn map_speed(speed: i8) -> u8 {
     }
 }
 
+/// Maps a PWM speed into its protocol-specific command value like `map_speed`, but rejects
+/// speeds outside -7 to 8 instead of clamping them.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidSpeed` if `speed` is outside -7 to 8.
+///
+/// # Example
+#[cfg_attr(any(feature = "single-output", feature = "combo-pwm"), doc = "```rust")]
+#[cfg_attr(
+    not(any(feature = "single-output", feature = "combo-pwm")),
+    doc = "```ignore"
+)]
+/// use brickbeam::{try_map_speed, Error};
+///
+/// assert_eq!(try_map_speed(-3).unwrap(), 13);
+/// assert!(matches!(try_map_speed(100), Err(Error::InvalidSpeed(100))));
+/// ```
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+pub fn try_map_speed(speed: i8) -> Result<u8> {
+    check_speed(speed).map(map_speed)
+}
+
+/// Checks that a PWM speed is within -7 to 8, the range `map_speed` encodes without clamping.
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+pub(crate) fn check_speed(speed: i8) -> Result<i8> {
+    if (-7..=8).contains(&speed) {
+        Ok(speed)
+    } else {
+        Err(Error::InvalidSpeed(speed))
+    }
+}
+
+/// Maps a protocol-specific PWM nibble back into a signed speed (-7 to 8), the inverse of
+/// `map_speed`.
+///
+/// Only the low four bits of `nibble` count. The nibble 8 (brake then float) maps to 8.
+///
+/// # Example
+#[cfg_attr(any(feature = "single-output", feature = "combo-pwm"), doc = "```rust")]
+#[cfg_attr(
+    not(any(feature = "single-output", feature = "combo-pwm")),
+    doc = "```ignore"
+)]
+/// use brickbeam::{map_speed, unmap_speed};
+///
+/// assert_eq!(unmap_speed(13), -3);
+/// assert_eq!(unmap_speed(map_speed(5)), 5);
+/// ```
+#[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+pub fn unmap_speed(nibble: u8) -> i8 {
+    match nibble & 0xF {
+        n @ 0..=8 => n as i8,
+        n => n as i8 - 16,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -96,6 +384,32 @@ mod tests {
     }
 
     #[test]
+    fn test_logical_channels_map_onto_channel_and_address() {
+        for logical in LogicalChannel::iter() {
+            assert_eq!(
+                LogicalChannel::new(logical.channel(), logical.address()),
+                logical
+            );
+        }
+        assert_eq!(LogicalChannel::Five.channel(), Channel::One);
+        assert_eq!(LogicalChannel::Five.address(), Address::Extra);
+        assert_eq!(LogicalChannel::Four.channel(), Channel::Four);
+        assert_eq!(LogicalChannel::Four.address(), Address::Default);
+        assert_eq!(LogicalChannel::from(Channel::Three), LogicalChannel::Three);
+        assert_eq!(
+            LogicalChannel::new(Channel::Four, Address::Extra),
+            LogicalChannel::Eight
+        );
+    }
+
+    #[test]
+    fn test_channel_iter_covers_all_channels_in_order() {
+        let channels: Vec<u8> = Channel::iter().map(|channel| channel as u8).collect();
+        assert_eq!(channels, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    #[cfg(any(feature = "single-output", feature = "combo-pwm"))]
     fn test_map_speed_values() {
         assert_eq!(map_speed(0), 0);
         assert_eq!(map_speed(8), 8);
@@ -112,8 +426,45 @@ mod tests {
     }
 
     #[test]
+    #[cfg(any(feature = "single-output", feature = "combo-pwm"))]
     fn test_map_speed_extreme_values() {
         assert_eq!(map_speed(100), 7); // Clamp excessive positive values to 7
         assert_eq!(map_speed(-100), 9); // Clamp excessive negative values to -7 (encoded as 9)
     }
+
+    #[test]
+    #[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+    fn test_try_map_speed_rejects_instead_of_clamping() {
+        for speed in -7..=8 {
+            assert_eq!(try_map_speed(speed).unwrap(), map_speed(speed));
+        }
+        assert!(matches!(try_map_speed(9), Err(Error::InvalidSpeed(9))));
+        assert!(matches!(try_map_speed(-8), Err(Error::InvalidSpeed(-8))));
+    }
+
+    #[test]
+    #[cfg(any(feature = "single-output", feature = "combo-pwm"))]
+    fn test_unmap_speed_inverts_map_speed() {
+        for speed in -7..=8 {
+            assert_eq!(unmap_speed(map_speed(speed)), speed);
+        }
+        assert_eq!(unmap_speed(15), -1);
+        assert_eq!(unmap_speed(9), -7);
+        assert_eq!(unmap_speed(0x1D), -3);
+    }
+
+    #[test]
+    fn test_output_selector_expands_outputs() {
+        assert_eq!(Output::iter().collect::<Vec<_>>(), Output::ALL);
+        assert_eq!(
+            OutputSelector::Both.outputs().collect::<Vec<_>>(),
+            [Output::RED, Output::BLUE]
+        );
+        assert_eq!(
+            OutputSelector::from(Output::BLUE)
+                .outputs()
+                .collect::<Vec<_>>(),
+            [Output::BLUE]
+        );
+    }
 }
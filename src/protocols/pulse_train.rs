@@ -0,0 +1,77 @@
+//! # Pulse Trains
+//!
+//! A `PulseTrain` is an encoded message together with the number of times it is transmitted.
+//! It knows its own airtime, so whoever schedules it can compute when the IR medium is free again
+//! instead of sleeping for a fixed delay.
+
+use super::timing::duration_of;
+use std::time::Duration;
+
+/// An encoded message (alternating marks and spaces in microseconds) and its repeat count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PulseTrain {
+    pulses: Vec<u32>,
+    repeats: u32,
+}
+
+impl PulseTrain {
+    /// Creates a pulse train that is transmitted once.
+    pub fn new(pulses: Vec<u32>) -> Self {
+        Self { pulses, repeats: 1 }
+    }
+
+    /// Sets how many times the message is transmitted; at least once.
+    pub fn with_repeats(mut self, repeats: u32) -> Self {
+        self.repeats = repeats.max(1);
+        self
+    }
+
+    /// Returns the pulses of a single transmission.
+    pub fn pulses(&self) -> &[u32] {
+        &self.pulses
+    }
+
+    /// Returns how many times the message is transmitted.
+    pub fn repeats(&self) -> u32 {
+        self.repeats
+    }
+
+    /// Returns the airtime of a single transmission.
+    pub fn duration(&self) -> Duration {
+        duration_of(&self.pulses)
+    }
+
+    /// Returns how long all transmissions occupy the IR medium when every transmission gets a
+    /// slot of at least `message_slot` (start to start).
+    pub fn occupancy(&self, message_slot: Duration) -> Duration {
+        self.duration().max(message_slot) * self.repeats
+    }
+
+    /// Consumes the pulse train, returning the pulses of a single transmission.
+    pub fn into_pulses(self) -> Vec<u32> {
+        self.pulses
+    }
+}
+
+impl From<Vec<u32>> for PulseTrain {
+    fn from(pulses: Vec<u32>) -> Self {
+        Self::new(pulses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pulse_train_duration_and_occupancy() {
+        let train = PulseTrain::new(vec![157, 1026, 157, 1026]).with_repeats(3);
+        assert_eq!(train.duration(), Duration::from_micros(2366));
+        assert_eq!(
+            train.occupancy(Duration::from_millis(16)),
+            Duration::from_millis(48)
+        );
+        assert_eq!(train.occupancy(Duration::ZERO), Duration::from_micros(7098));
+        assert_eq!(PulseTrain::from(vec![1]).with_repeats(0).repeats(), 1);
+    }
+}
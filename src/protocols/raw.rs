@@ -0,0 +1,498 @@
+//! # Raw Messages
+//!
+//! Builds PF frames from their bit-level fields, for tooling, bridges and tests that already know
+//! the bits and don't need a controller to track toggle bits or map speeds.
+//!
+//! A frame consists of four nibbles, most significant first: `T E C C`, `a M M M`, `D D D D` and
+//! the LRC, which is always computed here. The protocol decides the escape bit `E` and which mode
+//! bits `M` are valid:
+//!
+//! | `ProtocolKind` | `E` | `mode`                        | `data`              |
+//! |----------------|-----|-------------------------------|---------------------|
+//! | `Extended`     | 0   | `0b000`                       | the function        |
+//! | `ComboDirect`  | 0   | `0b001`                       | blue and red states |
+//! | `SingleOutput` | 0   | `0b1MO`: PWM/discrete, output | PWM step or command |
+//! | `ComboPwm`     | 1   | the blue speed nibble         | the red nibble      |
+//!
+//! Combo PWM messages have no toggle bit: their first bit is the address, and the nibble that
+//! carries the address and mode elsewhere holds the blue speed.
+//!
+//! `RawPfMessage` goes one level lower: it sets every bit of the frame, including the escape bit,
+//! without checking them against a protocol, so reserved and undocumented codes can be tried out.
+//!
+//! `FrameBreakdown` goes the other way: it splits a frame into its fields and prints them with the
+//! bit string, to compare a message with the trace of a logic analyzer.
+
+use super::{
+    compute_lrc,
+    timing::{PulseTiming, FRAME_PULSES},
+    verify_lrc, Address, Channel, Lrc,
+};
+use crate::{Error, Result};
+use std::fmt;
+
+/// The PF protocol a raw message belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolKind {
+    SingleOutput,
+    ComboDirect,
+    ComboPwm,
+    Extended,
+}
+
+/// The bit-level fields of a PF message, see the module documentation for their layout.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{Channel, ProtocolKind, RawMessageFields};
+///
+/// // Single Output PWM, blue output forward 5.
+/// let fields = RawMessageFields {
+///     toggle: true,
+///     channel: Channel::Two,
+///     address: false,
+///     mode: 0b101,
+///     data: 5,
+/// };
+/// assert_eq!(fields.frame(ProtocolKind::SingleOutput).unwrap(), 0x9556);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawMessageFields {
+    /// The toggle bit; must be `false` for Combo PWM.
+    pub toggle: bool,
+    pub channel: Channel,
+    /// The address bit, selecting the second address space of the receiver.
+    pub address: bool,
+    /// The three mode bits, or the blue speed nibble for Combo PWM.
+    pub mode: u8,
+    /// The data nibble, or the red speed nibble for Combo PWM.
+    pub data: u8,
+}
+
+impl RawMessageFields {
+    /// Assembles the 16-bit frame of the message, including its LRC.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if a field doesn't fit the protocol.
+    pub fn frame(&self, kind: ProtocolKind) -> Result<u16> {
+        let valid_mode = match kind {
+            ProtocolKind::Extended => self.mode == 0b000,
+            ProtocolKind::ComboDirect => self.mode == 0b001,
+            ProtocolKind::SingleOutput => (0b100..=0b111).contains(&self.mode),
+            ProtocolKind::ComboPwm => self.mode <= 0xF,
+        };
+        if !valid_mode {
+            return Err(Error::ProtocolError(format!(
+                "Mode {:#05b} is not valid for {:?} messages",
+                self.mode, kind
+            )));
+        }
+        if self.data > 0xF {
+            return Err(Error::ProtocolError(format!(
+                "Data {} doesn't fit into a nibble",
+                self.data
+            )));
+        }
+        let channel = self.channel as u16;
+        let (nibble1, nibble2) = match kind {
+            ProtocolKind::ComboPwm if self.toggle => {
+                return Err(Error::ProtocolError(
+                    "Combo PWM messages have no toggle bit".into(),
+                ))
+            }
+            ProtocolKind::ComboPwm => (
+                (u16::from(self.address) << 3) | 0b100 | channel,
+                u16::from(self.mode),
+            ),
+            _ => (
+                (u16::from(self.toggle) << 3) | channel,
+                (u16::from(self.address) << 3) | u16::from(self.mode),
+            ),
+        };
+        let data = u16::from(self.data);
+        let lrc = u16::from(compute_lrc([nibble1 as u8, nibble2 as u8, self.data]));
+        Ok((nibble1 << 12) | (nibble2 << 8) | (data << 4) | lrc)
+    }
+
+    /// Splits the frame of the message back into its fields, for printing.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if a field doesn't fit the protocol.
+    pub fn describe(&self, kind: ProtocolKind) -> Result<FrameBreakdown> {
+        self.frame(kind).map(FrameBreakdown::new)
+    }
+}
+
+/// A PF message built bit by bit, for experiments with reserved or undocumented codes.
+///
+/// The frame is `T E C C | a M M M | D D D D | LRC`. Unlike `RawMessageFields`, no field is
+/// checked against a protocol, only that it fits its bits; the LRC is computed unless `with_lrc`
+/// says otherwise. A Combo PWM message, for instance, is the escape bit set, the address in the
+/// toggle bit and the blue speed nibble spread over `a M M M`.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{Address, Channel, RawPfMessage};
+///
+/// // Extended message with the reserved function 0b0011.
+/// let message = RawPfMessage::new(Channel::Three)
+///     .with_address(Address::Extra)
+///     .with_data(0b0011);
+/// assert_eq!(message.frame().unwrap(), 0x2836);
+/// assert_eq!(message.pulses().unwrap().len(), 36);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawPfMessage {
+    toggle: bool,
+    escape: bool,
+    channel: Channel,
+    address: Address,
+    mode: u8,
+    data: u8,
+    lrc: Lrc,
+}
+
+impl RawPfMessage {
+    /// Creates a message on `channel` with every other bit cleared and a computed LRC.
+    pub const fn new(channel: Channel) -> Self {
+        Self {
+            toggle: false,
+            escape: false,
+            channel,
+            address: Address::Default,
+            mode: 0,
+            data: 0,
+            lrc: Lrc::Computed,
+        }
+    }
+
+    /// Sets the toggle bit `T`.
+    pub const fn with_toggle(mut self, toggle: bool) -> Self {
+        self.toggle = toggle;
+        self
+    }
+
+    /// Sets the escape bit `E`.
+    pub const fn with_escape(mut self, escape: bool) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// Sets the channel bits `C C`.
+    pub const fn with_channel(mut self, channel: Channel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Sets the address bit `a`.
+    pub const fn with_address(mut self, address: Address) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Sets the three mode bits `M M M` (0 to 7).
+    pub const fn with_mode(mut self, mode: u8) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the data nibble `D D D D` (0 to 15).
+    pub const fn with_data(mut self, data: u8) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// Selects the LRC nibble, e.g. a wrong one to probe a receiver.
+    pub const fn with_lrc(mut self, lrc: Lrc) -> Self {
+        self.lrc = lrc;
+        self
+    }
+
+    /// Assembles the 16-bit frame of the message, including its LRC.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the mode or data doesn't fit its bits.
+    pub fn frame(&self) -> Result<u16> {
+        if self.mode > 0b111 {
+            return Err(Error::ProtocolError(format!(
+                "Mode {} doesn't fit into three bits",
+                self.mode
+            )));
+        }
+        if self.data > 0xF {
+            return Err(Error::ProtocolError(format!(
+                "Data {} doesn't fit into a nibble",
+                self.data
+            )));
+        }
+        let nibble1 =
+            (u8::from(self.toggle) << 3) | (u8::from(self.escape) << 2) | self.channel as u8;
+        let nibble2 = ((self.address as u8) << 3) | self.mode;
+        let lrc = self.lrc.apply(compute_lrc([nibble1, nibble2, self.data]));
+        Ok(u16::from_be_bytes([
+            (nibble1 << 4) | nibble2,
+            (self.data << 4) | lrc,
+        ]))
+    }
+
+    /// Encodes the message into its pulse lengths in µs, with the standard symbol lengths.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the mode or data doesn't fit its bits.
+    pub fn pulses(&self) -> Result<Vec<u32>> {
+        self.pulses_with_timing(PulseTiming::STANDARD)
+    }
+
+    /// Encodes the message into its pulse lengths in µs, with the given symbol lengths.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the mode or data doesn't fit its bits.
+    pub fn pulses_with_timing(&self, timing: PulseTiming) -> Result<Vec<u32>> {
+        let pulses: [u32; FRAME_PULSES] = timing.encode_frame(self.frame()?);
+        Ok(pulses.to_vec())
+    }
+
+    /// Splits the frame of the message back into its fields, for printing.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if the mode or data doesn't fit its bits.
+    pub fn describe(&self) -> Result<FrameBreakdown> {
+        self.frame().map(FrameBreakdown::new)
+    }
+}
+
+/// The fields of a 16-bit PF frame, printed as a field breakdown followed by the bit string.
+///
+/// Frames with the escape bit set are printed in the Combo PWM layout, with the address in place
+/// of the toggle bit and the blue (`B`) and red (`R`) speed nibbles.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::FrameBreakdown;
+///
+/// // Single Output PWM, blue output forward 5, on channel 2.
+/// let fields = FrameBreakdown::new(0x9556);
+/// assert_eq!(
+///     fields.to_string(),
+///     "T=1 E=0 C=01 (channel 2) a=0 M=101 D=0101 L=0110 (valid) | 1001 0101 0101 0110"
+/// );
+///
+/// // Combo PWM on channel 1: red forward 5, blue backward 3.
+/// assert_eq!(
+///     FrameBreakdown::new(0x4D53).to_string(),
+///     "a=0 E=1 C=00 (channel 1) B=1101 R=0101 L=0011 (valid) | 0100 1101 0101 0011"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameBreakdown {
+    frame: u16,
+}
+
+impl FrameBreakdown {
+    /// Wraps a frame, including its LRC.
+    pub const fn new(frame: u16) -> Self {
+        Self { frame }
+    }
+
+    const fn nibble(&self, index: u16) -> u8 {
+        ((self.frame >> (12 - 4 * index)) & 0xF) as u8
+    }
+
+    /// Returns the frame.
+    pub const fn frame(&self) -> u16 {
+        self.frame
+    }
+
+    /// Returns the first bit: the toggle bit, or the address bit of a Combo PWM frame.
+    pub const fn toggle(&self) -> bool {
+        self.nibble(0) & 0b1000 != 0
+    }
+
+    /// Returns the escape bit, set for Combo PWM frames.
+    pub const fn escape(&self) -> bool {
+        self.nibble(0) & 0b100 != 0
+    }
+
+    pub const fn channel(&self) -> Channel {
+        Channel::ALL[(self.nibble(0) & 0b11) as usize]
+    }
+
+    /// Returns the address bit, or the top bit of the blue speed of a Combo PWM frame.
+    pub const fn address(&self) -> bool {
+        self.nibble(1) & 0b1000 != 0
+    }
+
+    /// Returns the three mode bits.
+    pub const fn mode(&self) -> u8 {
+        self.nibble(1) & 0b111
+    }
+
+    /// Returns the data nibble.
+    pub const fn data(&self) -> u8 {
+        self.nibble(2)
+    }
+
+    /// Returns the LRC nibble as sent.
+    pub const fn lrc(&self) -> u8 {
+        self.nibble(3)
+    }
+
+    /// Returns whether the LRC matches the other fields.
+    pub fn lrc_valid(&self) -> bool {
+        verify_lrc(self.frame)
+    }
+}
+
+impl From<u16> for FrameBreakdown {
+    fn from(frame: u16) -> Self {
+        Self::new(frame)
+    }
+}
+
+impl fmt::Display for FrameBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let first = u8::from(self.toggle());
+        let channel = self.channel() as u8;
+        if self.escape() {
+            write!(f, "a={} E=1 ", first)?;
+        } else {
+            write!(f, "T={} E=0 ", first)?;
+        }
+        write!(f, "C={:02b} (channel {}) ", channel, channel + 1)?;
+        if self.escape() {
+            write!(f, "B={:04b} R={:04b} ", self.nibble(1), self.data())?;
+        } else {
+            write!(
+                f,
+                "a={} M={:03b} D={:04b} ",
+                u8::from(self.address()),
+                self.mode(),
+                self.data()
+            )?;
+        }
+        let validity = if self.lrc_valid() { "valid" } else { "invalid" };
+        write!(f, "L={:04b} ({}) |", self.lrc(), validity)?;
+        for index in 0..4 {
+            write!(f, " {:04b}", self.nibble(index))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(mode: u8, data: u8) -> RawMessageFields {
+        RawMessageFields {
+            toggle: false,
+            channel: Channel::One,
+            address: false,
+            mode,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_frames_of_each_protocol() {
+        // Combo PWM on channel 1: red forward 5, blue backward 3.
+        assert_eq!(
+            fields(0xD, 5).frame(ProtocolKind::ComboPwm).unwrap(),
+            0x4D53
+        );
+        // Extended ToggleAddress with the address bit set.
+        let toggle_address = RawMessageFields {
+            address: true,
+            ..fields(0, 0b0110)
+        };
+        assert_eq!(
+            toggle_address.frame(ProtocolKind::Extended).unwrap(),
+            0x0861
+        );
+        // Combo Direct: red forward, blue backward.
+        assert_eq!(
+            fields(1, 0b1001).frame(ProtocolKind::ComboDirect).unwrap(),
+            0x0197
+        );
+    }
+
+    #[test]
+    fn test_frame_breakdown_fields() {
+        let message = RawPfMessage::new(Channel::Four)
+            .with_toggle(true)
+            .with_address(Address::Extra)
+            .with_data(0b0110)
+            .with_lrc(Lrc::Fixed(0));
+        let breakdown = message.describe().unwrap();
+        assert!(breakdown.toggle() && !breakdown.escape() && breakdown.address());
+        assert_eq!(breakdown.channel(), Channel::Four);
+        assert_eq!((breakdown.mode(), breakdown.data()), (0, 0b0110));
+        assert_eq!(breakdown.lrc(), 0);
+        assert!(!breakdown.lrc_valid());
+        assert_eq!(
+            breakdown.to_string(),
+            "T=1 E=0 C=11 (channel 4) a=1 M=000 D=0110 L=0000 (invalid) | 1011 1000 0110 0000"
+        );
+        assert_eq!(
+            fields(0b001, 0b1001)
+                .describe(ProtocolKind::ComboDirect)
+                .unwrap(),
+            FrameBreakdown::from(0x0197)
+        );
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        assert!(fields(0, 0).frame(ProtocolKind::SingleOutput).is_err());
+        assert!(fields(1, 0).frame(ProtocolKind::Extended).is_err());
+        assert!(fields(0, 16).frame(ProtocolKind::Extended).is_err());
+        let toggled = RawMessageFields {
+            toggle: true,
+            ..fields(0, 0)
+        };
+        assert!(toggled.frame(ProtocolKind::ComboPwm).is_err());
+    }
+
+    #[test]
+    fn test_raw_pf_message_matches_typed_fields() {
+        // Single Output PWM, blue output forward 5, from the example above.
+        let message = RawPfMessage::new(Channel::Two)
+            .with_toggle(true)
+            .with_mode(0b101)
+            .with_data(5);
+        assert_eq!(message.frame().unwrap(), 0x9556);
+        // Combo PWM: red forward 5, blue backward 3, with the blue nibble 0xD in `a M M M`.
+        let combo = RawPfMessage::new(Channel::One)
+            .with_escape(true)
+            .with_address(Address::Extra)
+            .with_mode(0b101)
+            .with_data(5);
+        assert_eq!(
+            combo.frame().unwrap(),
+            fields(0xD, 5).frame(ProtocolKind::ComboPwm).unwrap()
+        );
+        assert_eq!(
+            combo.pulses().unwrap(),
+            PulseTiming::STANDARD.encode_frame(0x4D53)
+        );
+    }
+
+    #[test]
+    fn test_raw_pf_message_lrc_and_limits() {
+        let message = RawPfMessage::new(Channel::Four).with_data(0b1010);
+        assert_eq!(message.frame().unwrap(), 0x30A6);
+        let fixed = message.with_lrc(Lrc::Fixed(0));
+        assert_eq!(fixed.frame().unwrap(), 0x30A0);
+        let flipped = message.with_lrc(Lrc::Flipped(0b0001));
+        assert_eq!(flipped.frame().unwrap(), 0x30A7);
+
+        assert!(message.with_mode(8).frame().is_err());
+        assert!(message.with_data(16).pulses().is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! # RCX Protocol
+//!
+//! This module implements the IR protocol of the LEGO® Mindstorms RCX brick and its IR tower.
+//! Unlike the PF protocols, it is a plain serial line sent over infrared:
+//!
+//! - 2400 baud, 8 data bits (least significant bit first), odd parity, 1 stop bit,
+//!
+//! - a 0 bit (including the start bit) is a burst of carrier for one bit time, a 1 bit (including
+//!   the idle stop bit) is silence,
+//!
+//! - the IR tower modulates at 76 kHz; the RCX receivers also accept 38 kHz.
+//!
+//! A message is the header `0x55 0xFF 0x00`, followed by the opcode and its parameters, each
+//! byte followed by its complement, and finally the checksum (the sum of the opcode and
+//! parameters) and its complement.
+//!
+//! The RCX ignores a message identical to the previous one, so the protocol alternates bit 3
+//! (`0x08`) of the opcode on every message, like a PF toggle bit.
+
+use crate::{Error, Result};
+
+/// The header preceding every RCX message.
+const HEADER: [u8; 3] = [0x55, 0xFF, 0x00];
+
+/// The opcode bit the RCX uses to tell repeated messages apart.
+const TOGGLE_BIT: u8 = 0x08;
+
+/// The default bit rate of the RCX and its IR tower.
+pub const RCX_BAUD: u32 = 2400;
+
+/// The carrier frequency of the IR tower in Hz.
+pub const RCX_CARRIER: u32 = 76_000;
+
+/// The motor outputs of an RCX, combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RcxMotors(u8);
+
+impl RcxMotors {
+    pub const A: Self = Self(0b001);
+    pub const B: Self = Self(0b010);
+    pub const C: Self = Self(0b100);
+    pub const ALL: Self = Self(0b111);
+
+    /// Returns the bit mask of the outputs, output A being bit 0.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+}
+
+impl std::ops::BitOr for RcxMotors {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Whether an RCX motor output is driven.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RcxMotorState {
+    Float = 0x00,
+    Off = 0x40,
+    On = 0x80,
+}
+
+/// The direction of an RCX motor output.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RcxDirection {
+    Reverse = 0x00,
+    Flip = 0x40,
+    Forward = 0x80,
+}
+
+/// A direct command of the RCX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RcxCommand {
+    /// Checks whether the RCX is in range; it answers with a reply the tower can receive.
+    Alive,
+    /// Plays one of the six built-in sounds (0 to 5).
+    PlaySystemSound(u8),
+    /// Switches motor outputs on, off (braking) or lets them float.
+    SetMotorState(RcxMotors, RcxMotorState),
+    /// Sets the direction of motor outputs.
+    SetMotorDirection(RcxMotors, RcxDirection),
+    /// Sets the power of motor outputs (0 to 7).
+    SetMotorPower(RcxMotors, u8),
+    /// Starts one of the ten program tasks (0 to 9).
+    StartTask(u8),
+    /// Stops one of the ten program tasks (0 to 9).
+    StopTask(u8),
+    /// Stops all running tasks.
+    StopAllTasks,
+    /// Turns the RCX off.
+    PowerOff,
+}
+
+impl RcxCommand {
+    /// Returns the opcode (without the toggle bit) and the parameters of the command.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if a parameter is out of range.
+    fn opcode_and_params(self) -> Result<(u8, Vec<u8>)> {
+        let check = |name: &str, value: u8, max: u8| {
+            if value > max {
+                Err(Error::ProtocolError(format!(
+                    "RCX {} {} is out of range 0 to {}",
+                    name, value, max
+                )))
+            } else {
+                Ok(value)
+            }
+        };
+        Ok(match self {
+            RcxCommand::Alive => (0x10, vec![]),
+            RcxCommand::PlaySystemSound(sound) => (0x51, vec![check("sound", sound, 5)?]),
+            RcxCommand::SetMotorState(motors, state) => (0x21, vec![motors.bits() | state as u8]),
+            RcxCommand::SetMotorDirection(motors, direction) => {
+                (0xE1, vec![motors.bits() | direction as u8])
+            }
+            // Source 2 takes the power as a constant.
+            RcxCommand::SetMotorPower(motors, power) => {
+                (0x13, vec![motors.bits(), 2, check("power", power, 7)?])
+            }
+            RcxCommand::StartTask(task) => (0x71, vec![check("task", task, 9)?]),
+            RcxCommand::StopTask(task) => (0x81, vec![check("task", task, 9)?]),
+            RcxCommand::StopAllTasks => (0x50, vec![]),
+            RcxCommand::PowerOff => (0x60, vec![]),
+        })
+    }
+}
+
+pub struct RcxProtocol {
+    baud: u32,
+    toggle: bool,
+}
+
+impl RcxProtocol {
+    pub fn new() -> Self {
+        Self {
+            baud: RCX_BAUD,
+            toggle: false,
+        }
+    }
+
+    /// Changes the bit rate of subsequent messages, e.g. to 4800 baud for an RCX 2.0.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` for a bit rate of 0.
+    pub fn set_baud(&mut self, baud: u32) -> Result<()> {
+        if baud == 0 {
+            return Err(Error::ProtocolError("RCX baud rate must not be 0".into()));
+        }
+        self.baud = baud;
+        Ok(())
+    }
+
+    /// Returns the bit rate of subsequent messages.
+    pub fn baud(&self) -> u32 {
+        self.baud
+    }
+
+    /// Encodes an RCX command.
+    pub fn encode_cmd(&mut self, cmd: RcxCommand) -> Result<Vec<u32>> {
+        let (opcode, params) = cmd.opcode_and_params()?;
+        self.encode_opcode(opcode, &params)
+    }
+
+    /// Encodes a message with any opcode and parameters, e.g. for commands `RcxCommand` lacks.
+    ///
+    /// Bit 3 of the opcode is replaced by the toggle bit, which flips with every message.
+    pub fn encode_opcode(&mut self, opcode: u8, params: &[u8]) -> Result<Vec<u32>> {
+        let opcode = if self.toggle {
+            opcode | TOGGLE_BIT
+        } else {
+            opcode & !TOGGLE_BIT
+        };
+        let pulses = encode_serial(&message_bytes(opcode, params), self.baud);
+        self.toggle = !self.toggle;
+        Ok(pulses)
+    }
+}
+
+/// Frames an opcode and its parameters into the bytes of an RCX message.
+fn message_bytes(opcode: u8, params: &[u8]) -> Vec<u8> {
+    let mut bytes = HEADER.to_vec();
+    let mut checksum = 0u8;
+    for &byte in std::iter::once(&opcode).chain(params) {
+        bytes.extend([byte, !byte]);
+        checksum = checksum.wrapping_add(byte);
+    }
+    bytes.extend([checksum, !checksum]);
+    bytes
+}
+
+/// Encodes bytes as 8O1 serial frames into alternating burst and gap lengths in µs.
+///
+/// Runs of equal bits merge into one burst or gap. The edges are rounded from the exact bit
+/// times, so the rounding errors of 417 µs bits don't add up over a message.
+fn encode_serial(bytes: &[u8], baud: u32) -> Vec<u32> {
+    let bits = bytes.iter().flat_map(|&byte| {
+        let data = (0..8).map(move |i| (byte >> i) & 1 == 1);
+        let parity = byte.count_ones() % 2 == 0;
+        std::iter::once(false).chain(data).chain([parity, true])
+    });
+    let edge = |bit: u64| ((bit * 1_000_000 + u64::from(baud) / 2) / u64::from(baud)) as u32;
+
+    let mut pulses = Vec::new();
+    let mut level = false;
+    let mut run_start = 0;
+    for (index, bit) in bits.enumerate() {
+        if bit != level {
+            pulses.push(edge(index as u64) - edge(run_start));
+            run_start = index as u64;
+            level = bit;
+        }
+    }
+    // Every frame ends with its stop bit, so the message ends with a gap.
+    let len = bytes.len() as u64 * 11;
+    pulses.push(edge(len) - edge(run_start));
+    pulses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes serial pulses back into bytes, checking the start, parity and stop bits.
+    fn decode_serial(pulses: &[u32], baud: u32) -> Vec<u8> {
+        let bit_time = 1_000_000.0 / f64::from(baud);
+        let mut bits = Vec::new();
+        for (index, &pulse) in pulses.iter().enumerate() {
+            let count = (f64::from(pulse) / bit_time).round() as usize;
+            bits.extend(std::iter::repeat_n(index % 2 == 1, count));
+        }
+        bits.chunks(11)
+            .map(|frame| {
+                assert!(!frame[0] && frame[10], "start or stop bit missing");
+                let byte = (0..8).fold(0u8, |byte, i| byte | u8::from(frame[1 + i]) << i);
+                assert_eq!((byte.count_ones() + u32::from(frame[9])) % 2, 1);
+                byte
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rcx_message_framing() {
+        assert_eq!(
+            message_bytes(0x51, &[0x02]),
+            [0x55, 0xFF, 0x00, 0x51, 0xAE, 0x02, 0xFD, 0x53, 0xAC]
+        );
+    }
+
+    #[test]
+    fn test_rcx_encode_round_trip_and_toggle() {
+        let mut proto = RcxProtocol::new();
+        let first = proto.encode_cmd(RcxCommand::Alive).unwrap();
+        let second = proto.encode_cmd(RcxCommand::Alive).unwrap();
+        assert_eq!(
+            decode_serial(&first, RCX_BAUD),
+            [0x55, 0xFF, 0x00, 0x10, 0xEF, 0x10, 0xEF]
+        );
+        assert_eq!(
+            decode_serial(&second, RCX_BAUD),
+            [0x55, 0xFF, 0x00, 0x18, 0xE7, 0x18, 0xE7]
+        );
+        // 7 frames of 11 bits at 2400 baud.
+        assert_eq!(first.iter().sum::<u32>(), 32_083);
+    }
+
+    #[test]
+    fn test_rcx_motor_commands_and_ranges() {
+        let (opcode, params) =
+            RcxCommand::SetMotorState(RcxMotors::A | RcxMotors::C, RcxMotorState::On)
+                .opcode_and_params()
+                .unwrap();
+        assert_eq!((opcode, params), (0x21, vec![0x85]));
+        let (_, params) = RcxCommand::SetMotorPower(RcxMotors::B, 7)
+            .opcode_and_params()
+            .unwrap();
+        assert_eq!(params, [0x02, 0x02, 0x07]);
+
+        let mut proto = RcxProtocol::new();
+        assert!(matches!(
+            proto.encode_cmd(RcxCommand::SetMotorPower(RcxMotors::ALL, 8)),
+            Err(Error::ProtocolError(_))
+        ));
+        assert!(matches!(proto.set_baud(0), Err(Error::ProtocolError(_))));
+        proto.set_baud(4800).unwrap();
+        let pulses = proto.encode_cmd(RcxCommand::StopAllTasks).unwrap();
+        assert_eq!(decode_serial(&pulses, 4800)[3], 0x50);
+    }
+}
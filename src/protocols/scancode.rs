@@ -0,0 +1,223 @@
+//! # rc-core Scancodes
+//!
+//! Linux rc-core decoders (including BPF decoders attached with `ir-keytable -e`) report each
+//! received message as a scancode plus a toggle flag, which a keymap then translates into an input
+//! keycode. This module defines how a 16-bit PF frame maps onto such a scancode, so that PF remotes
+//! can feed standard Linux input handling alongside brickbeam's own tooling.
+//!
+//! ## Mapping
+//!
+//! A PF frame consists of four nibbles, most significant first: `T/a E C C`, `a M M M`, `D D D D`
+//! and the LRC. The scancode is the frame without its LRC nibble (`frame >> 4`), i.e. 12 bits:
+//!
+//! - For messages with the escape bit cleared (Extended, Combo Direct and Single Output), the top
+//!   bit is the toggle bit. It is cleared in the scancode and reported separately, so repeated
+//!   presses of the same button map to the same key.
+//! - For Combo PWM (escape bit set) the top bit is the address bit and stays part of the scancode.
+//!
+//! Both directions are a shift and a mask, which keeps them trivial to implement in a BPF decoder.
+
+use super::{
+    compute_lrc, unmap_speed, verify_lrc, Channel, DirectState, ExtendedCommand, Output,
+    SingleOutputDiscrete,
+};
+use std::fmt::Write;
+
+const ESCAPE_BIT: u16 = 0x400;
+const TOGGLE_BIT: u16 = 0x800;
+
+/// A scancode together with a human readable description of the message it represents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScancodeEntry {
+    /// The rc-core scancode (12 significant bits).
+    pub scancode: u16,
+    /// A description of the message, e.g. `One RED PWM(5)`.
+    pub name: String,
+}
+
+fn lrc(payload: u16) -> u16 {
+    let nibble = |shift: u16| (payload >> shift) as u8;
+    u16::from(compute_lrc([nibble(8), nibble(4), nibble(0)]))
+}
+
+/// Converts a received 16-bit PF frame into its scancode and toggle flag.
+///
+/// Returns `None` if the LRC of the frame does not match.
+pub fn frame_to_scancode(frame: u16) -> Option<(u16, bool)> {
+    let payload = frame >> 4;
+    if !verify_lrc(frame) {
+        return None;
+    }
+    if payload & ESCAPE_BIT == 0 {
+        Some((payload & !TOGGLE_BIT, payload & TOGGLE_BIT != 0))
+    } else {
+        Some((payload, false))
+    }
+}
+
+/// Converts a scancode and toggle flag back into a complete 16-bit PF frame, including its LRC.
+///
+/// The toggle flag is ignored for Combo PWM scancodes, which have no toggle bit.
+pub fn scancode_to_frame(scancode: u16, toggle: bool) -> u16 {
+    let mut payload = scancode & 0xFFF;
+    if payload & ESCAPE_BIT == 0 && toggle {
+        payload |= TOGGLE_BIT;
+    }
+    (payload << 4) | lrc(payload)
+}
+
+fn scancode(channel: Channel, nibble2: u8, data: u8) -> u16 {
+    ((channel as u16) << 8) | (u16::from(nibble2) << 4) | u16::from(data)
+}
+
+/// Generates the scancodes of every documented message on every channel (address 0).
+///
+/// The table covers the Extended, Combo Direct, Single Output (PWM and discrete) and Combo PWM
+/// messages and is meant as a starting point for rc-core keymaps.
+pub fn scancode_table() -> Vec<ScancodeEntry> {
+    const EXTENDED: [ExtendedCommand; 6] = [
+        ExtendedCommand::BrakeThenFloatOnRedOutput,
+        ExtendedCommand::IncrementSpeedOnRedOutput,
+        ExtendedCommand::DecrementSpeedOnRedOutput,
+        ExtendedCommand::ToggleForwardOrFloatOnBlueOutput,
+        ExtendedCommand::ToggleAddress,
+        ExtendedCommand::AlignToggle,
+    ];
+    const DIRECT: [DirectState; 4] = [
+        DirectState::Float,
+        DirectState::Forward,
+        DirectState::Backward,
+        DirectState::Brake,
+    ];
+    const DISCRETE: [SingleOutputDiscrete; 16] = [
+        SingleOutputDiscrete::ToggleFullForward,
+        SingleOutputDiscrete::ToggleDirection,
+        SingleOutputDiscrete::IncrementNumericalPwm,
+        SingleOutputDiscrete::DecrementNumericalPwm,
+        SingleOutputDiscrete::IncrementPwm,
+        SingleOutputDiscrete::DecrementPwm,
+        SingleOutputDiscrete::FullForward,
+        SingleOutputDiscrete::FullBackward,
+        SingleOutputDiscrete::ToggleFullForwardBackward,
+        SingleOutputDiscrete::ClearC1,
+        SingleOutputDiscrete::SetC1,
+        SingleOutputDiscrete::ToggleC1,
+        SingleOutputDiscrete::ClearC2,
+        SingleOutputDiscrete::SetC2,
+        SingleOutputDiscrete::ToggleC2,
+        SingleOutputDiscrete::ToggleFullBackward,
+    ];
+
+    let mut table = Vec::new();
+    for channel in Channel::iter() {
+        for cmd in EXTENDED {
+            table.push(ScancodeEntry {
+                scancode: scancode(channel, 0b0000, cmd as u8),
+                name: format!("{:?} Extended {:?}", channel, cmd),
+            });
+        }
+        for blue in DIRECT {
+            for red in DIRECT {
+                table.push(ScancodeEntry {
+                    scancode: scancode(channel, 0b0001, ((blue as u8) << 2) | red as u8),
+                    name: format!("{:?} ComboDirect red={:?} blue={:?}", channel, red, blue),
+                });
+            }
+        }
+        for output in Output::iter() {
+            for data in 0..16u8 {
+                table.push(ScancodeEntry {
+                    scancode: scancode(channel, 0b0100 | output as u8, data),
+                    name: format!("{:?} {:?} PWM({})", channel, output, unmap_speed(data)),
+                });
+            }
+            for cmd in DISCRETE {
+                table.push(ScancodeEntry {
+                    scancode: scancode(channel, 0b0110 | output as u8, cmd as u8),
+                    name: format!("{:?} {:?} {:?}", channel, output, cmd),
+                });
+            }
+        }
+        for blue in 0..16u8 {
+            for red in 0..16u8 {
+                table.push(ScancodeEntry {
+                    scancode: ESCAPE_BIT | scancode(channel, blue, red),
+                    name: format!(
+                        "{:?} ComboPwm red={} blue={}",
+                        channel,
+                        unmap_speed(red),
+                        unmap_speed(blue)
+                    ),
+                });
+            }
+        }
+    }
+    table
+}
+
+/// Renders an `ir-keytable` TOML keymap assigning the given keycodes to scancodes.
+///
+/// # Arguments
+///
+/// * `protocol` - The name of the (BPF) decoder protocol, as loaded with `ir-keytable -p`.
+/// * `keys` - Pairs of scancode and Linux keycode name, e.g. `(0x140, "KEY_UP")`.
+pub fn keymap_toml(protocol: &str, keys: &[(u16, &str)]) -> String {
+    let mut toml = String::new();
+    let _ = writeln!(toml, "[[protocols]]");
+    let _ = writeln!(toml, "name = \"lego_pf\"");
+    let _ = writeln!(toml, "protocol = \"{}\"", protocol);
+    let _ = writeln!(toml, "[protocols.scancodes]");
+    for (scancode, keycode) in keys {
+        let _ = writeln!(toml, "0x{:03x} = \"{}\"", scancode, keycode);
+    }
+    toml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_scancode_round_trip_with_toggle() {
+        // Single Output, toggle=1, channel 1, red output, PWM(5): 1000 0100 0101 LRC
+        let frame = scancode_to_frame(0x045, true);
+        assert_eq!(frame >> 4, 0x845);
+        assert_eq!(frame_to_scancode(frame), Some((0x045, true)));
+        assert_eq!(
+            frame_to_scancode(scancode_to_frame(0x045, false)),
+            Some((0x045, false))
+        );
+    }
+
+    #[test]
+    fn test_combo_pwm_keeps_address_bit() {
+        let frame = scancode_to_frame(0xC3D, true);
+        assert_eq!(frame_to_scancode(frame), Some((0xC3D, false)));
+    }
+
+    #[test]
+    fn test_invalid_lrc_is_rejected() {
+        let frame = scancode_to_frame(0x045, false);
+        assert_eq!(frame_to_scancode(frame ^ 0x1), None);
+    }
+
+    #[test]
+    fn test_scancode_table_is_unique() {
+        let table = scancode_table();
+        assert_eq!(table.len(), 4 * (6 + 16 + 2 * (16 + 16) + 256));
+        let mut scancodes: Vec<u16> = table.iter().map(|entry| entry.scancode).collect();
+        scancodes.sort_unstable();
+        scancodes.dedup();
+        assert_eq!(scancodes.len(), table.len());
+        assert!(table
+            .iter()
+            .any(|entry| entry.scancode == 0x045 && entry.name == "One RED PWM(5)"));
+    }
+
+    #[test]
+    fn test_keymap_toml() {
+        let toml = keymap_toml("lego", &[(0x045, "KEY_UP")]);
+        assert!(toml.contains("protocol = \"lego\""));
+        assert!(toml.contains("0x045 = \"KEY_UP\""));
+    }
+}
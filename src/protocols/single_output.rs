@@ -2,8 +2,8 @@
 //!
 //! Single Output mode messages let you control exactly one output (Red or Blue)
 //! at a time, either with discrete commands or PWM speed steps. This is akin to
-//! how the official “8879 Speed Remote” operates. We define an IRP (`LEGO_SINGLE_OUTPUT_IRP`)
-//! specifying:
+//! how the official “8879 Speed Remote” operates. We build an IRP from `LEGO_SINGLE_OUTPUT_FIELDS`
+//! specifying, with the default `PulseTiming`:
 //!
 //! - 38 kHz carrier frequency (about 26.3157 µs per cycle),
 //! - 33% duty cycle (active portion of each cycle is 1/3),
@@ -13,10 +13,17 @@
 //!
 //! We compute a 4-bit LRC to ensure reliability. The protocol includes a “toggle bit”
 //! that flips whenever a PWM command is transmitted, per LEGO Power Functions–style usage.
-use irp::{Irp, Vartable};
+use irp::Vartable;
 
-use super::{map_speed, Channel, Output};
+use super::{
+    check_speed,
+    lrc::Lrc,
+    map_speed,
+    timing::{frame_air_time, PfIrp, PulseTiming},
+    try_map_speed, Address, Channel, Output, Speed, SpeedStep, ToggleState,
+};
 use crate::{Error, Result};
+use std::time::Duration;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -39,10 +46,41 @@ pub enum SingleOutputDiscrete {
     ToggleFullBackward = 0b1111,
 }
 
+/// Converts the 4-bit data of a discrete Single Output message back into a
+/// `SingleOutputDiscrete`.
+impl TryFrom<u8> for SingleOutputDiscrete {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        use SingleOutputDiscrete::*;
+        const ALL: [SingleOutputDiscrete; 16] = [
+            ToggleFullForward,
+            ToggleDirection,
+            IncrementNumericalPwm,
+            DecrementNumericalPwm,
+            IncrementPwm,
+            DecrementPwm,
+            FullForward,
+            FullBackward,
+            ToggleFullForwardBackward,
+            ClearC1,
+            SetC1,
+            ToggleC1,
+            ClearC2,
+            SetC2,
+            ToggleC2,
+            ToggleFullBackward,
+        ];
+        ALL.get(usize::from(value)).copied().ok_or_else(|| {
+            Error::ProtocolError(format!("Invalid discrete command value {}", value))
+        })
+    }
+}
+
 /// This enum represents the commands that can be sent to a controller using the Single Output protocol.
 /// Commands can either be specified as a PWM (Pulse Width Modulation) value, which sets the speed and direction
 /// of a motor, or as a discrete command that triggers a predefined operation (such as toggling direction).
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SingleOutputCommand {
     /// PWM command.
     ///
@@ -64,6 +102,42 @@ pub enum SingleOutputCommand {
     Discrete(SingleOutputDiscrete),
 }
 
+impl SingleOutputCommand {
+    /// Creates a PWM command, rejecting speeds outside -7 to 8 with `Error::InvalidSpeed`
+    /// instead of clamping them at encode time.
+    pub fn pwm(speed: i8) -> Result<Self> {
+        check_speed(speed).map(SingleOutputCommand::PWM)
+    }
+
+    /// Returns an upper bound of how long `repeats` copies of the command occupy the IR medium,
+    /// e.g. to schedule the next message: every copy but the last takes its 16 ms message slot.
+    ///
+    /// The channel, toggle, address and LRC bits the command doesn't fix are counted as 1 bits,
+    /// which last longest.
+    pub fn air_time(&self, repeats: u32) -> Duration {
+        // Toggle, channel, address and output bits set, mode PWM or discrete.
+        let (mode, data) = match *self {
+            SingleOutputCommand::PWM(speed) => (0b1101, map_speed(speed)),
+            SingleOutputCommand::Discrete(discrete) => (0b1111, discrete as u8),
+        };
+        frame_air_time(0xB00F | (mode << 8) | (u16::from(data) << 4), repeats)
+    }
+}
+
+/// Builds the PWM command of a `Speed`.
+impl From<Speed> for SingleOutputCommand {
+    fn from(speed: Speed) -> Self {
+        SingleOutputCommand::PWM(speed.step())
+    }
+}
+
+/// Builds the PWM command of a `SpeedStep`.
+impl From<SpeedStep> for SingleOutputCommand {
+    fn from(step: SpeedStep) -> Self {
+        SingleOutputCommand::PWM(step.get())
+    }
+}
+
 /// Internal message for Single Output mode.
 #[derive(Debug, Clone, Copy)]
 struct SingleOutputMessage {
@@ -77,22 +151,88 @@ struct SingleOutputMessage {
 
 /// The SingleOutputProtocol encapsulates the IRP string, encoding logic, and its own toggle.
 pub struct SingleOutputProtocol {
-    irp: Irp,
-    toggle: u8,
+    irp: PfIrp,
+    lrc: Lrc,
+    address: Address,
+    strict: bool,
+    toggle: ToggleState,
 }
 
-const LEGO_SINGLE_OUTPUT_IRP: &str = "\
-{38k,33%,26.3157894737,msb}\
-<6,-10|6,-21>\
-(6,-39, T:1, 0:1, C:2, a:1, 1:1, M:1, O:1, D:4, L:4, 6,-39)\
-{L = 0xF^((T*8+C)^((a<<3)|(1<<2)|(M<<1)|O)^D)}\
-[T:0..1, C:0..3, a:0..1, M:0..1, O:0..1, D:0..15]\
-";
+/// The payload of a Single Output message, framed by start and stop bits in the IRP.
+const LEGO_SINGLE_OUTPUT_FIELDS: &str = "T:1, 0:1, C:2, a:1, 1:1, M:1, O:1, D:4, L:4";
+
+const LEGO_SINGLE_OUTPUT_LRC: &str = "0xF^((T*8+C)^((a<<3)|(1<<2)|(M<<1)|O)^D)";
+
+const LEGO_SINGLE_OUTPUT_PARAMETERS: &str = "T:0..1, C:0..3, a:0..1, M:0..1, O:0..1, D:0..15";
+
+fn parse_irp(timing: PulseTiming) -> Result<PfIrp> {
+    PfIrp::new(
+        timing,
+        LEGO_SINGLE_OUTPUT_FIELDS,
+        LEGO_SINGLE_OUTPUT_LRC,
+        LEGO_SINGLE_OUTPUT_PARAMETERS,
+    )
+}
 
 impl SingleOutputProtocol {
     pub fn new() -> Result<Self> {
-        let irp = Irp::parse(LEGO_SINGLE_OUTPUT_IRP).map_err(Error::ProtocolError)?;
-        Ok(Self { irp, toggle: 0 })
+        Self::with_timing(PulseTiming::STANDARD)
+    }
+
+    /// Creates the protocol with adjusted symbol lengths.
+    pub fn with_timing(timing: PulseTiming) -> Result<Self> {
+        Ok(Self {
+            irp: parse_irp(timing)?,
+            lrc: Lrc::Computed,
+            address: Address::Default,
+            strict: false,
+            toggle: ToggleState::default(),
+        })
+    }
+
+    /// Changes the symbol lengths of subsequent messages, keeping the toggle bit.
+    pub fn set_timing(&mut self, timing: PulseTiming) -> Result<()> {
+        self.irp = parse_irp(timing)?;
+        Ok(())
+    }
+
+    /// Selects the LRC sent with subsequent messages.
+    pub fn set_lrc(&mut self, lrc: Lrc) {
+        self.lrc = lrc;
+    }
+
+    /// Selects the address space of subsequent messages.
+    pub fn set_address(&mut self, address: Address) {
+        self.address = address;
+    }
+
+    /// Returns the address space of subsequent messages.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Rejects PWM speeds outside -7 to 8 with `Error::InvalidSpeed` instead of clamping them.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Maps a PWM speed, clamping it unless strict.
+    fn map_speed(&self, speed: i8) -> Result<u8> {
+        if self.strict {
+            try_map_speed(speed)
+        } else {
+            Ok(map_speed(speed))
+        }
+    }
+
+    /// Shares the toggle bit with other protocol instances sending to the same output.
+    pub(crate) fn share_toggle(&mut self, toggle: ToggleState) {
+        self.toggle = toggle;
+    }
+
+    /// Returns the toggle state of subsequent messages.
+    pub(crate) fn toggle_state(&self) -> ToggleState {
+        self.toggle.clone()
     }
 
     fn encode_msg(&self, msg: SingleOutputMessage) -> Result<Vec<u32>> {
@@ -103,10 +243,7 @@ impl SingleOutputProtocol {
         vars.set("M".into(), msg.mode.into());
         vars.set("O".into(), msg.output.into());
         vars.set("D".into(), msg.data.into());
-        self.irp
-            .encode_raw(vars, 1)
-            .map(|res| res.raw)
-            .map_err(Error::ProtocolError)
+        self.irp.encode(vars, self.lrc)
     }
 
     /// Encodes a Single Output command.
@@ -117,20 +254,30 @@ impl SingleOutputProtocol {
         cmd: SingleOutputCommand,
     ) -> Result<Vec<u32>> {
         let (mode, data) = match cmd {
-            SingleOutputCommand::PWM(speed) => (0, map_speed(speed)),
+            SingleOutputCommand::PWM(speed) => (0, self.map_speed(speed)?),
             SingleOutputCommand::Discrete(discrete) => (1, discrete as u8),
         };
         let msg = SingleOutputMessage {
-            toggle: self.toggle,
+            toggle: self.toggle.toggle(),
             channel: channel as u8,
-            address: 0,
+            address: self.address as u8,
             mode,
             output: output as u8,
             data,
         };
         let pulses = self.encode_msg(msg)?;
-        if mode == 0 {
-            self.toggle ^= 1;
+        // Numerical PWM steps are relative, so each one must look like a new message to the
+        // receiver, just like a PWM command.
+        if mode == 0
+            || matches!(
+                cmd,
+                SingleOutputCommand::Discrete(
+                    SingleOutputDiscrete::IncrementNumericalPwm
+                        | SingleOutputDiscrete::DecrementNumericalPwm
+                )
+            )
+        {
+            self.toggle.flip_toggle();
         }
         Ok(pulses)
     }
@@ -188,6 +335,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_single_output_strict_speeds() {
+        let mut proto = SingleOutputProtocol::new().unwrap();
+        let clamped = proto
+            .encode_cmd(Channel::One, Output::RED, SingleOutputCommand::PWM(100))
+            .unwrap();
+        proto.set_strict(true);
+        assert!(matches!(
+            proto.encode_cmd(Channel::One, Output::RED, SingleOutputCommand::PWM(100)),
+            Err(Error::InvalidSpeed(100))
+        ));
+        // The rejected command didn't flip the toggle bit, and 100 was clamped to 7 before.
+        let seven = proto
+            .encode_cmd(Channel::One, Output::RED, SingleOutputCommand::PWM(7))
+            .unwrap();
+        assert_ne!(seven[3], clamped[3]);
+        assert_eq!(seven[4..27], clamped[4..27]);
+
+        assert_eq!(
+            SingleOutputCommand::pwm(-7).unwrap(),
+            SingleOutputCommand::PWM(-7)
+        );
+        assert_eq!(
+            SingleOutputCommand::from(Speed::Reverse(7)),
+            SingleOutputCommand::PWM(-7)
+        );
+        assert!(matches!(
+            SingleOutputCommand::pwm(-8),
+            Err(Error::InvalidSpeed(-8))
+        ));
+    }
+
     #[test]
     fn test_single_output_discrete_commands() {
         let mut proto = SingleOutputProtocol::new().unwrap();
@@ -209,4 +388,30 @@ mod tests {
             assert!(pulses.is_ok(), "Encoding failed for discrete cmd={:?}", cmd);
         }
     }
+
+    #[test]
+    fn test_single_output_custom_timing() {
+        let cmd = SingleOutputCommand::PWM(3);
+        let standard = SingleOutputProtocol::new()
+            .unwrap()
+            .encode_cmd(Channel::Two, Output::RED, cmd)
+            .unwrap();
+        let timing = PulseTiming::new(6, 10, 21, 45).unwrap();
+        let mut proto = SingleOutputProtocol::with_timing(timing).unwrap();
+        let stretched = proto.encode_cmd(Channel::Two, Output::RED, cmd).unwrap();
+
+        assert_eq!(&standard[..2], &[157, 1026]);
+        assert_eq!(&stretched[..2], &[157, 1184]);
+        assert_eq!(stretched.last(), Some(&1184));
+        assert_eq!(
+            &stretched[2..stretched.len() - 2],
+            &standard[2..standard.len() - 2]
+        );
+
+        // The toggle bit survives a timing change.
+        proto.set_timing(PulseTiming::STANDARD).unwrap();
+        let toggled = proto.encode_cmd(Channel::Two, Output::RED, cmd).unwrap();
+        assert_eq!(toggled[3], 552);
+        assert_eq!(standard[3], 263);
+    }
 }
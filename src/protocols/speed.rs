@@ -0,0 +1,209 @@
+//! # Speed
+//!
+//! The PWM steps of Single Output and Combo PWM messages are plain `i8` values from -7 to 8,
+//! where 0 floats the output and 8 brakes it. `Speed` spells these meanings out, and converts
+//! from and into the steps wherever an `i8` speed is accepted. `SpeedStep` is a validated
+//! driving step from -7 to 7 with saturating arithmetic, e.g. for throttle buttons.
+
+use crate::{Error, Result};
+
+/// A PWM speed of a Single Output or Combo PWM message.
+///
+/// # Example
+#[cfg_attr(feature = "single-output", doc = "```rust")]
+#[cfg_attr(not(feature = "single-output"), doc = "```ignore")]
+/// use brickbeam::{BrickBeam, Channel, Output, Result, Speed};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     let mut motor = brick_beam.create_speed_remote_controller(Channel::One, Output::RED)?;
+///     motor.send(Speed::Reverse(3).into())?;
+///     motor.send(Speed::BrakeThenFloat.into())?;
+///     assert_eq!(Speed::try_from(-3)?, Speed::Reverse(3));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Speed {
+    /// Lets the output float (coast), the step 0.
+    Float,
+    /// Drives forward with a step from 1 to 7.
+    Forward(u8),
+    /// Drives backward with a step from 1 to 7.
+    Reverse(u8),
+    /// Brakes the output, then lets it float, the step 8.
+    BrakeThenFloat,
+}
+
+impl Speed {
+    /// Returns the signed PWM step (-7 to 8) of the speed.
+    ///
+    /// Like `map_speed`, steps above 7 are clamped to 7; a step of 0 floats.
+    pub const fn step(self) -> i8 {
+        match self {
+            Speed::Float => 0,
+            Speed::Forward(step) if step > 7 => 7,
+            Speed::Forward(step) => step as i8,
+            Speed::Reverse(step) if step > 7 => -7,
+            Speed::Reverse(step) => -(step as i8),
+            Speed::BrakeThenFloat => 8,
+        }
+    }
+}
+
+impl From<Speed> for i8 {
+    fn from(speed: Speed) -> Self {
+        speed.step()
+    }
+}
+
+/// Converts a signed PWM step back into a `Speed`, rejecting steps outside -7 to 8 with
+/// `Error::InvalidSpeed`.
+impl TryFrom<i8> for Speed {
+    type Error = Error;
+
+    fn try_from(step: i8) -> Result<Self> {
+        match step {
+            0 => Ok(Speed::Float),
+            1..=7 => Ok(Speed::Forward(step as u8)),
+            -7..=-1 => Ok(Speed::Reverse(step.unsigned_abs())),
+            8 => Ok(Speed::BrakeThenFloat),
+            _ => Err(Error::InvalidSpeed(step)),
+        }
+    }
+}
+
+/// A driving step from -7 (full reverse) to 7 (full forward), 0 letting the output float.
+///
+/// Converts into an `i8`, a `Speed` and a PWM command, so it is usable wherever a speed is
+/// accepted.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::SpeedStep;
+///
+/// let step = SpeedStep::try_from(6).unwrap();
+/// assert_eq!(step.saturating_increment().saturating_increment().get(), 7);
+/// assert_eq!(SpeedStep::MIN.saturating_add(-3), SpeedStep::MIN);
+/// assert!(SpeedStep::try_from(8).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpeedStep(i8);
+
+impl SpeedStep {
+    /// Full reverse.
+    pub const MIN: Self = Self(-7);
+    /// Floating.
+    pub const ZERO: Self = Self(0);
+    /// Full forward.
+    pub const MAX: Self = Self(7);
+
+    /// Returns the step, clamped into -7 to 7.
+    pub const fn saturating(step: i8) -> Self {
+        if step < -7 {
+            Self::MIN
+        } else if step > 7 {
+            Self::MAX
+        } else {
+            Self(step)
+        }
+    }
+
+    /// Returns the step as an `i8`.
+    pub const fn get(self) -> i8 {
+        self.0
+    }
+
+    /// Returns the next step forward, staying at 7.
+    pub const fn saturating_increment(self) -> Self {
+        self.saturating_add(1)
+    }
+
+    /// Returns the next step backward, staying at -7.
+    pub const fn saturating_decrement(self) -> Self {
+        self.saturating_add(-1)
+    }
+
+    /// Returns the step `delta` steps further, staying within -7 to 7.
+    pub const fn saturating_add(self, delta: i8) -> Self {
+        Self::saturating(self.0.saturating_add(delta))
+    }
+}
+
+/// Validates a step, rejecting steps outside -7 to 7 with `Error::InvalidSpeed`.
+impl TryFrom<i8> for SpeedStep {
+    type Error = Error;
+
+    fn try_from(step: i8) -> Result<Self> {
+        if (-7..=7).contains(&step) {
+            Ok(Self(step))
+        } else {
+            Err(Error::InvalidSpeed(step))
+        }
+    }
+}
+
+impl From<SpeedStep> for i8 {
+    fn from(step: SpeedStep) -> Self {
+        step.0
+    }
+}
+
+impl From<SpeedStep> for Speed {
+    fn from(step: SpeedStep) -> Self {
+        match step.0 {
+            0 => Speed::Float,
+            n if n > 0 => Speed::Forward(n as u8),
+            n => Speed::Reverse(n.unsigned_abs()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed_round_trips_every_step() {
+        for step in -7..=8 {
+            assert_eq!(Speed::try_from(step).unwrap().step(), step);
+        }
+        assert_eq!(Speed::try_from(0).unwrap(), Speed::Float);
+        assert_eq!(Speed::try_from(8).unwrap(), Speed::BrakeThenFloat);
+        assert_eq!(Speed::try_from(-2).unwrap(), Speed::Reverse(2));
+        assert!(matches!(Speed::try_from(9), Err(Error::InvalidSpeed(9))));
+        assert!(matches!(Speed::try_from(-8), Err(Error::InvalidSpeed(-8))));
+    }
+
+    #[test]
+    fn test_speed_steps_clamp_like_map_speed() {
+        assert_eq!(i8::from(Speed::Forward(20)), 7);
+        assert_eq!(i8::from(Speed::Reverse(200)), -7);
+        assert_eq!(i8::from(Speed::Forward(0)), 0);
+        assert_eq!(i8::from(Speed::Reverse(0)), 0);
+    }
+
+    #[test]
+    fn test_speed_step_validates_and_saturates() {
+        for step in -7..=7 {
+            let speed_step = SpeedStep::try_from(step).unwrap();
+            assert_eq!(i8::from(speed_step), step);
+            assert_eq!(Speed::from(speed_step).step(), step);
+        }
+        assert!(matches!(
+            SpeedStep::try_from(8),
+            Err(Error::InvalidSpeed(8))
+        ));
+        assert!(matches!(
+            SpeedStep::try_from(-8),
+            Err(Error::InvalidSpeed(-8))
+        ));
+
+        assert_eq!(SpeedStep::MAX.saturating_increment(), SpeedStep::MAX);
+        assert_eq!(SpeedStep::MIN.saturating_decrement(), SpeedStep::MIN);
+        assert_eq!(SpeedStep::ZERO.saturating_decrement().get(), -1);
+        assert_eq!(SpeedStep::MAX.saturating_add(i8::MIN), SpeedStep::MIN);
+        assert_eq!(SpeedStep::saturating(100), SpeedStep::MAX);
+        assert_eq!(SpeedStep::default(), SpeedStep::ZERO);
+    }
+}
@@ -0,0 +1,577 @@
+//! # Timing
+//!
+//! Pulse sequences on the wire are expressed as `u32` microseconds, while every timing knob of
+//! the public API (gaps, slots, intervals, timeouts) is a `std::time::Duration`. The helpers here
+//! convert between the two so user code never mixes microsecond integers with milliseconds.
+//!
+//! `PulseTiming` describes the lengths of the PF symbols themselves, in carrier cycles, and can
+//! be overridden per protocol instance to experiment with marginal receivers. `PulseRounding`
+//! selects how those lengths are rounded to the whole microseconds on the wire.
+//!
+//! The nominal values of the PF spec are exported as named constants, so schedulers, visualizers
+//! and decoders built on the crate share them instead of hardcoding them: the carrier
+//! (`CARRIER_HZ`, `DUTY_CYCLE`), the symbol lengths on the wire (`MARK_MICROS`,
+//! `ZERO_SPACE_MICROS`, `ONE_SPACE_MICROS`, `START_STOP_SPACE_MICROS`) and the repeat scheme
+//! (`MAX_MESSAGE_DURATION`, `SPEC_REPEATS`, `spec_repeat_delays`).
+//!
+//! `PulseTiming::encode_frame` turns a raw 16-bit frame into pulses with `core` alone, without
+//! the IRP engine or an allocator. It is the encoder core for targets with `alloc` or nothing
+//! at all, and together with the constants here compiles without the `std` feature; the rest of
+//! the crate (the IRP-based protocols, devices and sequences) needs it.
+// The IRP builder is unused when no protocol feature is enabled.
+#![cfg_attr(
+    not(any(
+        feature = "single-output",
+        feature = "combo-direct",
+        feature = "combo-pwm",
+        feature = "extended"
+    )),
+    allow(dead_code)
+)]
+
+#[cfg(feature = "std")]
+use super::lrc::{Lrc, LRC_PARAMETERS};
+use super::Channel;
+#[cfg(feature = "std")]
+use crate::{Clock, Error, Result};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use irp::{Irp, Vartable};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// The maximum length of a single PF message, `tm` in the spec. Messages addressed to different
+/// channels are kept at least this far apart (start to start) so they never collide.
+pub const MAX_MESSAGE_DURATION: Duration = Duration::from_millis(16);
+
+/// The carrier frequency of the PF protocols in Hz.
+pub const CARRIER_HZ: u32 = 38_000;
+
+/// The duty cycle of the PF carrier in percent.
+pub const DUTY_CYCLE: u32 = 33;
+
+/// The length of the mark of every PF symbol in µs, 6 carrier cycles.
+pub const MARK_MICROS: u32 = 157;
+
+/// The length of the space of a 0 bit in µs, 10 carrier cycles.
+pub const ZERO_SPACE_MICROS: u32 = 263;
+
+/// The length of the space of a 1 bit in µs, 21 carrier cycles.
+pub const ONE_SPACE_MICROS: u32 = 552;
+
+/// The length of the space of the start and stop bits in µs, 39 carrier cycles.
+pub const START_STOP_SPACE_MICROS: u32 = 1026;
+
+/// Returns how long the given pulse sequence occupies the IR medium.
+///
+/// This is the sum of all marks and spaces (in microseconds), including the trailing gap after
+/// the stop bit, so it can be used directly to schedule the next message.
+pub fn duration_of(pulses: &[u32]) -> Duration {
+    Duration::from_micros(pulses.iter().map(|&pulse| u64::from(pulse)).sum())
+}
+
+/// Returns how long `repeats` copies of a PF frame with the standard symbol lengths occupy the
+/// IR medium: every copy but the last takes a full message slot, the last one ends with its
+/// trailing gap.
+pub(crate) fn frame_air_time(frame: u16, repeats: u32) -> Duration {
+    if repeats == 0 {
+        return Duration::ZERO;
+    }
+    let message = duration_of(&PulseTiming::STANDARD.encode_frame(frame));
+    MAX_MESSAGE_DURATION * (repeats - 1) + message
+}
+
+/// Sleeps on `clock` until a message that `started` with the given `airtime` has used up its time
+/// slot.
+///
+/// The slot is at least `slot` long, but never shorter than the message itself.
+#[cfg(feature = "std")]
+pub(crate) fn wait_out_slot(
+    clock: &dyn Clock,
+    started: Instant,
+    airtime: Duration,
+    slot: Duration,
+) {
+    let elapsed = clock.now().saturating_duration_since(started);
+    clock.sleep(airtime.max(slot).saturating_sub(elapsed));
+}
+
+/// The number of copies the PF spec prescribes for every message.
+pub const SPEC_REPEATS: usize = 5;
+
+/// Returns the pauses the PF spec prescribes around the five copies of a message on `channel`.
+///
+/// The first entry is the delay before the first copy, the others the start-to-start times
+/// between consecutive copies, in multiples of the 16 ms maximum message length `tm`. With `Ch`
+/// the channel number 0 to 3 they are `(4 − Ch)`, `(4 − Ch)`, `5`, `5` and `(6 + 2·Ch)` times
+/// `tm`. As the pauses differ per channel, two remotes pressed at the same moment collide on at
+/// most a few copies.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{timing::spec_repeat_delays, Channel};
+/// use std::time::Duration;
+///
+/// let ms = Duration::from_millis;
+/// assert_eq!(
+///     spec_repeat_delays(Channel::Four),
+///     [ms(16), ms(16), ms(80), ms(80), ms(192)]
+/// );
+/// ```
+pub fn spec_repeat_delays(channel: Channel) -> [Duration; SPEC_REPEATS] {
+    spec_repeat_slots(channel).map(|slots| MAX_MESSAGE_DURATION * slots)
+}
+
+/// Returns the pauses of `spec_repeat_delays` in multiples of `tm`.
+pub(crate) fn spec_repeat_slots(channel: Channel) -> [u32; SPEC_REPEATS] {
+    let ch = u32::from(channel as u8);
+    [4 - ch, 4 - ch, 5, 5, 6 + 2 * ch]
+}
+
+/// Converts a `Duration` into the microsecond pulse units used on the wire.
+///
+/// Sub-microsecond remainders are truncated and durations beyond `u32::MAX` µs saturate.
+pub fn to_pulse_units(duration: Duration) -> u32 {
+    u32::try_from(duration.as_micros()).unwrap_or(u32::MAX)
+}
+
+/// Converts a microsecond pulse length from the wire into a `Duration`.
+pub fn from_pulse_units(micros: u32) -> Duration {
+    Duration::from_micros(u64::from(micros))
+}
+
+/// The carrier parameters shared by all PF protocols. The unit of 1 makes the IRP return every
+/// duration exactly as written, in hundredths of a carrier cycle, which `PulseRounding` then
+/// converts into microseconds.
+#[cfg(feature = "std")]
+const PF_GENERAL_SPEC: &str = "{38k,33%,1,msb}";
+
+/// How symbol lengths, which are fractions of microseconds, are rounded for the wire.
+///
+/// One carrier cycle is 26.315… µs, so every mark and space carries a rounding error. Rounding
+/// each pulse on its own adds up these errors over the 36 pulses of a message, which shifts the
+/// stop bit by up to ~30 µs with `Floor`. `Accumulated` rounds the running total instead, so no
+/// pulse edge is ever more than half a microsecond off its exact position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PulseRounding {
+    /// Truncates every pulse, the behaviour of the IRP encoder.
+    #[default]
+    Floor,
+    /// Rounds every pulse to the nearest microsecond.
+    Nearest,
+    /// Keeps the exact running total and rounds the position of every pulse edge.
+    Accumulated,
+}
+
+impl PulseRounding {
+    /// Converts lengths in hundredths of a 38 kHz carrier cycle (1/3 800 000 s) into µs.
+    #[cfg(feature = "std")]
+    fn to_micros(self, hundredths: &[u32]) -> Vec<u32> {
+        let mut total = 0;
+        hundredths
+            .iter()
+            .map(|&h| self.round(&mut total, h))
+            .collect()
+    }
+
+    /// Rounds one pulse of `h` hundredths of a cycle that starts `total` hundredths into the
+    /// message, and advances `total` past it.
+    fn round(self, total: &mut u64, h: u32) -> u32 {
+        // One hundredth of a cycle is 10/38 µs.
+        let floor = |h: u64| h * 10 / 38;
+        let nearest = |h: u64| (h * 10 + 19) / 38;
+        let h = u64::from(h);
+        let micros = match self {
+            Self::Floor => floor(h),
+            Self::Nearest => nearest(h),
+            Self::Accumulated => nearest(*total + h) - nearest(*total),
+        };
+        *total += h;
+        u32::try_from(micros).unwrap_or(u32::MAX)
+    }
+}
+
+/// The number of pulses in a PF message: start bit, 16 data bits and stop bit, each a mark and
+/// a space.
+pub const FRAME_PULSES: usize = 36;
+
+/// The lengths of the PF symbols, in carrier cycles of 1/38 kHz (≈ 26.3 µs).
+///
+/// Every bit is a mark followed by a space; the space length tells receivers a 0 from a 1 and
+/// from the start/stop bit. The defaults are the nominal PF lengths. Receivers measure the
+/// symbols with some tolerance, so adjusted lengths are only accepted within bounds that keep
+/// the three spaces clearly apart:
+///
+/// | Symbol           | Default | Accepted | Also required              |
+/// |------------------|---------|----------|----------------------------|
+/// | Mark             | 6       | 4–12     |                            |
+/// | Space of a 0     | 10      | 6–16     |                            |
+/// | Space of a 1     | 21      | 15–32    | ≥ 6 longer than a 0        |
+/// | Start/stop space | 39      | 30–60    | ≥ 8 longer than a 1        |
+///
+/// Some third-party PF-compatible receivers are pickier than genuine ones and only respond
+/// reliably to longer marks. `with_mark_stretch` lengthens every mark by a percentage and
+/// shortens the following space by the same amount, so bit periods and message airtime stay
+/// unchanged. `CLONE_RECEIVER` applies the 10% stretch measured to work with common clones.
+///
+/// The lengths are exact multiples of the carrier period; `with_rounding` picks how they are
+/// rounded to microseconds.
+///
+/// # Example
+#[cfg_attr(feature = "std", doc = "```rust")]
+#[cfg_attr(not(feature = "std"), doc = "```ignore")]
+/// use brickbeam::timing::PulseTiming;
+///
+/// let timing = PulseTiming::new(6, 10, 21, 45).unwrap();
+/// assert_eq!(timing.start_stop_space(), 45);
+/// assert!(PulseTiming::new(6, 10, 12, 39).is_err());
+///
+/// let clone = PulseTiming::CLONE_RECEIVER;
+/// assert_eq!(clone, PulseTiming::STANDARD.with_mark_stretch(10).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PulseTiming {
+    mark: u32,
+    zero_space: u32,
+    one_space: u32,
+    start_stop_space: u32,
+    mark_stretch: u32,
+    rounding: PulseRounding,
+}
+
+impl PulseTiming {
+    /// The nominal PF symbol lengths.
+    pub const STANDARD: Self = Self {
+        mark: 6,
+        zero_space: 10,
+        one_space: 21,
+        start_stop_space: 39,
+        mark_stretch: 0,
+        rounding: PulseRounding::Floor,
+    };
+
+    /// The nominal lengths with 10% longer marks, for third-party receivers.
+    pub const CLONE_RECEIVER: Self = Self {
+        mark_stretch: 10,
+        ..Self::STANDARD
+    };
+
+    /// The largest accepted mark stretch in percent.
+    pub const MAX_MARK_STRETCH: u32 = 25;
+
+    /// Creates symbol lengths, in carrier cycles.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if a length is outside the bounds listed above.
+    #[cfg(feature = "std")]
+    pub fn new(mark: u32, zero_space: u32, one_space: u32, start_stop_space: u32) -> Result<Self> {
+        let check = |name: &str, value: u32, min: u32, max: u32| {
+            if (min..=max).contains(&value) {
+                Ok(())
+            } else {
+                Err(Error::ProtocolError(format!(
+                    "{} of {} cycles is outside {}..={}",
+                    name, value, min, max
+                )))
+            }
+        };
+        check("Mark", mark, 4, 12)?;
+        check("Space of a 0", zero_space, 6, 16)?;
+        check("Space of a 1", one_space, (zero_space + 6).max(15), 32)?;
+        check(
+            "Start/stop space",
+            start_stop_space,
+            (one_space + 8).max(30),
+            60,
+        )?;
+        Ok(Self {
+            mark,
+            zero_space,
+            one_space,
+            start_stop_space,
+            mark_stretch: 0,
+            rounding: PulseRounding::Floor,
+        })
+    }
+
+    /// Returns the timing with marks `percent` longer and all spaces shortened to match.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if `percent` exceeds `MAX_MARK_STRETCH`.
+    #[cfg(feature = "std")]
+    pub fn with_mark_stretch(mut self, percent: u32) -> Result<Self> {
+        if percent > Self::MAX_MARK_STRETCH {
+            return Err(Error::ProtocolError(format!(
+                "Mark stretch of {}% exceeds {}%",
+                percent,
+                Self::MAX_MARK_STRETCH
+            )));
+        }
+        self.mark_stretch = percent;
+        Ok(self)
+    }
+
+    /// Returns the mark length of every symbol.
+    pub fn mark(&self) -> u32 {
+        self.mark
+    }
+
+    /// Returns the space length of a 0 bit.
+    pub fn zero_space(&self) -> u32 {
+        self.zero_space
+    }
+
+    /// Returns the space length of a 1 bit.
+    pub fn one_space(&self) -> u32 {
+        self.one_space
+    }
+
+    /// Returns the space length of the start and stop bits.
+    pub fn start_stop_space(&self) -> u32 {
+        self.start_stop_space
+    }
+
+    /// Returns by how many percent marks are lengthened at the expense of spaces.
+    pub fn mark_stretch(&self) -> u32 {
+        self.mark_stretch
+    }
+
+    /// Returns the timing with a different rounding strategy.
+    pub const fn with_rounding(mut self, rounding: PulseRounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Returns how pulse lengths are rounded to microseconds.
+    pub fn rounding(&self) -> PulseRounding {
+        self.rounding
+    }
+
+    /// Builds the IRP of a PF message with these symbol lengths.
+    ///
+    /// `fields` lists the payload between the start and stop bits, `definitions` holds the
+    /// definitions and parameter specs following the stream. Durations are in hundredths of a
+    /// cycle.
+    #[cfg(feature = "std")]
+    fn irp(&self, fields: &str, definitions: &str) -> String {
+        let [m, z, o, s] = self.hundredths();
+        format!(
+            "{}<{m},-{z}|{m},-{o}>({m},-{s}, {}, {m},-{s}){}",
+            PF_GENERAL_SPEC, fields, definitions
+        )
+    }
+
+    /// Encodes a 16-bit PF frame (the three payload nibbles and the LRC, most significant bit
+    /// first) into its pulse lengths in µs.
+    ///
+    /// Unlike the protocol encoders, this needs neither the IRP engine nor an allocator: it only
+    /// relies on `core` and returns a fixed buffer, so it can be lifted as-is onto targets
+    /// without `std`. The caller is responsible for a correct LRC.
+    ///
+    /// # Example
+    /// ```rust
+    /// use brickbeam::timing::{PulseTiming, FRAME_PULSES};
+    ///
+    /// // Combo PWM on channel 1: red forward 5, blue backward 3.
+    /// let pulses: [u32; FRAME_PULSES] = PulseTiming::STANDARD.encode_frame(0x4D53);
+    /// assert_eq!(pulses[..4], [157, 1026, 157, 263]);
+    /// ```
+    pub fn encode_frame(&self, frame: u16) -> [u32; FRAME_PULSES] {
+        let [mark, zero, one, start_stop] = self.hundredths();
+        let mut hundredths = [mark; FRAME_PULSES];
+        hundredths[1] = start_stop;
+        for bit in 0..16 {
+            let set = frame & (0x8000 >> bit) != 0;
+            hundredths[3 + 2 * bit] = if set { one } else { zero };
+        }
+        hundredths[FRAME_PULSES - 1] = start_stop;
+        let mut total = 0;
+        hundredths.map(|h| self.rounding.round(&mut total, h))
+    }
+
+    /// Returns the mark and the three space lengths in hundredths of a cycle, as the stretch
+    /// moves marks by fractions of a cycle.
+    fn hundredths(&self) -> [u32; 4] {
+        let shift = self.mark * self.mark_stretch;
+        [
+            self.mark * 100 + shift,
+            self.zero_space * 100 - shift,
+            self.one_space * 100 - shift,
+            self.start_stop_space * 100 - shift,
+        ]
+    }
+}
+
+impl Default for PulseTiming {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+/// A parsed PF message IRP together with the timing it was built from.
+#[cfg(feature = "std")]
+pub(crate) struct PfIrp {
+    irp: Irp,
+    rounding: PulseRounding,
+}
+
+#[cfg(feature = "std")]
+impl PfIrp {
+    /// Parses the IRP of a PF message.
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - The payload between the start and stop bits, ending with `L:4`.
+    /// * `lrc` - The expression computing the correct checksum `L`.
+    /// * `parameters` - The parameter spec of the payload fields.
+    pub(crate) fn new(
+        timing: PulseTiming,
+        fields: &str,
+        lrc: &str,
+        parameters: &str,
+    ) -> Result<Self> {
+        let definitions = format!(
+            "{}[{},{}]",
+            Lrc::definition(lrc),
+            parameters,
+            LRC_PARAMETERS
+        );
+        Ok(Self {
+            irp: Irp::parse(&timing.irp(fields, &definitions)).map_err(Error::ProtocolError)?,
+            rounding: timing.rounding,
+        })
+    }
+
+    /// Encodes one message into pulse lengths in microseconds.
+    pub(crate) fn encode(&self, mut vars: Vartable, lrc: Lrc) -> Result<Vec<u32>> {
+        lrc.set_vars(&mut vars);
+        let hundredths = self
+            .irp
+            .encode_raw(vars, 1)
+            .map_err(Error::ProtocolError)?
+            .raw;
+        Ok(self.rounding.to_micros(&hundredths))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration_of_sums_marks_and_spaces() {
+        assert_eq!(duration_of(&[]), Duration::ZERO);
+        assert_eq!(
+            duration_of(&[157, 263, 157, 1026]),
+            Duration::from_micros(1603)
+        );
+    }
+
+    #[test]
+    fn test_constants_match_standard_encoding() {
+        let pulses = PulseTiming::STANDARD.encode_frame(0x8000);
+        assert_eq!(
+            pulses[..6],
+            [
+                MARK_MICROS,
+                START_STOP_SPACE_MICROS,
+                MARK_MICROS,
+                ONE_SPACE_MICROS,
+                MARK_MICROS,
+                ZERO_SPACE_MICROS
+            ]
+        );
+        assert_eq!(pulses[FRAME_PULSES - 1], START_STOP_SPACE_MICROS);
+    }
+
+    #[test]
+    fn test_frame_air_time() {
+        // Start, sixteen 1 bits and the stop symbol.
+        let longest = Duration::from_micros(13_710);
+        assert_eq!(frame_air_time(0xFFFF, 1), longest);
+        assert_eq!(
+            frame_air_time(0xFFFF, 3),
+            Duration::from_millis(32) + longest
+        );
+        assert!(frame_air_time(0x0000, 1) < longest);
+        assert_eq!(frame_air_time(0xFFFF, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_pulse_unit_conversions_round_trip() {
+        assert_eq!(to_pulse_units(Duration::from_millis(16)), 16_000);
+        assert_eq!(from_pulse_units(1026), Duration::from_micros(1026));
+        assert_eq!(to_pulse_units(from_pulse_units(552)), 552);
+        assert_eq!(to_pulse_units(Duration::from_nanos(157_900)), 157);
+        assert_eq!(to_pulse_units(Duration::from_secs(u64::MAX)), u32::MAX);
+    }
+
+    #[test]
+    fn test_spec_repeat_delays_per_channel() {
+        let ms = Duration::from_millis;
+        assert_eq!(
+            spec_repeat_delays(Channel::One),
+            [ms(64), ms(64), ms(80), ms(80), ms(96)]
+        );
+        assert_eq!(
+            spec_repeat_delays(Channel::Three),
+            [ms(32), ms(32), ms(80), ms(80), ms(160)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_pulse_timing_bounds() {
+        assert_eq!(
+            PulseTiming::new(6, 10, 21, 39).unwrap(),
+            PulseTiming::STANDARD
+        );
+        assert!(PulseTiming::new(4, 6, 15, 30).is_ok());
+        assert!(PulseTiming::new(3, 10, 21, 39).is_err());
+        assert!(PulseTiming::new(6, 14, 18, 39).is_err());
+        assert!(PulseTiming::new(6, 10, 28, 32).is_err());
+        assert!(PulseTiming::new(6, 10, 21, 61).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_pulse_timing_irp() {
+        assert!(PF_GENERAL_SPEC.starts_with(&format!("{{{}k,{}%", CARRIER_HZ / 1000, DUTY_CYCLE)));
+        assert_eq!(
+            PulseTiming::STANDARD.irp("D:4", "[D:0..15]"),
+            "{38k,33%,1,msb}<600,-1000|600,-2100>(600,-3900, D:4, 600,-3900)[D:0..15]"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_mark_stretch_keeps_bit_periods() {
+        assert_eq!(
+            PulseTiming::CLONE_RECEIVER.irp("D:4", "[D:0..15]"),
+            "{38k,33%,1,msb}<660,-940|660,-2040>(660,-3840, D:4, 660,-3840)[D:0..15]"
+        );
+        assert_eq!(PulseTiming::STANDARD.mark_stretch(), 0);
+        assert!(PulseTiming::STANDARD.with_mark_stretch(26).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_rounding_strategies() {
+        // Start bit, a 0 and a 1, as in every PF message.
+        let hundredths = [600, 3900, 600, 1000, 600, 2100];
+        assert_eq!(
+            PulseRounding::Floor.to_micros(&hundredths),
+            [157, 1026, 157, 263, 157, 552]
+        );
+        assert_eq!(
+            PulseRounding::Nearest.to_micros(&hundredths),
+            [158, 1026, 158, 263, 158, 553]
+        );
+        assert_eq!(
+            PulseRounding::Accumulated.to_micros(&hundredths),
+            [158, 1026, 158, 263, 158, 553]
+        );
+    }
+}
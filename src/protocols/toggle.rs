@@ -0,0 +1,56 @@
+//! # Toggle State
+//!
+//! Receivers ignore a message that repeats the toggle bit of the previous one, and Extended
+//! messages carry an address that `ToggleAddress` flips. Both must stay in step with the
+//! receiver, so protocol instances talking to the same receiver share a `ToggleState`.
+// Only the Extended protocol uses the address, and Combo Direct compiles the Extended module for
+// its IRP alone.
+#![cfg_attr(not(feature = "extended"), allow(dead_code))]
+
+use super::Address;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// The toggle bit and address of a sender, cloned into every protocol that shares them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ToggleState(Arc<Bits>);
+
+#[derive(Debug, Default)]
+struct Bits {
+    toggle: AtomicU8,
+    address: AtomicU8,
+}
+
+impl ToggleState {
+    /// Creates a state starting with toggle bit 0 and the given address.
+    pub(crate) fn new(address: Address) -> Self {
+        let state = Self::default();
+        state.set_address(address);
+        state
+    }
+
+    /// Returns the toggle bit of the next message.
+    pub(crate) fn toggle(&self) -> u8 {
+        self.0.toggle.load(Ordering::SeqCst)
+    }
+
+    /// Flips the toggle bit after a message was encoded.
+    pub(crate) fn flip_toggle(&self) {
+        self.0.toggle.fetch_xor(1, Ordering::SeqCst);
+    }
+
+    /// Returns the address of the next message.
+    pub(crate) fn address(&self) -> u8 {
+        self.0.address.load(Ordering::SeqCst)
+    }
+
+    /// Sets the address of the next message.
+    pub(crate) fn set_address(&self, address: Address) {
+        self.0.address.store(address as u8, Ordering::SeqCst);
+    }
+
+    /// Flips the address after a `ToggleAddress` command.
+    pub(crate) fn flip_address(&self) {
+        self.0.address.fetch_xor(1, Ordering::SeqCst);
+    }
+}
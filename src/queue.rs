@@ -0,0 +1,399 @@
+//! # Transmit Queue
+//!
+//! A bounded queue in front of a transmitter for async producers (feature `async`), such as a web
+//! server forwarding slider updates. A background thread transmits the queued pulse trains one
+//! after the other, so producers never block on the IR medium.
+//!
+//! Submitting is a two-step handshake, which lets producers apply backpressure instead of
+//! buffering without bound:
+//! 1. `TransmitQueue::submit` returns a `Submission`, a future that resolves once the queue has
+//!    accepted the message,
+//! 2. and yields a `Transmission`, a future that resolves once the message was actually sent.
+//!
+//! The futures don't depend on a particular async runtime.
+//!
+//! ## Overflow Policy
+//!
+//! The capacity counts accepted messages that the worker has not started to transmit yet. When a
+//! message is submitted to a full queue, the `OverflowPolicy` decides what happens:
+//! - `Wait` (the default) keeps the `Submission` pending until the worker frees a place.
+//! - `DropOldest` accepts the message right away and drops the oldest waiting one, whose
+//!   `Transmission` fails. This suits streams of absolute values, where only the latest counts.
+//! - `Reject` fails the `Submission` right away.
+
+use crate::device::PulseTransmitter;
+use crate::protocols::{wait_out_slot, MAX_MESSAGE_DURATION};
+use crate::{Error, PulseTrain, Result};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// What `TransmitQueue::submit` does when the queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the worker frees a place.
+    #[default]
+    Wait,
+    /// Drop the oldest waiting message to make room.
+    DropOldest,
+    /// Fail the submission.
+    Reject,
+}
+
+/// The outcome of one message, filled in by the worker and awaited by a `Transmission`.
+#[derive(Default)]
+struct Outcome(Mutex<(Option<Result<Duration>>, Option<Waker>)>);
+
+impl Outcome {
+    fn complete(&self, result: Result<Duration>) {
+        let waker = {
+            let mut outcome = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            outcome.0 = Some(result);
+            outcome.1.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+struct Entry {
+    train: PulseTrain,
+    outcome: Arc<Outcome>,
+}
+
+#[derive(Default)]
+struct State {
+    entries: VecDeque<Entry>,
+    // Submissions waiting for a free place.
+    waiting: Vec<Waker>,
+    closed: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    queued: Condvar,
+    capacity: usize,
+    overflow: OverflowPolicy,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A bounded queue transmitting pulse trains on a background thread.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{OverflowPolicy, PulseTransmitterEmulator, TransmitQueue};
+///
+/// async fn set_speed(queue: &TransmitQueue, pulses: Vec<u32>) -> brickbeam::Result<()> {
+///     // Resolves once the queue has room for the message ...
+///     let transmission = queue.submit(pulses).await?;
+///     // ... and this once it is on air.
+///     transmission.await?;
+///     Ok(())
+/// }
+///
+/// let queue = TransmitQueue::new(PulseTransmitterEmulator, 8, OverflowPolicy::Wait).unwrap();
+/// let _ = set_speed(&queue, vec![157, 1026, 157, 1026]);
+/// ```
+pub struct TransmitQueue {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl TransmitQueue {
+    /// Starts a worker thread transmitting on `transmitter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `transmitter` - The transmitter the worker sends on.
+    /// * `capacity` - How many accepted messages may wait for the worker; at least one.
+    /// * `overflow` - What happens when a message is submitted to a full queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProtocolError` if `capacity` is zero.
+    pub fn new<T>(transmitter: T, capacity: usize, overflow: OverflowPolicy) -> Result<Self>
+    where
+        T: PulseTransmitter + Send + 'static,
+    {
+        if capacity == 0 {
+            return Err(Error::ProtocolError(
+                "The queue capacity must be at least one".into(),
+            ));
+        }
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            queued: Condvar::new(),
+            capacity,
+            overflow,
+        });
+        let worker = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(&shared, &transmitter))
+        };
+        Ok(Self {
+            shared,
+            worker: Some(worker),
+        })
+    }
+
+    /// Submits a message, returning a future that resolves once the queue accepted it.
+    ///
+    /// The future fails with `Error::Transmitting` if the queue is full under
+    /// `OverflowPolicy::Reject`.
+    pub fn submit(&self, train: impl Into<PulseTrain>) -> Submission {
+        Submission {
+            shared: Arc::clone(&self.shared),
+            train: Some(train.into()),
+        }
+    }
+
+    /// Returns the number of accepted messages waiting for the worker.
+    pub fn len(&self) -> usize {
+        self.shared.lock().entries.len()
+    }
+
+    /// Returns `true` if no accepted message is waiting for the worker.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns how many accepted messages may wait for the worker.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+}
+
+/// Stops accepting messages and waits until the worker has transmitted the accepted ones.
+impl Drop for TransmitQueue {
+    fn drop(&mut self) {
+        let waiting = {
+            let mut state = self.shared.lock();
+            state.closed = true;
+            std::mem::take(&mut state.waiting)
+        };
+        waiting.into_iter().for_each(Waker::wake);
+        self.shared.queued.notify_all();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run(shared: &Shared, transmitter: &impl PulseTransmitter) {
+    loop {
+        let (entry, waiting) = {
+            let mut state = shared.lock();
+            while state.entries.is_empty() && !state.closed {
+                state = shared.queued.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+            let Some(entry) = state.entries.pop_front() else {
+                return;
+            };
+            (entry, std::mem::take(&mut state.waiting))
+        };
+        waiting.into_iter().for_each(Waker::wake);
+        entry.outcome.complete(transmit(transmitter, &entry.train));
+    }
+}
+
+/// Sends every repeat of a pulse train, one message slot apart.
+fn transmit(transmitter: &impl PulseTransmitter, train: &PulseTrain) -> Result<Duration> {
+    let airtime = train.duration();
+    for copy in 0..train.repeats() {
+        let clock = transmitter.clock();
+        let started = clock.now();
+        transmitter.send_pulses(train.pulses())?;
+        if copy + 1 < train.repeats() {
+            wait_out_slot(clock, started, airtime, MAX_MESSAGE_DURATION);
+        }
+    }
+    Ok(airtime * train.repeats())
+}
+
+/// A future resolving once the queue has accepted a message, see `TransmitQueue::submit`.
+pub struct Submission {
+    shared: Arc<Shared>,
+    train: Option<PulseTrain>,
+}
+
+impl Future for Submission {
+    type Output = Result<Transmission>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let shared = Arc::clone(&self.shared);
+        let mut state = shared.lock();
+        if state.closed {
+            return Poll::Ready(Err(Error::Transmitting("The queue is closed".into())));
+        }
+        if state.entries.len() >= shared.capacity {
+            match shared.overflow {
+                OverflowPolicy::Wait => {
+                    state.waiting.push(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some(dropped) = state.entries.pop_front() {
+                        dropped.outcome.complete(Err(Error::Transmitting(
+                            "Dropped from the full queue".into(),
+                        )));
+                    }
+                }
+                OverflowPolicy::Reject => {
+                    return Poll::Ready(Err(Error::Transmitting("The queue is full".into())));
+                }
+            }
+        }
+        let Some(train) = self.train.take() else {
+            return Poll::Ready(Err(Error::Transmitting(
+                "The message was already submitted".into(),
+            )));
+        };
+        let outcome = Arc::new(Outcome::default());
+        state.entries.push_back(Entry {
+            train,
+            outcome: Arc::clone(&outcome),
+        });
+        shared.queued.notify_one();
+        Poll::Ready(Ok(Transmission { outcome }))
+    }
+}
+
+/// A future resolving with the airtime of an accepted message once it was transmitted.
+///
+/// It fails with the transmitter's error, or with `Error::Transmitting` if the message was
+/// dropped under `OverflowPolicy::DropOldest`.
+pub struct Transmission {
+    outcome: Arc<Outcome>,
+}
+
+impl Future for Transmission {
+    type Output = Result<Duration>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut outcome = self.outcome.0.lock().unwrap_or_else(|e| e.into_inner());
+        match outcome.0.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                outcome.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::task::Wake;
+
+    /// Signals the start of every transmission and blocks it until released.
+    struct MockTransmitterGated {
+        started: Mutex<Sender<()>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl PulseTransmitter for MockTransmitterGated {
+        fn send_pulses(&self, _pulses: &[u32]) -> Result<()> {
+            let _ = self.started.lock().unwrap().send(());
+            let _ = self.release.lock().unwrap().recv();
+            Ok(())
+        }
+    }
+
+    /// Creates a queue of capacity one whose worker is busy with a first message, returning the
+    /// queue, the first message's transmission and the sender releasing transmissions.
+    fn busy_queue(overflow: OverflowPolicy) -> (TransmitQueue, Transmission, Sender<()>) {
+        let (started, started_rx) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        let transmitter = MockTransmitterGated {
+            started: Mutex::new(started),
+            release: Mutex::new(release_rx),
+        };
+        let queue = TransmitQueue::new(transmitter, 1, overflow).unwrap();
+        let first = block_on(queue.submit(vec![100, 200])).unwrap();
+        started_rx.recv().unwrap();
+        (queue, first, release)
+    }
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn test_wait_applies_backpressure() {
+        let (queue, first, release) = busy_queue(OverflowPolicy::Wait);
+        let second = block_on(queue.submit(vec![100, 200])).unwrap();
+        let mut third = queue.submit(vec![100, 200]);
+        assert!(poll_once(&mut third).is_pending());
+        assert_eq!(queue.len(), 1);
+
+        release.send(()).unwrap();
+        assert_eq!(block_on(first).unwrap(), Duration::from_micros(300));
+        let third = block_on(third).unwrap();
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        assert!(block_on(second).is_ok());
+        assert!(block_on(third).is_ok());
+    }
+
+    #[test]
+    fn test_drop_oldest_fails_the_dropped_transmission() {
+        let (queue, _first, release) = busy_queue(OverflowPolicy::DropOldest);
+        let second = block_on(queue.submit(vec![100, 200])).unwrap();
+        let third = block_on(queue.submit(vec![100, 200])).unwrap();
+        assert!(matches!(block_on(second), Err(Error::Transmitting(_))));
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        assert!(block_on(third).is_ok());
+    }
+
+    #[test]
+    fn test_reject_fails_the_submission() {
+        let (queue, _first, release) = busy_queue(OverflowPolicy::Reject);
+        let second = block_on(queue.submit(vec![100, 200])).unwrap();
+        assert!(matches!(
+            block_on(queue.submit(vec![100, 200])),
+            Err(Error::Transmitting(_))
+        ));
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        assert!(block_on(second).is_ok());
+    }
+
+    #[test]
+    fn test_zero_capacity_is_invalid() {
+        let transmitter = crate::PulseTransmitterEmulator;
+        assert!(TransmitQueue::new(transmitter, 0, OverflowPolicy::Wait).is_err());
+    }
+}
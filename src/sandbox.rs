@@ -0,0 +1,534 @@
+//! # Sandbox
+//!
+//! Runs sequences headlessly, without a transmitter or real time passing, and reports how the
+//! receivers would react. This lets automation authors test their programs in CI without a
+//! Raspberry Pi or a track.
+//!
+//! A `Sandbox` combines
+//! - a virtual clock, advanced by each message's airtime and the step's wait instead of sleeping,
+//! - a `VirtualReceiver` per channel, which applies every command the way a PF receiver does,
+//!   including the timeout that floats Combo outputs which aren't refreshed,
+//! - an `OutputMotion` per output, integrating the speed and distance of the driven motor with
+//!   a simple `MotionModel`,
+//! - and a timeline recording the receiver outputs after every step and timeout.
+
+use crate::protocols::duration_of;
+use crate::sequence::{ActionEncoder, Sequence, SequenceAction, SequenceStep};
+use crate::{
+    Channel, DirectState, ExtendedCommand, Output, Result, SingleOutputCommand,
+    SingleOutputDiscrete,
+};
+use std::fmt;
+use std::time::Duration;
+
+/// The state of one receiver output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputState {
+    /// The motor runs freely.
+    #[default]
+    Float,
+    /// The motor is short-circuited.
+    Brake,
+    /// The motor runs at a PWM step from -7 to 7 (never 0, which is `Float`).
+    Pwm(i8),
+}
+
+impl OutputState {
+    fn from_step(step: i8) -> Self {
+        match step.clamp(-7, 7) {
+            0 => Self::Float,
+            step => Self::Pwm(step),
+        }
+    }
+
+    fn step(self) -> i8 {
+        match self {
+            Self::Pwm(step) => step,
+            Self::Float | Self::Brake => 0,
+        }
+    }
+}
+
+impl fmt::Display for OutputState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Float => write!(f, "float"),
+            Self::Brake => write!(f, "brake"),
+            Self::Pwm(step) => write!(f, "{:+}", step),
+        }
+    }
+}
+
+/// How long a receiver keeps the outputs set by a Combo Direct or Combo PWM message without a
+/// refresh before it floats them, in case the remote went out of range.
+pub const RECEIVER_TIMEOUT: Duration = Duration::from_millis(1200);
+
+/// Simulates the output stage of a PF receiver on one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VirtualReceiver {
+    /// The red and blue outputs, indexed by `Output`.
+    pub outputs: [OutputState; 2],
+    // The outputs last set by a Combo message, which float on a timeout.
+    watched: [bool; 2],
+}
+
+impl VirtualReceiver {
+    /// Returns the state of one output.
+    pub fn output(&self, output: Output) -> OutputState {
+        self.outputs[output as usize]
+    }
+
+    /// Returns whether an output was set by a Combo message and floats on a timeout.
+    pub fn times_out(&self, output: Output) -> bool {
+        self.watched[output as usize]
+    }
+
+    /// Applies a received command.
+    pub fn apply(&mut self, action: SequenceAction) {
+        match action {
+            SequenceAction::SingleOutput(output, cmd) => {
+                let state = &mut self.outputs[output as usize];
+                *state = single_output(*state, cmd);
+                self.watched[output as usize] = false;
+            }
+            SequenceAction::ComboDirect(cmd) => {
+                self.outputs = [direct(cmd.red), direct(cmd.blue)];
+                self.watched = [true; 2];
+            }
+            SequenceAction::ComboPwm(cmd) => {
+                self.outputs = [pwm(cmd.speed_red), pwm(cmd.speed_blue)];
+                self.watched = [true; 2];
+            }
+            SequenceAction::Extended(cmd) => {
+                let [red, blue] = &mut self.outputs;
+                match cmd {
+                    ExtendedCommand::BrakeThenFloatOnRedOutput => *red = OutputState::Float,
+                    ExtendedCommand::IncrementSpeedOnRedOutput => {
+                        *red = OutputState::from_step(red.step() + 1)
+                    }
+                    ExtendedCommand::DecrementSpeedOnRedOutput => {
+                        *red = OutputState::from_step(red.step() - 1)
+                    }
+                    ExtendedCommand::ToggleForwardOrFloatOnBlueOutput => {
+                        *blue = match blue {
+                            OutputState::Float => OutputState::Pwm(7),
+                            _ => OutputState::Float,
+                        };
+                        self.watched[Output::BLUE as usize] = false;
+                        return;
+                    }
+                    ExtendedCommand::ToggleAddress | ExtendedCommand::AlignToggle => return,
+                }
+                self.watched[Output::RED as usize] = false;
+            }
+        }
+    }
+
+    /// Floats the outputs set by a Combo message, as the receiver does when no message refreshed
+    /// them for `RECEIVER_TIMEOUT`. Returns whether any output changed.
+    pub fn time_out(&mut self) -> bool {
+        let mut changed = false;
+        for (state, watched) in self.outputs.iter_mut().zip(&mut self.watched) {
+            if *watched {
+                changed |= *state != OutputState::Float;
+                *state = OutputState::Float;
+                *watched = false;
+            }
+        }
+        changed
+    }
+}
+
+/// How the motor on an output picks up and loses speed, in distance units per second.
+///
+/// The speed changes at a constant rate towards the target of the output: full PWM (step 7)
+/// drives at `top_speed`, lower steps proportionally slower, and a floating motor coasts down to
+/// a stop. Braking stops the motor at once. The defaults roughly match a PF train motor in cm/s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionModel {
+    /// The speed at PWM step 7.
+    pub top_speed: f64,
+    /// How fast the speed changes while driven, per second.
+    pub acceleration: f64,
+    /// How fast the speed drops while floating, per second.
+    pub coasting: f64,
+}
+
+impl Default for MotionModel {
+    fn default() -> Self {
+        Self {
+            top_speed: 50.0,
+            acceleration: 50.0,
+            coasting: 100.0,
+        }
+    }
+}
+
+/// The simulated motion of the motor on one output.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OutputMotion {
+    /// The speed, negative when running backwards.
+    pub speed: f64,
+    /// The distance travelled since the start, negative when it went backwards more.
+    pub position: f64,
+}
+
+impl OutputMotion {
+    /// Moves the motor for `elapsed` while its output is in `state`.
+    fn advance(&mut self, model: &MotionModel, state: OutputState, elapsed: Duration) {
+        let (target, rate) = match state {
+            OutputState::Brake => (0.0, f64::INFINITY),
+            OutputState::Float => (0.0, model.coasting),
+            OutputState::Pwm(step) => (model.top_speed * f64::from(step) / 7.0, model.acceleration),
+        };
+        let elapsed = elapsed.as_secs_f64();
+        let change = target - self.speed;
+        // The speed ramps until it reaches the target and stays there; braking stops at once.
+        let ramp = if rate.is_infinite() {
+            0.0
+        } else {
+            (change.abs() / rate).min(elapsed)
+        };
+        let speed = if rate.is_infinite() || ramp < elapsed {
+            target
+        } else {
+            self.speed + change.signum() * rate * ramp
+        };
+        self.position += (self.speed + speed) / 2.0 * ramp + speed * (elapsed - ramp);
+        self.speed = speed;
+    }
+}
+
+fn pwm(speed: i8) -> OutputState {
+    if speed == 8 {
+        OutputState::Brake
+    } else {
+        OutputState::from_step(speed)
+    }
+}
+
+fn direct(state: DirectState) -> OutputState {
+    match state {
+        DirectState::Float => OutputState::Float,
+        DirectState::Forward => OutputState::Pwm(7),
+        DirectState::Backward => OutputState::Pwm(-7),
+        DirectState::Brake => OutputState::Brake,
+    }
+}
+
+fn single_output(state: OutputState, cmd: SingleOutputCommand) -> OutputState {
+    use SingleOutputDiscrete::*;
+
+    let step = state.step();
+    let discrete = match cmd {
+        SingleOutputCommand::PWM(speed) => return pwm(speed),
+        SingleOutputCommand::Discrete(discrete) => discrete,
+    };
+    match discrete {
+        ToggleFullForward => match state {
+            OutputState::Pwm(7) => OutputState::Float,
+            _ => OutputState::Pwm(7),
+        },
+        ToggleFullBackward => match state {
+            OutputState::Pwm(-7) => OutputState::Float,
+            _ => OutputState::Pwm(-7),
+        },
+        ToggleFullForwardBackward => match state {
+            OutputState::Pwm(7) => OutputState::Pwm(-7),
+            _ => OutputState::Pwm(7),
+        },
+        ToggleDirection => OutputState::from_step(-step),
+        IncrementNumericalPwm => OutputState::from_step(step + 1),
+        DecrementNumericalPwm => OutputState::from_step(step - 1),
+        // Increment/Decrement PWM speed up or slow down in the current direction.
+        IncrementPwm if step < 0 => OutputState::from_step(step - 1),
+        IncrementPwm => OutputState::from_step(step + 1),
+        DecrementPwm if step < 0 => OutputState::from_step(step + 1),
+        DecrementPwm => OutputState::from_step(step - 1),
+        FullForward => OutputState::Pwm(7),
+        FullBackward => OutputState::Pwm(-7),
+        ClearC1 | SetC1 | ToggleC1 | ClearC2 | SetC2 | ToggleC2 => state,
+    }
+}
+
+/// The receiver outputs of one channel at a point in virtual time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelineEntry {
+    /// Virtual time at which the receiver acted, i.e. the end of the message or the timeout.
+    pub at: Duration,
+    pub channel: Channel,
+    pub red: OutputState,
+    pub blue: OutputState,
+    /// Whether the receiver floated its Combo outputs because no message refreshed them.
+    pub timed_out: bool,
+    /// The motion of the red and blue motors at that time, indexed by `Output`.
+    pub motion: [OutputMotion; 2],
+}
+
+impl fmt::Display for TimelineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [red, blue] = self.motion;
+        write!(
+            f,
+            "{:>10.3} s  {:?}  red={} blue={}  v={:+.1}/{:+.1} x={:+.1}/{:+.1}{}",
+            self.at.as_secs_f64(),
+            self.channel,
+            self.red,
+            self.blue,
+            red.speed,
+            blue.speed,
+            red.position,
+            blue.position,
+            if self.timed_out { "  (timeout)" } else { "" }
+        )
+    }
+}
+
+/// Executes sequences against virtual receivers on a virtual clock.
+///
+/// Between the messages, the receivers time out as real ones do and the motors move as the
+/// `MotionModel` says. Combo Direct and Combo PWM sequences therefore need a message at least
+/// every `RECEIVER_TIMEOUT` to keep their outputs running.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::sandbox::{OutputState, Sandbox};
+/// use brickbeam::Sequence;
+///
+/// let sequence: Sequence = "1 pwm red 5 wait 2s\n1 pwm red 0".parse().unwrap();
+/// let mut sandbox = Sandbox::new().unwrap();
+/// let timeline = sandbox.run(&sequence).unwrap();
+/// assert_eq!(timeline[0].red, OutputState::Pwm(5));
+/// assert_eq!(timeline[1].red, OutputState::Float);
+/// assert!(sandbox.now() > std::time::Duration::from_secs(2));
+/// ```
+pub struct Sandbox {
+    encoder: ActionEncoder<'static>,
+    receivers: [VirtualReceiver; 4],
+    // When each receiver got its last message.
+    refreshed: [Duration; 4],
+    model: MotionModel,
+    motion: [[OutputMotion; 2]; 4],
+    now: Duration,
+    timeline: Vec<TimelineEntry>,
+}
+
+impl Sandbox {
+    /// Creates a sandbox with all outputs floating and all motors standing at virtual time zero.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            encoder: ActionEncoder::new()?,
+            receivers: [VirtualReceiver::default(); 4],
+            refreshed: [Duration::ZERO; 4],
+            model: MotionModel::default(),
+            motion: [[OutputMotion::default(); 2]; 4],
+            now: Duration::ZERO,
+            timeline: Vec::new(),
+        })
+    }
+
+    /// Moves the motors as `model` says instead of the default model.
+    pub fn with_motion_model(mut self, model: MotionModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Executes every step of `sequence` and returns the whole timeline recorded so far.
+    pub fn run(&mut self, sequence: &Sequence) -> Result<&[TimelineEntry]> {
+        for step in sequence.steps() {
+            self.step(*step)?;
+        }
+        Ok(&self.timeline)
+    }
+
+    /// Executes a single step: encodes its message, advances the clock by the airtime, applies
+    /// the command to the receiver and then advances the clock by the step's wait. Receivers
+    /// floating outputs on a timeout on the way add their own entries to the timeline.
+    pub fn step(&mut self, step: SequenceStep) -> Result<TimelineEntry> {
+        let pulses = self.encoder.encode(step.channel, step.action)?;
+        self.advance(duration_of(&pulses));
+        self.receivers[step.channel as usize].apply(step.action);
+        self.refreshed[step.channel as usize] = self.now;
+        let entry = self.record(step.channel, false);
+        self.advance(step.wait);
+        Ok(entry)
+    }
+
+    /// Lets `elapsed` pass on the virtual clock, moving the motors and timing out the receivers.
+    pub fn advance(&mut self, elapsed: Duration) {
+        let until = self.now + elapsed;
+        loop {
+            let next = Channel::iter()
+                .filter(|&channel| {
+                    Output::iter().any(|output| self.receivers[channel as usize].times_out(output))
+                })
+                .map(|channel| (self.refreshed[channel as usize] + RECEIVER_TIMEOUT, channel))
+                .filter(|&(deadline, _)| deadline <= until)
+                .min_by_key(|&(deadline, _)| deadline);
+            let Some((deadline, channel)) = next else {
+                break;
+            };
+            self.move_motors(deadline.saturating_sub(self.now));
+            if self.receivers[channel as usize].time_out() {
+                self.record(channel, true);
+            }
+        }
+        self.move_motors(until - self.now);
+    }
+
+    fn move_motors(&mut self, elapsed: Duration) {
+        for (receiver, motion) in self.receivers.iter().zip(&mut self.motion) {
+            for (state, motion) in receiver.outputs.iter().zip(motion) {
+                motion.advance(&self.model, *state, elapsed);
+            }
+        }
+        self.now += elapsed;
+    }
+
+    fn record(&mut self, channel: Channel, timed_out: bool) -> TimelineEntry {
+        let receiver = &self.receivers[channel as usize];
+        let entry = TimelineEntry {
+            at: self.now,
+            channel,
+            red: receiver.output(Output::RED),
+            blue: receiver.output(Output::BLUE),
+            timed_out,
+            motion: self.motion[channel as usize],
+        };
+        self.timeline.push(entry);
+        entry
+    }
+
+    /// Returns the current virtual time.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Returns the receiver of a channel.
+    pub fn receiver(&self, channel: Channel) -> &VirtualReceiver {
+        &self.receivers[channel as usize]
+    }
+
+    /// Returns the motion of the motor on one output.
+    pub fn motion(&self, channel: Channel, output: Output) -> OutputMotion {
+        self.motion[channel as usize][output as usize]
+    }
+
+    /// Returns the timeline recorded so far.
+    pub fn timeline(&self) -> &[TimelineEntry] {
+        &self.timeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ComboDirectCommand, ComboPwmCommand};
+
+    #[test]
+    fn test_receiver_applies_every_protocol() {
+        let mut receiver = VirtualReceiver::default();
+        receiver.apply(SequenceAction::ComboPwm(ComboPwmCommand::brake_both()));
+        assert_eq!(receiver.outputs, [OutputState::Brake; 2]);
+        receiver.apply(SequenceAction::ComboDirect(ComboDirectCommand::from((
+            DirectState::Forward,
+            DirectState::Backward,
+        ))));
+        assert_eq!(
+            receiver.outputs,
+            [OutputState::Pwm(7), OutputState::Pwm(-7)]
+        );
+        receiver.apply(SequenceAction::Extended(
+            ExtendedCommand::DecrementSpeedOnRedOutput,
+        ));
+        receiver.apply(SequenceAction::Extended(
+            ExtendedCommand::ToggleForwardOrFloatOnBlueOutput,
+        ));
+        assert_eq!(receiver.outputs, [OutputState::Pwm(6), OutputState::Float]);
+        receiver.apply(SequenceAction::SingleOutput(
+            Output::BLUE,
+            SingleOutputCommand::Discrete(SingleOutputDiscrete::DecrementPwm),
+        ));
+        assert_eq!(receiver.output(Output::BLUE), OutputState::Pwm(-1));
+        receiver.apply(SequenceAction::SingleOutput(
+            Output::BLUE,
+            SingleOutputCommand::Discrete(SingleOutputDiscrete::IncrementPwm),
+        ));
+        assert_eq!(receiver.output(Output::BLUE), OutputState::Pwm(-2));
+    }
+
+    #[test]
+    fn test_sandbox_runs_on_virtual_clock() {
+        let sequence: Sequence = "train cargo 2\n\
+                                  cargo combo 3 -3 wait 60s\n\
+                                  cargo combo 0 0"
+            .parse()
+            .unwrap();
+        let mut sandbox = Sandbox::new().unwrap();
+        let started = std::time::Instant::now();
+        let timeline = sandbox.run(&sequence).unwrap().to_vec();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(timeline.len(), 3);
+        assert_eq!(timeline[0].channel, Channel::Two);
+        assert_eq!(timeline[0].red, OutputState::Pwm(3));
+        assert_eq!(timeline[0].blue, OutputState::Pwm(-3));
+        assert!(timeline[0].to_string().contains("Two  red=+3 blue=-3"));
+        // Nothing refreshed the Combo PWM outputs, so the receiver floated them.
+        assert!(timeline[1].timed_out);
+        assert_eq!(timeline[1].at, timeline[0].at + RECEIVER_TIMEOUT);
+        assert_eq!(timeline[1].red, OutputState::Float);
+        assert!(timeline[2].at > Duration::from_secs(60));
+        assert_eq!(timeline[2].red, OutputState::Float);
+    }
+
+    #[test]
+    fn test_single_output_does_not_time_out() {
+        let sequence: Sequence = "1 pwm red 7 wait 10s".parse().unwrap();
+        let mut sandbox = Sandbox::new().unwrap();
+        assert_eq!(sandbox.run(&sequence).unwrap().len(), 1);
+        assert_eq!(
+            sandbox.receiver(Channel::One).output(Output::RED),
+            OutputState::Pwm(7)
+        );
+    }
+
+    #[test]
+    fn test_motion_integrates_speed_and_position() {
+        let model = MotionModel {
+            top_speed: 70.0,
+            acceleration: 35.0,
+            coasting: 10.0,
+        };
+        let mut motion = OutputMotion::default();
+        // 2 s to reach full speed, covering 70, then 1 s at it.
+        motion.advance(&model, OutputState::Pwm(7), Duration::from_secs(3));
+        assert_eq!(motion.speed, 70.0);
+        assert_eq!(motion.position, 140.0);
+        motion.advance(&model, OutputState::Float, Duration::from_secs(1));
+        assert_eq!(motion.speed, 60.0);
+        assert_eq!(motion.position, 205.0);
+        motion.advance(&model, OutputState::Pwm(-3), Duration::from_secs(1));
+        assert_eq!(motion.speed, 25.0);
+        motion.advance(&model, OutputState::Brake, Duration::from_secs(1));
+        assert_eq!(motion.speed, 0.0);
+        assert_eq!(motion.position, 247.5);
+    }
+
+    #[test]
+    fn test_sandbox_moves_motors_until_timeout() {
+        let sequence: Sequence = "1 combo 7 0 wait 5s".parse().unwrap();
+        let mut sandbox = Sandbox::new().unwrap().with_motion_model(MotionModel {
+            top_speed: 10.0,
+            acceleration: f64::INFINITY,
+            coasting: f64::INFINITY,
+        });
+        let timeline = sandbox.run(&sequence).unwrap().to_vec();
+        assert_eq!(timeline.len(), 2);
+        let driven = timeline[1].at - timeline[0].at;
+        let red = sandbox.motion(Channel::One, Output::RED);
+        assert_eq!(red.speed, 0.0);
+        assert!((red.position - 10.0 * driven.as_secs_f64()).abs() < 1e-9);
+        assert_eq!(sandbox.motion(Channel::One, Output::BLUE).position, 0.0);
+    }
+}
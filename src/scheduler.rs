@@ -0,0 +1,795 @@
+//! # Scheduler
+//!
+//! Only one message can be on the IR medium at a time. With four trains plus lights, new commands
+//! and the keepalives that stop Combo PWM receivers from timing out compete for the same airtime,
+//! so something has to decide which message goes next. `Scheduler` keeps the pending messages of
+//! every channel and hands them out one by one according to a `FairnessPolicy`.
+//!
+//! The scheduler only orders messages; it neither encodes nor transmits them. That keeps the
+//! arbitration logic deterministic and testable, and lets callers drive it from whatever loop or
+//! thread suits them.
+//!
+//! `poll` additionally keeps track of when the medium is free: every message handed out reserves
+//! the medium for its airtime (`PulseTrain::duration` times its repeats), with each transmission
+//! occupying at least one message slot. Short messages therefore follow each other as closely as
+//! the minimum gap allows, while long or repeated ones are never cut into.
+//!
+//! The pending messages can be saved to and restored from a small text file (`save` and
+//! `restore`), so a daemon that is restarted in the middle of a sequence resumes where it left
+//! off instead of losing the rest of it. With `with_store`, every change to the queue is written
+//! through to the file, and the file is synced to the disk before it replaces the previous one, so
+//! even a crash or power loss loses nothing that was queued.
+//!
+//! Within a channel, messages are always sent in the order they were queued. A keepalive is only
+//! a refresh of the last command, so at most one is kept per channel, and queueing a new command
+//! on that channel drops it.
+//!
+//! A real PF remote sends every message five times, with pauses that depend on its channel (see
+//! `timing::spec_repeat_delays`), so that up to four remotes pressed at once collide on a few
+//! copies at most. A single emitter driving four trains can do better: it knows all messages in
+//! advance and can place the copies of one channel into the pauses of the others. That is what
+//! `FairnessPolicy::SlotTiming` does: `poll` hands out every copy on its own, in the spec pauses
+//! of its channel, and copies that fall due at the same moment follow each other back to back,
+//! so a channel's rhythm shifts by at most a few message lengths. `run` drives the scheduler on a
+//! transmitter until every message is sent.
+
+use crate::device::PulseTransmitter;
+use crate::protocols::timing::{spec_repeat_slots, wait_out_slot, SPEC_REPEATS};
+use crate::protocols::MAX_MESSAGE_DURATION;
+use crate::{Channel, Error, PulseTrain, Result};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The first line of a saved queue, identifying the file format.
+const STORE_HEADER: &str = "# brickbeam queue v1";
+
+/// Numbers the temporary files of `Scheduler::save`, so concurrent saves never share one.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// How the `Scheduler` chooses between channels that have pending messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FairnessPolicy {
+    /// Serve the channels in turn, starting after the channel served last.
+    #[default]
+    RoundRobin,
+    /// Serve the channel whose pending message was queued most recently.
+    NewestFirst,
+    /// Serve the channel with the highest priority (see `Scheduler::set_priority`).
+    /// Channels of equal priority are served round-robin.
+    Priority,
+    /// Send every message as `PulseTrain::repeats` separate copies, at most the five of the PF
+    /// spec, each in the pause the spec prescribes for its channel, interleaving the copies of
+    /// different channels. The earliest due copy is served first, the lower channel on a tie.
+    ///
+    /// Only `poll` follows the pauses; `pop` hands out the copies left of a message at once and
+    /// serves the channels round-robin.
+    SlotTiming,
+}
+
+#[derive(Debug)]
+struct Pending {
+    train: PulseTrain,
+    keepalive: bool,
+    sequence: u64,
+    /// The number of copies handed out by `FairnessPolicy::SlotTiming`.
+    sent: u32,
+    /// When the next copy falls due under `FairnessPolicy::SlotTiming`, once the message is at
+    /// the front of its queue and has been seen by `poll`.
+    due: Option<Instant>,
+}
+
+impl Pending {
+    /// Returns the message with the copies that are left to send.
+    fn remaining(&self) -> PulseTrain {
+        PulseTrain::new(self.train.pulses().to_vec())
+            .with_repeats(self.train.repeats().saturating_sub(self.sent))
+    }
+}
+
+/// Orders pending messages of all channels according to a `FairnessPolicy`.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{Channel, FairnessPolicy, PulseTrain, Scheduler};
+///
+/// let mut scheduler = Scheduler::new(FairnessPolicy::Priority);
+/// scheduler.set_priority(Channel::Two, 10);
+/// scheduler.push(Channel::One, vec![157, 1026]);
+/// scheduler.push(Channel::Two, PulseTrain::new(vec![157, 1026]).with_repeats(2));
+/// assert_eq!(scheduler.pop().map(|(channel, _)| channel), Some(Channel::Two));
+/// ```
+#[derive(Debug)]
+pub struct Scheduler {
+    policy: FairnessPolicy,
+    queues: [VecDeque<Pending>; 4],
+    priorities: [u8; 4],
+    last_served: Option<Channel>,
+    sequence: u64,
+    message_slot: Duration,
+    ready_at: Option<Instant>,
+    store: Option<PathBuf>,
+    store_error: Option<Error>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(FairnessPolicy::default())
+    }
+}
+
+impl Scheduler {
+    /// Creates an empty scheduler using the given policy.
+    pub fn new(policy: FairnessPolicy) -> Self {
+        Self {
+            policy,
+            queues: Default::default(),
+            priorities: [0; 4],
+            last_served: None,
+            sequence: 0,
+            message_slot: MAX_MESSAGE_DURATION,
+            ready_at: None,
+            store: None,
+            store_error: None,
+        }
+    }
+
+    /// Writes the pending messages to `path` after every change to the queue, i.e. `push`,
+    /// `push_keepalive`, `restore`, `clear` and every message handed out.
+    ///
+    /// The file is not read; call `restore` with the same path to resume a saved queue. A failed
+    /// write doesn't stop the scheduler; the next change tries again and `take_store_error`
+    /// reports the failure.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use brickbeam::{Channel, Scheduler};
+    ///
+    /// let mut scheduler = Scheduler::default().with_store("/var/lib/brickbeam/queue");
+    /// scheduler.restore("/var/lib/brickbeam/queue")?;
+    /// scheduler.push(Channel::One, vec![157, 1026]);
+    /// # Ok::<(), brickbeam::Error>(())
+    /// ```
+    pub fn with_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.store = Some(path.into());
+        self
+    }
+
+    /// Returns and clears the error of the last failed write to the store, if the writes
+    /// haven't succeeded since.
+    pub fn take_store_error(&mut self) -> Option<Error> {
+        self.store_error.take()
+    }
+
+    /// Writes the queue through to the store, if one is configured.
+    fn write_through(&mut self) {
+        if let Some(path) = &self.store {
+            self.store_error = self.save(path).err();
+        }
+    }
+
+    /// Sets the minimum start-to-start time of two transmissions handed out by `poll`.
+    ///
+    /// Defaults to the maximum PF message length; transmissions longer than the slot keep the
+    /// medium busy for their full airtime. With `FairnessPolicy::SlotTiming`, it is the pause unit
+    /// `tm` instead, and every copy only occupies the medium for its airtime; receivers tolerating
+    /// shorter pauses allow faster updates.
+    pub fn set_message_slot(&mut self, message_slot: Duration) {
+        self.message_slot = message_slot;
+    }
+
+    /// Returns the active policy.
+    pub fn policy(&self) -> FairnessPolicy {
+        self.policy
+    }
+
+    /// Switches the policy; pending messages are kept.
+    pub fn set_policy(&mut self, policy: FairnessPolicy) {
+        self.policy = policy;
+    }
+
+    /// Sets the priority of a channel (higher is served first) for `FairnessPolicy::Priority`.
+    /// All channels start at priority 0.
+    pub fn set_priority(&mut self, channel: Channel, priority: u8) {
+        self.priorities[channel as usize] = priority;
+    }
+
+    /// Queues a new command for the channel, superseding its pending keepalive.
+    ///
+    /// Under `FairnessPolicy::SlotTiming`, it also supersedes the copies left of a message that
+    /// is partly sent, as the receiver only needs one of them and the newer command replaces it.
+    pub fn push(&mut self, channel: Channel, train: impl Into<PulseTrain>) {
+        self.enqueue(channel, train.into(), false);
+        self.write_through();
+    }
+
+    /// Queues a keepalive for the channel, replacing a keepalive that is still pending.
+    pub fn push_keepalive(&mut self, channel: Channel, train: impl Into<PulseTrain>) {
+        self.enqueue(channel, train.into(), true);
+        self.write_through();
+    }
+
+    fn enqueue(&mut self, channel: Channel, train: PulseTrain, keepalive: bool) {
+        let queue = &mut self.queues[channel as usize];
+        queue.retain(|pending| !pending.keepalive && pending.sent == 0);
+        queue.push_back(Pending {
+            train,
+            keepalive,
+            sequence: self.sequence,
+            sent: 0,
+            due: None,
+        });
+        self.sequence += 1;
+    }
+
+    /// Writes all pending messages to `path`, replacing the file atomically. The new file is synced
+    /// to the disk before the rename, and the directory after it.
+    ///
+    /// Messages are stored in the order they were queued, one per line, so `restore` recreates
+    /// the same queue. Priorities and the policy are configuration and are not stored.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut pending: Vec<(Channel, &Pending)> = Channel::iter()
+            .flat_map(|channel| {
+                self.queues[channel as usize]
+                    .iter()
+                    .map(move |pending| (channel, pending))
+            })
+            .collect();
+        pending.sort_by_key(|(_, pending)| pending.sequence);
+
+        let mut content = String::new();
+        let _ = writeln!(content, "{}", STORE_HEADER);
+        for (channel, pending) in pending {
+            let pulses: Vec<String> = pending.train.pulses().iter().map(u32::to_string).collect();
+            let _ = writeln!(
+                content,
+                "{} {} {} {}",
+                channel as u8 + 1,
+                if pending.keepalive {
+                    "keepalive"
+                } else {
+                    "command"
+                },
+                pending.remaining().repeats(),
+                pulses.join(",")
+            );
+        }
+
+        // A hidden file next to the target, unique to this process and save, so the rename
+        // stays on one file system and never picks up another writer's half-written file.
+        let path = path.as_ref();
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(path.file_name().unwrap_or_default());
+        temp_name.push(format!(
+            ".{}.{}.tmp",
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp = path.with_file_name(temp_name);
+        let written = write_synced(&temp, &content).and_then(|()| {
+            fs::rename(&temp, path)?;
+            sync_parent(path)
+        });
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        Ok(written?)
+    }
+
+    /// Queues the messages saved at `path` after any messages that are already pending.
+    ///
+    /// The file is validated as a whole; nothing is queued if any line is invalid.
+    ///
+    /// A missing file is treated as an empty queue, so a daemon can restore unconditionally on
+    /// startup. Returns the number of restored messages.
+    pub fn restore(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut lines = content.lines();
+        if lines.next() != Some(STORE_HEADER) {
+            return Err(store_error("missing or unknown header"));
+        }
+        let messages = lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                parse_store_line(line)
+                    .ok_or_else(|| store_error(&format!("invalid line '{}'", line)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let restored = messages.len();
+        for (channel, keepalive, train) in messages {
+            self.enqueue(channel, train, keepalive);
+        }
+        if restored > 0 {
+            self.write_through();
+        }
+        Ok(restored)
+    }
+
+    /// Removes and returns the next message to transmit, if any, regardless of airtime.
+    pub fn pop(&mut self) -> Option<(Channel, PulseTrain)> {
+        let channel = self.next_channel()?;
+        let pending = self.queues[channel as usize].pop_front()?;
+        self.last_served = Some(channel);
+        self.write_through();
+        Some((channel, pending.remaining()))
+    }
+
+    /// Removes and returns the next message if the medium is free at `now`, reserving the medium
+    /// for the message's airtime.
+    ///
+    /// Returns `None` while a previously handed out message still occupies the medium or if no
+    /// message is pending; `ready_at` tells when to poll again.
+    ///
+    /// With `FairnessPolicy::SlotTiming`, it returns the next copy that is due at `now` instead,
+    /// keeping the message queued until all its copies are handed out. The pause before the first
+    /// copy counts from the first `poll` after the message reached the front of its queue.
+    pub fn poll(&mut self, now: Instant) -> Option<(Channel, PulseTrain)> {
+        if self.policy == FairnessPolicy::SlotTiming {
+            self.schedule_first_copies(now);
+        }
+        if self.ready_at.is_some_and(|ready_at| now < ready_at) {
+            return None;
+        }
+        let (channel, train, occupancy) = if self.policy == FairnessPolicy::SlotTiming {
+            let (channel, copy) = self.pop_due_copy(now)?;
+            let airtime = copy.duration();
+            (channel, copy, airtime)
+        } else {
+            let (channel, train) = self.pop()?;
+            let occupancy = train.occupancy(self.message_slot);
+            (channel, train, occupancy)
+        };
+        self.ready_at = Some(now + occupancy);
+        Some((channel, train))
+    }
+
+    /// Returns the earliest time `poll` may hand out the next message, or `None` if the medium
+    /// has not been used yet.
+    ///
+    /// With `FairnessPolicy::SlotTiming`, it also waits for the next copy to fall due.
+    pub fn ready_at(&self) -> Option<Instant> {
+        if self.policy != FairnessPolicy::SlotTiming {
+            return self.ready_at;
+        }
+        // Messages not seen by `poll` yet have no due time and sort first.
+        match self
+            .queues
+            .iter()
+            .filter_map(VecDeque::front)
+            .map(|pending| pending.due)
+            .min()
+        {
+            Some(Some(due)) => Some(self.ready_at.map_or(due, |ready_at| ready_at.max(due))),
+            _ => self.ready_at,
+        }
+    }
+
+    /// Transmits all pending messages on `transmitter`, sleeping on its clock until `poll` hands
+    /// out the next one. The repeats of a message are sent in slots of at least the message slot.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of the transmitter; the messages not handed out yet stay queued.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use brickbeam::{timing::PulseTiming, Channel, FairnessPolicy, PulseTrain};
+    /// use brickbeam::{PulseTransmitterEmulator, Scheduler};
+    ///
+    /// let mut scheduler = Scheduler::new(FairnessPolicy::SlotTiming);
+    /// // Full speed forward on the red output of all four channels.
+    /// for (channel, frame) in Channel::iter().zip([0x047C, 0x147D, 0x247E, 0x347F]) {
+    ///     let pulses = PulseTiming::STANDARD.encode_frame(frame).to_vec();
+    ///     scheduler.push(channel, PulseTrain::new(pulses).with_repeats(5));
+    /// }
+    /// scheduler.run(&PulseTransmitterEmulator).unwrap();
+    /// ```
+    pub fn run<T: PulseTransmitter + ?Sized>(&mut self, transmitter: &T) -> Result<()> {
+        let clock = transmitter.clock();
+        while !self.is_empty() {
+            let now = clock.now();
+            let Some((_, train)) = self.poll(now) else {
+                let ready_at = self.ready_at().unwrap_or(now);
+                clock.sleep(ready_at.saturating_duration_since(now));
+                continue;
+            };
+            for repeat in 0..train.repeats() {
+                let started = clock.now();
+                transmitter.send_pulses(train.pulses())?;
+                if repeat + 1 < train.repeats() {
+                    wait_out_slot(clock, started, train.duration(), self.message_slot);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of pending messages over all channels.
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Returns `true` if no message is pending.
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    /// Drops all pending messages of the channel.
+    pub fn clear(&mut self, channel: Channel) {
+        self.queues[channel as usize].clear();
+        self.write_through();
+    }
+
+    /// Schedules the first copy of the messages that reached the front of their queue since the
+    /// last `poll`.
+    fn schedule_first_copies(&mut self, now: Instant) {
+        for channel in Channel::iter() {
+            if let Some(head) = self.queues[channel as usize].front_mut() {
+                let first = self.message_slot * spec_repeat_slots(channel)[0];
+                head.due.get_or_insert(now + first);
+            }
+        }
+    }
+
+    /// Hands out the copy that is due earliest at `now`, dropping its message once all copies
+    /// are out.
+    fn pop_due_copy(&mut self, now: Instant) -> Option<(Channel, PulseTrain)> {
+        let channel = Channel::iter()
+            .filter_map(|channel| {
+                let due = self.queues[channel as usize].front()?.due?;
+                (due <= now).then_some((due, channel))
+            })
+            .min_by_key(|&(due, channel)| (due, channel as u8))
+            .map(|(_, channel)| channel)?;
+
+        let slots = spec_repeat_slots(channel);
+        let queue = &mut self.queues[channel as usize];
+        let head = queue.front_mut()?;
+        let copy = PulseTrain::new(head.train.pulses().to_vec());
+        head.sent += 1;
+        head.due =
+            Some(now + self.message_slot * slots[(head.sent as usize).min(SPEC_REPEATS - 1)]);
+        if head.sent >= head.train.repeats().min(SPEC_REPEATS as u32) {
+            queue.pop_front();
+        }
+        self.last_served = Some(channel);
+        self.write_through();
+        Some((channel, copy))
+    }
+
+    /// Channels with pending messages, in round-robin order starting after the last served one.
+    fn round_robin(&self) -> impl Iterator<Item = Channel> + '_ {
+        let start = self.last_served.map_or(0, |channel| channel as usize + 1);
+        (0..Channel::ALL.len())
+            .map(move |offset| Channel::ALL[(start + offset) % Channel::ALL.len()])
+            .filter(|&channel| !self.queues[channel as usize].is_empty())
+    }
+
+    fn next_channel(&self) -> Option<Channel> {
+        match self.policy {
+            FairnessPolicy::RoundRobin | FairnessPolicy::SlotTiming => self.round_robin().next(),
+            FairnessPolicy::NewestFirst => self.round_robin().max_by_key(|&channel| {
+                self.queues[channel as usize]
+                    .back()
+                    .map_or(0, |pending| pending.sequence)
+            }),
+            FairnessPolicy::Priority => {
+                let highest = self
+                    .round_robin()
+                    .map(|channel| self.priorities[channel as usize])
+                    .max()?;
+                self.round_robin()
+                    .find(|&channel| self.priorities[channel as usize] == highest)
+            }
+        }
+    }
+}
+
+fn store_error(reason: &str) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid queue file: {}", reason),
+    ))
+}
+
+/// Writes `content` to a new file at `path` and flushes it to the disk, so a rename of the file
+/// never exposes a partially written one after a power loss.
+fn write_synced(path: &Path, content: &str) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()
+}
+
+/// Flushes the directory entry of `path` to the disk, making a preceding rename durable.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::File::open(parent)?.sync_all()
+}
+
+/// Directories can't be opened for syncing here; the rename is as durable as the platform makes it.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn parse_store_line(line: &str) -> Option<(Channel, bool, PulseTrain)> {
+    let mut fields = line.split_whitespace();
+    let channel = match fields.next()?.parse::<usize>().ok()? {
+        number @ 1..=4 => Channel::ALL[number - 1],
+        _ => return None,
+    };
+    let keepalive = match fields.next()? {
+        "command" => false,
+        "keepalive" => true,
+        _ => return None,
+    };
+    let repeats = fields.next()?.parse().ok()?;
+    let pulses = fields
+        .next()?
+        .split(',')
+        .map(|pulse| pulse.parse().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((
+        channel,
+        keepalive,
+        PulseTrain::new(pulses).with_repeats(repeats),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::device::MockTransmitterRecorder;
+
+    fn drain(scheduler: &mut Scheduler) -> Vec<(Channel, Vec<u32>)> {
+        std::iter::from_fn(|| scheduler.pop())
+            .map(|(channel, train)| (channel, train.into_pulses()))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_robin_alternates_channels() {
+        let mut scheduler = Scheduler::new(FairnessPolicy::RoundRobin);
+        scheduler.push(Channel::One, vec![1]);
+        scheduler.push(Channel::One, vec![2]);
+        scheduler.push(Channel::Three, vec![3]);
+        let order: Vec<_> = drain(&mut scheduler)
+            .into_iter()
+            .map(|(_, p)| p[0])
+            .collect();
+        assert_eq!(order, [1, 3, 2]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_newest_first_serves_latest_channel() {
+        let mut scheduler = Scheduler::new(FairnessPolicy::NewestFirst);
+        scheduler.push(Channel::One, vec![1]);
+        scheduler.push(Channel::Two, vec![2]);
+        scheduler.push(Channel::Four, vec![4]);
+        let order: Vec<_> = drain(&mut scheduler).into_iter().map(|(c, _)| c).collect();
+        assert_eq!(order, [Channel::Four, Channel::Two, Channel::One]);
+    }
+
+    #[test]
+    fn test_priority_prefers_important_channels() {
+        let mut scheduler = Scheduler::new(FairnessPolicy::Priority);
+        scheduler.set_priority(Channel::Three, 5);
+        scheduler.push(Channel::One, vec![1]);
+        scheduler.push(Channel::Three, vec![3]);
+        scheduler.push(Channel::Three, vec![3]);
+        let order: Vec<_> = drain(&mut scheduler).into_iter().map(|(c, _)| c).collect();
+        assert_eq!(order, [Channel::Three, Channel::Three, Channel::One]);
+    }
+
+    #[test]
+    fn test_keepalives_are_coalesced_and_superseded() {
+        let mut scheduler = Scheduler::default();
+        scheduler.push_keepalive(Channel::One, vec![1]);
+        scheduler.push_keepalive(Channel::One, vec![2]);
+        assert_eq!(scheduler.len(), 1);
+        scheduler.push(Channel::One, vec![3]);
+        assert_eq!(drain(&mut scheduler), [(Channel::One, vec![3])]);
+    }
+
+    #[test]
+    fn test_poll_reserves_airtime_times_repeats() {
+        let mut scheduler = Scheduler::default();
+        scheduler.push(
+            Channel::One,
+            PulseTrain::new(vec![10_000, 10_000]).with_repeats(2),
+        );
+        scheduler.push(Channel::Two, vec![157, 1026]);
+        scheduler.push(Channel::Three, vec![157, 1026]);
+
+        let start = Instant::now();
+        assert_eq!(scheduler.poll(start).map(|(c, _)| c), Some(Channel::One));
+        let ready_at = start + Duration::from_millis(40);
+        assert_eq!(scheduler.ready_at(), Some(ready_at));
+        assert!(scheduler
+            .poll(ready_at - Duration::from_micros(1))
+            .is_none());
+
+        assert_eq!(scheduler.poll(ready_at).map(|(c, _)| c), Some(Channel::Two));
+        assert_eq!(scheduler.ready_at(), Some(ready_at + MAX_MESSAGE_DURATION));
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_slot_timing_interleaves_channels_in_spec_pauses() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut scheduler = Scheduler::new(FairnessPolicy::SlotTiming);
+        let now = transmitter.clock.now();
+        // Messages of 10 ms airtime, told apart by their first pulse.
+        scheduler.push(
+            Channel::One,
+            PulseTrain::new(vec![1000, 9000]).with_repeats(5),
+        );
+        scheduler.push(
+            Channel::Four,
+            PulseTrain::new(vec![4000, 6000]).with_repeats(5),
+        );
+        scheduler.run(&transmitter).unwrap();
+
+        // The clock only moves while the scheduler sleeps.
+        let sent: Vec<(Duration, u32)> = transmitter
+            .timeline()
+            .into_iter()
+            .map(|(at, pulses)| (at - now, pulses[0]))
+            .collect();
+        let channel = |first| -> Vec<Duration> {
+            sent.iter()
+                .filter(|&&(_, pulse)| pulse == first)
+                .map(|&(at, _)| at)
+                .collect()
+        };
+        // Channel 1 pauses 4, 4, 5, 5, 6 slots, channel 4 pauses 1, 1, 5, 5, 12 slots.
+        assert_eq!(channel(1000), [ms(64), ms(128), ms(208), ms(288), ms(384)]);
+        // Both fall due at 384 ms; channel 4 follows once channel 1 has left the medium.
+        assert_eq!(channel(4000), [ms(16), ms(32), ms(112), ms(192), ms(394)]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_slot_timing_newer_message_drops_remaining_copies() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut scheduler = Scheduler::new(FairnessPolicy::SlotTiming);
+        let now = transmitter.clock.now();
+        scheduler.push(Channel::Four, PulseTrain::new(vec![1, 999]).with_repeats(5));
+        assert!(scheduler.poll(now).is_none());
+        assert!(scheduler.poll(now + ms(16)).is_some());
+        scheduler.push(Channel::Four, PulseTrain::new(vec![2, 999]).with_repeats(5));
+        scheduler.run(&transmitter).unwrap();
+
+        let sent = transmitter.sent();
+        assert!(sent.iter().all(|pulses| pulses[0] == 2));
+        assert_eq!(sent.len(), SPEC_REPEATS);
+    }
+
+    #[test]
+    fn test_slot_timing_busy_medium_defers_copies() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new(FairnessPolicy::SlotTiming);
+        scheduler.push(Channel::Four, vec![20_000]);
+        scheduler.push(Channel::Three, vec![1_000]);
+        assert!(scheduler.poll(now).is_none());
+        assert_eq!(scheduler.ready_at(), Some(now + ms(16)));
+        assert_eq!(
+            scheduler.poll(now + ms(16)).map(|(c, _)| c),
+            Some(Channel::Four)
+        );
+        assert!(scheduler.poll(now + ms(32)).is_none());
+        assert_eq!(scheduler.ready_at(), Some(now + ms(36)));
+        assert_eq!(
+            scheduler.poll(now + ms(36)).map(|(c, _)| c),
+            Some(Channel::Three)
+        );
+        assert!(scheduler.is_empty());
+    }
+
+    fn store_path(test: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("brickbeam-queue-{}-{}", test, std::process::id()))
+    }
+
+    #[test]
+    fn test_save_and_restore_round_trip() {
+        let path = store_path("round-trip");
+        let mut scheduler = Scheduler::default();
+        scheduler.push(
+            Channel::Two,
+            PulseTrain::new(vec![157, 1026]).with_repeats(5),
+        );
+        scheduler.push_keepalive(Channel::One, vec![157, 263]);
+        scheduler.push(Channel::Two, vec![157, 552]);
+        scheduler.save(&path).unwrap();
+
+        let mut restored = Scheduler::default();
+        assert_eq!(restored.restore(&path).unwrap(), 3);
+        let mut original = Vec::new();
+        while let Some(message) = scheduler.pop() {
+            original.push(message);
+        }
+        let mut reloaded = Vec::new();
+        while let Some(message) = restored.pop() {
+            reloaded.push(message);
+        }
+        assert_eq!(original, reloaded);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_store_written_through_on_every_change() {
+        let path = store_path("write-through");
+        let _ = fs::remove_file(&path);
+        let mut scheduler = Scheduler::default().with_store(&path);
+        let stored = || Scheduler::default().restore(&path).unwrap();
+
+        scheduler.push(Channel::One, vec![157, 1026]);
+        scheduler.push_keepalive(Channel::Two, vec![157, 263]);
+        assert_eq!(stored(), 2);
+        scheduler.pop();
+        assert_eq!(stored(), 1);
+        scheduler.clear(Channel::Two);
+        assert_eq!(stored(), 0);
+        assert!(scheduler.take_store_error().is_none());
+
+        // No temporary files are left behind.
+        let dir = path.parent().unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(!fs::read_dir(dir).unwrap().any(|entry| {
+            let entry = entry.unwrap().file_name().to_string_lossy().into_owned();
+            entry.starts_with(&format!(".{}", name))
+        }));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_store_errors_reported() {
+        let path = store_path("missing-dir").join("queue");
+        let mut scheduler = Scheduler::default().with_store(&path);
+        scheduler.push(Channel::One, vec![157, 1026]);
+        assert_eq!(scheduler.len(), 1);
+        assert!(matches!(scheduler.take_store_error(), Some(Error::Io(_))));
+        assert!(scheduler.take_store_error().is_none());
+    }
+
+    #[test]
+    fn test_restore_missing_and_corrupt_files() {
+        let path = store_path("corrupt");
+        let _ = fs::remove_file(&path);
+        let mut scheduler = Scheduler::default();
+        assert_eq!(scheduler.restore(&path).unwrap(), 0);
+
+        fs::write(
+            &path,
+            format!(
+                "{}\n1 command 1 157,1026\n5 command 1 157,1026\n",
+                STORE_HEADER
+            ),
+        )
+        .unwrap();
+        assert!(matches!(scheduler.restore(&path), Err(Error::Io(_))));
+        assert!(scheduler.is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,352 @@
+//! # Sequence files
+//!
+//! Sequences can be written as plain text, one step per line, so shows and timetables can be
+//! edited without recompiling:
+//!
+//! ```text
+//! # Names make the script independent of the channel switches on the receivers.
+//! train cargo 1
+//! train passenger 2
+//!
+//! cargo pwm red 5 wait 10s
+//! passenger combo 3 -3 wait 500ms
+//! 3 direct forward brake
+//! cargo extended brake
+//! ```
+//!
+//! A step names its target (a channel from 1 to 4 or a train declared with `train`), one action
+//! and an optional `wait` (in `ms` or `s`) before the next step:
+//!
+//! - `pwm <red|blue> <speed>` - a Single Output PWM command,
+//! - `combo <red speed> <blue speed>` - a Combo PWM command,
+//! - `direct <red state> <blue state>` - a Combo Direct command (`float`, `forward`, `backward`
+//!   or `brake`),
+//! - `extended <brake|increment|decrement|toggle-blue|toggle-address|align-toggle>`.
+//!
+//! `StepParser` parses the same grammar line by line, e.g. for commands streamed over a pipe.
+//!
+//! `Sequence::parse` reports every problem of a file at once instead of stopping at the first
+//! one, and also runs `Sequence::validate`, so typos are caught at load time rather than in the
+//! middle of a show. `Sequence::dry_run` then plays the result through a virtual transmitter.
+
+use super::{ActionEncoder, Sequence, SequenceAction, SequenceStep};
+use crate::device::PulseTransmitter;
+use crate::protocols::{check_speed, duration_of, MAX_MESSAGE_DURATION};
+use crate::{
+    Channel, ComboDirectCommand, ComboPwmCommand, DirectState, Error, ExtendedCommand, Output,
+    Result, SingleOutputCommand,
+};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A problem found while parsing or validating a sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// The 1-based line of the file, or the 1-based step number for sequences built in code.
+    pub line: usize,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+const EXTENDED_NAMES: [(&str, ExtendedCommand); 6] = [
+    ("brake", ExtendedCommand::BrakeThenFloatOnRedOutput),
+    ("increment", ExtendedCommand::IncrementSpeedOnRedOutput),
+    ("decrement", ExtendedCommand::DecrementSpeedOnRedOutput),
+    (
+        "toggle-blue",
+        ExtendedCommand::ToggleForwardOrFloatOnBlueOutput,
+    ),
+    ("toggle-address", ExtendedCommand::ToggleAddress),
+    ("align-toggle", ExtendedCommand::AlignToggle),
+];
+
+/// Parses sequence lines one at a time, remembering the trains declared so far.
+#[derive(Debug, Clone, Default)]
+pub struct StepParser {
+    trains: Vec<(String, Channel)>,
+}
+
+impl StepParser {
+    /// Creates a parser without any declared trains.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses one line of the sequence grammar.
+    ///
+    /// Returns the step of the line, or `None` for blank lines, comments and train declarations.
+    pub fn parse_line(&mut self, line: &str) -> std::result::Result<Option<SequenceStep>, String> {
+        let content = line.split('#').next().unwrap_or_default();
+        let words: Vec<&str> = content.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(None),
+            ["train", name, channel] => {
+                let channel = parse_channel(channel)?;
+                if self.trains.iter().any(|(known, _)| known == name) {
+                    return Err(format!("Train '{}' is declared twice", name));
+                }
+                self.trains.push((name.to_string(), channel));
+                Ok(None)
+            }
+            [target, rest @ ..] => parse_step(&self.trains, target, rest).map(Some),
+        }
+    }
+}
+
+impl Sequence {
+    /// Parses and validates a sequence file.
+    ///
+    /// # Errors
+    ///
+    /// Returns every syntax error, unknown train name and validation issue of the file.
+    pub fn parse(text: &str) -> std::result::Result<Self, Vec<ValidationIssue>> {
+        let mut parser = StepParser::new();
+        let mut steps = Vec::new();
+        let mut lines = Vec::new();
+        let mut issues = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            match parser.parse_line(line) {
+                Ok(Some(step)) => {
+                    steps.push(step);
+                    lines.push(line_number);
+                }
+                Ok(None) => (),
+                Err(message) => issues.push(ValidationIssue {
+                    line: line_number,
+                    message,
+                }),
+            }
+        }
+
+        let sequence = Self::from(steps);
+        issues.extend(
+            sequence
+                .validate()
+                .into_iter()
+                .map(|issue| ValidationIssue {
+                    line: lines[issue.line - 1],
+                    ..issue
+                }),
+        );
+        if issues.is_empty() {
+            Ok(sequence)
+        } else {
+            issues.sort_by_key(|issue| issue.line);
+            Err(issues)
+        }
+    }
+
+    /// Checks speed ranges and timing feasibility of every step.
+    ///
+    /// A wait shorter than the maximum PF message length between two steps would let the next
+    /// message collide with the previous one on the IR medium.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        for (index, step) in self.steps.iter().enumerate() {
+            let line = index + 1;
+            let speeds: &[i8] = match step.action {
+                SequenceAction::SingleOutput(_, SingleOutputCommand::PWM(speed)) => &[speed],
+                SequenceAction::ComboPwm(cmd) => &[cmd.speed_red, cmd.speed_blue],
+                _ => &[],
+            };
+            for &speed in speeds {
+                if let Err(e) = check_speed(speed) {
+                    issues.push(ValidationIssue {
+                        line,
+                        message: e.to_string(),
+                    });
+                }
+            }
+            if index + 1 < self.steps.len() && step.wait < MAX_MESSAGE_DURATION {
+                issues.push(ValidationIssue {
+                    line,
+                    message: format!(
+                        "Wait of {:?} is shorter than the {:?} message slot; the next message may collide",
+                        step.wait, MAX_MESSAGE_DURATION
+                    ),
+                });
+            }
+        }
+        issues
+    }
+
+    /// Plays the sequence through `pulse_transmitter` without waiting between steps, e.g. through
+    /// `PulseTransmitterEmulator` to check a sequence without hardware.
+    ///
+    /// Returns how long the sequence takes when played for real: the airtime of all messages
+    /// plus all waits.
+    pub fn dry_run(&self, pulse_transmitter: &impl PulseTransmitter) -> Result<Duration> {
+        let mut encoder = ActionEncoder::new()?;
+        let mut total = Duration::ZERO;
+        for step in &self.steps {
+            let pulses = encoder.encode(step.channel, step.action)?;
+            pulse_transmitter.send_pulses(&pulses)?;
+            total += duration_of(&pulses) + step.wait;
+        }
+        Ok(total)
+    }
+}
+
+/// Parses and validates a sequence file, joining all issues into one `Error::ProtocolError`.
+impl FromStr for Sequence {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s).map_err(|issues| {
+            let issues: Vec<String> = issues.iter().map(ValidationIssue::to_string).collect();
+            Error::ProtocolError(issues.join("; "))
+        })
+    }
+}
+
+fn parse_channel(word: &str) -> std::result::Result<Channel, String> {
+    match word.parse::<usize>() {
+        Ok(number @ 1..=4) => Ok(Channel::ALL[number - 1]),
+        _ => Err(format!("Invalid channel '{}', expected 1 to 4", word)),
+    }
+}
+
+fn parse_step(
+    trains: &[(String, Channel)],
+    target: &str,
+    words: &[&str],
+) -> std::result::Result<SequenceStep, String> {
+    let channel = match trains.iter().find(|(name, _)| *name == target) {
+        Some((_, channel)) => *channel,
+        None if target.chars().all(|c| c.is_ascii_digit()) => parse_channel(target)?,
+        None => return Err(format!("Unknown train '{}'", target)),
+    };
+    let (words, wait) = match words {
+        [action @ .., "wait", wait] => (action, parse_duration(wait)?),
+        _ => (words, Duration::ZERO),
+    };
+    let action = match words {
+        ["pwm", output, speed] => SequenceAction::SingleOutput(
+            parse_output(output)?,
+            SingleOutputCommand::PWM(parse_speed(speed)?),
+        ),
+        ["combo", red, blue] => SequenceAction::ComboPwm(
+            ComboPwmCommand::new(parse_speed(red)?, parse_speed(blue)?)
+                .map_err(|e| e.to_string())?,
+        ),
+        ["direct", red, blue] => SequenceAction::ComboDirect(ComboDirectCommand::from((
+            parse_direct_state(red)?,
+            parse_direct_state(blue)?,
+        ))),
+        ["extended", name] => SequenceAction::Extended(
+            EXTENDED_NAMES
+                .iter()
+                .find(|(known, _)| known == name)
+                .map(|(_, cmd)| *cmd)
+                .ok_or_else(|| format!("Unknown extended command '{}'", name))?,
+        ),
+        _ => return Err(format!("Invalid step '{} {}'", target, words.join(" "))),
+    };
+    Ok(SequenceStep {
+        channel,
+        action,
+        wait,
+    })
+}
+
+fn parse_output(word: &str) -> std::result::Result<Output, String> {
+    match word {
+        "red" => Ok(Output::RED),
+        "blue" => Ok(Output::BLUE),
+        _ => Err(format!("Invalid output '{}', expected red or blue", word)),
+    }
+}
+
+fn parse_speed(word: &str) -> std::result::Result<i8, String> {
+    word.parse()
+        .map_err(|_| format!("Invalid speed '{}'", word))
+}
+
+fn parse_direct_state(word: &str) -> std::result::Result<DirectState, String> {
+    word.parse().map_err(|e: Error| e.to_string())
+}
+
+fn parse_duration(word: &str) -> std::result::Result<Duration, String> {
+    let (number, unit) = word
+        .find(|c: char| !c.is_ascii_digit())
+        .map(|split| word.split_at(split))
+        .unwrap_or((word, ""));
+    let value: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid wait '{}'", word))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "s" => Ok(Duration::from_secs(value)),
+        _ => Err(format!(
+            "Invalid wait '{}', expected e.g. 500ms or 2s",
+            word
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+
+    const SHOW: &str = "\
+# A small show
+train cargo 1
+cargo pwm red 5 wait 2s
+2 combo 3 -3 wait 500ms
+3 direct forward brake wait 20ms
+cargo extended brake
+";
+
+    #[test]
+    fn test_parse_sequence_file() {
+        let sequence = Sequence::parse(SHOW).unwrap();
+        assert_eq!(sequence.steps().len(), 4);
+        assert_eq!(sequence.steps()[0].channel, Channel::One);
+        assert_eq!(sequence.steps()[0].wait, Duration::from_secs(2));
+        assert_eq!(sequence.steps()[2].channel, Channel::Three);
+        assert!(matches!(
+            sequence.steps()[3].action,
+            SequenceAction::Extended(ExtendedCommand::BrakeThenFloatOnRedOutput)
+        ));
+    }
+
+    #[test]
+    fn test_parse_reports_all_issues_with_lines() {
+        let issues = Sequence::parse(
+            "\
+train cargo 1
+freight pwm red 5 wait 1s
+cargo pwm red 9 wait 1s
+5 combo 1 1 wait 1s
+cargo pwm red 3 wait 5ms
+cargo combo 0 0
+",
+        )
+        .unwrap_err();
+        let lines: Vec<usize> = issues.iter().map(|issue| issue.line).collect();
+        assert_eq!(lines, [2, 3, 4, 5]);
+        assert!(issues[0].message.contains("Unknown train 'freight'"));
+        assert!(issues[1].message.contains("Invalid speed 9"));
+        assert!(issues[3].message.contains("collide"));
+        assert!("1 fly away".parse::<Sequence>().is_err());
+    }
+
+    #[test]
+    fn test_dry_run_sends_every_step_without_waiting() {
+        let transmitter = MockTransmitterRecorder::default();
+        let sequence = Sequence::parse(SHOW).unwrap();
+        let total = sequence.dry_run(&transmitter).unwrap();
+        assert_eq!(transmitter.sent().len(), 4);
+        assert!(total > Duration::from_millis(2520));
+    }
+}
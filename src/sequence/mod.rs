@@ -0,0 +1,531 @@
+//! # Sequences
+//!
+//! A `Sequence` is a scripted list of steps, each sending one command on one channel and then
+//! waiting before the next step, e.g. "start train one, wait 10 s, stop it, switch the lights".
+//! `SequencePlayer` plays a sequence on a transmitter.
+//!
+//! Playing blocks the calling thread, so it usually runs on a thread of its own. Like the remote
+//! controllers, a player is bound to the thread that created it, so create it on the playing
+//! thread and attach a `SequenceControl` with `with_control`. The control handle lets any other
+//! thread `pause`, `resume` or `skip_step` the running sequence, e.g. when a child puts a hand on the
+//! track. What happens to moving trains during a pause is decided by the `PauseBehavior`.
+//!
+//! Sequences can also be loaded from text files and checked before they run; see the `file`
+//! module.
+
+use crate::clock;
+use crate::controller::registry::ControllerRegistry;
+use crate::controller::repetition::{transmit, RepeatPolicy};
+use crate::device::PulseTransmitter;
+use crate::protocols::{
+    ComboDirectProtocol, ComboPwmProtocol, ExtendedProtocol, SingleOutputProtocol,
+};
+use crate::{
+    Address, Channel, ComboDirectCommand, ComboPwmCommand, ExtendedCommand, LogicalChannel, Output,
+    Result, SingleOutputCommand,
+};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+mod file;
+
+pub use file::{StepParser, ValidationIssue};
+
+/// A command of any PF protocol, as sent by a sequence step.
+#[derive(Debug, Clone, Copy)]
+pub enum SequenceAction {
+    SingleOutput(Output, SingleOutputCommand),
+    ComboDirect(ComboDirectCommand),
+    ComboPwm(ComboPwmCommand),
+    Extended(ExtendedCommand),
+}
+
+/// One step of a sequence: send `action` on `channel`, then wait for `wait`.
+#[derive(Debug, Clone, Copy)]
+pub struct SequenceStep {
+    pub channel: Channel,
+    pub action: SequenceAction,
+    pub wait: Duration,
+}
+
+/// An ordered list of steps.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{Channel, ComboPwmCommand, Sequence, SequenceAction};
+/// use std::time::Duration;
+///
+/// let sequence = Sequence::new()
+///     .then(
+///         Channel::One,
+///         SequenceAction::ComboPwm(ComboPwmCommand::new(5, 0).unwrap()),
+///         Duration::from_secs(10),
+///     )
+///     .then(
+///         Channel::One,
+///         SequenceAction::ComboPwm(ComboPwmCommand::stopped()),
+///         Duration::ZERO,
+///     );
+/// assert_eq!(sequence.steps().len(), 2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Sequence {
+    steps: Vec<SequenceStep>,
+}
+
+impl Sequence {
+    /// Creates an empty sequence.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a step sending `action` on `channel` and waiting `wait` afterwards.
+    pub fn then(mut self, channel: Channel, action: SequenceAction, wait: Duration) -> Self {
+        self.steps.push(SequenceStep {
+            channel,
+            action,
+            wait,
+        });
+        self
+    }
+
+    /// Returns the steps of the sequence.
+    pub fn steps(&self) -> &[SequenceStep] {
+        &self.steps
+    }
+}
+
+impl From<Vec<SequenceStep>> for Sequence {
+    fn from(steps: Vec<SequenceStep>) -> Self {
+        Self { steps }
+    }
+}
+
+/// What a paused `SequencePlayer` does with trains that are currently moving.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PauseBehavior {
+    /// Send nothing while paused; receivers keep their current speeds.
+    #[default]
+    HoldSpeeds,
+    /// Float every channel the sequence has used when pausing, and restore the last speed of
+    /// every output when resuming.
+    StopTrains,
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    paused: bool,
+    skip: bool,
+}
+
+/// A handle to pause, resume or skip steps of a running `SequencePlayer` from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct SequenceControl {
+    shared: Arc<(Mutex<ControlState>, Condvar)>,
+}
+
+impl SequenceControl {
+    /// Creates a new handle, to be attached to a player with `SequencePlayer::with_control`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, ControlState> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Pauses the sequence before its next step; a running wait is frozen.
+    pub fn pause(&self) {
+        self.state().paused = true;
+        self.shared.1.notify_all();
+    }
+
+    /// Resumes a paused sequence, continuing the frozen wait.
+    pub fn resume(&self) {
+        self.state().paused = false;
+        self.shared.1.notify_all();
+    }
+
+    /// Ends the current step: cuts its wait short, or skips the step entirely if it has not been
+    /// sent yet (e.g. because the sequence is paused).
+    pub fn skip_step(&self) {
+        self.state().skip = true;
+        self.shared.1.notify_all();
+    }
+
+    /// Returns `true` while the sequence is paused.
+    pub fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+}
+
+/// Encodes actions of every protocol, keeping one toggle state per receiver like `PulseEncoder`,
+/// or sharing those of a `BrickBeam`.
+pub(crate) struct ActionEncoder<'a> {
+    single_output: SingleOutputProtocol,
+    extended: ExtendedProtocol,
+    combo_direct: ComboDirectProtocol,
+    combo_pwm: ComboPwmProtocol,
+    address: Address,
+    registry: ControllerRegistry,
+    shared_registry: Option<&'a ControllerRegistry>,
+}
+
+impl<'a> ActionEncoder<'a> {
+    pub(crate) fn new() -> Result<Self> {
+        Ok(Self {
+            single_output: SingleOutputProtocol::new()?,
+            extended: ExtendedProtocol::new()?,
+            combo_direct: ComboDirectProtocol::new()?,
+            combo_pwm: ComboPwmProtocol::new()?,
+            address: Address::Default,
+            registry: ControllerRegistry::default(),
+            shared_registry: None,
+        })
+    }
+
+    /// Shares the toggle states with the controllers of a `BrickBeam` registry.
+    pub(crate) fn share_registry(&mut self, registry: &'a ControllerRegistry) {
+        self.shared_registry = Some(registry);
+    }
+
+    /// Sends subsequent messages to the receivers in `address` space.
+    pub(crate) fn set_address(&mut self, address: Address) {
+        self.address = address;
+    }
+
+    pub(crate) fn encode(&mut self, channel: Channel, action: SequenceAction) -> Result<Vec<u32>> {
+        let logical = LogicalChannel::new(channel, self.address);
+        let registry = self.shared_registry.unwrap_or(&self.registry);
+        match action {
+            SequenceAction::SingleOutput(output, cmd) => {
+                self.single_output.set_address(self.address);
+                self.single_output
+                    .share_toggle(registry.toggle(logical, Some(output)));
+                self.single_output.encode_cmd(channel, output, cmd)
+            }
+            SequenceAction::ComboDirect(cmd) => {
+                self.combo_direct.set_address(self.address);
+                self.combo_direct.encode_cmd(channel, cmd)
+            }
+            SequenceAction::ComboPwm(cmd) => {
+                self.combo_pwm.set_address(self.address);
+                self.combo_pwm.encode_cmd(channel, cmd)
+            }
+            SequenceAction::Extended(cmd) => {
+                self.extended.share_toggle(registry.toggle(logical, None));
+                self.extended.encode_cmd(channel, cmd)
+            }
+        }
+    }
+}
+
+/// Plays a `Sequence` on a `PulseTransmitter`.
+///
+/// Messages are sent like those of the remote controllers: as the copies of a `RepeatPolicy`, to
+/// the receivers of one address space, and, for a player created by `BrickBeam`, with the toggle
+/// bits shared with `BrickBeam::speed` and `BrickBeam::extended`.
+///
+/// # Example
+/// ```rust
+/// use brickbeam::{
+///     BrickBeam, Channel, ComboPwmCommand, Result, Sequence, SequenceAction, SequenceControl,
+/// };
+/// use std::{thread, time::Duration};
+///
+/// fn main() -> Result<()> {
+///     let brick_beam = BrickBeam::new("/dev/lirc0")?;
+///     let sequence = Sequence::new().then(
+///         Channel::One,
+///         SequenceAction::ComboPwm(ComboPwmCommand::stopped().with_red(5)?),
+///         Duration::from_secs(60),
+///     );
+///     let control = SequenceControl::new();
+///     thread::scope(|scope| {
+///         let playing = scope.spawn(|| {
+///             brick_beam
+///                 .create_sequence_player(sequence)?
+///                 .with_control(control.clone())
+///                 .play()
+///         });
+///         thread::sleep(Duration::from_millis(10));
+///         control.skip_step();
+///         playing.join().unwrap()
+///     })?;
+///     Ok(())
+/// }
+/// ```
+pub struct SequencePlayer<'a, T: PulseTransmitter> {
+    pulse_transmitter: &'a T,
+    sequence: Sequence,
+    control: SequenceControl,
+    pause_behavior: PauseBehavior,
+    encoder: ActionEncoder<'a>,
+    repeat_policy: RepeatPolicy,
+    last_speeds: Vec<(Channel, Option<Output>, SequenceAction)>,
+}
+
+impl<'a, T: PulseTransmitter> SequencePlayer<'a, T> {
+    pub fn new(pulse_transmitter: &'a T, sequence: impl Into<Sequence>) -> Result<Self> {
+        Ok(Self {
+            pulse_transmitter,
+            sequence: sequence.into(),
+            control: SequenceControl::default(),
+            pause_behavior: PauseBehavior::default(),
+            encoder: ActionEncoder::new()?,
+            repeat_policy: RepeatPolicy::default(),
+            last_speeds: Vec::new(),
+        })
+    }
+
+    /// Sends every message as the copies `policy` asks for, e.g. `RepeatPolicy::spec()` for
+    /// distant receivers. Each step then waits after its last copy.
+    pub fn with_repeat_policy(mut self, policy: RepeatPolicy) -> Self {
+        self.repeat_policy = policy;
+        self
+    }
+
+    /// Sends the steps to the receivers switched to the extra address space, i.e. logical
+    /// channels 5 to 8, or back to the default one.
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.encoder.set_address(address);
+        self
+    }
+
+    /// Shares the toggle states with the other controllers of a `BrickBeam` registry.
+    pub(crate) fn with_registry(mut self, registry: &'a ControllerRegistry) -> Self {
+        self.encoder.share_registry(registry);
+        self
+    }
+
+    /// Sets what happens to moving trains while the sequence is paused.
+    pub fn with_pause_behavior(mut self, pause_behavior: PauseBehavior) -> Self {
+        self.pause_behavior = pause_behavior;
+        self
+    }
+
+    /// Attaches a control handle shared with other threads.
+    pub fn with_control(mut self, control: SequenceControl) -> Self {
+        self.control = control;
+        self
+    }
+
+    /// Returns a handle to control the sequence while it plays.
+    pub fn control(&self) -> SequenceControl {
+        self.control.clone()
+    }
+
+    /// Plays all steps, blocking until the sequence is finished.
+    ///
+    /// Returns the summed airtime of all transmitted messages. A transmission error stops the
+    /// sequence and is returned.
+    pub fn play(&mut self) -> Result<Duration> {
+        let mut airtime = Duration::ZERO;
+        for index in 0..self.sequence.steps.len() {
+            airtime += self.play_step(self.sequence.steps[index])?;
+        }
+        Ok(airtime)
+    }
+
+    /// Plays a single step that is not part of the sequence, e.g. one received over a pipe,
+    /// honoring pauses and skips like any other step.
+    ///
+    /// Returns the summed airtime of all transmitted messages.
+    pub fn play_step(&mut self, step: SequenceStep) -> Result<Duration> {
+        let mut airtime = self.hold_while_paused()?;
+        if std::mem::take(&mut self.control.state().skip) {
+            return Ok(airtime);
+        }
+        airtime += self.send(step.channel, step.action)?;
+        self.remember_speed(step.channel, step.action);
+        airtime += self.wait(step.wait)?;
+        Ok(airtime)
+    }
+
+    fn send(&mut self, channel: Channel, action: SequenceAction) -> Result<Duration> {
+        let pulses = self.encoder.encode(channel, action)?;
+        transmit(self.pulse_transmitter, channel, &pulses, self.repeat_policy)
+    }
+
+    /// Keeps the last absolute speed command per output, so it can be restored after a pause.
+    fn remember_speed(&mut self, channel: Channel, action: SequenceAction) {
+        let output = match action {
+            SequenceAction::SingleOutput(output, SingleOutputCommand::PWM(_)) => Some(output),
+            SequenceAction::ComboDirect(_) | SequenceAction::ComboPwm(_) => None,
+            _ => return,
+        };
+        self.last_speeds.retain(|(known_channel, known_output, _)| {
+            *known_channel != channel || (output.is_some() && *known_output != output)
+        });
+        self.last_speeds.push((channel, output, action));
+    }
+
+    /// Blocks while paused, stopping and restoring trains according to the pause behavior.
+    fn hold_while_paused(&mut self) -> Result<Duration> {
+        if !self.control.is_paused() {
+            return Ok(Duration::ZERO);
+        }
+        let mut airtime = Duration::ZERO;
+        let restore = self.pause_behavior == PauseBehavior::StopTrains;
+        if restore {
+            let mut channels: Vec<Channel> = self.last_speeds.iter().map(|(c, _, _)| *c).collect();
+            channels.sort_by_key(|&channel| channel as u8);
+            channels.dedup();
+            for channel in channels {
+                airtime += self.send(
+                    channel,
+                    SequenceAction::ComboPwm(ComboPwmCommand::stopped()),
+                )?;
+            }
+        }
+        {
+            let control = self.control.clone();
+            let mut state = control.state();
+            while state.paused {
+                state = control
+                    .shared
+                    .1
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+        }
+        if restore {
+            for (channel, _, action) in self.last_speeds.clone() {
+                airtime += self.send(channel, action)?;
+            }
+        }
+        Ok(airtime)
+    }
+
+    /// Waits for `wait` on the clock of the transmitter, freezing the remaining time while
+    /// paused and ending early on a skip.
+    fn wait(&mut self, wait: Duration) -> Result<Duration> {
+        let pulse_transmitter = self.pulse_transmitter;
+        let clock = pulse_transmitter.clock();
+        let mut airtime = Duration::ZERO;
+        let mut remaining = wait;
+        loop {
+            let started = clock.now();
+            let deadline = started + remaining;
+            let control = self.control.clone();
+            let mut state = control.state();
+            while !state.paused && !state.skip && clock.now() < deadline {
+                state = clock::wait_until(clock, &control.shared.1, state, deadline);
+            }
+            if std::mem::take(&mut state.skip) {
+                return Ok(airtime);
+            }
+            let paused = state.paused;
+            drop(state);
+            if !paused {
+                return Ok(airtime);
+            }
+            remaining = deadline.saturating_duration_since(clock.now());
+            airtime += self.hold_while_paused()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockTransmitterRecorder;
+    use std::thread;
+
+    fn forward_then_stop(wait: Duration) -> Sequence {
+        Sequence::new()
+            .then(
+                Channel::One,
+                SequenceAction::ComboPwm(ComboPwmCommand::new(5, 0).unwrap()),
+                wait,
+            )
+            .then(
+                Channel::One,
+                SequenceAction::ComboPwm(ComboPwmCommand::stopped()),
+                Duration::ZERO,
+            )
+    }
+
+    #[test]
+    fn test_play_sends_every_step() {
+        let transmitter = MockTransmitterRecorder::default();
+        let mut player = SequencePlayer::new(&transmitter, forward_then_stop(Duration::ZERO))
+            .expect("Should create SequencePlayer");
+        assert!(player.play().unwrap() > Duration::ZERO);
+        assert_eq!(transmitter.sent().len(), 2);
+    }
+
+    #[test]
+    fn test_play_repeats_to_address() {
+        let transmitter = MockTransmitterRecorder::default();
+        let sequence = Sequence::new().then(
+            Channel::Two,
+            SequenceAction::SingleOutput(Output::BLUE, SingleOutputCommand::PWM(3)),
+            Duration::ZERO,
+        );
+        SequencePlayer::new(&transmitter, sequence)
+            .unwrap()
+            .with_repeat_policy(RepeatPolicy::fixed(3, Duration::ZERO))
+            .with_address(Address::Extra)
+            .play()
+            .unwrap();
+
+        let sent = transmitter.sent();
+        assert_eq!(sent.len(), 3);
+        assert!(sent.iter().all(|pulses| pulses == &sent[0]));
+        // The address bit follows the toggle, escape and two channel bits.
+        assert!(sent[0][11] > 400);
+    }
+
+    #[test]
+    fn test_skip_step_cuts_wait_short() {
+        let transmitter = MockTransmitterRecorder::default();
+        let control = SequenceControl::new();
+        thread::scope(|scope| {
+            let playing = scope.spawn(|| {
+                SequencePlayer::new(&transmitter, forward_then_stop(Duration::from_secs(30)))?
+                    .with_control(control.clone())
+                    .play()
+            });
+            transmitter.wait_until_sent(1);
+            control.skip_step();
+            playing.join().unwrap().unwrap();
+        });
+        let sent_at = transmitter.sent_at();
+        assert_eq!(sent_at.len(), 2);
+        assert!(sent_at[1] - sent_at[0] < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_pause_stops_and_resume_restores_trains() {
+        let transmitter = MockTransmitterRecorder::default();
+        let control = SequenceControl::new();
+        thread::scope(|scope| {
+            let playing = scope.spawn(|| {
+                SequencePlayer::new(&transmitter, forward_then_stop(Duration::from_millis(100)))?
+                    .with_pause_behavior(PauseBehavior::StopTrains)
+                    .with_control(control.clone())
+                    .play()
+            });
+            transmitter.wait_until_sent(1);
+            control.pause();
+            transmitter.wait_until_sent(2);
+            transmitter.clock.advance(Duration::from_secs(1));
+            assert_eq!(transmitter.sent().len(), 2, "Forward, then stop");
+            control.resume();
+            while !playing.is_finished() {
+                transmitter.clock.advance(Duration::from_millis(10));
+                thread::yield_now();
+            }
+            playing.join().unwrap().unwrap();
+        });
+
+        let sent = transmitter.sent();
+        assert_eq!(sent.len(), 4, "Forward, stop, restore, final stop");
+        assert_eq!(sent[0], sent[2]);
+        assert_eq!(sent[1], sent[3]);
+        // The wait resumes where the pause froze it rather than ending with the paused time.
+        let sent_at = transmitter.sent_at();
+        assert!(sent_at[3] - sent_at[2] >= Duration::from_millis(100));
+    }
+}